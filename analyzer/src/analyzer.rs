@@ -1,21 +1,36 @@
 //! An analyzer
 
-use core::cell::RefCell;
+use core::{cell::RefCell, ops::RangeInclusive};
 
-use alloc::{rc::Rc, vec::Vec};
-use ebpf_consts::maps::MapType;
+use alloc::{boxed::Box, rc::Rc, string::String, vec::Vec};
+use ebpf_consts::{
+    maps::MapType, programs::ProgType, MAX_COMBINED_STACK_SIZE, READABLE_REGISTER_COUNT,
+};
 
 use crate::{
-    blocks::{FunctionBlocks, IllegalStructure, ProgramInfo, TERMINAL_PSEUDO_BLOCK},
+    blocks::{BlockId, IllegalStructure, ProgramInfo},
     branch::{
-        context::BranchContext,
+        context::{
+            BranchContext, PruneReason, DEFAULT_INSTRUCTION_LIMIT, DEFAULT_PENDING_BRANCH_LIMIT,
+            DEFAULT_TOTAL_BRANCH_LIMIT,
+        },
         vm::{Branch, BranchState, StaticHelpers},
     },
-    interpreter::{context::VmContext, run, value::Verifiable, vm::Vm},
-    spec::IllegalInstruction,
+    interpreter::{
+        context::{AccessObserver, VmContext},
+        run,
+        value::Verifiable,
+        vm::Vm,
+    },
+    spec::{
+        features::FeatureSet, line_info::LineInfo, live_registers, program_type::ProgramType,
+        proto::KfuncPrototype, CodeOffset, IllegalInstruction,
+    },
+    track::{pointees::kernel_region::KernelStruct, TrackedValue},
 };
 
 /// eBPF map info
+#[derive(Clone)]
 pub struct MapInfo {
     /// Map type as is in [ebpf_consts::maps]
     pub map_type: MapType,
@@ -25,6 +40,17 @@ pub struct MapInfo {
     pub key_size: u32,
     /// Size (in bytes) of the map value
     pub value_size: u32,
+    /// Whether map values are read-only to programs (`BPF_F_RDONLY_PROG`, e.g. `.rodata`)
+    pub readonly: bool,
+    /// Initial contents of map values (e.g. of `.rodata`)
+    ///
+    /// It is only used for [MapInfo::readonly] maps, making constant reads precise.
+    pub initial_value: Option<Vec<u8>>,
+    /// Program type shared by all programs in a [MapType::ProgArray]
+    ///
+    /// Tail calls only work between programs of the same type.
+    /// `None` for other maps or when the type is unknown.
+    pub program_type: Option<ProgType>,
 }
 
 /// Configuration: how the analyzer checks the code
@@ -109,24 +135,145 @@ pub struct AnalyzerConfig<'a> {
     /// The verifier goes through each possible branch, looking for invalid operations.
     /// This setting limits total processed instruction, summing up all the processed branches.
     pub processed_instruction_limit: usize,
+    /// Maximum basic block count, summing up blocks of all functions
+    ///
+    /// Programs with larger control flow graphs are rejected before interpretation.
+    /// `None` means no limit.
+    pub max_blocks: Option<usize>,
+    /// Maximum count of pending branches, that is, forked but not yet explored
+    ///
+    /// Each conditional jump whose outcome is unknown forks a branch.
+    pub max_pending_branches: usize,
+    /// Maximum count of branches (the initial one and all forked ones), including pruned ones
+    pub max_total_branches: usize,
+    /// Maximum count of map values that the program may hold at once on any path
+    ///
+    /// A map value returned by a helper is live as long as it is kept in callee-saved registers,
+    /// on the stack or in other regions. `None` means no limit.
+    pub max_live_map_values: Option<usize>,
+    /// Maximum distance that a single addition or subtraction may move a pointer
+    ///
+    /// Like the kernel limiting pointer offsets, adding to (or subtracting from) a pointer
+    /// a scalar that may lie out of `-limit..=limit` is rejected right away instead of
+    /// at access time. Pointers already carrying larger offsets are left alone.
+    /// `None` means no limit.
+    pub max_pointer_offset: Option<u64>,
+    /// Maximum count of times that a path may jump back to a loop header before leaving the loop
+    ///
+    /// Loops are verified by running them iteration by iteration, like any other code.
+    /// Jumps back to the header of a natural loop (see [ProgramInfo::loops]), `ja` included,
+    /// are counted per header, and the count resets once the path leaves the loop body
+    /// by any of its exits, `break`s included. Paths going over the cap are rejected right away
+    /// instead of exhausting [AnalyzerConfig::processed_instruction_limit].
+    ///
+    /// For simple counted loops (e.g., `for (i = 0; i < 10; i++)`), the induction variable
+    /// is also narrowed through [crate::track::comparable::Comparable] at the first back edge,
+    /// rejecting loops that cannot exit within the cap before running them.
+    /// `None` means no limit, leaving non-terminating loops to the instruction limit.
+    pub max_loop_iterations: Option<usize>,
+    /// Whether `r1` (the context) must never be overwritten in the main function
+    ///
+    /// Some program types need the context to stay in `r1` for later instructions
+    /// or tail calls. See [ProgramInfo::context_overwrites] for what counts as a write.
+    pub preserve_context: bool,
     /// Gets map file descriptor info
     pub map_fd_collector: &'a dyn Fn(i32) -> Option<MapInfo>,
+    /// Type of the program under verification
+    ///
+    /// If specified:
+    /// - it is checked against the program type of [MapType::ProgArray] maps on tail calls;
+    /// - `r1` is set up as its context before calling [AnalyzerConfig::setup];
+    /// - its defaults apply to [AnalyzerConfig::helpers] (if empty),
+    ///   [AnalyzerConfig::expected_return] and [AnalyzerConfig::features] (if `None`).
+    pub program_type: Option<ProgramType>,
+    /// Allowed instruction set extensions, e.g. those supported by the target kernel
+    ///
+    /// Instructions requiring other features are rejected. `None` falls back to
+    /// [ProgramType::features], leaving the instruction set unrestricted without a program type.
+    pub features: Option<FeatureSet>,
+    /// Allowed return values (`r0` at top-level exits)
+    ///
+    /// Some program types only accept certain return values, e.g. XDP actions `0..=4`.
+    /// If specified, any path that may return a value out of the range is rejected.
+    pub expected_return: Option<RangeInclusive<i64>>,
+    /// Statically registered kernel functions (kfuncs), indexed by their BTF ids
+    ///
+    /// Arguments and return values are checked the same way as [AnalyzerConfig::helpers].
+    pub kfuncs: StaticHelpers,
+    /// Resolves kernel function (kfunc) prototypes by their BTF ids
+    ///
+    /// It is only consulted for kfuncs out of [AnalyzerConfig::kfuncs], once per kfunc,
+    /// when a call to it is first verified. Calls to kfuncs that it cannot resolve
+    /// (returning `None`) are rejected, while unreachable ones are left alone.
+    pub kfunc_resolver: Option<&'a dyn Fn(i32) -> Option<KfuncPrototype>>,
+    /// Layouts of kernel structs that helpers may return pointers to
+    ///
+    /// See [crate::spec::proto::ReturnType::TypedKernelPointer].
+    pub kernel_structs: &'a [KernelStruct<'a>],
+    /// Source lines of the program, read from its `.BTF.ext` section
+    ///
+    /// If specified, errors at known instructions are wrapped in [VerificationError::At].
+    pub line_info: Option<&'a LineInfo>,
+    /// Gets notified of every memory access during verification
+    ///
+    /// See [AccessObserver] for details.
+    pub access_observer: Option<&'a dyn AccessObserver>,
+}
+
+impl AnalyzerConfig<'static> {
+    /// The default configuration, for constants that cannot call [Default::default]
+    pub const DEFAULT: Self = Self {
+        helpers: &[],
+        setup: &|_| {},
+        processed_instruction_limit: DEFAULT_INSTRUCTION_LIMIT,
+        max_blocks: None,
+        max_pending_branches: DEFAULT_PENDING_BRANCH_LIMIT,
+        max_total_branches: DEFAULT_TOTAL_BRANCH_LIMIT,
+        max_live_map_values: None,
+        max_pointer_offset: None,
+        max_loop_iterations: None,
+        preserve_context: false,
+        map_fd_collector: &|_| None,
+        program_type: None,
+        features: None,
+        expected_return: None,
+        kfuncs: &[],
+        kfunc_resolver: None,
+        kernel_structs: &[],
+        line_info: None,
+        access_observer: None,
+    };
 }
 
 impl<'a> Default for AnalyzerConfig<'a> {
     fn default() -> Self {
-        Self {
-            helpers: Default::default(),
-            setup: &|_| {},
-            processed_instruction_limit: 1_000_000,
-            map_fd_collector: &|_| None,
-        }
+        AnalyzerConfig::DEFAULT
     }
 }
 
 /// The analyzer (or eBPF verifier)
 pub struct Analyzer;
 
+/// The parsed program along with its verification result, see [Analyzer::verify]
+pub struct Report {
+    /// The parsed program
+    ///
+    /// Fields filled in during analysis (e.g. [ProgramInfo::max_stack_depth])
+    /// only cover what was analyzed before the verification failed, if it did.
+    pub info: ProgramInfo,
+    /// The verification result
+    pub result: Result<(), VerificationError>,
+    /// The union of signed ranges of each register at all top-level exits
+    ///
+    /// A register is `None` if it does not hold a scalar at some exit
+    /// (or if no exit was reached).
+    pub exit_register_ranges: [Option<(i64, i64)>; READABLE_REGISTER_COUNT as usize],
+    /// Distinct branches that were not explored and why, sorted
+    ///
+    /// It helps in debugging false accepts, e.g., a path wrongly deemed unsatisfiable.
+    pub pruned_branches: Vec<PruneReason>,
+}
+
 /// Verification error
 #[derive(Debug)]
 pub enum VerificationError {
@@ -134,12 +281,54 @@ pub enum VerificationError {
     IllegalStructure(IllegalStructure),
     /// Illegal instruction
     IllegalInstruction(IllegalInstruction),
-    /// Illegal DAG
-    IllegalGraph,
+    /// A block (identified by its index within its function) unreachable from the function entry
+    UnreachableCode(BlockId),
     /// Invalid operation
     IllegalStateChange(Branch),
     /// Illegal context
     IllegalContext(&'static str),
+    /// The branch may return a value out of [AnalyzerConfig::expected_return]
+    InvalidReturnValue(Branch),
+    /// The instruction requires features out of [AnalyzerConfig::features]
+    UnsupportedFeatures((CodeOffset, FeatureSet)),
+    /// The combined stack depth along the call chain (function indices from the main function)
+    /// exceeds [MAX_COMBINED_STACK_SIZE], see [ProgramInfo::deepest_call_chain]
+    StackTooDeep(Vec<usize>),
+    /// The program has more basic blocks than [AnalyzerConfig::max_blocks]
+    TooManyBlocks,
+    /// Exceeding [AnalyzerConfig::max_pending_branches] or [AnalyzerConfig::max_total_branches]
+    BranchLimitExceeded,
+    /// The instruction overwrites `r1` under [AnalyzerConfig::preserve_context]
+    ContextOverwritten(CodeOffset),
+    /// An error at a source file and line, see [AnalyzerConfig::line_info]
+    At((String, u32, Box<VerificationError>)),
+}
+
+impl VerificationError {
+    /// Returns the instruction where the error occurs, if known
+    ///
+    /// For errors carrying a [Branch], it is the instruction where the branch got invalidated,
+    /// or the one it last executed (see [BranchState::fault_pc]).
+    pub fn pc(&self) -> Option<CodeOffset> {
+        match self {
+            Self::IllegalStateChange(branch) | Self::InvalidReturnValue(branch) => {
+                Some(branch.borrow().fault_pc())
+            }
+            Self::UnsupportedFeatures((pc, _)) | Self::ContextOverwritten(pc) => Some(*pc),
+            Self::At((_, _, err)) => err.pc(),
+            _ => None,
+        }
+    }
+
+    /// Wraps the error in [VerificationError::At] if its source line is known
+    pub fn locate(self, lines: &LineInfo) -> Self {
+        match self.pc().and_then(|pc| lines.line_info(pc)) {
+            Some((file, line)) if !matches!(self, Self::At(_)) => {
+                Self::At((file, line, Box::new(self)))
+            }
+            _ => self,
+        }
+    }
 }
 
 impl From<IllegalInstruction> for VerificationError {
@@ -151,54 +340,97 @@ impl From<IllegalInstruction> for VerificationError {
 impl Analyzer {
     /// Analyze an eBPF program
     pub fn analyze(code: &[u64], config: &AnalyzerConfig) -> Result<ProgramInfo, VerificationError> {
-        let info = ProgramInfo::new(code)?;
-        Analyzer::has_unreachable_block(&info.functions)?;
-        Analyzer::has_forbidden_state_change(code, &info, config)?;
-        Ok(info)
+        let report = Analyzer::verify(code, config)?;
+        report.result.map(|_| report.info)
     }
 
-    /// Runs a BFS to see if there is any unreachable blocks
-    fn has_unreachable_block(blocks: &FunctionBlocks) -> Result<(), VerificationError> {
-        for code in blocks {
-            let mut reached: Vec<bool> = Vec::new();
-            reached.resize(code.block_count(), false);
-
-            let mut stack: Vec<usize> = Vec::new();
-            stack.push(0);
-            while let Some(block) = stack.pop() {
-                if !reached[block] {
-                    reached[block] = true;
-                    if code.from[block].is_empty() {
-                        return Err(VerificationError::IllegalStructure(
-                            IllegalStructure::BlockOpenEnd,
-                        ));
-                    }
-                    for to in &code.from[block] {
-                        if *to != TERMINAL_PSEUDO_BLOCK {
-                            stack.push(*to);
-                        }
-                    }
-                }
-            }
+    /// Analyze an eBPF program, keeping the parsed [ProgramInfo] even if the verification fails
+    ///
+    /// It returns `Err` only if the code cannot be parsed at all.
+    pub fn verify(code: &[u64], config: &AnalyzerConfig) -> Result<Report, VerificationError> {
+        let mut info = ProgramInfo::new(code)?;
+        let mut exit_register_ranges = [None; READABLE_REGISTER_COUNT as usize];
+        let mut pruned_branches = Vec::new();
+        let result = Analyzer::verify_parsed(
+            code,
+            &mut info,
+            &mut exit_register_ranges,
+            &mut pruned_branches,
+            config,
+        )
+        .map_err(|err| match config.line_info {
+            Some(lines) => err.locate(lines),
+            None => err,
+        });
+        Ok(Report {
+            info,
+            result,
+            exit_register_ranges,
+            pruned_branches,
+        })
+    }
 
-            if reached.iter().any(|b| !b) {
-                return Err(VerificationError::IllegalGraph);
+    fn verify_parsed(
+        code: &[u64],
+        info: &mut ProgramInfo,
+        ranges: &mut [Option<(i64, i64)>],
+        pruned: &mut Vec<PruneReason>,
+        config: &AnalyzerConfig,
+    ) -> Result<(), VerificationError> {
+        if let Some(max_blocks) = config.max_blocks {
+            let blocks: usize = info.functions.iter().map(|f| f.block_count()).sum();
+            if blocks > max_blocks {
+                return Err(VerificationError::TooManyBlocks);
             }
         }
-        Ok(())
+        let features = config
+            .features
+            .or_else(|| config.program_type.map(|t| t.features()));
+        if let Some(features) = features {
+            let unsupported = ProgramInfo::unsupported_instructions(code, features);
+            if let Some(first) = unsupported.first() {
+                return Err(VerificationError::UnsupportedFeatures(*first));
+            }
+        }
+        if config.preserve_context {
+            if let Some(first) = info.context_overwrites(code).first() {
+                return Err(VerificationError::ContextOverwritten(*first));
+            }
+        }
+        Analyzer::has_forbidden_state_change(code, info, ranges, pruned, config)
+    }
+
+    /// Merges the signed ranges of registers at an exit into `ranges`
+    fn merge_exit_ranges(ranges: &mut [Option<(i64, i64)>], vm: &BranchState, first: bool) {
+        for (i, range) in ranges.iter_mut().enumerate() {
+            let current = match vm.ro_reg(i as u8).inner() {
+                Some(TrackedValue::Scalar(s)) => {
+                    let signed = s.signed_range();
+                    Some((*signed.start(), *signed.end()))
+                }
+                _ => None,
+            };
+            *range = match (*range, current) {
+                _ if first => current,
+                (Some((min, max)), Some((start, end))) => Some((min.min(start), max.max(end))),
+                _ => None,
+            };
+        }
     }
 
     fn has_forbidden_state_change(
         code: &[u64],
-        info: &ProgramInfo,
+        info: &mut ProgramInfo,
+        ranges: &mut [Option<(i64, i64)>],
+        pruned: &mut Vec<PruneReason>,
         config: &AnalyzerConfig,
     ) -> Result<(), VerificationError> {
         if info.functions.is_empty() {
             Err(VerificationError::IllegalStructure(IllegalStructure::Empty))
         } else {
-            let mut maps: Vec<(i32, MapInfo)> = Vec::new();
-            maps.reserve(info.maps.len());
-            for fd in &info.maps {
+            let fds = info.map_fds();
+            let mut maps: Vec<(i32, MapInfo)> = Vec::with_capacity(fds.len());
+            for fd in &fds {
                 if let Some(map) = (config.map_fd_collector)(*fd) {
                     maps.push((*fd, map));
                 } else {
@@ -210,23 +442,99 @@ impl Analyzer {
 
             let mut branches = BranchContext::new();
             branches.set_instruction_limit(config.processed_instruction_limit);
-            let mut branch = BranchState::new(config.helpers, maps);
+            branches.set_branch_limits(config.max_pending_branches, config.max_total_branches);
+            branches.set_live_registers(live_registers(code));
+            if let Some(observer) = config.access_observer {
+                branches.set_observer(observer);
+            }
+            if let Some(resolver) = config.kfunc_resolver {
+                branches.set_kfunc_resolver(resolver);
+            }
+            let helpers = match config.program_type {
+                Some(program_type) if config.helpers.is_empty() => program_type.helpers(),
+                _ => config.helpers,
+            };
+            let expected_return = config
+                .expected_return
+                .clone()
+                .or_else(|| config.program_type.and_then(|t| t.expected_return()));
+
+            info.maps.clone_from(&maps);
+            let mut branch = BranchState::new(helpers, maps);
+            branch.set_kfunc_table(config.kfuncs);
+            branch.set_kernel_structs(config.kernel_structs);
+            branch.set_program_type(config.program_type.map(|t| t.kind()));
+            branch.set_max_live_map_values(config.max_live_map_values);
+            branch.set_max_pointer_offset(config.max_pointer_offset);
+            branch.set_max_loop_iterations(config.max_loop_iterations);
+            branch.set_loops(info.loops(code));
+            branch.set_subprograms(info.functions.iter().map(|f| f.block_starts[0]).collect());
+            if let Some(program_type) = config.program_type {
+                program_type
+                    .setup(&mut branch)
+                    .map_err(VerificationError::IllegalContext)?;
+            }
             (config.setup)(&mut branch);
-            branches.add_pending_branch(Rc::new(RefCell::new(branch)));
-            while let Some(branch) = branches.next() {
-                let mut vm = branch.borrow_mut();
-                run(code, &mut vm, &mut branches);
-                if !vm.is_valid() || !vm.ro_reg(0).is_valid() {
+            let initial = Rc::new(RefCell::new(branch));
+            branches.add_pending_branch(initial.clone());
+            let result = Analyzer::explore(code, info, ranges, expected_return, &mut branches)
+                .and_then(|_| match info.deepest_call_chain(code) {
+                    (chain, depth) if depth > MAX_COMBINED_STACK_SIZE => {
+                        Err(VerificationError::StackTooDeep(chain))
+                    }
+                    _ => Ok(()),
+                });
+            *pruned = initial.borrow().pruned_branches();
+            result
+        }
+    }
+
+    /// Runs all branches until they exit or fail
+    fn explore(
+        code: &[u64],
+        info: &mut ProgramInfo,
+        ranges: &mut [Option<(i64, i64)>],
+        expected_return: Option<RangeInclusive<i64>>,
+        branches: &mut BranchContext,
+    ) -> Result<(), VerificationError> {
+        let mut first = true;
+        while let Some(branch) = branches.next() {
+            let mut vm = branch.borrow_mut();
+            run(code, &mut vm, branches);
+            if branches.is_branch_limit_exceeded() {
+                return Err(VerificationError::BranchLimitExceeded);
+            }
+            if !vm.is_valid() || !vm.ro_reg(0).is_valid() {
+                drop(vm);
+                return Err(VerificationError::IllegalStateChange(branch));
+            }
+            if let Some(ref expected) = expected_return {
+                let in_range = match vm.ro_reg(0).inner() {
+                    Some(TrackedValue::Scalar(s)) => {
+                        let range = s.signed_range();
+                        expected.contains(range.start()) && expected.contains(range.end())
+                    }
+                    _ => false,
+                };
+                if !in_range {
                     drop(vm);
-                    return Err(VerificationError::IllegalStateChange(branch));
+                    return Err(VerificationError::InvalidReturnValue(branch));
                 }
-                if !branches.is_valid() {
-                    return Err(VerificationError::IllegalContext(
-                        branches.invalid_message(),
-                    ));
+            }
+            for (function, depth) in vm.stack_depths().iter().enumerate() {
+                if function >= info.max_stack_depth.len() {
+                    info.max_stack_depth.resize(function + 1, 0);
                 }
+                info.max_stack_depth[function] = info.max_stack_depth[function].max(*depth);
+            }
+            Analyzer::merge_exit_ranges(ranges, &vm, first);
+            first = false;
+            if !branches.is_valid() {
+                return Err(VerificationError::IllegalContext(
+                    branches.invalid_message(),
+                ));
             }
-            Ok(())
         }
+        Ok(())
     }
 }
@@ -2,11 +2,16 @@
 
 use core::cmp::Ordering;
 
-use alloc::vec::Vec;
+use alloc::{format, string::String, vec::Vec};
+
+use ebpf_consts::{mask::*, *};
 
 use crate::{
-    analyzer::VerificationError,
-    spec::{CodeOffset, IllegalInstruction, Instruction, JumpInstruction, ParsedInstruction},
+    analyzer::{MapInfo, VerificationError},
+    spec::{
+        features::FeatureSet, instructions, CodeOffset, IllegalInstruction, Instruction,
+        JumpInstruction, ParsedInstruction,
+    },
 };
 
 /// Id of a code block
@@ -32,8 +37,28 @@ pub type FunctionBlocks = Vec<FunctionBlock>;
 pub struct ProgramInfo {
     /// Functions
     pub functions: FunctionBlocks,
-    /// Used map file descriptors
-    pub maps: Vec<i32>,
+    /// Used maps along with their info resolved by
+    /// [crate::analyzer::AnalyzerConfig::map_fd_collector], filled in by
+    /// [crate::analyzer::Analyzer::analyze]
+    pub maps: Vec<(i32, MapInfo)>,
+    /// Offsets of the instructions loading map file descriptors, with the descriptors
+    pub map_references: Vec<(CodeOffset, i32)>,
+    /// BTF ids of called kernel functions (kfuncs)
+    pub kfuncs: Vec<i32>,
+    /// Maximum stack depth (in bytes) of each function, filled in by
+    /// [crate::analyzer::Analyzer::analyze]
+    pub max_stack_depth: Vec<usize>,
+}
+
+/// Linking information of a relocatable code section, used by [ProgramInfo::link]
+pub struct SectionInfo<'a> {
+    /// Functions defined in this section, with their offsets relative to the section start
+    pub symbols: &'a [(&'a str, CodeOffset)],
+    /// Pseudo calls to functions (possibly) in other sections
+    ///
+    /// Each item is the offset of the call instruction (or the `BPF_IMM64_FUNC` wide instruction)
+    /// relative to the section start and the name of the called function.
+    pub relocations: &'a [(CodeOffset, &'a str)],
 }
 
 /// Error when we cannot parse the code into blocks
@@ -65,13 +90,13 @@ impl Boundaries {
         offset: i32,
     ) -> Result<CodeOffset, IllegalInstruction> {
         // Checks if the target PC is out of bounds
-        let target = if let Some(t) = pc.checked_add_signed(offset as isize) {
-            t
-        } else {
-            return Err(IllegalInstruction::OutOfBoundJump);
+        let target = match pc.checked_add_signed(offset as isize) {
+            Some(t) if t < code.len() => t,
+            _ => return Err(IllegalInstruction::OutOfBoundJump),
         };
 
-        let bound = if offset >= 0 { code.len() } else { pc - 1 };
+        // Backward jumps (or calls) may target the jumping instruction itself
+        let bound = if offset >= 0 { code.len() } else { pc };
 
         // Checks if the target instruction is out of bounds
         let size = match Instruction::from(code, target) {
@@ -92,7 +117,7 @@ impl Boundaries {
     /// Compute the absolute PC that a jump instruction jumps to
     ///
     /// You should only use this after checking all jumps.
-    fn unchecked_jump(pc: CodeOffset, offset: i16) -> CodeOffset {
+    fn unchecked_jump(pc: CodeOffset, offset: i32) -> CodeOffset {
         pc.checked_add_signed(offset as isize).unwrap()
     }
 
@@ -106,9 +131,7 @@ impl Boundaries {
         let mut functions: Vec<CodeOffset> = Vec::new();
         labels.push(0);
         functions.push(0);
-        let mut pc = 0 as CodeOffset;
-        while pc < code.len() {
-            let parsed = Instruction::from(code, pc);
+        for (pc, parsed) in instructions(code) {
             parsed.validate()?;
             let (insn, pc_inc) = match parsed {
                 ParsedInstruction::None => return Err(IllegalInstruction::IllegalInstruction),
@@ -129,23 +152,27 @@ impl Boundaries {
 
             // Detect used maps
             if let Some(fd) = insn.is_ldimm64_map_fd() {
-                if !info.maps.contains(&fd) {
-                    info.maps.push(fd);
-                }
+                info.map_references.push((pc, fd));
             }
 
-            pc += pc_inc;
+            // Detect used kfuncs
+            if let Some(id) = insn.is_kfunc_call() {
+                if !info.kfuncs.contains(&id) {
+                    info.kfuncs.push(id);
+                }
+            }
 
+            let next = pc + pc_inc;
             if let Some(jump) = insn.jumps_to() {
                 match jump {
-                    JumpInstruction::Exit => labels.push(pc),
+                    JumpInstruction::Exit => labels.push(next),
                     JumpInstruction::Unconditional(offset) => {
-                        labels.push(pc);
-                        labels.push(Self::checked_jump(code, pc, offset as i32)?);
+                        labels.push(next);
+                        labels.push(Self::checked_jump(code, next, offset)?);
                     }
                     JumpInstruction::Conditional(offset) => {
-                        labels.push(pc);
-                        labels.push(Self::checked_jump(code, pc, offset as i32)?);
+                        labels.push(next);
+                        labels.push(Self::checked_jump(code, next, offset as i32)?);
                     }
                 }
             }
@@ -220,7 +247,7 @@ impl Boundaries {
                         {
                             from[block_id].push(block_id + 1);
                             to[block_id + 1].push(block_id);
-                            offset
+                            offset as i32
                         }
                         // Cond 2.
                         Some(JumpInstruction::Exit) => {
@@ -308,7 +335,71 @@ impl FunctionBlock {
     /// Parses the eBPF code into function blocks
     pub fn new(code: &[u64], info: &mut ProgramInfo) -> Result<Vec<FunctionBlock>, VerificationError> {
         let boundaries = Boundaries::sorted_boundaries(code, info)?;
-        boundaries.parse_functions(code)
+        let functions = boundaries.parse_functions(code)?;
+        for function in &functions {
+            function.check_reachability()?;
+        }
+        Ok(functions)
+    }
+
+    /// Runs a DFS from the function entry to see if there is any unreachable block
+    fn check_reachability(&self) -> Result<(), VerificationError> {
+        let mut reached = alloc::vec![false; self.block_count()];
+        let mut stack = alloc::vec![0];
+        while let Some(block) = stack.pop() {
+            if !reached[block] {
+                reached[block] = true;
+                stack.extend(
+                    self.from[block]
+                        .iter()
+                        .filter(|to| **to != TERMINAL_PSEUDO_BLOCK),
+                );
+            }
+        }
+        match reached.iter().position(|reached| !reached) {
+            Some(block) => Err(VerificationError::UnreachableCode(block)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the back edges `(from, to)` of the control flow graph, found by a DFS
+    ///
+    /// An edge is a back edge if it leads to a block still on the DFS stack,
+    /// that is, a block from which the source block is reachable, thus forming a loop.
+    /// Edges are listed in DFS order.
+    pub fn back_edges(&self) -> Vec<(BlockId, BlockId)> {
+        #[derive(Clone, Copy)]
+        enum State {
+            Unvisited,
+            OnStack,
+            Done,
+        }
+        let mut states = alloc::vec![State::Unvisited; self.block_count()];
+        let mut edges = Vec::new();
+        // Blocks on the DFS stack, with the index of the next successor to visit
+        let mut stack: Vec<(BlockId, usize)> = alloc::vec![(0, 0)];
+        states[0] = State::OnStack;
+        while let Some((block, next)) = stack.last_mut() {
+            let block = *block;
+            let successor = self.from[block].get(*next).copied();
+            *next += 1;
+            match successor {
+                Some(TERMINAL_PSEUDO_BLOCK) => {}
+                Some(successor) => match states[successor] {
+                    State::Unvisited => {
+                        states[successor] = State::OnStack;
+                        stack.push((successor, 0));
+                    }
+                    State::OnStack => edges.push((block, successor)),
+                    State::Done => {}
+                },
+                None => {
+                    states[block] = State::Done;
+                    stack.pop();
+                }
+            }
+        }
+        edges
     }
 
     /// Returns the function count
@@ -323,14 +414,500 @@ impl ProgramInfo {
         let mut info = Self {
             functions: Vec::new(),
             maps: Vec::new(),
+            map_references: Vec::new(),
+            kfuncs: Vec::new(),
+            max_stack_depth: Vec::new(),
         };
         info.functions = FunctionBlock::new(code, &mut info)?;
         Ok(info)
     }
-}
 
-#[cfg(test)]
-use ebpf_consts::*;
+    /// Prints the control flow graph of each function, for debugging
+    ///
+    /// Blocks are listed along with their start offsets, their successors (`from`)
+    /// and their predecessors (`to`). The terminal pseudo block is printed as `EXIT`.
+    ///
+    /// ```text
+    /// function 0
+    ///   block 0 @0: successors [1, 2], predecessors []
+    ///   block 1 @1: successors [2], predecessors [0]
+    ///   block 2 @2: successors [EXIT], predecessors [0, 1]
+    /// ```
+    pub fn dump_cfg(&self) -> String {
+        let blocks = |ids: &[BlockId]| {
+            let names: Vec<String> = ids.iter().map(|id| Self::block_name(*id)).collect();
+            names.join(", ")
+        };
+        let mut output = String::new();
+        for (i, function) in self.functions.iter().enumerate() {
+            output += &format!("function {i}\n");
+            for (id, start) in function.block_starts.iter().enumerate() {
+                output += &format!(
+                    "  block {id} @{start}: successors [{}], predecessors [{}]\n",
+                    blocks(&function.from[id]),
+                    blocks(&function.to[id]),
+                );
+            }
+        }
+        output
+    }
+
+    /// Prints the control flow graph in Graphviz DOT format, see [ProgramInfo::dump_cfg]
+    ///
+    /// Each function is a cluster, whose blocks are named `f<function>_b<block>`
+    /// and labeled with their start offsets, and the terminal pseudo block is `f<function>_exit`.
+    pub fn dot(&self) -> String {
+        let mut output = String::from("digraph cfg {\n");
+        for (i, function) in self.functions.iter().enumerate() {
+            output += &format!("  subgraph cluster_{i} {{\n    label=\"function {i}\";\n");
+            for (id, start) in function.block_starts.iter().enumerate() {
+                output += &format!("    f{i}_b{id} [label=\"@{start}\"];\n");
+            }
+            output += &format!("    f{i}_exit [label=\"EXIT\"];\n");
+            for (id, successors) in function.from.iter().enumerate() {
+                for successor in successors {
+                    let name = match *successor {
+                        TERMINAL_PSEUDO_BLOCK => format!("f{i}_exit"),
+                        block => format!("f{i}_b{block}"),
+                    };
+                    output += &format!("    f{i}_b{id} -> {name};\n");
+                }
+            }
+            output += "  }\n";
+        }
+        output += "}\n";
+        output
+    }
+
+    fn block_name(id: BlockId) -> String {
+        match id {
+            TERMINAL_PSEUDO_BLOCK => String::from("EXIT"),
+            id => format!("{id}"),
+        }
+    }
+
+    /// Returns `true` if any function contains loops, see [FunctionBlock::back_edges]
+    ///
+    /// Recursive calls are not loops in this sense, since calls do not end blocks.
+    pub fn has_loops(&self) -> bool {
+        self.functions
+            .iter()
+            .any(|function| !function.back_edges().is_empty())
+    }
+
+    /// Returns the used map file descriptors, in the order they first appear
+    pub fn map_fds(&self) -> Vec<i32> {
+        let mut fds = Vec::new();
+        for (_, fd) in &self.map_references {
+            if !fds.contains(fd) {
+                fds.push(*fd);
+            }
+        }
+        fds
+    }
+
+    /// Links multiple code sections into one program
+    ///
+    /// The sections are concatenated in order, with the first one being the entry.
+    /// Relocated pseudo calls are fixed up to point to the functions they refer to,
+    /// while other relative jumps and calls are left as is.
+    pub fn link(
+        sections: &[(&[u64], SectionInfo)],
+    ) -> Result<(Vec<u64>, ProgramInfo), VerificationError> {
+        let mut code: Vec<u64> = Vec::new();
+        let mut bases: Vec<CodeOffset> = Vec::new();
+        for (section, _) in sections {
+            bases.push(code.len());
+            code.extend_from_slice(section);
+        }
+
+        let find_symbol = |name: &str| {
+            sections
+                .iter()
+                .zip(bases.iter())
+                .find_map(|((_, info), base)| {
+                    info.symbols
+                        .iter()
+                        .find(|(symbol, _)| *symbol == name)
+                        .map(|(_, offset)| base + offset)
+                })
+                .ok_or(IllegalInstruction::OutOfBoundFunction)
+        };
+
+        for ((section, info), base) in sections.iter().zip(bases.iter()) {
+            for (offset, name) in info.relocations {
+                if *offset >= section.len() {
+                    return Err(IllegalInstruction::OutOfBoundFunction.into());
+                }
+                let pc = base + offset;
+                let insn = Instruction::from_raw(code[pc]);
+                if insn.is_pseudo_call().is_none() && insn.is_ldimm64_func().is_none() {
+                    return Err(IllegalInstruction::IllegalInstruction.into());
+                }
+                let target = find_symbol(name)?;
+                let imm = i32::try_from(target as isize - (pc + 1) as isize)
+                    .map_err(|_| IllegalInstruction::OutOfBoundFunction)?;
+                code[pc] = Instruction::pack(
+                    insn.opcode,
+                    insn.src_reg(),
+                    insn.dst_reg(),
+                    insn.off,
+                    imm,
+                );
+            }
+        }
+
+        let info = ProgramInfo::new(&code)?;
+        Ok((code, info))
+    }
+
+    /// Recomputes relative jumps and pseudo calls for an upcoming edit of the code
+    ///
+    /// `mapping` maps an old instruction offset to the new one, with removed instructions
+    /// mapped to wherever jumps to them should now land (usually the next kept instruction).
+    /// It should be called before actually moving the instructions around,
+    /// and it fails if any jump or call falls out of the code or its new offset
+    /// no longer fits into the instruction.
+    pub fn rewrite_jumps(
+        code: &mut [u64],
+        mapping: &dyn Fn(CodeOffset) -> CodeOffset,
+    ) -> Result<(), IllegalInstruction> {
+        let relocate = |pc: CodeOffset, offset: i32, len: CodeOffset| {
+            // Both jumps and calls are relative to `pc + 1`
+            let target = (pc + 1)
+                .checked_add_signed(offset as isize)
+                .filter(|target| *target < len)?;
+            Some(mapping(target) as i64 - mapping(pc) as i64 - 1)
+        };
+        let mut pc = 0 as CodeOffset;
+        while pc < code.len() {
+            let (insn, pc_inc) = match Instruction::from(code, pc) {
+                ParsedInstruction::None => return Err(IllegalInstruction::IllegalInstruction),
+                ParsedInstruction::Instruction(i) => (i, 1),
+                ParsedInstruction::WideInstruction(w) => (w.instruction, 2),
+            };
+            let (mut off, mut imm) = (insn.off, insn.imm);
+            if let Some(offset) = insn.is_pseudo_call().or_else(|| insn.is_ldimm64_func()) {
+                imm = relocate(pc, offset, code.len())
+                    .and_then(|offset| i32::try_from(offset).ok())
+                    .ok_or(IllegalInstruction::OutOfBoundFunction)?;
+            } else if insn.is_long_jump() {
+                imm = relocate(pc, imm, code.len())
+                    .and_then(|offset| i32::try_from(offset).ok())
+                    .ok_or(IllegalInstruction::OutOfBoundJump)?;
+            } else if let Some(
+                JumpInstruction::Conditional(_) | JumpInstruction::Unconditional(_),
+            ) = insn.jumps_to()
+            {
+                off = relocate(pc, off as i32, code.len())
+                    .and_then(|offset| i16::try_from(offset).ok())
+                    .ok_or(IllegalInstruction::OutOfBoundJump)?;
+            }
+            code[pc] = Instruction::pack(insn.opcode, insn.src_reg(), insn.dst_reg(), off, imm);
+            pc += pc_inc;
+        }
+        Ok(())
+    }
+
+    /// Computes the instructions reachable from a block of the main function
+    ///
+    /// Besides following the block graph, it also steps into functions referenced by pseudo
+    /// calls or `BPF_IMM64_FUNC` instructions. The returned offsets are sorted and include
+    /// both slots of wide instructions, which is handy for minimizing a failing program.
+    ///
+    /// `code` should be the one this [ProgramInfo] is parsed from.
+    pub fn reachable_slice(&self, code: &[u64], entry_block: BlockId) -> Vec<CodeOffset> {
+        let block_end = |function: usize, block: BlockId| {
+            self.functions[function]
+                .block_starts
+                .get(block + 1)
+                .or_else(|| self.functions.get(function + 1).map(|f| &f.block_starts[0]))
+                .copied()
+                .unwrap_or(code.len())
+        };
+        let mut reached: Vec<Vec<bool>> = self
+            .functions
+            .iter()
+            .map(|f| alloc::vec![false; f.block_count()])
+            .collect();
+        let mut slice = Vec::new();
+        let mut pending = alloc::vec![(0usize, entry_block)];
+        while let Some((function, block)) = pending.pop() {
+            if block == TERMINAL_PSEUDO_BLOCK || reached[function][block] {
+                continue;
+            }
+            reached[function][block] = true;
+
+            let (mut pc, end) = (
+                self.functions[function].block_starts[block],
+                block_end(function, block),
+            );
+            while pc < end {
+                let (insn, pc_inc) = match Instruction::from(code, pc) {
+                    ParsedInstruction::None => {
+                        unreachable!("Should have verified in ProgramInfo::new")
+                    }
+                    ParsedInstruction::Instruction(i) => (i, 1),
+                    ParsedInstruction::WideInstruction(w) => (w.instruction, 2),
+                };
+                if let Some(offset) = insn.is_pseudo_call().or_else(|| insn.is_ldimm64_func()) {
+                    let target = (pc + 1).wrapping_add_signed(offset as isize);
+                    if let Some(callee) = self
+                        .functions
+                        .iter()
+                        .position(|f| f.block_starts[0] == target)
+                    {
+                        pending.push((callee, 0));
+                    }
+                }
+                slice.extend(pc..(pc + pc_inc));
+                pc += pc_inc;
+            }
+            let successors = &self.functions[function].from[block];
+            pending.extend(successors.iter().map(|b| (function, *b)));
+        }
+        slice.sort_unstable();
+        slice
+    }
+
+    /// Returns the functions that each function calls, either directly by pseudo calls
+    /// or as callbacks referenced by `BPF_IMM64_FUNC` instructions
+    ///
+    /// `code` should be the one this [ProgramInfo] is parsed from.
+    pub fn call_graph(&self, code: &[u64]) -> Vec<Vec<usize>> {
+        let function_at = |pc: CodeOffset| {
+            self.functions
+                .partition_point(|f| f.block_starts[0] <= pc)
+                .saturating_sub(1)
+        };
+        let mut callees: Vec<Vec<usize>> = alloc::vec![Vec::new(); self.functions.len()];
+        for (pc, parsed) in instructions(code) {
+            let insn = match parsed {
+                ParsedInstruction::None => continue,
+                ParsedInstruction::Instruction(i) => i,
+                ParsedInstruction::WideInstruction(w) => w.instruction,
+            };
+            if let Some(offset) = insn.is_pseudo_call().or_else(|| insn.is_ldimm64_func()) {
+                let target = (pc + 1).wrapping_add_signed(offset as isize);
+                let (caller, callee) = (function_at(pc), function_at(target));
+                if !callees[caller].contains(&callee) {
+                    callees[caller].push(callee);
+                }
+            }
+        }
+        callees
+    }
+
+    /// Returns the call chain (function indices from the main function) with the largest
+    /// sum of [ProgramInfo::max_stack_depth], along with the sum
+    ///
+    /// Like the kernel, it adds up the maximum depth of each function, no matter whether
+    /// the deepest parts of their frames are written before or after the calls.
+    /// Functions already on a chain are not called again, so recursion is only counted once.
+    pub fn deepest_call_chain(&self, code: &[u64]) -> (Vec<usize>, usize) {
+        let callees = self.call_graph(code);
+        let depth = |function: usize| self.max_stack_depth.get(function).copied().unwrap_or(0);
+        let mut deepest = (alloc::vec![0], depth(0));
+        // Functions on the DFS stack, with the index of the next callee to visit
+        let mut chain: Vec<(usize, usize)> = alloc::vec![(0, 0)];
+        let mut combined = depth(0);
+        while let Some((function, next)) = chain.last_mut() {
+            let function = *function;
+            let callee = callees[function].get(*next).copied();
+            *next += 1;
+            match callee {
+                Some(callee) if chain.iter().any(|(f, _)| *f == callee) => {}
+                Some(callee) => {
+                    chain.push((callee, 0));
+                    combined += depth(callee);
+                    if combined > deepest.1 {
+                        deepest = (chain.iter().map(|(f, _)| *f).collect(), combined);
+                    }
+                }
+                None => {
+                    combined -= depth(function);
+                    chain.pop();
+                }
+            }
+        }
+        deepest
+    }
+
+    /// Aggregates the instruction set extensions required by the code
+    ///
+    /// It does not validate the code. See [Instruction::required_features].
+    pub fn required_features(code: &[u64]) -> FeatureSet {
+        Self::unsupported_instructions(code, FeatureSet::empty())
+            .iter()
+            .fold(FeatureSet::empty(), |acc, (_, missing)| acc | *missing)
+    }
+
+    /// Lists instructions that the `target` does not support
+    ///
+    /// Each item is the offset of the instruction along with the features missing from `target`.
+    pub fn unsupported_instructions(
+        code: &[u64],
+        target: FeatureSet,
+    ) -> Vec<(CodeOffset, FeatureSet)> {
+        let mut unsupported = Vec::new();
+        for (pc, parsed) in instructions(code) {
+            let insn = match parsed {
+                ParsedInstruction::None => break,
+                ParsedInstruction::Instruction(i) => i,
+                ParsedInstruction::WideInstruction(w) => w.instruction,
+            };
+            let missing = insn.required_features() - target;
+            if !missing.is_empty() {
+                unsupported.push((pc, missing));
+            }
+        }
+        unsupported
+    }
+
+    /// Checks whether the program may store through a pointer derived from `r1` (the context)
+    ///
+    /// It is a quick structural check, running a light data-flow analysis that tracks
+    /// registers holding `r1 + offset` (through moves, additions, subtractions and stack spills)
+    /// and looks for stores (including atomic ones) through them.
+    /// It over-approximates and does not validate the code.
+    pub fn writes_context(code: &[u64]) -> bool {
+        // (registers derived from r1 as a bitmask, whether such a value was spilled to the stack)
+        type State = (u16, bool);
+        let mut states: Vec<Option<State>> = Vec::new();
+        states.resize(code.len(), None);
+        let mut pending: Vec<(CodeOffset, State)> = alloc::vec![(0, (1 << 1, false))];
+        while let Some((pc, (mut derived, mut spilled))) = pending.pop() {
+            let state = match states.get_mut(pc) {
+                Some(state) => state,
+                None => continue,
+            };
+            if let Some((old_derived, old_spilled)) = *state {
+                if old_derived | derived == old_derived && (old_spilled || !spilled) {
+                    continue;
+                }
+                derived |= old_derived;
+                spilled |= old_spilled;
+            }
+            *state = Some((derived, spilled));
+
+            let insn = match Instruction::from(code, pc) {
+                ParsedInstruction::None => continue,
+                ParsedInstruction::Instruction(i) => i,
+                ParsedInstruction::WideInstruction(w) => {
+                    derived &= !(1 << (w.instruction.dst_reg() & 0xF));
+                    pending.push((pc + 2, (derived, spilled)));
+                    continue;
+                }
+            };
+            let is_derived = |reg: u8| derived & (1 << (reg & 0xF)) != 0;
+            let (src, dst) = (insn.src_reg(), insn.dst_reg());
+            let next = pc + 1;
+            let opcode = insn.opcode;
+            match opcode & BPF_OPCODE_CLASS_MASK {
+                BPF_ALU64 => {
+                    let operation = opcode & BPF_OPCODE_ALU_MASK;
+                    let from_src = opcode & BPF_OPCODE_SRC_MASK == BPF_X && is_derived(src);
+                    let keeps = match operation {
+                        BPF_MOV => from_src,
+                        BPF_ADD => is_derived(dst) || from_src,
+                        BPF_SUB => is_derived(dst) && !from_src,
+                        _ => false,
+                    };
+                    if keeps {
+                        derived |= 1 << dst;
+                    } else {
+                        derived &= !(1 << dst);
+                    }
+                }
+                BPF_ALU => derived &= !(1 << dst),
+                BPF_LDX => {
+                    if src == STACK_REGISTER && spilled && opcode & BPF_OPCODE_SIZE_MASK == BPF_DW {
+                        derived |= 1 << dst;
+                    } else {
+                        derived &= !(1 << dst);
+                    }
+                }
+                BPF_ST | BPF_STX => {
+                    if is_derived(dst) {
+                        return true;
+                    }
+                    if opcode & BPF_OPCODE_CLASS_MASK == BPF_STX {
+                        if dst == STACK_REGISTER && is_derived(src) {
+                            spilled = true;
+                        }
+                        if opcode & BPF_OPCODE_MODIFIER_MASK == BPF_ATOMIC {
+                            // Fetching atomic operations overwrite src (or r0)
+                            derived &= !(1 << src) & !1;
+                        }
+                    }
+                }
+                BPF_JMP | BPF_JMP32 => match insn.jumps_to() {
+                    Some(JumpInstruction::Exit) => continue,
+                    Some(JumpInstruction::Unconditional(off)) => {
+                        pending.push((next.wrapping_add_signed(off as isize), (derived, spilled)));
+                        continue;
+                    }
+                    Some(JumpInstruction::Conditional(off)) => {
+                        pending.push((next.wrapping_add_signed(off as isize), (derived, spilled)));
+                    }
+                    None => {
+                        if let Some(imm) = insn.is_pseudo_call() {
+                            // The callee receives r1 ~ r5 and a fresh stack
+                            let callee = next.wrapping_add_signed(imm as isize);
+                            pending.push((callee, (derived & 0b111110, false)));
+                        }
+                        // Calls clobber r0 ~ r5
+                        derived &= !0b111111;
+                    }
+                },
+                _ => {}
+            }
+            pending.push((next, (derived, spilled)));
+        }
+        false
+    }
+
+    /// Returns offsets of instructions in the main function that overwrite `r1` (the context)
+    ///
+    /// Only explicit writes count, that is, arithmetic, loads and fetching atomic operations
+    /// into `r1`. Calls clobbering `r1` ~ `r5` are not considered writes.
+    ///
+    /// `code` should be the one this [ProgramInfo] is parsed from.
+    pub fn context_overwrites(&self, code: &[u64]) -> Vec<CodeOffset> {
+        let end = self
+            .functions
+            .get(1)
+            .map(|f| f.block_starts[0])
+            .unwrap_or(code.len());
+        let mut overwrites = Vec::new();
+        for (pc, parsed) in instructions(code).take_while(|(pc, _)| *pc < end) {
+            let (insn, wide) = match parsed {
+                ParsedInstruction::None => break,
+                ParsedInstruction::Instruction(i) => (i, false),
+                ParsedInstruction::WideInstruction(w) => (w.instruction, true),
+            };
+            let opcode = insn.opcode;
+            let written = match opcode & BPF_OPCODE_CLASS_MASK {
+                BPF_ALU | BPF_ALU64 | BPF_LDX => Some(insn.dst_reg()),
+                BPF_LD if wide => Some(insn.dst_reg()),
+                BPF_STX if opcode & BPF_OPCODE_MODIFIER_MASK == BPF_ATOMIC => {
+                    if insn.imm == BPF_ATOMIC_CMPXCHG {
+                        Some(0)
+                    } else if insn.imm & BPF_ATOMIC_FETCH != 0 {
+                        Some(insn.src_reg())
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+            if written == Some(1) {
+                overwrites.push(pc);
+            }
+        }
+        overwrites
+    }
+}
 
 #[test]
 pub fn test_inter_function_jump() {
@@ -406,5 +983,427 @@ pub fn test_inter_function_jump() {
     ];
     let result = ProgramInfo::new(complex_normal);
     assert!(result.is_ok());
-    assert!(result.unwrap().functions.len() == 3);
+    let info = result.unwrap();
+    assert!(info.functions.len() == 3);
+    let dot = info.dot();
+    for i in 0..3 {
+        assert!(dot.contains(&format!("f{i}_b0 -> f{i}_exit;")), "{dot}");
+    }
+    assert!(dot.contains("f1_b0 [label=\"@3\"];"));
+    assert!(info
+        .dump_cfg()
+        .contains("function 2\n  block 0 @6: successors [EXIT], predecessors []\n"));
+    // The recursive call in `recur` is not a loop
+    assert!(info.functions.iter().all(|f| f.back_edges().is_empty()));
+    assert!(!info.has_loops());
+}
+
+#[test]
+pub fn test_back_edges() {
+    let acyclic: &[u64] = &[
+        // 0: if r0 == 0 goto +1
+        Instruction::pack(BPF_JMP | BPF_K | BPF_JEQ, 0, 0, 1, 0),
+        // 1: r0 = 0
+        Instruction::pack(BPF_ALU | BPF_K | BPF_MOV, 0, 0, 0, 0),
+        // 2: exit
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let info = ProgramInfo::new(acyclic).unwrap();
+    assert!(info.functions[0].back_edges().is_empty());
+    assert!(!info.has_loops());
+
+    let nested: &[u64] = &[
+        // 0: r0 = 0
+        Instruction::pack(BPF_ALU64 | BPF_K | BPF_MOV, 0, 0, 0, 0),
+        // 1: r0 += 1
+        Instruction::pack(BPF_ALU64 | BPF_K | BPF_ADD, 0, 0, 0, 1),
+        // 2: if r0 == 5 goto -2
+        Instruction::pack(BPF_JMP | BPF_K | BPF_JEQ, 0, 0, -2, 5),
+        // 3: if r0 < 10 goto -3
+        Instruction::pack(BPF_JMP | BPF_K | BPF_JLT, 0, 0, -3, 10),
+        // 4: exit
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let info = ProgramInfo::new(nested).unwrap();
+    assert_eq!(info.functions[0].block_starts, [0, 1, 3, 4]);
+    assert_eq!(info.functions[0].back_edges(), [(2, 1), (1, 1)]);
+    assert!(info.has_loops());
+}
+
+#[test]
+pub fn test_dump_cfg() {
+    let code: &[u64] = &[
+        // 0: if r0 == 0 goto +1
+        Instruction::pack(BPF_JMP | BPF_K | BPF_JEQ, 0, 0, 1, 0),
+        // 1: r0 = 0
+        Instruction::pack(BPF_ALU | BPF_K | BPF_MOV, 0, 0, 0, 0),
+        // 2: exit
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let info = ProgramInfo::new(code).unwrap();
+    assert_eq!(
+        info.dump_cfg(),
+        "function 0
+  block 0 @0: successors [1, 2], predecessors []
+  block 1 @1: successors [2], predecessors [0]
+  block 2 @2: successors [EXIT], predecessors [0, 1]
+"
+    );
+    let dot = info.dot();
+    for edge in ["f0_b0 -> f0_b1;", "f0_b0 -> f0_b2;", "f0_b2 -> f0_exit;"] {
+        assert!(dot.contains(edge), "{dot}");
+    }
+}
+
+#[test]
+pub fn test_required_features() {
+    let code: &[u64] = &[
+        // 0: r0 = 0
+        // 1: lock *(u64 *)(r10 - 8) += r0
+        // 2: if w0 == 0 goto +0
+        // 3: exit
+        Instruction::pack(BPF_ALU64 | BPF_K | BPF_MOV, 0, 0, 0, 0),
+        Instruction::pack(BPF_STX | BPF_ATOMIC | BPF_DW, 0, 10, -8, BPF_ATOMIC_ADD),
+        Instruction::pack(BPF_JMP32 | BPF_K | BPF_JEQ, 0, 0, 0, 0),
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    assert_eq!(
+        ProgramInfo::required_features(code),
+        FeatureSet::JMP32 | FeatureSet::ATOMICS
+    );
+
+    let unsupported = ProgramInfo::unsupported_instructions(code, FeatureSet::JMP32);
+    assert_eq!(unsupported.len(), 1);
+    assert_eq!(unsupported[0], (1, FeatureSet::ATOMICS));
+    assert!(ProgramInfo::unsupported_instructions(code, FeatureSet::all()).is_empty());
+}
+
+#[test]
+pub fn test_link() {
+    let main: &[u64] = &[
+        // Code:
+        //   main:
+        // 0: call helper (relocated)
+        // 1: call other (relocated)
+        // 2: exit
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, -1),
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, -1),
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let lib: &[u64] = &[
+        // Code:
+        //   other:
+        // 0: exit
+        //   helper:
+        // 1: R0 = 0
+        // 2: exit
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+        Instruction::pack(BPF_ALU | BPF_K | BPF_MOV, 0, 0, 0, 0),
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let main_info = SectionInfo {
+        symbols: &[("main", 0)],
+        relocations: &[(0, "helper"), (1, "other")],
+    };
+    let lib_info = SectionInfo {
+        symbols: &[("other", 0), ("helper", 1)],
+        relocations: &[],
+    };
+    let (code, info) = ProgramInfo::link(&[(main, main_info), (lib, lib_info)]).unwrap();
+    assert_eq!(code.len(), 6);
+    assert_eq!(Instruction::from_raw(code[0]).is_pseudo_call(), Some(3));
+    assert_eq!(Instruction::from_raw(code[1]).is_pseudo_call(), Some(1));
+    assert_eq!(code[3..], lib[..]);
+    assert_eq!(info.functions.len(), 3);
+    assert_eq!(info.functions[2].block_starts[0], 4);
+
+    // Functions that are never called end up as unreachable code in the previous function
+    let main_info = SectionInfo {
+        symbols: &[("main", 0)],
+        relocations: &[(0, "helper"), (1, "helper")],
+    };
+    let lib_info = SectionInfo {
+        symbols: &[("other", 0), ("helper", 1)],
+        relocations: &[],
+    };
+    assert!(matches!(
+        ProgramInfo::link(&[(main, main_info), (lib, lib_info)]),
+        Err(VerificationError::UnreachableCode(1))
+    ));
+
+    let missing = SectionInfo {
+        symbols: &[("main", 0)],
+        relocations: &[(0, "nonexistent")],
+    };
+    assert!(matches!(
+        ProgramInfo::link(&[(main, missing)]),
+        Err(VerificationError::IllegalInstruction(
+            IllegalInstruction::OutOfBoundFunction
+        ))
+    ));
+}
+
+#[test]
+pub fn test_rewrite_jumps() {
+    let exit = Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0);
+    let nop = Instruction::pack(BPF_JMP | BPF_JA, 0, 0, 0, 0);
+    let ret = |value| Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, value);
+    let mut code = alloc::vec![
+        // 0: call +3
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 3),
+        // 1: if r0 == 0 goto +1
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1, 0),
+        // 2: r0 = 1
+        ret(1),
+        // 3: exit
+        exit,
+        // 4: r0 = 0
+        ret(0),
+        // 5: exit
+        exit,
+    ];
+    // Inserts a nop before #2
+    ProgramInfo::rewrite_jumps(&mut code, &|pc| if pc >= 2 { pc + 1 } else { pc }).unwrap();
+    code.insert(2, nop);
+    assert_eq!(Instruction::from_raw(code[0]).is_pseudo_call(), Some(4));
+    assert!(matches!(
+        Instruction::from_raw(code[1]).jumps_to(),
+        Some(JumpInstruction::Conditional(2))
+    ));
+    assert_eq!(code[4], exit);
+    assert_eq!(code[5], ret(0));
+    assert!(ProgramInfo::new(&code).is_ok());
+
+    // Removes the nop
+    ProgramInfo::rewrite_jumps(&mut code, &|pc| if pc > 2 { pc - 1 } else { pc }).unwrap();
+    code.remove(2);
+    assert_eq!(Instruction::from_raw(code[0]).is_pseudo_call(), Some(3));
+    assert!(matches!(
+        Instruction::from_raw(code[1]).jumps_to(),
+        Some(JumpInstruction::Conditional(1))
+    ));
+
+    // Out of range
+    let mut code = alloc::vec![Instruction::pack(BPF_JMP | BPF_JA, 0, 0, 0x7FFF, 0), exit];
+    assert!(matches!(
+        ProgramInfo::rewrite_jumps(&mut code, &|pc| pc),
+        Err(IllegalInstruction::OutOfBoundJump)
+    ));
+    let mut code = alloc::vec![Instruction::pack(BPF_JMP | BPF_JA, 0, 0, 0, 0), exit];
+    assert!(matches!(
+        ProgramInfo::rewrite_jumps(&mut code, &|pc| pc * 0x10000),
+        Err(IllegalInstruction::OutOfBoundJump)
+    ));
+}
+
+#[test]
+pub fn test_writes_context() {
+    let exit = Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0);
+    // r2 = r1 + 8; *(u32 *)(r2 + 0) = 1
+    assert!(ProgramInfo::writes_context(&[
+        Instruction::pack(BPF_ALU64 | BPF_X | BPF_MOV, 1, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_K | BPF_ADD, 0, 2, 0, 8),
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 2, 0, 1),
+        exit,
+    ]));
+    // *(u64 *)(r10 - 8) = r1; r3 = *(u64 *)(r10 - 8); *(u8 *)(r3 + 0) = r0
+    assert!(ProgramInfo::writes_context(&[
+        Instruction::pack(BPF_STX | BPF_MEM | BPF_DW, 1, 10, -8, 0),
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 10, 3, -8, 0),
+        Instruction::pack(BPF_STX | BPF_MEM | BPF_B, 0, 3, 0, 0),
+        exit,
+    ]));
+    // r0 = *(u32 *)(r1 + 0); *(u32 *)(r10 - 4) = r0
+    assert!(!ProgramInfo::writes_context(&[
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 1, 0, 0, 0),
+        Instruction::pack(BPF_STX | BPF_MEM | BPF_W, 0, 10, -4, 0),
+        exit,
+    ]));
+    // if r0 == 0 goto +1; r1 = r10; *(u32 *)(r1 - 4) = 0
+    let branched = [
+        Instruction::pack(BPF_JMP | BPF_K | BPF_JEQ, 0, 0, 1, 0),
+        Instruction::pack(BPF_ALU64 | BPF_X | BPF_MOV, 10, 1, 0, 0),
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 1, -4, 0),
+        exit,
+    ];
+    // The jump skips the assignment, leaving r1 the context
+    assert!(ProgramInfo::writes_context(&branched));
+    let mut unconditional = branched;
+    unconditional[0] = Instruction::pack(BPF_ALU64 | BPF_K | BPF_MOV, 0, 0, 0, 0);
+    assert!(!ProgramInfo::writes_context(&unconditional));
+}
+
+#[test]
+pub fn test_context_overwrites() {
+    let exit = Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0);
+    let fetch_add = BPF_ADD as i32 | BPF_ATOMIC_FETCH;
+    let code = [
+        // r6 = r1; r1 += 8
+        Instruction::pack(BPF_ALU64 | BPF_X | BPF_MOV, 1, 6, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_K | BPF_ADD, 0, 1, 0, 8),
+        // r1 = atomic_fetch_add((u64 *)(r10 - 8), r1)
+        Instruction::pack(BPF_STX | BPF_ATOMIC | BPF_DW, 1, 10, -8, fetch_add),
+        // lock *(u64 *)(r10 - 8) += r1
+        Instruction::pack(BPF_STX | BPF_ATOMIC | BPF_DW, 1, 10, -8, BPF_ADD as i32),
+        // call sub; r1 = *(u64 *)(r6 + 0)
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 2),
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 6, 1, 0, 0),
+        exit,
+        // sub: r1 = 0
+        Instruction::pack(BPF_ALU64 | BPF_K | BPF_MOV, 0, 1, 0, 0),
+        exit,
+    ];
+    let info = ProgramInfo::new(&code).unwrap();
+    assert_eq!(info.context_overwrites(&code), [1, 2, 5]);
+}
+
+#[test]
+pub fn test_long_jump() {
+    let exit = Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0);
+    let ret = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0);
+    // 0: if r0 == 0 goto +1
+    // 1: gotol +40000
+    // 2: r0 = 0
+    // ...
+    // 40002: exit
+    let mut code = alloc::vec![ret; 40003];
+    code[0] = Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1, 0);
+    code[1] = Instruction::pack(BPF_JMP32 | BPF_JA, 0, 0, 0, 40000);
+    code[40002] = exit;
+    let info = ProgramInfo::new(&code).unwrap();
+    assert_eq!(info.functions[0].block_starts, [0, 1, 2, 40002]);
+    assert_eq!(info.functions[0].from[1], [3]);
+
+    // Inserts a nop before #2, going beyond what `off` is able to hold
+    ProgramInfo::rewrite_jumps(&mut code, &|pc| if pc >= 2 { pc + 30000 } else { pc }).unwrap();
+    assert!(matches!(
+        Instruction::from_raw(code[1]).jumps_to(),
+        Some(JumpInstruction::Unconditional(70000))
+    ));
+}
+
+#[test]
+pub fn test_reachable_slice() {
+    let exit = Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0);
+    let code: &[u64] = &[
+        // 0: call +4
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 4),
+        // 1: if r0 == 0 goto +1
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1, 0),
+        // 2: goto +1
+        Instruction::pack(BPF_JMP | BPF_JA, 0, 0, 1, 0),
+        // 3: r0 = 1 (dead code when starting from #2)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 1),
+        // 4: exit
+        exit,
+        // 5: r0 = 0 ll
+        Instruction::pack(BPF_LD | BPF_IMM | BPF_DW, 0, 0, 0, 0),
+        0,
+        // 7: exit
+        exit,
+    ];
+    let info = ProgramInfo::new(code).unwrap();
+    assert_eq!(info.reachable_slice(code, 0), [0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(info.reachable_slice(code, 1), [2, 4]);
+    assert_eq!(info.reachable_slice(code, 2), [3, 4]);
+}
+
+#[test]
+pub fn test_deepest_call_chain() {
+    let call = |imm| Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, imm);
+    let exit = Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0);
+    let code: &[u64] = &[
+        // 0: main calls #1 and #2
+        call(2),
+        call(4),
+        exit,
+        // 3: function #1 calls #2 and itself
+        call(2),
+        call(-2),
+        exit,
+        // 6: function #2
+        exit,
+    ];
+    let mut info = ProgramInfo::new(code).unwrap();
+    assert_eq!(
+        info.call_graph(code),
+        [alloc::vec![1, 2], alloc::vec![2, 1], alloc::vec![]]
+    );
+    info.max_stack_depth = alloc::vec![8, 16, 32];
+    assert_eq!(info.deepest_call_chain(code), (alloc::vec![0, 1, 2], 56));
+    info.max_stack_depth = alloc::vec![8, 16];
+    assert_eq!(info.deepest_call_chain(code), (alloc::vec![0, 1], 24));
+}
+
+#[test]
+pub fn test_unreachable_code() {
+    let exit = Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0);
+    let ret = |value| Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, value);
+    assert!(matches!(
+        ProgramInfo::new(&[ret(0), exit, ret(1), exit]),
+        Err(VerificationError::UnreachableCode(1))
+    ));
+    // Unreachable blocks in subprograms
+    assert!(matches!(
+        ProgramInfo::new(&[
+            Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 1),
+            exit,
+            exit,
+            ret(1),
+            exit,
+        ]),
+        Err(VerificationError::UnreachableCode(1))
+    ));
+    // Self-referencing blocks and recursive calls
+    assert!(ProgramInfo::new(&[
+        ret(0),
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, -2, 0),
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, -3),
+        exit,
+    ])
+    .is_ok());
+}
+
+#[test]
+pub fn test_call_offset_extremes() {
+    let exit = Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0);
+    let call = |imm| Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, imm);
+    let func = |imm| Instruction::pack(BPF_LD | BPF_IMM | BPF_DW, BPF_IMM64_FUNC, 1, 0, imm);
+    let starts = |code: &[u64]| {
+        let info = ProgramInfo::new(code).unwrap();
+        info.functions
+            .iter()
+            .map(|f| f.block_starts.clone())
+            .collect::<Vec<_>>()
+    };
+
+    // Calling the last instruction, either directly or by function pointers
+    assert_eq!(starts(&[call(1), exit, exit]), [[0], [2]]);
+    assert_eq!(starts(&[func(2), 0, exit, exit]), [[0], [3]]);
+    // Calling past the end
+    assert!(matches!(
+        ProgramInfo::new(&[call(2), exit, exit]),
+        Err(VerificationError::IllegalInstruction(
+            IllegalInstruction::OutOfBoundFunction
+        ))
+    ));
+    assert!(matches!(
+        ProgramInfo::new(&[Instruction::pack(BPF_JMP | BPF_JA, 0, 0, 1, 0), exit]),
+        Err(VerificationError::IllegalInstruction(
+            IllegalInstruction::OutOfBoundJump
+        ))
+    ));
+
+    // Calling offset 0, deduplicated with the main function
+    assert_eq!(starts(&[call(1), exit, call(-3), exit]), [[0], [2]]);
+    assert_eq!(starts(&[call(1), exit, func(-3), 0, exit]), [[0], [2]]);
+    // Recursive calls to the calling function itself
+    assert_eq!(starts(&[call(-1), exit]), [[0]]);
+    assert_eq!(starts(&[call(1), exit, call(-1), exit]), [[0], [2]]);
+    assert!(matches!(
+        ProgramInfo::new(&[call(-2), exit]),
+        Err(VerificationError::IllegalInstruction(
+            IllegalInstruction::OutOfBoundFunction
+        ))
+    ));
 }
@@ -8,8 +8,14 @@ use ebpf_atomic::{Atomic, AtomicError};
 
 use crate::{
     interpreter::value::*,
-    spec::proto::{ArgumentType, IllegalFunctionCall},
-    track::{pointees::InnerRegion, pointer::Pointer, scalar::Scalar, TrackedValue},
+    spec::proto::{ArgumentType, IllegalFunctionCall, MemoryExtent, ResourceOperation},
+    track::{
+        id::Id,
+        pointees::InnerRegion,
+        pointer::Pointer,
+        scalar::{Scalar, ShiftAssign as ScalarShift},
+        TrackError, TrackedValue,
+    },
 };
 
 /// A value wrapping up [TrackedValue] while also tracking its validity
@@ -45,6 +51,47 @@ impl CheckedValue {
         self.0.get_mut()
     }
 
+    /// Interprets the value as a constant memory size
+    pub(crate) fn constant_size(&self) -> Result<usize, IllegalFunctionCall> {
+        if let Some(TrackedValue::Scalar(s)) = self.inner() {
+            if let Some(size) = s.value64() {
+                Ok(size as usize)
+            } else {
+                Err(IllegalFunctionCall::NotAConstant)
+            }
+        } else {
+            Err(IllegalFunctionCall::TypeMismatch)
+        }
+    }
+
+    /// Checks that `size` bytes can be copied from `src` into this destination
+    ///
+    /// The destination only needs to be writable, while the source must be readable.
+    pub(crate) fn check_copy(&self, src: &Self, size: &Self) -> Result<(), IllegalFunctionCall> {
+        let size = size.constant_size()?;
+        if let (Some(TrackedValue::Pointer(dst)), Some(TrackedValue::Pointer(src))) =
+            (self.inner(), src.inner())
+        {
+            src.get_all(size)
+                .map_err(IllegalFunctionCall::IllegalPointer)?;
+            dst.set_all(size)
+                .map_err(IllegalFunctionCall::IllegalPointer)
+        } else {
+            Err(IllegalFunctionCall::TypeMismatch)
+        }
+    }
+
+    /// Checks whether the value is subsumed by `other`
+    ///
+    /// Any value is subsumed by an uninitialized one, which is never read.
+    pub fn is_subset_of(&self, other: &Self) -> bool {
+        match (self.inner(), other.inner()) {
+            (_, None) => true,
+            (Some(v), Some(w)) => v.is_subset_of(w),
+            (None, Some(_)) => false,
+        }
+    }
+
     pub(crate) fn check_arg_type(
         &self,
         wants: &ArgumentType,
@@ -93,30 +140,87 @@ impl CheckedValue {
             }
             ArgumentType::DynamicMemory(_) => {
                 if let Some(reg) = extra {
-                    if let Some(TrackedValue::Scalar(s)) = reg.inner() {
-                        if let Some(size) = s.value64() {
-                            self.check_arg_type(&ArgumentType::FixedMemory(size as usize), None)
-                        } else {
-                            Err(IllegalFunctionCall::NotAConstant)
-                        }
-                    } else {
-                        Err(IllegalFunctionCall::TypeMismatch)
-                    }
+                    let size = reg.constant_size()?;
+                    self.check_arg_type(&ArgumentType::FixedMemory(size), None)
                 } else {
                     Err(IllegalFunctionCall::TypeMismatch)
                 }
             }
-            ArgumentType::ResourceType((type_id, _)) => {
+            ArgumentType::InitializedMemory(MemoryExtent::Fixed(size)) => {
                 if let Some(TrackedValue::Pointer(p)) = self.inner() {
+                    p.get_all(*size)
+                        .map_err(IllegalFunctionCall::IllegalPointer)
+                } else {
+                    Err(IllegalFunctionCall::TypeMismatch)
+                }
+            }
+            ArgumentType::InitializedMemory(MemoryExtent::StackFrame) => {
+                // `extra` is the frame pointer
+                match (self.inner(), extra.and_then(|frame| frame.inner())) {
+                    (Some(TrackedValue::Pointer(p)), Some(TrackedValue::Pointer(frame)))
+                        if p.is_pointing_to(frame.get_pointing_to()) =>
+                    {
+                        let start = p.offset().value64();
+                        let end = frame.offset().value64();
+                        let Some(len) = start.zip(end).and_then(|(s, e)| e.checked_sub(s)) else {
+                            return Err(IllegalFunctionCall::OutofRange);
+                        };
+                        let depth = match p.get_pointing_region().borrow_mut().inner() {
+                            InnerRegion::Stack(stack) => stack.depth(),
+                            _ => return Err(IllegalFunctionCall::TypeMismatch),
+                        };
+                        if (len as usize) < depth {
+                            return Err(IllegalFunctionCall::OutofRange);
+                        }
+                        p.get_all(len as usize)
+                            .map_err(IllegalFunctionCall::IllegalPointer)
+                    }
+                    _ => Err(IllegalFunctionCall::TypeMismatch),
+                }
+            }
+            ArgumentType::CopyDestination(_) => {
+                // Checked along with the source by [CheckedValue::check_copy]
+                Err(IllegalFunctionCall::TypeMismatch)
+            }
+            ArgumentType::ResourceType((type_id, op)) => {
+                if let Some(TrackedValue::Pointer(p)) = self.inner() {
+                    if !p.non_null() {
+                        // Nullable resources must be checked against null first
+                        return Err(IllegalFunctionCall::IllegalPointer(
+                            TrackError::PointerNullable,
+                        ));
+                    }
+                    let deallocates = matches!(op, ResourceOperation::Deallocates);
+                    if deallocates {
+                        // Only the pointer to the start of the resource may release it
+                        if p.offset().value64() != Some(0) {
+                            return Err(IllegalFunctionCall::OutofRange);
+                        }
+                    }
                     if let InnerRegion::Any((any, _)) = p.get_pointing_region().borrow_mut().inner()
                     {
-                        if *type_id == any && p.is_mutable() && p.is_readable() && p.non_null() {
+                        // Read-only objects, e.g., kernel ones, are never released
+                        if *type_id == any && p.is_readable() && (p.is_mutable() || !deallocates) {
                             return Ok(());
                         }
                     }
                 }
                 Err(IllegalFunctionCall::TypeMismatch)
             }
+            ArgumentType::OutPointer(_) => {
+                if let Some(TrackedValue::Pointer(p)) = self.inner() {
+                    p.set_all(8).map_err(IllegalFunctionCall::IllegalPointer)
+                } else {
+                    Err(IllegalFunctionCall::TypeMismatch)
+                }
+            }
+            ArgumentType::Callback => {
+                if let Some(TrackedValue::FuncPointer(_)) = self.inner() {
+                    Ok(())
+                } else {
+                    Err(IllegalFunctionCall::TypeMismatch)
+                }
+            }
         }
     }
 }
@@ -176,7 +280,8 @@ macro_rules! impl_scalar_or_pointer_assign_op {
                     }
                 }
                 (TrackedValue::Scalar(s1), TrackedValue::Pointer(p2)) => {
-                    if p2.is_arithmetic() && p2.non_null() {
+                    // `scalar - pointer` makes no sense
+                    if !$sub && p2.is_arithmetic() && p2.non_null() {
                         let mut value = p2.clone();
                         value.$fn(s1);
                         *v1 = TrackedValue::Pointer(value);
@@ -184,7 +289,11 @@ macro_rules! impl_scalar_or_pointer_assign_op {
                         self.invalidate();
                     }
                 }
+                (TrackedValue::FuncPointer(_), _) | (_, TrackedValue::FuncPointer(_)) => {
+                    self.invalidate();
+                }
                 (TrackedValue::Pointer(p1), TrackedValue::Pointer(p2)) => {
+                    // The distance between pointers into the same region
                     if $sub {
                         if let Some(result) = p1.sub(p2) {
                             *v1 = TrackedValue::Scalar(result);
@@ -211,17 +320,6 @@ macro_rules! impl_scalar_only_assign_op {
     };
 }
 
-macro_rules! impl_scalar_only_unknown_op {
-    ($fn:ident) => {
-        fn $fn(&mut self, rhs: &'a Self) {
-            let inners = (self.inner_mut(), rhs.inner());
-            let (v1, v2) = unwrap_or_return!(self, inners);
-            let (s1, _) = unwrap_scalars_or_return!(self, v1, v2);
-            s1.mark_as_unknown();
-        }
-    };
-}
-
 // Type conversion
 impl Castable for CheckedValue {
     fn lower_half(&self) -> Self {
@@ -246,23 +344,30 @@ impl Castable for CheckedValue {
             self.invalidate();
         }
     }
+
+    fn sign_extend_assign(&mut self, width: u8) {
+        if let Some(TrackedValue::Scalar(ref mut s)) = self.inner_mut() {
+            s.sign_extend(width);
+        } else {
+            self.invalidate();
+        }
+    }
 }
 
 impl<'a> AddAssign<&'a Self> for CheckedValue {
     impl_scalar_or_pointer_assign_op!(add_assign, false);
 }
 impl<'a> SubAssign<&'a Self> for CheckedValue {
-    // TODO: Support subtracting pointers of the same memory region
     impl_scalar_or_pointer_assign_op!(sub_assign, true);
 }
 impl<'a> MulAssign<&'a Self> for CheckedValue {
     impl_scalar_only_assign_op!(mul_assign);
 }
 impl<'a> DivAssign<&'a Self> for CheckedValue {
-    impl_scalar_only_unknown_op!(div_assign);
+    impl_scalar_only_assign_op!(div_assign);
 }
 impl<'a> RemAssign<&'a Self> for CheckedValue {
-    impl_scalar_only_unknown_op!(rem_assign);
+    impl_scalar_only_assign_op!(rem_assign);
 }
 impl<'a> BitAndAssign<&'a Self> for CheckedValue {
     impl_scalar_only_assign_op!(bitand_assign);
@@ -274,8 +379,30 @@ impl<'a> BitXorAssign<&'a Self> for CheckedValue {
     impl_scalar_only_assign_op!(bitxor_assign);
 }
 
+macro_rules! impl_checked_signed_div {
+    ($op:ident, $self:ident, $rhs:ident, $width:expr) => {{
+        debug_assert!($width == 32 || $width == 64);
+        let inners = ($self.inner_mut(), $rhs.inner());
+        let (v1, v2) = unwrap_or_return!($self, inners);
+        let (s1, s2) = unwrap_scalars_or_return!($self, v1, v2);
+        if $width == 32 {
+            s1.$op::<32>(s2);
+        } else {
+            s1.$op::<64>(s2);
+        }
+    }};
+}
+
 // TODO: Maybe prevent intentional zero division (against a constant zero register)
-impl SafeDivAssign<&Self> for CheckedValue {}
+impl SafeDivAssign<&Self> for CheckedValue {
+    fn signed_div_assign(&mut self, rhs: &Self, width: u8) {
+        impl_checked_signed_div!(signed_div, self, rhs, width);
+    }
+
+    fn signed_rem_assign(&mut self, rhs: &Self, width: u8) {
+        impl_checked_signed_div!(signed_rem, self, rhs, width);
+    }
+}
 
 macro_rules! impl_checked_shift {
     ($op:ident, $self:ident, $rhs:ident, $width:expr) => {{
@@ -284,32 +411,24 @@ macro_rules! impl_checked_shift {
         let (v1, v2) = unwrap_or_return!($self, inners);
         let (s1, s2) = unwrap_scalars_or_return!($self, v1, v2);
         if $width == 32 {
-            if let Some(value) = s2.value32() {
-                s1.$op::<32>(value as u64);
-            } else {
-                s1.mark_as_unknown();
-            }
+            ScalarShift::<32, &Scalar>::$op(s1, s2);
         } else {
-            if let Some(value) = s2.value64() {
-                s1.$op::<64>(value);
-            } else {
-                s1.mark_as_unknown();
-            }
+            ScalarShift::<64, &Scalar>::$op(s1, s2);
         }
     }};
 }
 
 impl<'a> ShiftAssign<&'a Self> for CheckedValue {
     fn signed_shr(&mut self, rhs: &'a Self, width: u8) {
-        impl_checked_shift!(ashr, self, rhs, width);
+        impl_checked_shift!(ashr_assign, self, rhs, width);
     }
 
     fn r_shift(&mut self, rhs: &'a Self, width: u8) {
-        impl_checked_shift!(shr, self, rhs, width);
+        impl_checked_shift!(shr_assign, self, rhs, width);
     }
 
     fn l_shift(&mut self, rhs: &'a Self, width: u8) {
-        impl_checked_shift!(shl, self, rhs, width);
+        impl_checked_shift!(shl_assign, self, rhs, width);
     }
 }
 
@@ -319,12 +438,20 @@ impl NegAssign for CheckedValue {
     }
 }
 impl ByteSwap for CheckedValue {
-    fn host_to_le(&mut self, _width: i32) {
-        self.mark_as_unknown();
+    fn host_to_le(&mut self, width: i32) {
+        if let Some(TrackedValue::Scalar(s)) = self.inner_mut() {
+            s.host_to_le(width);
+        } else {
+            self.invalidate();
+        }
     }
 
-    fn host_to_be(&mut self, _width: i32) {
-        self.mark_as_unknown();
+    fn host_to_be(&mut self, width: i32) {
+        if let Some(TrackedValue::Scalar(s)) = self.inner_mut() {
+            s.host_to_be(width);
+        } else {
+            self.invalidate();
+        }
     }
 }
 
@@ -393,6 +520,16 @@ impl Dereference for CheckedValue {
             }
         }
     }
+
+    fn provenance(&self, offset: i16) -> Option<(Id, RangeInclusive<i64>)> {
+        if let Some(TrackedValue::Pointer(p)) = self.inner() {
+            let mut ptr = p.clone();
+            ptr += &Scalar::constant64(offset as i64 as u64);
+            Some((ptr.get_pointing_to(), ptr.offset().signed_range()))
+        } else {
+            None
+        }
+    }
 }
 
 macro_rules! unwrap_scalar_or_return {
@@ -408,9 +545,7 @@ macro_rules! unwrap_scalar_or_return {
 
 impl Atomic for CheckedValue {
     fn fetch_add(&self, offset: i16, rhs: &Self, size: usize) -> Result<CheckedValue, AtomicError> {
-        if size != 4 && size != 8 {
-            return Err(AtomicError::UnsupportedBitness);
-        }
+        ebpf_atomic::check_size(size)?;
         let p = unwrap_pointer_or_return!(self, Err(AtomicError::IllegalAccess));
         let _ = unwrap_scalar_or_return!(rhs, Err(AtomicError::IllegalAccess));
         let mut ptr = p.clone();
@@ -461,6 +596,7 @@ impl Debug for CheckedValue {
         match self.inner() {
             Some(TrackedValue::Pointer(p)) => f.write_fmt(format_args!("{p:?}")),
             Some(TrackedValue::Scalar(s)) => f.write_fmt(format_args!("{s:?}")),
+            Some(TrackedValue::FuncPointer(i)) => f.write_fmt(format_args!("func#{i}")),
             None => f.write_str("_"),
         }
     }
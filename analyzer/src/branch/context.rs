@@ -1,30 +1,95 @@
 //! See [BranchContext] and [VmContext].
 
-use alloc::vec::Vec;
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
 
-use crate::interpreter::{context::VmContext, value::Verifiable};
+use crate::{
+    interpreter::{
+        context::{AccessObserver, VmContext},
+        value::Verifiable,
+        vm::Vm,
+    },
+    spec::{proto::KfuncPrototype, CodeOffset},
+};
 
 use super::{
     checked_value::CheckedValue,
     vm::{Branch, BranchState},
 };
 
+/// Default limit of processed instructions, see [BranchContext::set_instruction_limit]
+pub const DEFAULT_INSTRUCTION_LIMIT: usize = 1_000_000;
+/// Default limit of pending branches, see [BranchContext::set_branch_limits]
+pub const DEFAULT_PENDING_BRANCH_LIMIT: usize = 8192;
+/// Default limit of all forked branches, see [BranchContext::set_branch_limits]
+pub const DEFAULT_TOTAL_BRANCH_LIMIT: usize = 100_000;
+
+/// Why a branch is not explored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PruneReason {
+    /// The state at the offset is subsumed by an explored one (see [BranchState::is_subset_of])
+    Subsumed(CodeOffset),
+    /// The conditional jump at the offset always or never jumps, pruning the other path
+    Contradiction(CodeOffset),
+}
+
+/// A state explored from the start of a branch
+struct VisitedState {
+    state: BranchState,
+    /// Index of the visited state the branch was forked from
+    parent: Option<usize>,
+}
+
 /// A simple context collecting all unexplored branches
-pub struct BranchContext {
-    branches: Vec<Branch>,
+///
+/// Branches whose states are subsumed by explored ones at the same instruction
+/// (see [BranchState::is_subset_of]) are pruned.
+pub struct BranchContext<'a> {
+    /// Pending branches, along with the visited state they are forked from
+    branches: Vec<(Branch, Option<usize>)>,
+    /// States at the start of explored branches
+    visited: Vec<VisitedState>,
+    /// Indices into `visited`, by `pc`
+    visited_at: BTreeMap<CodeOffset, Vec<usize>>,
+    /// The visited state of the branch being explored
+    current: Option<usize>,
     instruction_count: usize,
     instruction_limit: usize,
+    /// Number of branches forked so far
+    branch_count: usize,
+    pending_branch_limit: usize,
+    total_branch_limit: usize,
+    branch_limit_exceeded: bool,
     invalid: Option<&'static str>,
+    observer: Option<&'a dyn AccessObserver>,
+    kfunc_resolver: Option<&'a dyn Fn(i32) -> Option<KfuncPrototype>>,
+    /// Kfuncs already passed to the resolver, resolved or not
+    resolved_kfuncs: Vec<i32>,
+    /// Registers live before each instruction, see [BranchContext::set_live_registers]
+    live_registers: Vec<u16>,
 }
 
-impl BranchContext {
+impl<'a> BranchContext<'a> {
     /// Creates an empty context
-    pub fn new() -> BranchContext {
+    pub fn new() -> BranchContext<'a> {
         BranchContext {
             branches: Vec::new(),
+            visited: Vec::new(),
+            visited_at: BTreeMap::new(),
+            current: None,
             instruction_count: 0,
-            instruction_limit: 1000000,
+            instruction_limit: DEFAULT_INSTRUCTION_LIMIT,
+            branch_count: 0,
+            pending_branch_limit: DEFAULT_PENDING_BRANCH_LIMIT,
+            total_branch_limit: DEFAULT_TOTAL_BRANCH_LIMIT,
+            branch_limit_exceeded: false,
             invalid: None,
+            observer: None,
+            kfunc_resolver: None,
+            resolved_kfuncs: Vec::new(),
+            live_registers: Vec::new(),
         }
     }
 
@@ -37,31 +102,124 @@ impl BranchContext {
     pub fn set_instruction_limit(&mut self, limit: usize) {
         self.instruction_limit = limit;
     }
+
+    /// Sets limits for pending (unexplored) branches and for all forked branches
+    pub fn set_branch_limits(&mut self, pending: usize, total: usize) {
+        self.pending_branch_limit = pending;
+        self.total_branch_limit = total;
+    }
+
+    /// Returns `true` if the context is invalidated by [BranchContext::set_branch_limits]
+    pub fn is_branch_limit_exceeded(&self) -> bool {
+        self.branch_limit_exceeded
+    }
+
+    /// Sets an observer to get notified of memory accesses
+    pub fn set_observer(&mut self, observer: &'a dyn AccessObserver) {
+        self.observer = Some(observer);
+    }
+
+    /// Sets a resolver for kfuncs unknown to the branches
+    ///
+    /// Each kfunc is resolved only once, when a call to it is first verified,
+    /// and made available to all branches (see [BranchState::add_kfunc]).
+    pub fn set_kfunc_resolver(&mut self, resolver: &'a dyn Fn(i32) -> Option<KfuncPrototype>) {
+        self.kfunc_resolver = Some(resolver);
+    }
+
+    /// Sets the registers live before each instruction (see [live_registers](crate::spec::live_registers))
+    ///
+    /// Only live registers are checked for type changes across loop iterations.
+    /// Without it, all registers are considered live.
+    pub fn set_live_registers(&mut self, live: Vec<u16>) {
+        self.live_registers = live;
+    }
+
+    /// Checks whether the branch is subsumed by a visited state
+    ///
+    /// Since branches are explored depth-first, a visited state is either fully explored
+    /// or one that the branch is forked from. The latter is skipped,
+    /// since pruning against it may hide an infinite loop.
+    fn is_subsumed(&self, branch: &BranchState, pc: CodeOffset, parent: Option<usize>) -> bool {
+        let Some(candidates) = self.visited_at.get(&pc) else {
+            return false;
+        };
+        let ancestors: BTreeSet<usize> =
+            core::iter::successors(parent, |p| self.visited[*p].parent).collect();
+        candidates
+            .iter()
+            .filter(|i| !ancestors.contains(i))
+            .any(|i| branch.is_subset_of(&self.visited[*i].state))
+    }
+
+    /// Checks whether a live register changes between a scalar and a pointer since the branch
+    /// last passed the same instruction, i.e., across an iteration of a loop
+    fn changes_type_across_loop(
+        &self,
+        branch: &BranchState,
+        pc: CodeOffset,
+        parent: Option<usize>,
+    ) -> bool {
+        let Some(candidates) = self.visited_at.get(&pc) else {
+            return false;
+        };
+        let live = self.live_registers.get(pc).copied().unwrap_or(u16::MAX);
+        core::iter::successors(parent, |p| self.visited[*p].parent)
+            .filter(|i| candidates.contains(i))
+            .any(|i| branch.has_register_type_change(&self.visited[i].state, live))
+    }
 }
 
-impl Default for BranchContext {
+impl<'a> Default for BranchContext<'a> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl Iterator for BranchContext {
+impl<'a> Iterator for BranchContext<'a> {
     type Item = Branch;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.branches.pop()
+        while let Some((branch, parent)) = self.branches.pop() {
+            let pc = *branch.borrow_mut().pc();
+            if self.is_subsumed(&branch.borrow(), pc, parent) {
+                branch.borrow().prune(PruneReason::Subsumed(pc));
+                continue;
+            }
+            if self.changes_type_across_loop(&branch.borrow(), pc, parent) {
+                branch
+                    .borrow()
+                    .invalidate("register type changes across loop iteration");
+            }
+            let index = self.visited.len();
+            self.visited.push(VisitedState {
+                state: branch.borrow().clone(),
+                parent,
+            });
+            self.visited_at.entry(pc).or_default().push(index);
+            self.current = Some(index);
+            return Some(branch);
+        }
+        None
     }
 }
 
-impl Verifiable for BranchContext {
+impl<'a> Verifiable for BranchContext<'a> {
     fn is_valid(&self) -> bool {
         self.invalid.is_none()
     }
 }
 
-impl VmContext<CheckedValue, BranchState> for BranchContext {
+impl<'a> VmContext<CheckedValue, BranchState> for BranchContext<'a> {
     fn add_pending_branch(&mut self, vm: Branch) {
-        self.branches.push(vm);
+        self.branches.push((vm, self.current));
+        self.branch_count += 1;
+        if self.branches.len() > self.pending_branch_limit
+            || self.branch_count > self.total_branch_limit
+        {
+            self.branch_limit_exceeded = true;
+            self.invalid = Some("Too many branches");
+        }
     }
 
     fn increment_pc(&mut self) {
@@ -70,4 +228,21 @@ impl VmContext<CheckedValue, BranchState> for BranchContext {
             self.invalid = Some("Too many instructions to process");
         }
     }
+
+    fn observer(&self) -> Option<&dyn AccessObserver> {
+        self.observer
+    }
+
+    fn prepare_kfunc(&mut self, vm: &mut BranchState, id: i32) {
+        let Some(resolve) = self.kfunc_resolver else {
+            return;
+        };
+        if vm.has_kfunc(id) || self.resolved_kfuncs.contains(&id) {
+            return;
+        }
+        self.resolved_kfuncs.push(id);
+        if let Some(prototype) = resolve(id) {
+            vm.add_kfunc(id, prototype);
+        }
+    }
 }
@@ -20,6 +20,7 @@ use crate::{
 
 use super::{
     checked_value::CheckedValue,
+    context::PruneReason,
     vm::{Branch, BranchState},
 };
 
@@ -38,6 +39,13 @@ impl BranchState {
         }
     }
 
+    /// Records that the other path of the conditional jump being executed is unsatisfiable
+    fn prune_contradiction(&mut self) {
+        // The pc already points to the next instruction
+        let pc = *self.pc() - 1;
+        self.prune(PruneReason::Contradiction(pc));
+    }
+
     /// Sets the limit for a [crate::track::pointees::dyn_region::DynamicRegion]
     fn fork_pointer_le(
         &mut self,
@@ -57,6 +65,7 @@ impl BranchState {
     }
 
     /// Sets the limit for a [crate::track::pointees::dyn_region::DynamicRegion]
+    /// or a [crate::track::pointees::packet_region::PacketRegion]
     ///
     /// It handles only the case `ptr <= end`.
     /// Use [self::fork_pointer_le()] to handle both, including `end <= ptr`.
@@ -74,7 +83,7 @@ impl BranchState {
         {
             let pointee_ref = p1.get_pointing_region();
             let mut pointee = pointee_ref.borrow_mut();
-            if let InnerRegion::Dyn(_) = pointee.inner() {
+            if let InnerRegion::Dyn(_) | InnerRegion::Packet(_) = pointee.inner() {
                 // dropping to allow cloning
                 drop(pointee);
                 // fallthrough
@@ -84,15 +93,23 @@ impl BranchState {
                 let offset = p1.offset();
                 // borrowing again
                 let mut pointee = pointee_ref.borrow_mut();
-                if let InnerRegion::Dyn(region) = pointee.inner() {
-                    region.set_limit(offset);
-                } else {
-                    unreachable!();
+                match pointee.inner() {
+                    InnerRegion::Dyn(region) => {
+                        region.set_limit(offset);
+                        if region.is_bounded(offset) {
+                            // `p1 <= end`, whatever its offset is
+                            p1.set_window(0);
+                        }
+                    }
+                    InnerRegion::Packet(region) => region.check_access(offset),
+                    _ => unreachable!(),
                 }
                 *self.pc() = fork.target;
                 Ok(Some(Rc::new(RefCell::new(branch))))
             } else {
-                self.invalidate("Only comparison of pointers of dynamic regions is allowed");
+                self.invalidate(
+                    "Only comparison of pointers of dynamic regions or packets is allowed",
+                );
                 Err(())
             }
         } else {
@@ -119,10 +136,12 @@ macro_rules! match_scalar_comparison {
                 $fork:ident, $width:ident) => {
         match $s1.$op($s2, $width) {
             ComparisonResult::Always => {
+                $self.prune_contradiction();
                 *$self.pc() = $fork.target;
                 None
             }
             ComparisonResult::Never => {
+                $self.prune_contradiction();
                 *$self.pc() = $fork.fall_through;
                 None
             }
@@ -163,6 +182,10 @@ impl Forker<CheckedValue, BranchState> for BranchState {
         // Mut borrow workaround
         let pair = unwrap_checked_values!(self, dst, src);
         match pair {
+            (FuncPointer(_), _) | (_, FuncPointer(_)) => {
+                self.invalidate("Function pointer comparison not allowed");
+                None
+            }
             (Pointer(p1), Pointer(p2)) => {
                 if width == 64 && p1.is_pointing_to(p2.get_pointing_to()) {
                     self.invalidate("Pointer comparison not implemented");
@@ -181,15 +204,20 @@ impl Forker<CheckedValue, BranchState> for BranchState {
                     // if p1 == 0
                     if p1.non_null() {
                         // p1 != 0: fall through
+                        self.prune_contradiction();
                         *self.pc() = fork.fall_through;
                         None
                     } else {
                         // p1 != 0: fall through
                         p1.set_non_null();
+                        let id = p1.get_pointing_to();
                         *self.pc() = fork.fall_through;
-                        // p1 == 0: jumps
+                        // p1 == 0: jumps, where nothing is allocated
                         let mut branch = self.clone();
                         *branch.pc() = fork.target;
+                        if branch.is_allocated_resource(id) {
+                            branch.deallocate_resource(id);
+                        }
                         if dst_i >= 0 {
                             *branch.reg(dst_i as u8) = scalar::Scalar::constant64(0).into();
                         }
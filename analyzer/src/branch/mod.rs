@@ -6,4 +6,3 @@ pub mod fork;
 pub mod checked_value;
 pub mod context;
 pub mod resource;
-pub mod id;
@@ -2,17 +2,25 @@
 
 use alloc::{collections::VecDeque, vec::Vec};
 
-use super::id::{Id, IdGen};
+use crate::track::id::{Id, IdGen};
 
 /// Tracks the allocation of resources
-#[derive(Clone, Default)]
+#[derive(Clone, Default, PartialEq, Eq)]
 pub struct ResourceTracker {
     /// Allocated resources (must be freed)
     resources: VecDeque<Id>,
     /// Externally provided resources (no need to free)
     external: Vec<Id>,
-    /// Locks, unused for now
+    /// Whether the program is holding a spin lock
     locked: bool,
+    /// Initialized `bpf_timer` objects: `(region, offset)`
+    timers: Vec<(Id, u64)>,
+    /// Map values handed out to the program (also external resources)
+    map_values: Vec<Id>,
+    /// Map values counted towards [ResourceTracker::set_max_map_values]
+    counted_map_values: Vec<Id>,
+    /// Maximum count of map values tracked at once
+    max_map_values: Option<usize>,
 }
 
 impl ResourceTracker {
@@ -28,12 +36,46 @@ impl ResourceTracker {
         if let Some(index) = self.external.iter().position(|i| *i == id) {
             // TODO: Decide if we should use `swap_remove`
             self.external.remove(index);
+            self.timers.retain(|(region, _)| *region != id);
+            self.map_values.retain(|value| *value != id);
+            self.counted_map_values.retain(|value| *value != id);
             true
         } else {
             false
         }
     }
 
+    /// Adds a map value as an external resource
+    pub fn map_value(&mut self, ids: &mut IdGen) -> Id {
+        let id = self.external(ids);
+        self.map_values.push(id);
+        self.counted_map_values.push(id);
+        id
+    }
+
+    /// Checks if the resource is a map value that is still available
+    pub fn is_map_value(&self, id: Id) -> bool {
+        self.map_values.contains(&id)
+    }
+
+    /// Stops counting map values that `live` returns `false` for
+    ///
+    /// They remain available as map values.
+    pub fn release_map_values(&mut self, live: impl Fn(Id) -> bool) {
+        self.counted_map_values.retain(|id| live(*id));
+    }
+
+    /// Limits the count of map values tracked at once, see [ResourceTracker::map_value]
+    pub fn set_max_map_values(&mut self, limit: Option<usize>) {
+        self.max_map_values = limit;
+    }
+
+    /// Returns `true` if more map values are tracked than allowed
+    pub fn is_map_value_limit_exceeded(&self) -> bool {
+        self.max_map_values
+            .is_some_and(|limit| self.counted_map_values.len() > limit)
+    }
+
     /// Allocates a resource
     pub fn allocate(&mut self, ids: &mut IdGen) -> Id {
         let id = ids.next_id();
@@ -52,6 +94,11 @@ impl ResourceTracker {
         }
     }
 
+    /// Checks if a certain resource is allocated by the program and not yet released
+    pub fn is_allocated(&self, id: Id) -> bool {
+        self.resources.contains(&id)
+    }
+
     /// Checks if a certain resource is available
     pub fn contains(&self, id: Id) -> bool {
         self.resources.contains(&id) || self.external.contains(&id)
@@ -82,6 +129,18 @@ impl ResourceTracker {
         self.locked
     }
 
+    /// Marks the `bpf_timer` at `offset` in the region as initialized
+    pub fn init_timer(&mut self, region: Id, offset: u64) {
+        if !self.is_timer_initialized(region, offset) {
+            self.timers.push((region, offset));
+        }
+    }
+
+    /// Checks if the `bpf_timer` at `offset` in the region is initialized
+    pub fn is_timer_initialized(&self, region: Id, offset: u64) -> bool {
+        self.timers.contains(&(region, offset))
+    }
+
     /// Returns `true` if all resources are cleaned up
     pub fn is_empty(&self) -> bool {
         !self.locked && self.resources.is_empty()
@@ -94,6 +153,7 @@ fn test_res_tracker() {
     assert!(!tracker.is_locked());
     assert_eq!(tracker.allocate(&mut IdGen::default()), 1);
     assert!(tracker.contains(1));
+    assert!(tracker.is_allocated(1));
     assert!(!tracker.deallocate(0));
     assert!(tracker.deallocate(1));
     assert!(!tracker.contains(1));
@@ -108,7 +168,31 @@ fn test_res_tracker() {
     assert_eq!(tracker.external(&mut IdGen::default()), 1);
     assert!(tracker.is_empty());
     assert!(tracker.contains(1));
+    assert!(!tracker.is_allocated(1));
     assert!(tracker.invalidate_external(1));
     assert!(!tracker.contains(1));
     assert!(!tracker.invalidate_external(1));
+
+    let id = tracker.external(&mut IdGen::default());
+    assert!(!tracker.is_timer_initialized(id, 0));
+    tracker.init_timer(id, 0);
+    assert!(tracker.is_timer_initialized(id, 0));
+    assert!(!tracker.is_timer_initialized(id, 16));
+    assert!(tracker.invalidate_external(id));
+    assert!(!tracker.is_timer_initialized(id, 0));
+
+    let mut ids = IdGen::default();
+    tracker.set_max_map_values(Some(1));
+    let first = tracker.map_value(&mut ids);
+    assert!(tracker.contains(first));
+    assert!(!tracker.is_map_value_limit_exceeded());
+    let second = tracker.map_value(&mut ids);
+    assert!(tracker.is_map_value_limit_exceeded());
+    tracker.release_map_values(|id| id != first);
+    assert!(tracker.contains(first) && tracker.is_map_value(first));
+    assert!(!tracker.is_map_value_limit_exceeded());
+    tracker.map_value(&mut ids);
+    assert!(tracker.invalidate_external(second));
+    assert!(!tracker.is_map_value_limit_exceeded());
+    assert!(!tracker.is_map_value(second));
 }
@@ -3,9 +3,11 @@
 //! Since the VM branches when doing conditional jumps (see [super::fork]),
 //! usually the VM is kept behind an [Rc] with [Branch].
 
+use alloc::collections::{BTreeMap, BTreeSet};
 use core::{
     cell::{RefCell, UnsafeCell},
     fmt::Debug,
+    iter::zip,
 };
 
 use alloc::{
@@ -13,7 +15,7 @@ use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
-use ebpf_consts::{maps::MapType, *};
+use ebpf_consts::{maps::MapType, mask::*, programs::ProgType, *};
 
 use crate::{
     analyzer::MapInfo,
@@ -21,15 +23,22 @@ use crate::{
         value::Verifiable,
         vm::{CallerContext, Vm},
     },
+    loops::{InductionVariable, Loop},
     safe::{mut_borrow_items, safe_ref_unsafe_cell},
-    spec::{proto::VerifiableCall, Instruction},
+    spec::{
+        proto::{KfuncPrototype, VerifiableCall},
+        CodeOffset, Instruction,
+    },
     track::{
+        comparable::{Comparable, ComparisonResult},
+        id::{Id, IdGen},
         pointees::{
             empty_region::EmptyRegion,
+            kernel_region::{KernelRegion, KernelStruct},
             map_resource::{SimpleMap, MAP_TYPE_ID},
             pointed,
             stack_region::StackRegion,
-            InnerRegion, Pointee,
+            AnyType, InnerRegion, Pointee,
         },
         pointer::Pointer,
         scalar::Scalar,
@@ -37,11 +46,7 @@ use crate::{
     },
 };
 
-use super::{
-    checked_value::CheckedValue,
-    id::{Id, IdGen},
-    resource::ResourceTracker,
-};
+use super::{checked_value::CheckedValue, context::PruneReason, resource::ResourceTracker};
 
 /// A collection of used helper functions
 ///
@@ -51,6 +56,10 @@ pub type StaticHelpers = &'static [&'static dyn VerifiableCall<CheckedValue, Bra
 /// Inner state of [BranchState]
 pub(crate) struct InnerState {
     pc: usize,
+    /// The instruction being executed, see [BranchState::fault_pc]
+    instruction: CodeOffset,
+    /// The instruction being executed when the state first got invalidated
+    fault: Option<CodeOffset>,
     ids: IdGen,
     invalid: Vec<String>,
     registers: [CheckedValue; 11],
@@ -66,11 +75,49 @@ pub(crate) struct InnerState {
     helpers: StaticHelpers,
     resources: ResourceTracker,
     maps: Rc<RefCell<Vec<(i32, Pointee)>>>,
+    kfuncs: Rc<RefCell<Vec<(i32, KfuncPrototype)>>>,
+    kfunc_table: StaticHelpers,
+    /// Regions of kernel structs by type ids, see [BranchState::set_kernel_structs]
+    kernel_regions: Rc<Vec<(AnyType, KernelRegion)>>,
+    /// See [BranchState::set_program_type]
+    program_type: Option<ProgType>,
+    /// See [BranchState::set_max_pointer_offset]
+    max_pointer_offset: Option<u64>,
+    /// See [BranchState::set_max_loop_iterations]
+    max_loop_iterations: Option<usize>,
+    /// Loops found in the code, see [BranchState::set_loops]
+    loops: Rc<Vec<Loop>>,
+    /// Times that the path has jumped back to each loop header since it last entered the loop
+    loop_iterations: BTreeMap<CodeOffset, usize>,
+    /// Entries of subprograms, indexed by [TrackedValue::FuncPointer]
+    subprograms: Rc<Vec<CodeOffset>>,
+    /// Maximum stack depth of each function, recorded when their frames get popped
+    stack_depths: Vec<usize>,
+    /// A callback (entry and arguments) to call right after the current helper returns
+    scheduled_callback: Option<(CodeOffset, [CheckedValue; 5])>,
+    /// Call trace lengths right after entering callbacks, marking callback frames
+    callback_frames: Vec<usize>,
+    /// Pruned branches, shared by all branches forked from the same initial state
+    pruned: Rc<RefCell<BTreeSet<PruneReason>>>,
 }
 
 impl InnerState {
+    /// Tracks a region with a freshly generated id
+    ///
+    /// Ids only grow, keeping `regions` sorted by ids, that is, by their creation order,
+    /// which lookups, clones and [BranchState::for_each_region] rely on.
+    fn push_region(&mut self, region: Pointee) {
+        if let Some(Ok(last)) = self.regions.last().map(|last| last.try_borrow()) {
+            debug_assert!(last.get_id() < region.borrow().get_id());
+        }
+        self.regions.push(region);
+    }
+
+    /// Generates a frame pointer (`r10`), pointing to the end of the current stack
     pub(super) fn gen_stack_pointer(&self) -> Pointer {
-        Pointer::nrwa(self.stack.clone())
+        let mut frame = Pointer::nrwa(self.stack.clone());
+        frame += &Scalar::constant64(STACK_SIZE as u64);
+        frame
     }
 }
 
@@ -94,6 +141,8 @@ impl BranchState {
     pub fn new(helpers: StaticHelpers, maps: Vec<(i32, MapInfo)>) -> Self {
         let mut state = InnerState {
             pc: 0,
+            instruction: 0,
+            fault: None,
             ids: IdGen::default(),
             invalid: Vec::new(),
             registers: Default::default(),
@@ -104,9 +153,21 @@ impl BranchState {
             regions: alloc::vec![EmptyRegion::instance()],
             helpers,
             maps: Rc::new(RefCell::new(Vec::new())),
+            kfuncs: Rc::new(RefCell::new(Vec::new())),
+            kfunc_table: &[],
+            kernel_regions: Rc::new(Vec::new()),
+            program_type: None,
+            max_pointer_offset: None,
+            max_loop_iterations: None,
+            loops: Rc::new(Vec::new()),
+            loop_iterations: BTreeMap::new(),
+            subprograms: Rc::new(Vec::new()),
+            stack_depths: Vec::new(),
+            scheduled_callback: None,
+            callback_frames: Vec::new(),
+            pruned: Rc::new(RefCell::new(BTreeSet::new())),
         };
-        let mut frame = state.gen_stack_pointer();
-        frame += &Scalar::constant64(512);
+        let frame = state.gen_stack_pointer();
         *state.registers[10].inner_mut() = Some(TrackedValue::Pointer(frame));
         let id = state.resources.external(&mut state.ids);
         debug_assert!(id == 1);
@@ -116,13 +177,20 @@ impl BranchState {
         let map_fds = state.maps.clone();
         let mut state_maps = map_fds.borrow_mut();
         for (fd, info) in maps {
-            let map = pointed(SimpleMap::new(
+            let mut map = SimpleMap::new(
                 info.map_type,
                 info.max_size as usize,
                 info.key_size as usize,
                 info.value_size as usize,
-            ));
-            state_maps.push((fd, map));
+                info.readonly,
+            );
+            if let Some(value) = info.initial_value {
+                map.set_initial_value(value);
+            }
+            if let Some(program_type) = info.program_type {
+                map.set_program_type(program_type);
+            }
+            state_maps.push((fd, pointed(map)));
         }
 
         let mut vm = Self(UnsafeCell::new(state));
@@ -132,23 +200,52 @@ impl BranchState {
         vm
     }
 
+    /// Creates a new machine state with extra regions supplied by the runtime
+    ///
+    /// The regions are tracked as external resources (see [BranchState::add_external_resource]),
+    /// so that pointers to them can be set up in registers afterwards.
+    pub fn with_regions(
+        helpers: StaticHelpers,
+        maps: Vec<(i32, MapInfo)>,
+        extra_regions: Vec<Pointee>,
+    ) -> Self {
+        let mut vm = Self::new(helpers, maps);
+        for region in extra_regions {
+            vm.add_external_resource(region);
+        }
+        vm
+    }
+
     fn get_stack(&self) -> Pointee {
         self.inner().stack.clone()
     }
 
-    fn get_region(&self, id: Id, stack_id: Id, borrowed: Option<Id>) -> Pointee {
-        if id == stack_id {
-            self.get_stack()
-        } else {
-            let borrowed_id = borrowed.unwrap_or(0);
-            let index = self.inner().regions.binary_search_by(|r| {
-                r.try_borrow()
-                    .map(|r| r.get_id())
-                    .unwrap_or(borrowed_id)
-                    .cmp(&id)
-            });
-            self.inner().regions[index.unwrap()].clone()
+    /// Looks up a region (or a stack frame) by its id
+    ///
+    /// `borrowed` is the id of the region that is currently mutably borrowed, if any.
+    fn get_region(&self, id: Id, borrowed: Option<Id>) -> Pointee {
+        let borrowed_id = borrowed.unwrap_or(0);
+        let id_of = |r: &Pointee| r.try_borrow().map(|r| r.get_id()).unwrap_or(borrowed_id);
+        let inner = self.inner();
+        if let Some(frame) = self.frames().find(|frame| id_of(frame) == id) {
+            return frame.clone();
         }
+        let index = inner.regions.binary_search_by(|r| id_of(r).cmp(&id));
+        inner.regions[index.unwrap()].clone()
+    }
+
+    /// Calls `action` on each tracked region (excluding the outermost stack frame),
+    /// in the order of their ids, which is also the order they are created
+    pub fn for_each_region(&self, mut action: impl FnMut(&Pointee)) {
+        for region in &self.inner().regions {
+            action(region);
+        }
+    }
+
+    /// Iterates through the stack frames, starting from the current one
+    fn frames(&self) -> impl Iterator<Item = &Pointee> {
+        let inner = self.inner();
+        core::iter::once(&inner.stack).chain(inner.call_trace.iter().rev().map(|c| &c.stack))
     }
 
     /// Starts tracking this resource,
@@ -157,7 +254,166 @@ impl BranchState {
         let inner = self.inner_mut();
         let id = inner.resources.external(&mut inner.ids);
         region.borrow_mut().set_id(id);
-        inner.regions.push(region);
+        inner.push_region(region);
+    }
+
+    /// Starts tracking a map value region as an external resource
+    ///
+    /// Map values no longer referenced from callee-saved registers (`r6 ~ r10`),
+    /// stack frames or other regions are not counted as live any more.
+    /// It returns `false` if the live map values exceed
+    /// [BranchState::set_max_live_map_values] after adding this one.
+    pub fn add_map_value_resource(&mut self, region: Pointee) -> bool {
+        let referenced = RefCell::new(Vec::new());
+        let collector = |id| {
+            referenced.borrow_mut().push(id);
+            None
+        };
+        let inner = self.inner_mut();
+        let registers = inner.registers[6..]
+            .iter()
+            .chain(inner.call_trace.iter().flat_map(|c| c.registers.iter()));
+        for reg in registers {
+            if let Some(TrackedValue::Pointer(p)) = reg.inner() {
+                referenced.borrow_mut().push(p.get_pointing_to());
+            }
+        }
+        inner.stack.borrow_mut().redirects(&collector);
+        for caller in &inner.call_trace {
+            caller.stack.borrow_mut().redirects(&collector);
+        }
+        for region in &inner.regions {
+            // The map being looked up is borrowed
+            if let Ok(mut region) = region.try_borrow_mut() {
+                region.redirects(&collector);
+            }
+        }
+        let referenced = referenced.into_inner();
+        inner
+            .resources
+            .release_map_values(|id| referenced.contains(&id));
+
+        let id = inner.resources.map_value(&mut inner.ids);
+        region.borrow_mut().set_id(id);
+        inner.push_region(region);
+        !inner.resources.is_map_value_limit_exceeded()
+    }
+
+    /// Limits the count of live map values, see [BranchState::add_map_value_resource]
+    pub fn set_max_live_map_values(&mut self, limit: Option<usize>) {
+        self.inner_mut().resources.set_max_map_values(limit);
+    }
+
+    /// Sets the type of the program, checked against program arrays on tail calls
+    pub fn set_program_type(&mut self, program_type: Option<ProgType>) {
+        self.inner_mut().program_type = program_type;
+    }
+
+    /// Returns the type of the program, `None` if unknown
+    pub fn program_type(&self) -> Option<ProgType> {
+        self.inner().program_type
+    }
+
+    /// Limits scalars added to (or subtracted from) pointers to `-limit..=limit`
+    pub fn set_max_pointer_offset(&mut self, limit: Option<u64>) {
+        self.inner_mut().max_pointer_offset = limit;
+    }
+
+    /// Limits the times that a path may jump back to a loop header before leaving the loop
+    pub fn set_max_loop_iterations(&mut self, limit: Option<usize>) {
+        self.inner_mut().max_loop_iterations = limit;
+    }
+
+    /// Sets the loops to check against [BranchState::set_max_loop_iterations]
+    pub fn set_loops(&mut self, loops: Vec<Loop>) {
+        self.inner_mut().loops = Rc::new(loops);
+    }
+
+    /// Counts the iterations of the loops that the jump at `from` stays in
+    ///
+    /// Jumping back to a loop header loops once more, invalidating the branch if it exceeds
+    /// [BranchState::set_max_loop_iterations]. Jumping out of the loop body resets the count.
+    fn count_loop_iterations(&mut self, from: CodeOffset) {
+        let Some(limit) = self.inner().max_loop_iterations else {
+            return;
+        };
+        let loops = self.inner().loops.clone();
+        for l in loops.iter().filter(|l| l.contains(from)) {
+            let inner = self.inner_mut();
+            if !l.contains(inner.pc) {
+                inner.loop_iterations.remove(&l.header);
+                continue;
+            } else if inner.pc != l.header {
+                continue;
+            }
+            let count = inner.loop_iterations.entry(l.header).or_default();
+            *count += 1;
+            let count = *count;
+            let converges = count <= limit
+                && match l.induction {
+                    Some(induction) if count == 1 => self.converges(&induction, limit - 1),
+                    _ => true,
+                };
+            if !converges {
+                self.invalidate("Loop does not terminate within the iteration limit");
+                return;
+            }
+        }
+    }
+
+    /// Checks if a counted loop exits within `remaining` more iterations, starting
+    /// from the loop header
+    ///
+    /// Instead of running the whole loop, it narrows the induction variable with
+    /// [Comparable] iteration by iteration, until the comparison can no longer continue
+    /// the loop.
+    fn converges(&self, induction: &InductionVariable, remaining: usize) -> bool {
+        let Some(TrackedValue::Scalar(mut value)) =
+            self.ro_reg(induction.register).inner().cloned()
+        else {
+            return true;
+        };
+        type Compare = fn(&mut Scalar, &mut Scalar, u8) -> ComparisonResult<Scalar>;
+        let (compare, negated): (Compare, bool) = match induction.comparison & BPF_OPCODE_JMP_MASK {
+            BPF_JEQ => (Comparable::eq, false),
+            BPF_JNE => (Comparable::eq, true),
+            BPF_JSET => (Comparable::set, false),
+            BPF_JLT => (Comparable::lt, false),
+            BPF_JGE => (Comparable::lt, true),
+            BPF_JLE => (Comparable::le, false),
+            BPF_JGT => (Comparable::le, true),
+            BPF_JSLT => (Comparable::slt, false),
+            BPF_JSGE => (Comparable::slt, true),
+            BPF_JSLE => (Comparable::sle, false),
+            BPF_JSGT => (Comparable::sle, true),
+            _ => return true,
+        };
+        let width = if induction.comparison & BPF_OPCODE_CLASS_MASK == BPF_JMP32 {
+            32
+        } else {
+            64
+        };
+        // Whether the loop goes on when the comparison yields `true`
+        let continues_if_true = induction.continues_if_taken != negated;
+        let step = Scalar::constant64(induction.step as u64);
+        for _ in 0..=remaining {
+            if induction.steps_first {
+                value += &step;
+            }
+            let mut bound = Scalar::constant64(induction.bound as i64 as u64);
+            match compare(&mut value, &mut bound, width) {
+                ComparisonResult::Always if !continues_if_true => return true,
+                ComparisonResult::Never if continues_if_true => return true,
+                ComparisonResult::Perhaps((otherwise, _)) if !continues_if_true => {
+                    value = otherwise
+                }
+                _ => {}
+            }
+            if !induction.steps_first {
+                value += &step;
+            }
+        }
+        false
     }
 
     /// Marks an external resource as unavailable.
@@ -176,7 +432,12 @@ impl BranchState {
         let inner = self.inner_mut();
         let id = inner.resources.allocate(&mut inner.ids);
         region.borrow_mut().set_id(id);
-        inner.regions.push(region);
+        inner.push_region(region);
+    }
+
+    /// Checks whether the resource is allocated by the program and not yet released
+    pub fn is_allocated_resource(&self, id: Id) -> bool {
+        self.inner().resources.is_allocated(id)
     }
 
     /// Marks an allocated resource as released.
@@ -194,19 +455,252 @@ impl BranchState {
                 }
             }
             let redirector = |i| if i == id { Some(invalid.clone()) } else { None };
-            // Redirect stack
+            // Redirect stack and caller frames
             inner.stack.borrow_mut().redirects(&redirector);
+            for caller in &mut inner.call_trace {
+                caller.stack.borrow_mut().redirects(&redirector);
+                for reg in &mut caller.registers {
+                    if let Some(TrackedValue::Pointer(p)) = reg.inner_mut() {
+                        if p.is_pointing_to(id) {
+                            p.redirect(invalid.clone());
+                        }
+                    }
+                }
+            }
             // Redirect regions
             for region in &inner.regions {
                 region.borrow_mut().redirects(&redirector);
             }
-            // TODO: Consider invalidating pointers from call_trace
             // TODO: Maybe remove that region from self.inner().regions
         } else {
             self.invalidate("Deallocating unknown resource");
         }
     }
 
+    /// Makes a kernel function (kfunc) available to the program
+    ///
+    /// It takes precedence over kfuncs from [BranchState::set_kfunc_table].
+    pub fn add_kfunc(&mut self, id: i32, prototype: KfuncPrototype) {
+        self.inner_mut().kfuncs.borrow_mut().push((id, prototype));
+    }
+
+    /// Checks whether the kfunc is available, either added or statically registered
+    pub fn has_kfunc(&self, id: i32) -> bool {
+        let inner = self.inner();
+        inner.kfuncs.borrow().iter().any(|(i, _)| *i == id)
+            || usize::try_from(id).is_ok_and(|i| i < inner.kfunc_table.len())
+    }
+
+    /// Sets the statically registered kfuncs, indexed by their BTF ids
+    pub fn set_kfunc_table(&mut self, kfuncs: StaticHelpers) {
+        self.inner_mut().kfunc_table = kfuncs;
+    }
+
+    /// Sets the layouts of kernel structs, see [crate::spec::proto::ReturnType::TypedKernelPointer]
+    ///
+    /// Regions are built from the layouts up front, leaving out invalid ones
+    /// (see [KernelRegion::new]), so that the layouts need not outlive the branch.
+    pub fn set_kernel_structs(&mut self, structs: &[KernelStruct]) {
+        let regions = structs
+            .iter()
+            .filter_map(|layout| Some((layout.type_id, KernelRegion::new(layout)?)))
+            .collect();
+        self.inner_mut().kernel_regions = Rc::new(regions);
+    }
+
+    /// Creates a region for the kernel struct with the type id, if its layout is known
+    pub fn kernel_region(&self, type_id: AnyType) -> Option<KernelRegion> {
+        let regions = &self.inner().kernel_regions;
+        let (_, region) = regions.iter().find(|(id, _)| *id == type_id)?;
+        Some(region.clone())
+    }
+
+    /// Sets the entries of subprograms, so that `BPF_IMM64_FUNC` yields a [TrackedValue::FuncPointer]
+    pub fn set_subprograms(&mut self, entries: Vec<CodeOffset>) {
+        self.inner_mut().subprograms = Rc::new(entries);
+    }
+
+    /// Records a pruned branch
+    pub fn prune(&self, reason: PruneReason) {
+        self.inner().pruned.borrow_mut().insert(reason);
+    }
+
+    /// Returns the distinct pruned branches, sorted, of this state and all related ones
+    ///
+    /// The record is shared among the initial state and all states forked from it.
+    pub fn pruned_branches(&self) -> Vec<PruneReason> {
+        self.inner().pruned.borrow().iter().copied().collect()
+    }
+
+    /// Returns the deepest written byte of the current stack frame, counting from the frame pointer
+    pub fn stack_depth(&self) -> usize {
+        Self::depth_of(&self.inner().stack)
+    }
+
+    /// Returns the maximum stack depth of each function (indexed by their order in the code)
+    ///
+    /// Depths are recorded whenever a function returns, so it only covers returned functions.
+    pub fn stack_depths(&self) -> &[usize] {
+        &self.inner().stack_depths
+    }
+
+    fn depth_of(frame: &Pointee) -> usize {
+        match frame.borrow_mut().inner() {
+            InnerRegion::Stack(stack) => stack.depth(),
+            _ => 0,
+        }
+    }
+
+    /// Records the depth of the current frame, before it gets popped
+    fn record_stack_depth(&mut self) {
+        let inner = self.inner_mut();
+        let depth = Self::depth_of(&inner.stack);
+        // `pc` points to the instruction after `exit`
+        let function = inner
+            .subprograms
+            .partition_point(|entry| *entry < inner.pc)
+            .saturating_sub(1);
+        if function >= inner.stack_depths.len() {
+            inner.stack_depths.resize(function + 1, 0);
+        }
+        inner.stack_depths[function] = inner.stack_depths[function].max(depth);
+    }
+
+    /// Checks whether this state is subsumed by `other`, so that exploring
+    /// `other` covers all that this state may do
+    ///
+    /// Both should be at the same instruction with the same resources and call trace.
+    /// Registers and stack frames get compared with [CheckedValue::is_subset_of]
+    /// and [StackRegion::is_subset_of], while other regions get compared with
+    /// [MemoryRegion::is_subset_of].
+    pub fn is_subset_of(&self, other: &BranchState) -> bool {
+        let (this, that) = (self.inner(), other.inner());
+        if this.pc != that.pc
+            || !self.is_valid()
+            || !other.is_valid()
+            || this.resources != that.resources
+            || this.call_trace.len() != that.call_trace.len()
+            || this.callback_frames != that.callback_frames
+            || this.regions.len() != that.regions.len()
+        {
+            return false;
+        }
+        let registers =
+            |a: &[CheckedValue], b: &[CheckedValue]| zip(a, b).all(|(v, w)| v.is_subset_of(w));
+        let depths = || {
+            this.stack_depths.len() <= that.stack_depths.len()
+                && zip(&this.stack_depths, &that.stack_depths).all(|(a, b)| a <= b)
+        };
+        let callers = || {
+            zip(&this.call_trace, &that.call_trace)
+                .all(|(a, b)| a.pc == b.pc && registers(&a.registers, &b.registers))
+        };
+        let frames = || {
+            zip(self.frames(), other.frames()).all(|(a, b)| {
+                match (a.borrow_mut().inner(), b.borrow_mut().inner()) {
+                    (InnerRegion::Stack(a), InnerRegion::Stack(b)) => a.is_subset_of(b),
+                    _ => false,
+                }
+            })
+        };
+        let regions = || {
+            zip(&this.regions, &that.regions).all(|(a, b)| {
+                let (mut a, mut b) = (a.borrow_mut(), b.borrow_mut());
+                // Stack frames are compared along with the call trace
+                let frames = matches!(
+                    (a.inner(), b.inner()),
+                    (InnerRegion::Stack(_), InnerRegion::Stack(_))
+                );
+                a.get_id() == b.get_id() && (frames || a.is_subset_of(&mut *b))
+            })
+        };
+        depths()
+            && registers(&this.registers, &that.registers)
+            && callers()
+            && frames()
+            && regions()
+    }
+
+    /// Checks whether a register holds a scalar in one state and a pointer in the other
+    ///
+    /// It is meant for states at a loop header, with `earlier` from a previous iteration.
+    /// Only registers of the current frame in `live` (bit `i` standing for `ri`)
+    /// that are initialized in both states are compared.
+    pub fn has_register_type_change(&self, earlier: &BranchState, live: u16) -> bool {
+        let (this, that) = (self.inner(), earlier.inner());
+        if this.call_trace.len() != that.call_trace.len() {
+            return false;
+        }
+        let is_pointer = |value: &CheckedValue| match value.inner() {
+            Some(TrackedValue::Pointer(_)) => Some(true),
+            Some(TrackedValue::Scalar(_)) => Some(false),
+            _ => None,
+        };
+        zip(&this.registers, &that.registers)
+            .enumerate()
+            .filter(|(i, _)| live & (1 << i) != 0)
+            .any(|(_, (a, b))| {
+                matches!((is_pointer(a), is_pointer(b)), (Some(a), Some(b)) if a != b)
+            })
+    }
+
+    /// Schedules a callback, called with `args` (as `r1` ~ `r5`) right after the helper returns
+    ///
+    /// The callback returns to the instruction after the helper call, where
+    /// `r0` becomes an unknown scalar. The callback must return either `0` or `1`.
+    /// It returns `false` if `function` is not a [TrackedValue::FuncPointer].
+    pub fn schedule_callback(&mut self, function: &CheckedValue, args: [CheckedValue; 5]) -> bool {
+        let inner = self.inner_mut();
+        match function.inner() {
+            Some(TrackedValue::FuncPointer(index)) if *index < inner.subprograms.len() => {
+                inner.scheduled_callback = Some((inner.subprograms[*index], args));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Enters a scheduled callback, as if it were called by a `call` instruction
+    fn call_callback(&mut self, entry: CodeOffset, args: [CheckedValue; 5]) {
+        let offset = entry as isize - self.inner().pc as isize;
+        self.call_relative(offset as i32);
+        let inner = self.inner_mut();
+        inner.callback_frames.push(inner.call_trace.len());
+        for (i, arg) in args.into_iter().enumerate() {
+            inner.registers[i + 1] = arg;
+        }
+    }
+
+    /// Acquires the (only) spin lock, returning `false` if it is already held
+    pub fn lock(&mut self) -> bool {
+        self.inner_mut().resources.lock()
+    }
+
+    /// Releases the spin lock, returning `false` if it is not held
+    pub fn unlock(&mut self) -> bool {
+        self.inner_mut().resources.unlock()
+    }
+
+    /// Checks if the program is holding a spin lock
+    pub fn is_locked(&self) -> bool {
+        self.inner().resources.is_locked()
+    }
+
+    /// Marks the `bpf_timer` at `offset` in the region as initialized
+    pub fn init_timer(&mut self, region: Id, offset: u64) {
+        self.inner_mut().resources.init_timer(region, offset)
+    }
+
+    /// Checks if the `bpf_timer` at `offset` in the region is initialized
+    pub fn is_timer_initialized(&self, region: Id, offset: u64) -> bool {
+        self.inner().resources.is_timer_initialized(region, offset)
+    }
+
+    /// Checks if the region is a map value that is still available
+    pub fn is_map_value(&self, region: Id) -> bool {
+        self.inner().resources.is_map_value(region)
+    }
+
     /// Returns `true` if the register is a pointer
     /// and it points to a non-existing resource
     pub fn is_invalid_resource(&self, i: u8) -> bool {
@@ -223,6 +717,13 @@ impl BranchState {
         &self.inner().invalid
     }
 
+    /// Returns the instruction where the state got invalidated,
+    /// or the last executed one if it is still valid
+    pub fn fault_pc(&self) -> CodeOffset {
+        let inner = self.inner();
+        inner.fault.unwrap_or(inner.instruction)
+    }
+
     fn inner(&self) -> &InnerState {
         safe_ref_unsafe_cell(&self.0)
     }
@@ -231,9 +732,31 @@ impl BranchState {
         self.0.get_mut()
     }
 
+    /// Calls a helper function or a kfunc, setting `r0` and clobbering `r1 ~ r5`
+    fn call_function(&mut self, function: &dyn VerifiableCall<CheckedValue, BranchState>) {
+        if self.is_locked() && !function.allowed_while_locked() {
+            self.invalidate("Helper call while holding a lock");
+        } else if let Ok(v) = function.call(self) {
+            *self.reg(0) = v;
+            if !self.is_valid() {
+                // Keep r1~r5 for debugging
+                return;
+            }
+            for i in 1..=5 {
+                *self.reg(i) = CheckedValue::default();
+            }
+            if let Some((entry, args)) = self.inner_mut().scheduled_callback.take() {
+                self.call_callback(entry, args);
+            }
+        } else {
+            self.invalidate("Function call failed");
+        }
+    }
+
     pub(super) fn update_pointers(&mut self, pointer: &mut Pointer) {
+        let id = pointer.get_pointing_to();
         if let InnerRegion::Stack(stack) = self.get_stack().borrow_mut().inner() {
-            stack.update_pointers(pointer);
+            stack.update_pointers(id);
         }
     }
 }
@@ -244,43 +767,88 @@ impl Clone for BranchState {
     /// The underlying memory regions are duplicated,
     /// making the pointer in the generated state independent of the cloned one.
     fn clone(&self) -> Self {
-        let mut regions = Vec::new();
         let inner = self.inner();
-        regions.reserve(inner.regions.len());
+        let mut regions = Vec::with_capacity(inner.regions.len());
         for region in &inner.regions {
             regions.push(region.borrow().safe_clone());
         }
+        // Frames of called functions are also tracked as regions,
+        // and the cloned frames should be the very ones in `regions`.
+        let clone_frame = |frame: &Pointee| {
+            let id = frame.borrow().get_id();
+            match regions.binary_search_by(|r| r.borrow().get_id().cmp(&id)) {
+                Ok(index) => regions[index].clone(),
+                Err(_) => frame.borrow().safe_clone(),
+            }
+        };
+        let stack = clone_frame(&inner.stack);
+        let call_trace = inner
+            .call_trace
+            .iter()
+            .map(|caller| CallerContext {
+                pc: caller.pc,
+                registers: caller.registers.clone(),
+                stack: clone_frame(&caller.stack),
+            })
+            .collect();
         let mut another = Self(UnsafeCell::new(InnerState {
             pc: inner.pc,
+            instruction: inner.instruction,
+            fault: inner.fault,
             ids: inner.ids.clone(),
             invalid: inner.invalid.clone(),
             registers: Default::default(),
             temp_reg: inner.temp_reg.clone(),
-            call_trace: inner.call_trace.clone(),
-            stack: inner.stack.borrow().safe_clone(),
+            call_trace,
+            stack,
             resources: inner.resources.clone(),
             regions,
             helpers: inner.helpers,
             maps: inner.maps.clone(),
+            kfuncs: inner.kfuncs.clone(),
+            kfunc_table: inner.kfunc_table,
+            kernel_regions: inner.kernel_regions.clone(),
+            program_type: inner.program_type,
+            max_pointer_offset: inner.max_pointer_offset,
+            max_loop_iterations: inner.max_loop_iterations,
+            loops: inner.loops.clone(),
+            loop_iterations: inner.loop_iterations.clone(),
+            subprograms: inner.subprograms.clone(),
+            stack_depths: inner.stack_depths.clone(),
+            // Branches never fork within helper calls
+            scheduled_callback: None,
+            callback_frames: inner.callback_frames.clone(),
+            pruned: inner.pruned.clone(),
         }));
-        let stack_id = inner.stack.borrow_mut().get_id();
-        another
-            .inner()
-            .stack
+        // The outermost frame is the only one not in `regions`
+        let outermost = another.frames().last().unwrap();
+        let outermost_id = outermost.borrow().get_id();
+        outermost
             .borrow_mut()
-            .redirects(&|i| Some(another.get_region(i, stack_id, None)));
+            .redirects(&|i| Some(another.get_region(i, Some(outermost_id))));
         for region in &another.inner().regions {
             let mut borrow = region.borrow_mut();
             let id = borrow.get_id();
-            let redirector = |i| Some(another.get_region(i, stack_id, Some(id)));
+            let redirector = |i| Some(another.get_region(i, Some(id)));
             borrow.redirects(&redirector);
         }
-        for (i, register) in inner.registers.iter().enumerate() {
+        let redirect = |register: &CheckedValue| {
             let mut v = register.clone();
             if let Some(TrackedValue::Pointer(ref mut p)) = v.inner_mut() {
-                p.redirect(another.get_region(p.get_pointing_to(), stack_id, None));
+                p.redirect(another.get_region(p.get_pointing_to(), None));
             }
-            another.inner_mut().registers[i] = v;
+            v
+        };
+        let registers = inner.registers.each_ref().map(redirect);
+        let saved = inner
+            .call_trace
+            .iter()
+            .map(|caller| caller.registers.each_ref().map(redirect))
+            .collect::<Vec<_>>();
+        let another_inner = another.inner_mut();
+        another_inner.registers = registers;
+        for (caller, registers) in another_inner.call_trace.iter_mut().zip(saved) {
+            caller.registers = registers;
         }
         another
     }
@@ -294,7 +862,9 @@ impl Vm<CheckedValue> for BranchState {
     fn invalidate(&self, message: &'static str) {
         unsafe {
             // Safe since we are single-threaded and only invalidating things
-            (*self.0.get()).invalid.push(message.to_string());
+            let inner = &mut *self.0.get();
+            inner.fault.get_or_insert(inner.instruction);
+            inner.invalid.push(message.to_string());
         }
     }
 
@@ -365,23 +935,27 @@ impl Vm<CheckedValue> for BranchState {
         if helper <= 0 {
             self.invalidate("Invalid helper id");
         } else if let Some(helper) = self.inner().helpers.get(helper as usize) {
-            if let Ok(v) = helper.call(self) {
-                *self.reg(0) = v;
-                if !self.is_valid() {
-                    // Keep r1~r5 for debugging
-                    return;
-                }
-                for i in 1..=5 {
-                    *self.reg(i) = CheckedValue::default();
-                }
-            } else {
-                self.invalidate("Function call failed");
-            }
+            self.call_function(*helper);
         } else {
             self.invalidate("Invalid helper id");
         }
     }
 
+    fn call_kfunc(&mut self, imm: i32) {
+        let kfuncs = self.inner().kfuncs.clone();
+        let kfuncs = kfuncs.borrow();
+        if let Some((_, kfunc)) = kfuncs.iter().find(|(id, _)| *id == imm) {
+            self.call_function(kfunc);
+        } else if let Some(kfunc) = usize::try_from(imm)
+            .ok()
+            .and_then(|i| self.inner().kfunc_table.get(i))
+        {
+            self.call_function(*kfunc);
+        } else {
+            self.invalidate("Kfunc not available");
+        }
+    }
+
     fn call_relative(&mut self, imm: i32) {
         let inner = self.inner_mut();
         inner.call_trace.push(CallerContext {
@@ -405,19 +979,38 @@ impl Vm<CheckedValue> for BranchState {
     }
 
     fn return_relative(&mut self) -> bool {
+        self.record_stack_depth();
         let id = self.inner().stack.borrow_mut().get_id();
         self.remove_external_resource(id);
         let inner = self.inner_mut();
+        if inner.callback_frames.last() == Some(&inner.call_trace.len()) {
+            inner.callback_frames.pop();
+            if !matches!(
+                inner.registers[0].inner(),
+                Some(TrackedValue::Scalar(s)) if *s.unsigned_range().end() <= 1
+            ) {
+                self.invalidate("Callback return value out of range");
+            }
+            // The return value of the helper
+            *self.reg(0) = Scalar::unknown().into();
+        }
+        let inner = self.inner_mut();
         if let Some(caller) = inner.call_trace.pop() {
             inner.pc = caller.pc;
             inner.stack = caller.stack.clone();
             inner.registers[10] = inner.gen_stack_pointer().into();
+            // r0 is the return value, r1~r5 are caller-saved and r6~r9 callee-saved
+            for i in 1..=5 {
+                inner.registers[i] = CheckedValue::default();
+            }
             for i in 6..=9 {
                 inner.registers[i] = caller.registers[i - 6].clone();
             }
             true
         } else {
-            if !self.inner().resources.is_empty() {
+            if self.is_locked() {
+                self.invalidate("Lock not released");
+            } else if !self.inner().resources.is_empty() {
                 self.invalidate("Resource not cleaned up");
             }
             false
@@ -458,9 +1051,49 @@ impl Vm<CheckedValue> for BranchState {
                 }
                 None
             }
+            BPF_IMM64_FUNC => {
+                // `pc` points to the second half of the wide instruction
+                let inner = self.inner();
+                let target = inner.pc.wrapping_add_signed(insn.imm as isize);
+                let index = inner
+                    .subprograms
+                    .iter()
+                    .position(|entry| *entry == target)?;
+                Some(TrackedValue::FuncPointer(index).into())
+            }
             _ => None,
         }
     }
+
+    fn check_pointer_arithmetic(&self, insn: &Instruction) {
+        let Some(limit) = self.inner().max_pointer_offset else {
+            return;
+        };
+        let constant;
+        let src = if insn.opcode & BPF_OPCODE_SRC_MASK == BPF_X {
+            self.ro_reg(insn.src_reg()).inner()
+        } else {
+            constant = TrackedValue::Scalar(Scalar::constant64(insn.imm as i64 as u64));
+            Some(&constant)
+        };
+        let scalar = match (self.ro_reg(insn.dst_reg()).inner(), src) {
+            (Some(TrackedValue::Pointer(_)), Some(TrackedValue::Scalar(s)))
+            | (Some(TrackedValue::Scalar(s)), Some(TrackedValue::Pointer(_))) => s,
+            _ => return,
+        };
+        let range = scalar.signed_range();
+        if range.start().unsigned_abs() > limit || range.end().unsigned_abs() > limit {
+            self.invalidate("Pointer offset out of window");
+        }
+    }
+
+    fn executing(&mut self, pc: usize) {
+        self.inner_mut().instruction = pc;
+    }
+
+    fn jumped(&mut self, from: usize) {
+        self.count_loop_iterations(from);
+    }
 }
 
 impl Debug for BranchState {
@@ -472,6 +1105,11 @@ impl Debug for BranchState {
         f.write_fmt(format_args!("  pc:    {}\n", self.inner().pc))?;
         f.write_fmt(format_args!("  regs:  {:?}\n", self.inner().registers))?;
         f.write_fmt(format_args!("  stack: {:?}\n", self.inner().stack))?;
+        let mut regions = Vec::new();
+        self.for_each_region(|region| {
+            regions.push(region.try_borrow().map_or(0, |region| region.get_id()));
+        });
+        f.write_fmt(format_args!("  regions: {regions:?}\n"))?;
         f.write_fmt(format_args!("}} // End BranchState\n"))
     }
 }
@@ -516,6 +1154,43 @@ fn test_clone_or_not(clone: bool) {
     }
 }
 
+#[test]
+pub fn test_kfunc_out_of_range() {
+    use crate::spec::proto::helpers::BPF_HELPER_GET_SCALAR;
+    let mut vm = BranchState::new(&[], Vec::new());
+    vm.set_kfunc_table(&[BPF_HELPER_GET_SCALAR]);
+    vm.call_kfunc(0);
+    assert!(vm.is_valid());
+    assert!(vm.ro_reg(0).is_valid());
+    vm.call_kfunc(1);
+    assert!(!vm.is_valid());
+    assert_eq!(vm.messages(), ["Kfunc not available"]);
+    let mut vm = BranchState::new(&[], Vec::new());
+    vm.call_kfunc(-1);
+    assert!(!vm.is_valid());
+}
+
+#[test]
+pub fn test_with_regions() {
+    use crate::{
+        interpreter::value::Dereference,
+        track::pointees::{dyn_region::DynamicRegion, pointed},
+    };
+    let region = pointed(DynamicRegion::new(8));
+    let mut vm = BranchState::with_regions(&[], Vec::new(), alloc::vec![region.clone()]);
+    // The stack comes first
+    assert_eq!(region.borrow().get_id(), 2);
+
+    let setup = |vm: &mut BranchState| *vm.reg(6) = Pointer::nrwa(region.clone()).into();
+    setup(&mut vm);
+    let value = unsafe { vm.ro_reg(6).get_at(4, 4) };
+    assert!(matches!(
+        value.as_ref().and_then(|v| v.inner()),
+        Some(TrackedValue::Scalar(_))
+    ));
+    assert!(unsafe { vm.ro_reg(6).get_at(8, 4) }.is_none());
+}
+
 #[test]
 pub fn test_no_clone() {
     test_clone_or_not(false);
@@ -525,3 +1200,47 @@ pub fn test_no_clone() {
 pub fn test_cloned() {
     test_clone_or_not(true);
 }
+
+#[test]
+pub fn test_return_relative_registers() {
+    let value = |vm: &BranchState, i: u8| match vm.ro_reg(i).inner() {
+        Some(TrackedValue::Scalar(s)) => s.value64(),
+        _ => None,
+    };
+    let mut vm = BranchState::new(&[], Vec::new());
+    for i in 0..=9 {
+        *vm.reg(i) = Scalar::constant64(100 + i as u64).into();
+    }
+    vm.call_relative(4);
+    assert_eq!(*vm.pc(), 4);
+    for i in 1..=5 {
+        assert_eq!(value(&vm, i), Some(100 + i as u64));
+    }
+    for i in 6..=9 {
+        assert!(!vm.ro_reg(i).is_valid());
+    }
+
+    // The callee messes with every register
+    for i in 0..=9 {
+        *vm.reg(i) = Scalar::constant64(i as u64).into();
+    }
+    *vm.reg(0) = Scalar::constant64(42).into();
+    assert!(vm.return_relative());
+    assert!(vm.is_valid());
+    assert_eq!(*vm.pc(), 0);
+    assert_eq!(value(&vm, 0), Some(42));
+    for i in 1..=5 {
+        assert!(!vm.ro_reg(i).is_valid());
+    }
+    for i in 6..=9 {
+        assert_eq!(value(&vm, i), Some(100 + i as u64));
+    }
+    assert!(matches!(
+        vm.ro_reg(10).inner(),
+        Some(TrackedValue::Pointer(p)) if p.is_pointing_to(vm.get_stack().borrow().get_id())
+    ));
+
+    // Top-level exits leave registers alone
+    assert!(!vm.return_relative());
+    assert_eq!(value(&vm, 0), Some(42));
+}
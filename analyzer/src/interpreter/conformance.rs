@@ -0,0 +1,83 @@
+//! An in-tree conformance harness, see [run_case]
+//!
+//! Unlike the external [bpf_conformance](https://github.com/Alan-Jowett/bpf_conformance) runner,
+//! it needs neither LLVM nor `std`: the assembly is parsed with [bpfasm]
+//! and run on [UncheckedVm].
+
+use core::{cell::RefCell, num::Wrapping};
+
+use bpfasm::ParseError;
+
+use super::{
+    context::NoOpContext,
+    helper::HelperCollection,
+    run_with_budget,
+    vm::{UncheckedVm, Vm},
+};
+
+/// Maximum number of instructions executed for a case
+pub const CONFORMANCE_BUDGET: usize = 1_000_000;
+
+/// Why a conformance case fails
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConformanceError {
+    /// The assembly cannot be parsed
+    Parse(ParseError),
+    /// The VM gets invalidated with the message
+    Invalid(&'static str),
+    /// `r0` is not the expected value, with the actual one
+    Mismatch(u64),
+}
+
+/// Assembles and runs a conformance case, checking `r0` on exit
+///
+/// As is with bpf_conformance, `r1` points to `memory` and `r2` holds its length.
+///
+/// # Safety
+///
+/// The program runs unchecked on real memory. Callers must make sure that it only
+/// accesses `memory` (within `r1 ~ r1 + r2`) and its own stack, as the conformance
+/// cases do, or it may read or write arbitrary memory.
+pub unsafe fn run_case(
+    asm: &str,
+    memory: &mut [u8],
+    expected: u64,
+) -> Result<(), ConformanceError> {
+    let code = bpfasm::parse(asm).map_err(ConformanceError::Parse)?;
+    let vm = RefCell::new(UncheckedVm::<Wrapping<u64>>::new(HelperCollection::new(&[])));
+    let mut vm = vm.borrow_mut();
+    vm.reg(1).0 = memory.as_mut_ptr() as u64;
+    vm.reg(2).0 = memory.len() as u64;
+    run_with_budget(&code, &mut vm, &mut NoOpContext {}, CONFORMANCE_BUDGET);
+    if let Some(message) = vm.message() {
+        return Err(ConformanceError::Invalid(message));
+    }
+    match vm.ro_reg(0).0 {
+        r0 if r0 == expected => Ok(()),
+        r0 => Err(ConformanceError::Mismatch(r0)),
+    }
+}
+
+#[test]
+fn test_run_case() {
+    // Safety: the cases only access the memory passed in
+    unsafe {
+        assert_eq!(run_case("mov r0, 1\nadd r0, 2\nexit", &mut [], 3), Ok(()));
+        assert_eq!(
+            run_case("mov r0, 1\nexit", &mut [], 3),
+            Err(ConformanceError::Mismatch(1))
+        );
+        assert_eq!(
+            run_case("ldxh r0, [r1+1]\nexit", &mut [1, 2, 3], 0x0302),
+            Ok(())
+        );
+        assert!(matches!(
+            run_case("mov r0, 1\nfoo", &mut [], 1),
+            Err(ConformanceError::Parse(_))
+        ));
+        assert_eq!(
+            run_case("ja -1\nexit", &mut [], 0),
+            Err(ConformanceError::Invalid("Instruction budget exhausted"))
+        );
+    }
+}
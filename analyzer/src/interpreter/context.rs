@@ -1,16 +1,19 @@
 //! This module defines trais used by the interpreter:
 //! - [VmContext]
 //! - [Forker]
+//! - [AccessObserver]
 //!
 //! Also, simplistic implementations are provided for [Wrapping<u64>].
 
-use core::{cell::RefCell, num::Wrapping};
+use core::{cell::RefCell, num::Wrapping, ops::RangeInclusive};
 
 use alloc::rc::Rc;
 
+use crate::track::id::Id;
+
 use super::{
     value::{Verifiable, VmValue},
-    vm::{UncheckedVm, Vm},
+    vm::{ConcreteVm, UncheckedVm, Vm},
 };
 
 /// Execution context for a VM, designed for verifier branch tracking
@@ -19,6 +22,25 @@ pub trait VmContext<Value: VmValue, V: Vm<Value>>: Verifiable {
     fn add_pending_branch(&mut self, vm: Rc<RefCell<V>>);
     /// Tracks total instructions executed
     fn increment_pc(&mut self);
+    /// Returns the observer to notify of memory accesses, if any
+    fn observer(&self) -> Option<&dyn AccessObserver> {
+        None
+    }
+    /// Called before the VM calls a kfunc, allowing the context to make it available
+    fn prepare_kfunc(&mut self, _vm: &mut V, _id: i32) {}
+}
+
+/// Observes memory accesses along with their address provenance
+///
+/// The interpreter notifies the observer after each successful load or store
+/// (atomic operations count as both), given the id of the accessed region
+/// and the range of possible offsets relative to the start of that region.
+/// Accesses through values without provenance info are not reported.
+pub trait AccessObserver {
+    /// Called after loading `size` bytes from a region
+    fn on_load(&self, region: Id, offset: RangeInclusive<i64>, size: usize);
+    /// Called after storing `size` bytes into a region
+    fn on_store(&self, region: Id, offset: RangeInclusive<i64>, size: usize);
 }
 
 /// A no-op context for interpreter
@@ -88,9 +110,20 @@ pub trait Forker<Value: VmValue, B: Vm<Value> + ?Sized> {
     forker_ops!(jeq, jset, jlt, jle, jslt, jsle);
 }
 
+/// Values with a concrete `u64` representation
+trait Concrete {
+    fn concrete(&self) -> u64;
+}
+
+impl Concrete for Wrapping<u64> {
+    fn concrete(&self) -> u64 {
+        self.0
+    }
+}
+
 macro_rules! fork_it {
     ($self:ident, $dst:ident, $src:ident, $fork:ident, $op:ident, $t:ident) => {{
-        *$self.pc() = if ($dst.1.0 as $t).$op(&($src.1.0 as $t)) {
+        *$self.pc() = if ($dst.1.concrete() as $t).$op(&($src.1.concrete() as $t)) {
             $fork.target
         } else {
             $fork.fall_through
@@ -100,11 +133,11 @@ macro_rules! fork_it {
 }
 
 macro_rules! impl_fork_it {
-    ($fn:ident, $op:ident, $t32:ident, $t64:ident) => {
+    ($value:ty, $fn:ident, $op:ident, $t32:ident, $t64:ident) => {
         fn $fn(
             &mut self,
-            dst: (i8, &mut Wrapping<u64>),
-            src: (i8, &mut Wrapping<u64>),
+            dst: (i8, &mut $value),
+            src: (i8, &mut $value),
             fork: Fork,
             width: u8,
         ) -> Option<Rc<RefCell<Self>>> {
@@ -117,34 +150,34 @@ macro_rules! impl_fork_it {
     };
 }
 
-impl Forker<Wrapping<u64>, Self> for UncheckedVm<Wrapping<u64>> {
-    impl_fork_it!(jeq, eq, u32, u64);
-    impl_fork_it!(jlt, lt, u32, u64);
-    impl_fork_it!(jle, le, u32, u64);
-    impl_fork_it!(jslt, lt, i32, i64);
-    impl_fork_it!(jsle, le, i32, i64);
-
-    fn jset(
-        &mut self,
-        dst: (i8, &mut Wrapping<u64>),
-        src: (i8, &mut Wrapping<u64>),
-        fork: Fork,
-        width: u8,
-    ) -> Option<Rc<RefCell<Self>>> {
-        if width == 32 {
-            *self.pc() = if (dst.1.0 as u32) & (src.1.0 as u32) != 0 {
-                fork.target
-            } else {
-                fork.fall_through
-            };
-            None
-        } else {
-            *self.pc() = if dst.1.0 & src.1.0 != 0 {
-                fork.target
-            } else {
-                fork.fall_through
-            };
-            None
+/// Implements a [Forker] that jumps depending on actual values
+macro_rules! impl_forker {
+    ($vm:ty, $value:ty) => {
+        impl Forker<$value, Self> for $vm {
+            impl_fork_it!($value, jeq, eq, u32, u64);
+            impl_fork_it!($value, jlt, lt, u32, u64);
+            impl_fork_it!($value, jle, le, u32, u64);
+            impl_fork_it!($value, jslt, lt, i32, i64);
+            impl_fork_it!($value, jsle, le, i32, i64);
+
+            fn jset(
+                &mut self,
+                dst: (i8, &mut $value),
+                src: (i8, &mut $value),
+                fork: Fork,
+                width: u8,
+            ) -> Option<Rc<RefCell<Self>>> {
+                let mask = if width == 32 { u32::MAX as u64 } else { u64::MAX };
+                *self.pc() = if dst.1.concrete() & src.1.concrete() & mask != 0 {
+                    fork.target
+                } else {
+                    fork.fall_through
+                };
+                None
+            }
         }
-    }
+    };
 }
+
+impl_forker!(UncheckedVm<Wrapping<u64>>, Wrapping<u64>);
+impl_forker!(ConcreteVm, Wrapping<u64>);
@@ -0,0 +1,421 @@
+//! The instruction dispatch of the interpreter, see [run].
+//!
+//! `dispatch_expanded.rs` is generated from this file by expanding the [opcode_match]
+//! macros, for builds without the `opcode-macros` dependency. Regenerate it with
+//! `scripts/expand-dispatch.sh` after changing this file.
+
+use core::cell::RefMut;
+
+use ebpf_consts::*;
+use opcode_macros::opcode_match;
+
+use crate::{interpreter::context::Fork, spec::Instruction};
+
+use super::{
+    context::VmContext, notify_load, notify_store, provenance_of, run_call, value::VmValue,
+    vm::Vm,
+};
+
+/// Runs (or, interprets) the code on the given VM
+pub fn run<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
+    code: &[u64],
+    vm: &mut RefMut<M>,
+    context: &mut C,
+) {
+    while vm.is_valid() && context.is_valid() {
+        context.increment_pc();
+
+        let start = *vm.pc();
+        vm.executing(start);
+        let insn = Instruction::from_raw(code[start]);
+        *vm.pc() += 1;
+        let opcode = insn.opcode;
+        opcode_match! {
+            opcode in ebpf_consts,
+            // ALU / ALU64: Binary operators
+            [[BPF_ALU: ALU32, BPF_ALU64: ALU64], [BPF_X: X, BPF_K: K],
+             [
+                // Algebraic
+                BPF_ADD: add_assign,
+                BPF_SUB: sub_assign,
+                BPF_MUL: mul_assign,
+                BPF_DIV: safe_div_assign,
+                BPF_MOD: safe_rem_assign,
+                // Bitwise
+                BPF_AND: bitand_assign,
+                BPF_OR : bitor_assign,
+                BPF_XOR: bitxor_assign,
+             ]
+            ] => bind matched {
+                #?((add_assign)|(sub_assign))
+                    vm.check_pointer_arithmetic(&insn);
+                ##
+                // Gettings the dst operant
+                let dst_r = insn.dst_reg();
+                // Gettings the src operant
+                #?((K))
+                    #?((ALU32))
+                        let src = &mut Value::constantu32(insn.imm as u32);
+                    ##
+                    #?((ALU64))
+                        let src = &mut Value::constanti32(insn.imm);
+                    ##
+                    let dst = vm.reg(dst_r);
+                ##
+                #?((X))
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                ##
+                #?((ALU32))
+                    let src = &src.lower_half();
+                ##
+
+                #?((ALU32))
+                    dst.lower_half_assign();
+                ##
+
+                #?((safe_div_assign,K)|(safe_rem_assign,K))
+                    if insn.imm == 0 {
+                        vm.invalidate("Div by 0");
+                        break;
+                    }
+                ##
+
+                // Signed division / modulus, selected by the offset
+                #?((safe_div_assign)|(safe_rem_assign))
+                    let width = width_of(matched);
+                ##
+                #?((safe_div_assign))
+                    if insn.is_signed_division() {
+                        dst.signed_div_assign(src, width);
+                    } else {
+                        dst.safe_div_assign(src);
+                    }
+                ##
+                #?((safe_rem_assign))
+                    if insn.is_signed_division() {
+                        dst.signed_rem_assign(src, width);
+                    } else {
+                        dst.safe_rem_assign(src);
+                    }
+                ##
+                #?((!safe_div_assign,!safe_rem_assign))
+                    dst.#=2(src);
+                ##
+                #?((ALU32))
+                    dst.zero_upper_half_assign();
+                ##
+                vm.update_reg(dst_r);
+            }
+            // BPF_ALU_MOV: Sign extending for BPF_K, or BPF_X with a non-zero offset
+            [[BPF_ALU: ALU32, BPF_ALU64: ALU64], [BPF_X: X, BPF_K: K],
+             [BPF_MOV: mov]
+            ] => {
+                let dst_r = insn.dst_reg();
+                #?((K))
+                    #?((ALU32))
+                        let src = &mut Value::constantu32(insn.imm as u32);
+                    ##
+                    #?((ALU64))
+                        let src = &mut Value::constanti32(insn.imm);
+                    ##
+                    let dst = vm.reg(dst_r);
+                ##
+                #?((X))
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                ##
+
+                *dst = src.clone();
+
+                #?((X))
+                    if let Some(width) = insn.is_sign_extending_move() {
+                        dst.sign_extend_assign(width);
+                    }
+                ##
+                #?((ALU32))
+                    dst.zero_upper_half_assign();
+                ##
+                vm.update_reg(dst_r);
+            }
+            // Shifts: Width aware
+            [[BPF_ALU: ALU32, BPF_ALU64: ALU64], [BPF_X: X, BPF_K: K],
+             [
+                BPF_LSH: l_shift,
+                BPF_RSH: r_shift,
+                BPF_ARSH: signed_shr
+             ]
+            ] => bind matched {
+                // Gettings the dst operant
+                let dst_r = insn.dst_reg();
+                // Gettings the src operant
+                #?((K))
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                ##
+                #?((X))
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                ##
+                let width = width_of(matched);
+
+                #?((ALU32))
+                    dst.lower_half_assign();
+                ##
+
+                dst.#=2(src, width);
+                #?((ALU32))
+                    dst.zero_upper_half_assign();
+                ##
+                vm.update_reg(dst_r);
+            }
+            // ALU / ALU64: Unary operators
+            [[BPF_ALU: ALU32, BPF_ALU64: ALU64], [BPF_K: K],
+             [
+                BPF_NEG: neg_assign,
+             ]
+            ] => {
+                let dst_r = insn.dst_reg();
+                let dst = vm.reg(dst_r);
+                dst.#=2();
+                #?((ALU32))
+                    dst.zero_upper_half_assign();
+                ##
+                vm.update_reg(dst_r);
+            }
+            // ALU / ALU64: Byte swap
+            [[BPF_ALU: ALU32], [BPF_END: END],
+             [
+                BPF_TO_LE: host_to_le,
+                BPF_TO_BE: host_to_be,
+             ]
+            ] => {
+                let dst_r = insn.dst_reg();
+                let dst = vm.reg(dst_r);
+                dst.#=2(insn.imm);
+                vm.update_reg(dst_r);
+            }
+            // JMP32 / JMP: Conditional
+            #![exhaustive(mask::BPF_OPCODE_JMP_MASK
+                except BPF_JA, BPF_CALL, BPF_EXIT, BPF_JCOND, 0xf0)]
+            [[BPF_JMP32: JMP32, BPF_JMP: JMP64], [BPF_X: X, BPF_K: K],
+             [BPF_JEQ..=BPF_JSGE: _, BPF_JLT..=BPF_JSLE: _]
+            ] => bind matched {
+                let pc = *vm.pc();
+                let width = width_of(matched);
+
+                let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
+                let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
+                #?((K))
+                    let _ = src_r;
+                    let src_r = -1i8;
+                    // The immediate is always sign-extended, regardless of the signedness
+                    // of the operator. JMP32 only compares the lower half anyway.
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                ##
+                #?((X))
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, src_r));
+                    let src_r = src_r as i8;
+                ##
+                let operation = matched & mask::BPF_OPCODE_JMP_MASK;
+                let fork = Fork { target: pc.wrapping_add_signed(insn.off as isize), fall_through: pc };
+                // Inverse comparisons swap the branches
+                let fork = if matches!(operation, BPF_JNE | BPF_JGT | BPF_JGE | BPF_JSGT | BPF_JSGE) {
+                    Fork { target: fork.fall_through, fall_through: fork.target }
+                } else {
+                    fork
+                };
+                let (dst, src) = ((dst_r as i8, dst), (src_r, src));
+                let result = match operation {
+                    BPF_JEQ | BPF_JNE => vm_bak.jeq(dst, src, fork, width),
+                    BPF_JLT | BPF_JGE => vm_bak.jlt(dst, src, fork, width),
+                    BPF_JLE | BPF_JGT => vm_bak.jle(dst, src, fork, width),
+                    BPF_JSLT | BPF_JSGE => vm_bak.jslt(dst, src, fork, width),
+                    BPF_JSLE | BPF_JSGT => vm_bak.jsle(dst, src, fork, width),
+                    // BPF_JSET
+                    _ => vm_bak.jset(dst, src, fork, width),
+                };
+                *vm.pc() = *vm_bak.pc();
+                vm.jumped(pc - 1);
+                if let Some(branch) = result {
+                    branch.borrow_mut().jumped(pc - 1);
+                    context.add_pending_branch(branch);
+                }
+            }
+            // BPF_JA: Unconditional jump, with `gotol` (BPF_JMP32) taking its offset from `imm`
+            [[BPF_JMP: JMP, BPF_JMP32: JMP32], [BPF_JA: JA]] => {
+                #?((JMP))
+                    let offset = insn.off as isize;
+                ##
+                #?((JMP32))
+                    let offset = insn.imm as isize;
+                ##
+                let from = *vm.pc() - 1;
+                *vm.pc() = vm.pc().wrapping_add_signed(offset);
+                vm.jumped(from);
+            }
+            // BPF_EXIT: Exits
+            [[BPF_JMP: JMP], [BPF_EXIT: EXIT]] => {
+                if vm.return_relative() {
+                    *vm.pc() = *vm.pc();
+                } else {
+                    return;
+                }
+            }
+            [[BPF_JMP: JMP], [BPF_CALL: CALL]] => {
+                run_call(insn, vm, context);
+            }
+            // Store / load
+            [[BPF_LDX: LDX, BPF_STX: STX, BPF_ST: ST], [BPF_MEM: MEM],
+             [
+                BPF_B: "1",
+                BPF_H: "2",
+                BPF_W: "4",
+                BPF_DW: "8",
+             ]
+            ] => {
+                const SIZE: usize = #=2;
+                #?((LDX))
+                    let src = vm.ro_reg(insn.src_reg());
+                    let provenance = provenance_of(context, src, insn.off);
+                    if let Some(value) = unsafe { src.get_at(insn.off, SIZE) } {
+                        *vm.reg(insn.dst_reg()) = value;
+                        notify_load(context, provenance, SIZE);
+                    } else {
+                        vm.invalidate("Illegal access");
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                ##
+                #?((STX))
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    let src = vm.ro_reg(insn.src_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, src) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                ##
+                #?((ST))
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, &Value::constant64(insn.imm as u32 as u64)) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.dst_reg());
+                ##
+            }
+            [[BPF_LD: LD], [BPF_IMM: IMM], [BPF_DW: DW]] => {
+                let next = code[*vm.pc()];
+                match insn.src_reg() {
+                    BPF_IMM64_IMM => {
+                        let value = insn.imm as u32 as u64 | (next & 0xFFFF_FFFF_0000_0000);
+                        *vm.reg(insn.dst_reg()) = Value::constant64(value);
+                        vm.update_reg(insn.dst_reg());
+                    }
+                    _ => {
+                        if let Some(value) = vm.load_imm64(&insn, next) {
+                            *vm.reg(insn.dst_reg()) = value;
+                            vm.update_reg(insn.dst_reg());
+                        } else {
+                            vm.invalidate("Unsupported imm64 instruction");
+                            break;
+                        }
+                    }
+                }
+                *vm.pc() += 1;
+            }
+            // Unsupported widths, leaving the error to the value implementation
+            [[BPF_STX: STX], [BPF_ATOMIC: ATOMIC], [BPF_B: "1", BPF_H: "2"]] => {
+                run_atomic(insn, vm, context, #=2);
+            }
+            #[cfg(feature = "atomic32")]
+            [[BPF_STX: STX], [BPF_ATOMIC: ATOMIC], [BPF_W: W]] => {
+                run_atomic(insn, vm, context, 4);
+            }
+            #[cfg(feature = "atomic64")]
+            [[BPF_STX: STX], [BPF_ATOMIC: ATOMIC], [BPF_DW: DW]] => {
+                run_atomic(insn, vm, context, 8);
+            }
+            _ => {
+                vm.invalidate("Unrecognized opcode");
+                break;
+            }
+        };
+    }
+}
+
+/// The operand width of an ALU or a JMP instruction, 32 for `BPF_ALU` and `BPF_JMP32`
+fn width_of(opcode: u8) -> u8 {
+    match opcode & mask::BPF_OPCODE_CLASS_MASK {
+        BPF_ALU | BPF_JMP32 => 32,
+        _ => 64,
+    }
+}
+
+fn run_atomic<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
+    insn: Instruction,
+    vm: &mut RefMut<M>,
+    context: &mut C,
+    size: usize,
+) {
+    let provenance = provenance_of(context, vm.ro_reg(insn.dst_reg()), insn.off);
+    let atomic_code = insn.imm;
+    opcode_match! {
+        atomic_code as i32 in ebpf_consts,
+        [[BPF_ATOMIC_FETCH: FETCH, BPF_ATOMIC_NO_FETCH: NO_FETCH],
+         [
+            BPF_ATOMIC_ADD: "fetch_add",
+            BPF_ATOMIC_OR : "fetch_or",
+            BPF_ATOMIC_AND: "fetch_and",
+            BPF_ATOMIC_XOR: "fetch_xor",
+         ]
+        ] => {
+            let src_r = insn.src_reg();
+            let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
+            let result = dst.#=1(insn.off, src, size);
+            if let Err(err) = &result {
+                vm.invalidate(err.message());
+                return;
+            }
+            #?((FETCH))
+                if let Ok(old) = result {
+                    *vm.reg(src_r) = old;
+                }
+            ##
+            vm.update_reg(insn.dst_reg());
+            vm.update_reg(src_r);
+        }
+        [[BPF_ATOMIC_FETCH: FETCH], [BPF_ATOMIC_XCHG: XCHG]] => {
+            let src_r =  insn.src_reg();
+            let (src, dst) = return_if_none!(vm.two_regs(src_r, insn.dst_reg()));
+            match dst.swap(insn.off, src, size) {
+                Ok(old) => *vm.reg(src_r) = old,
+                Err(err) => vm.invalidate(err.message()),
+            }
+            vm.update_reg(insn.dst_reg());
+            vm.update_reg(src_r);
+        }
+        [[BPF_ATOMIC_FETCH: FETCH], [BPF_ATOMIC_CMPXCHG: CMPXCHG]] => {
+            let src_r = insn.src_reg();
+            let (dst, src, expected) = return_if_none!(vm.three_regs(insn.dst_reg(), src_r, 0));
+            match dst.compare_exchange(insn.off, expected, src, size) {
+                Ok(old) => *vm.reg(0) = old,
+                Err(err) => vm.invalidate(err.message()),
+            }
+            vm.update_reg(insn.dst_reg());
+            vm.update_reg(0);
+            vm.update_reg(src_r);
+        }
+        _ => vm.invalidate("Atomic failed"),
+    };
+    if vm.is_valid() {
+        notify_load(context, provenance.clone(), size);
+        notify_store(context, provenance, size);
+    }
+}
@@ -0,0 +1,1908 @@
+//! The instruction dispatch of the interpreter, see [run].
+//!
+//! GENERATED from `dispatch.rs` by expanding its `opcode_match!` macros and formatting
+//! the result with `rustfmt`. Do not edit by hand: regenerate it whenever `dispatch.rs` changes.
+
+use core::cell::RefMut;
+
+use ebpf_consts::*;
+
+use crate::{interpreter::context::Fork, spec::Instruction};
+
+use super::{
+    context::VmContext, notify_load, notify_store, provenance_of, run_call, value::VmValue, vm::Vm,
+};
+
+/// Runs (or, interprets) the code on the given VM
+pub fn run<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
+    code: &[u64],
+    vm: &mut RefMut<M>,
+    context: &mut C,
+) {
+    while vm.is_valid() && context.is_valid() {
+        context.increment_pc();
+
+        let start = *vm.pc();
+        vm.executing(start);
+        let insn = Instruction::from_raw(code[start]);
+        *vm.pc() += 1;
+        let opcode = insn.opcode;
+        {
+            const BPF_ALU_BPF_X_BPF_ADD: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_ADD;
+            const BPF_ALU_BPF_X_BPF_SUB: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_SUB;
+            const BPF_ALU_BPF_X_BPF_MUL: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_MUL;
+            const BPF_ALU_BPF_X_BPF_DIV: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_DIV;
+            const BPF_ALU_BPF_X_BPF_MOD: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_MOD;
+            const BPF_ALU_BPF_X_BPF_AND: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_AND;
+            const BPF_ALU_BPF_X_BPF_OR: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_OR;
+            const BPF_ALU_BPF_X_BPF_XOR: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_XOR;
+            const BPF_ALU_BPF_K_BPF_ADD: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_ADD;
+            const BPF_ALU_BPF_K_BPF_SUB: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_SUB;
+            const BPF_ALU_BPF_K_BPF_MUL: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_MUL;
+            const BPF_ALU_BPF_K_BPF_DIV: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_DIV;
+            const BPF_ALU_BPF_K_BPF_MOD: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_MOD;
+            const BPF_ALU_BPF_K_BPF_AND: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_AND;
+            const BPF_ALU_BPF_K_BPF_OR: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_OR;
+            const BPF_ALU_BPF_K_BPF_XOR: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_XOR;
+            const BPF_ALU64_BPF_X_BPF_ADD: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_ADD;
+            const BPF_ALU64_BPF_X_BPF_SUB: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_SUB;
+            const BPF_ALU64_BPF_X_BPF_MUL: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_MUL;
+            const BPF_ALU64_BPF_X_BPF_DIV: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_DIV;
+            const BPF_ALU64_BPF_X_BPF_MOD: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_MOD;
+            const BPF_ALU64_BPF_X_BPF_AND: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_AND;
+            const BPF_ALU64_BPF_X_BPF_OR: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_OR;
+            const BPF_ALU64_BPF_X_BPF_XOR: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_XOR;
+            const BPF_ALU64_BPF_K_BPF_ADD: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_ADD;
+            const BPF_ALU64_BPF_K_BPF_SUB: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_SUB;
+            const BPF_ALU64_BPF_K_BPF_MUL: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_MUL;
+            const BPF_ALU64_BPF_K_BPF_DIV: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_DIV;
+            const BPF_ALU64_BPF_K_BPF_MOD: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_MOD;
+            const BPF_ALU64_BPF_K_BPF_AND: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_AND;
+            const BPF_ALU64_BPF_K_BPF_OR: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_OR;
+            const BPF_ALU64_BPF_K_BPF_XOR: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_XOR;
+            const BPF_ALU_BPF_X_BPF_MOV: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_MOV;
+            const BPF_ALU_BPF_K_BPF_MOV: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_MOV;
+            const BPF_ALU64_BPF_X_BPF_MOV: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_MOV;
+            const BPF_ALU64_BPF_K_BPF_MOV: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_MOV;
+            const BPF_ALU_BPF_X_BPF_LSH: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_LSH;
+            const BPF_ALU_BPF_X_BPF_RSH: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_RSH;
+            const BPF_ALU_BPF_X_BPF_ARSH: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_X | ebpf_consts::BPF_ARSH;
+            const BPF_ALU_BPF_K_BPF_LSH: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_LSH;
+            const BPF_ALU_BPF_K_BPF_RSH: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_RSH;
+            const BPF_ALU_BPF_K_BPF_ARSH: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_ARSH;
+            const BPF_ALU64_BPF_X_BPF_LSH: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_LSH;
+            const BPF_ALU64_BPF_X_BPF_RSH: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_RSH;
+            const BPF_ALU64_BPF_X_BPF_ARSH: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_X | ebpf_consts::BPF_ARSH;
+            const BPF_ALU64_BPF_K_BPF_LSH: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_LSH;
+            const BPF_ALU64_BPF_K_BPF_RSH: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_RSH;
+            const BPF_ALU64_BPF_K_BPF_ARSH: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_ARSH;
+            const BPF_ALU_BPF_K_BPF_NEG: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_K | ebpf_consts::BPF_NEG;
+            const BPF_ALU64_BPF_K_BPF_NEG: u8 =
+                ebpf_consts::BPF_ALU64 | ebpf_consts::BPF_K | ebpf_consts::BPF_NEG;
+            const BPF_ALU_BPF_END_BPF_TO_LE: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_END | ebpf_consts::BPF_TO_LE;
+            const BPF_ALU_BPF_END_BPF_TO_BE: u8 =
+                ebpf_consts::BPF_ALU | ebpf_consts::BPF_END | ebpf_consts::BPF_TO_BE;
+            const BPF_JMP32_BPF_X_BPF_JEQ_TO_BPF_JSGE: u8 =
+                ebpf_consts::BPF_JMP32 | ebpf_consts::BPF_X;
+            const BPF_JMP32_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK: u8 = {
+                let bits = ebpf_consts::BPF_JEQ | ebpf_consts::BPF_JSGE;
+                (bits & bits.wrapping_neg()).wrapping_sub(1)
+            };
+            const BPF_JMP32_BPF_X_BPF_JLT_TO_BPF_JSLE: u8 =
+                ebpf_consts::BPF_JMP32 | ebpf_consts::BPF_X;
+            const BPF_JMP32_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK: u8 = {
+                let bits = ebpf_consts::BPF_JLT | ebpf_consts::BPF_JSLE;
+                (bits & bits.wrapping_neg()).wrapping_sub(1)
+            };
+            const BPF_JMP32_BPF_K_BPF_JEQ_TO_BPF_JSGE: u8 =
+                ebpf_consts::BPF_JMP32 | ebpf_consts::BPF_K;
+            const BPF_JMP32_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK: u8 = {
+                let bits = ebpf_consts::BPF_JEQ | ebpf_consts::BPF_JSGE;
+                (bits & bits.wrapping_neg()).wrapping_sub(1)
+            };
+            const BPF_JMP32_BPF_K_BPF_JLT_TO_BPF_JSLE: u8 =
+                ebpf_consts::BPF_JMP32 | ebpf_consts::BPF_K;
+            const BPF_JMP32_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK: u8 = {
+                let bits = ebpf_consts::BPF_JLT | ebpf_consts::BPF_JSLE;
+                (bits & bits.wrapping_neg()).wrapping_sub(1)
+            };
+            const BPF_JMP_BPF_X_BPF_JEQ_TO_BPF_JSGE: u8 = ebpf_consts::BPF_JMP | ebpf_consts::BPF_X;
+            const BPF_JMP_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK: u8 = {
+                let bits = ebpf_consts::BPF_JEQ | ebpf_consts::BPF_JSGE;
+                (bits & bits.wrapping_neg()).wrapping_sub(1)
+            };
+            const BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE: u8 = ebpf_consts::BPF_JMP | ebpf_consts::BPF_X;
+            const BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK: u8 = {
+                let bits = ebpf_consts::BPF_JLT | ebpf_consts::BPF_JSLE;
+                (bits & bits.wrapping_neg()).wrapping_sub(1)
+            };
+            const BPF_JMP_BPF_K_BPF_JEQ_TO_BPF_JSGE: u8 = ebpf_consts::BPF_JMP | ebpf_consts::BPF_K;
+            const BPF_JMP_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK: u8 = {
+                let bits = ebpf_consts::BPF_JEQ | ebpf_consts::BPF_JSGE;
+                (bits & bits.wrapping_neg()).wrapping_sub(1)
+            };
+            const BPF_JMP_BPF_K_BPF_JLT_TO_BPF_JSLE: u8 = ebpf_consts::BPF_JMP | ebpf_consts::BPF_K;
+            const BPF_JMP_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK: u8 = {
+                let bits = ebpf_consts::BPF_JLT | ebpf_consts::BPF_JSLE;
+                (bits & bits.wrapping_neg()).wrapping_sub(1)
+            };
+            const BPF_JMP_BPF_JA: u8 = ebpf_consts::BPF_JMP | ebpf_consts::BPF_JA;
+            const BPF_JMP32_BPF_JA: u8 = ebpf_consts::BPF_JMP32 | ebpf_consts::BPF_JA;
+            const BPF_JMP_BPF_EXIT: u8 = ebpf_consts::BPF_JMP | ebpf_consts::BPF_EXIT;
+            const BPF_JMP_BPF_CALL: u8 = ebpf_consts::BPF_JMP | ebpf_consts::BPF_CALL;
+            const BPF_LDX_BPF_MEM_BPF_B: u8 =
+                ebpf_consts::BPF_LDX | ebpf_consts::BPF_MEM | ebpf_consts::BPF_B;
+            const BPF_LDX_BPF_MEM_BPF_H: u8 =
+                ebpf_consts::BPF_LDX | ebpf_consts::BPF_MEM | ebpf_consts::BPF_H;
+            const BPF_LDX_BPF_MEM_BPF_W: u8 =
+                ebpf_consts::BPF_LDX | ebpf_consts::BPF_MEM | ebpf_consts::BPF_W;
+            const BPF_LDX_BPF_MEM_BPF_DW: u8 =
+                ebpf_consts::BPF_LDX | ebpf_consts::BPF_MEM | ebpf_consts::BPF_DW;
+            const BPF_STX_BPF_MEM_BPF_B: u8 =
+                ebpf_consts::BPF_STX | ebpf_consts::BPF_MEM | ebpf_consts::BPF_B;
+            const BPF_STX_BPF_MEM_BPF_H: u8 =
+                ebpf_consts::BPF_STX | ebpf_consts::BPF_MEM | ebpf_consts::BPF_H;
+            const BPF_STX_BPF_MEM_BPF_W: u8 =
+                ebpf_consts::BPF_STX | ebpf_consts::BPF_MEM | ebpf_consts::BPF_W;
+            const BPF_STX_BPF_MEM_BPF_DW: u8 =
+                ebpf_consts::BPF_STX | ebpf_consts::BPF_MEM | ebpf_consts::BPF_DW;
+            const BPF_ST_BPF_MEM_BPF_B: u8 =
+                ebpf_consts::BPF_ST | ebpf_consts::BPF_MEM | ebpf_consts::BPF_B;
+            const BPF_ST_BPF_MEM_BPF_H: u8 =
+                ebpf_consts::BPF_ST | ebpf_consts::BPF_MEM | ebpf_consts::BPF_H;
+            const BPF_ST_BPF_MEM_BPF_W: u8 =
+                ebpf_consts::BPF_ST | ebpf_consts::BPF_MEM | ebpf_consts::BPF_W;
+            const BPF_ST_BPF_MEM_BPF_DW: u8 =
+                ebpf_consts::BPF_ST | ebpf_consts::BPF_MEM | ebpf_consts::BPF_DW;
+            const BPF_LD_BPF_IMM_BPF_DW: u8 =
+                ebpf_consts::BPF_LD | ebpf_consts::BPF_IMM | ebpf_consts::BPF_DW;
+            const BPF_STX_BPF_ATOMIC_BPF_B: u8 =
+                ebpf_consts::BPF_STX | ebpf_consts::BPF_ATOMIC | ebpf_consts::BPF_B;
+            const BPF_STX_BPF_ATOMIC_BPF_H: u8 =
+                ebpf_consts::BPF_STX | ebpf_consts::BPF_ATOMIC | ebpf_consts::BPF_H;
+            const BPF_STX_BPF_ATOMIC_BPF_W: u8 =
+                ebpf_consts::BPF_STX | ebpf_consts::BPF_ATOMIC | ebpf_consts::BPF_W;
+            const BPF_STX_BPF_ATOMIC_BPF_DW: u8 =
+                ebpf_consts::BPF_STX | ebpf_consts::BPF_ATOMIC | ebpf_consts::BPF_DW;
+            const _: () = {
+                const fn covered(opcode: u8) -> bool {
+                    match opcode {
+                        BPF_ALU_BPF_X_BPF_ADD => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_SUB => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_MUL => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_DIV => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_MOD => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_AND => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_OR => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_XOR => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_ADD => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_SUB => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_MUL => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_DIV => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_MOD => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_AND => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_OR => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_XOR => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_ADD => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_SUB => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_MUL => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_DIV => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_MOD => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_AND => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_OR => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_XOR => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_ADD => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_SUB => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_MUL => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_DIV => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_MOD => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_AND => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_OR => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_XOR => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_MOV => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_MOV => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_MOV => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_MOV => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_LSH => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_RSH => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_X_BPF_ARSH => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_LSH => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_RSH => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_ARSH => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_LSH => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_RSH => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_X_BPF_ARSH => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_LSH => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_RSH => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_ARSH => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_K_BPF_NEG => {
+                            return true;
+                        }
+                        BPF_ALU64_BPF_K_BPF_NEG => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_END_BPF_TO_LE => {
+                            return true;
+                        }
+                        BPF_ALU_BPF_END_BPF_TO_BE => {
+                            return true;
+                        }
+                        _ if (opcode & BPF_JMP32_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)
+                            == BPF_JMP32_BPF_X_BPF_JEQ_TO_BPF_JSGE
+                            && ebpf_consts::BPF_JEQ
+                                <= (opcode & !BPF_JMP32_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)
+                            && (opcode & !BPF_JMP32_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)
+                                <= ebpf_consts::BPF_JSGE =>
+                        {
+                            return true;
+                        }
+                        _ if (opcode & BPF_JMP32_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)
+                            == BPF_JMP32_BPF_X_BPF_JLT_TO_BPF_JSLE
+                            && ebpf_consts::BPF_JLT
+                                <= (opcode & !BPF_JMP32_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)
+                            && (opcode & !BPF_JMP32_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)
+                                <= ebpf_consts::BPF_JSLE =>
+                        {
+                            return true;
+                        }
+                        _ if (opcode & BPF_JMP32_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)
+                            == BPF_JMP32_BPF_K_BPF_JEQ_TO_BPF_JSGE
+                            && ebpf_consts::BPF_JEQ
+                                <= (opcode & !BPF_JMP32_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)
+                            && (opcode & !BPF_JMP32_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)
+                                <= ebpf_consts::BPF_JSGE =>
+                        {
+                            return true;
+                        }
+                        _ if (opcode & BPF_JMP32_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)
+                            == BPF_JMP32_BPF_K_BPF_JLT_TO_BPF_JSLE
+                            && ebpf_consts::BPF_JLT
+                                <= (opcode & !BPF_JMP32_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)
+                            && (opcode & !BPF_JMP32_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)
+                                <= ebpf_consts::BPF_JSLE =>
+                        {
+                            return true;
+                        }
+                        _ if (opcode & BPF_JMP_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)
+                            == BPF_JMP_BPF_X_BPF_JEQ_TO_BPF_JSGE
+                            && ebpf_consts::BPF_JEQ
+                                <= (opcode & !BPF_JMP_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)
+                            && (opcode & !BPF_JMP_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)
+                                <= ebpf_consts::BPF_JSGE =>
+                        {
+                            return true;
+                        }
+                        _ if (opcode & BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)
+                            == BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE
+                            && ebpf_consts::BPF_JLT
+                                <= (opcode & !BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)
+                            && (opcode & !BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)
+                                <= ebpf_consts::BPF_JSLE =>
+                        {
+                            return true;
+                        }
+                        _ if (opcode & BPF_JMP_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)
+                            == BPF_JMP_BPF_K_BPF_JEQ_TO_BPF_JSGE
+                            && ebpf_consts::BPF_JEQ
+                                <= (opcode & !BPF_JMP_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)
+                            && (opcode & !BPF_JMP_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)
+                                <= ebpf_consts::BPF_JSGE =>
+                        {
+                            return true;
+                        }
+                        _ if (opcode & BPF_JMP_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)
+                            == BPF_JMP_BPF_K_BPF_JLT_TO_BPF_JSLE
+                            && ebpf_consts::BPF_JLT
+                                <= (opcode & !BPF_JMP_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)
+                            && (opcode & !BPF_JMP_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)
+                                <= ebpf_consts::BPF_JSLE =>
+                        {
+                            return true;
+                        }
+                        BPF_JMP_BPF_JA => {
+                            return true;
+                        }
+                        BPF_JMP32_BPF_JA => {
+                            return true;
+                        }
+                        BPF_JMP_BPF_EXIT => {
+                            return true;
+                        }
+                        BPF_JMP_BPF_CALL => {
+                            return true;
+                        }
+                        BPF_LDX_BPF_MEM_BPF_B => {
+                            return true;
+                        }
+                        BPF_LDX_BPF_MEM_BPF_H => {
+                            return true;
+                        }
+                        BPF_LDX_BPF_MEM_BPF_W => {
+                            return true;
+                        }
+                        BPF_LDX_BPF_MEM_BPF_DW => {
+                            return true;
+                        }
+                        BPF_STX_BPF_MEM_BPF_B => {
+                            return true;
+                        }
+                        BPF_STX_BPF_MEM_BPF_H => {
+                            return true;
+                        }
+                        BPF_STX_BPF_MEM_BPF_W => {
+                            return true;
+                        }
+                        BPF_STX_BPF_MEM_BPF_DW => {
+                            return true;
+                        }
+                        BPF_ST_BPF_MEM_BPF_B => {
+                            return true;
+                        }
+                        BPF_ST_BPF_MEM_BPF_H => {
+                            return true;
+                        }
+                        BPF_ST_BPF_MEM_BPF_W => {
+                            return true;
+                        }
+                        BPF_ST_BPF_MEM_BPF_DW => {
+                            return true;
+                        }
+                        BPF_LD_BPF_IMM_BPF_DW => {
+                            return true;
+                        }
+                        BPF_STX_BPF_ATOMIC_BPF_B => {
+                            return true;
+                        }
+                        BPF_STX_BPF_ATOMIC_BPF_H => {
+                            return true;
+                        }
+                        #[cfg(feature = "atomic32")]
+                        BPF_STX_BPF_ATOMIC_BPF_W => {
+                            return true;
+                        }
+                        #[cfg(feature = "atomic64")]
+                        BPF_STX_BPF_ATOMIC_BPF_DW => {
+                            return true;
+                        }
+                        _ => {}
+                    }
+                    false
+                }
+                const fn check(mask: u8, bases: &[u8], except: &[u8]) {
+                    const PREFIX: &[u8] =
+                        b"Opcodes not covered in the `exhaustive` field of an arm:";
+                    const DIGITS: &[u8] = b"0123456789abcdef";
+                    let mut report = [0u8; 512];
+                    let mut len = 0;
+                    while len < PREFIX.len() {
+                        report[len] = PREFIX[len];
+                        len += 1;
+                    }
+                    let step = mask & mask.wrapping_neg();
+                    let mut full = false;
+                    let mut i = 0;
+                    while i < bases.len() && !full {
+                        let mut field: u8 = 0;
+                        loop {
+                            let mut skipped = field & !mask != 0;
+                            let mut k = 0;
+                            while k < except.len() {
+                                skipped |= field == except[k];
+                                k += 1;
+                            }
+                            let opcode = (bases[i] & !mask) | field;
+                            if !skipped && !covered(opcode) {
+                                if len + 24 > report.len() {
+                                    report[len] = b' ';
+                                    report[len + 1] = b'.';
+                                    report[len + 2] = b'.';
+                                    report[len + 3] = b'.';
+                                    len += 4;
+                                    full = true;
+                                    break;
+                                }
+                                report[len] = b' ';
+                                report[len + 1] = b'0';
+                                report[len + 2] = b'x';
+                                len += 3;
+                                let opcode = opcode as u64;
+                                let mut shift = 4;
+                                while shift < 64 && opcode >> shift != 0 {
+                                    shift += 4;
+                                }
+                                while shift > 0 {
+                                    shift -= 4;
+                                    report[len] = DIGITS[((opcode >> shift) & 0xf) as usize];
+                                    len += 1;
+                                }
+                            }
+                            if field == mask {
+                                break;
+                            }
+                            field += step;
+                        }
+                        i += 1;
+                    }
+                    if len != PREFIX.len() {
+                        if let Ok(message) = core::str::from_utf8(report.split_at(len).0) {
+                            panic!("{}", message);
+                        }
+                    }
+                }
+                check(
+                    ebpf_consts::mask::BPF_OPCODE_JMP_MASK,
+                    &[
+                        ebpf_consts::BPF_JMP32 | ebpf_consts::BPF_X | ebpf_consts::BPF_JEQ,
+                        ebpf_consts::BPF_JMP32 | ebpf_consts::BPF_X | ebpf_consts::BPF_JLT,
+                        ebpf_consts::BPF_JMP32 | ebpf_consts::BPF_K | ebpf_consts::BPF_JEQ,
+                        ebpf_consts::BPF_JMP32 | ebpf_consts::BPF_K | ebpf_consts::BPF_JLT,
+                        ebpf_consts::BPF_JMP | ebpf_consts::BPF_X | ebpf_consts::BPF_JEQ,
+                        ebpf_consts::BPF_JMP | ebpf_consts::BPF_X | ebpf_consts::BPF_JLT,
+                        ebpf_consts::BPF_JMP | ebpf_consts::BPF_K | ebpf_consts::BPF_JEQ,
+                        ebpf_consts::BPF_JMP | ebpf_consts::BPF_K | ebpf_consts::BPF_JLT,
+                    ],
+                    &[
+                        ebpf_consts::BPF_JA,
+                        ebpf_consts::BPF_CALL,
+                        ebpf_consts::BPF_EXIT,
+                        ebpf_consts::BPF_JCOND,
+                        0xf0,
+                    ],
+                );
+            };
+            match opcode {
+                BPF_ALU_BPF_X_BPF_ADD => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_ADD;
+                    vm.check_pointer_arithmetic(&insn);
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.add_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_SUB => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_SUB;
+                    vm.check_pointer_arithmetic(&insn);
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.sub_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_MUL => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_MUL;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.mul_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_DIV => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_DIV;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    let width = width_of(matched);
+                    if insn.is_signed_division() {
+                        dst.signed_div_assign(src, width);
+                    } else {
+                        dst.safe_div_assign(src);
+                    }
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_MOD => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_MOD;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    let width = width_of(matched);
+                    if insn.is_signed_division() {
+                        dst.signed_rem_assign(src, width);
+                    } else {
+                        dst.safe_rem_assign(src);
+                    }
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_AND => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_AND;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.bitand_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_OR => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_OR;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.bitor_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_XOR => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_XOR;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.bitxor_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_ADD => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_ADD;
+                    vm.check_pointer_arithmetic(&insn);
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.add_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_SUB => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_SUB;
+                    vm.check_pointer_arithmetic(&insn);
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.sub_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_MUL => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_MUL;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.mul_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_DIV => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_DIV;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    if insn.imm == 0 {
+                        vm.invalidate("Div by 0");
+                        break;
+                    }
+                    let width = width_of(matched);
+                    if insn.is_signed_division() {
+                        dst.signed_div_assign(src, width);
+                    } else {
+                        dst.safe_div_assign(src);
+                    }
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_MOD => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_MOD;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    if insn.imm == 0 {
+                        vm.invalidate("Div by 0");
+                        break;
+                    }
+                    let width = width_of(matched);
+                    if insn.is_signed_division() {
+                        dst.signed_rem_assign(src, width);
+                    } else {
+                        dst.safe_rem_assign(src);
+                    }
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_AND => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_AND;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.bitand_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_OR => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_OR;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.bitor_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_XOR => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_XOR;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let src = &src.lower_half();
+                    dst.lower_half_assign();
+                    dst.bitxor_assign(src);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_ADD => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_ADD;
+                    vm.check_pointer_arithmetic(&insn);
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    dst.add_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_SUB => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_SUB;
+                    vm.check_pointer_arithmetic(&insn);
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    dst.sub_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_MUL => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_MUL;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    dst.mul_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_DIV => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_DIV;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let width = width_of(matched);
+                    if insn.is_signed_division() {
+                        dst.signed_div_assign(src, width);
+                    } else {
+                        dst.safe_div_assign(src);
+                    }
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_MOD => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_MOD;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let width = width_of(matched);
+                    if insn.is_signed_division() {
+                        dst.signed_rem_assign(src, width);
+                    } else {
+                        dst.safe_rem_assign(src);
+                    }
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_AND => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_AND;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    dst.bitand_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_OR => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_OR;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    dst.bitor_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_XOR => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_XOR;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    dst.bitxor_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_ADD => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_ADD;
+                    vm.check_pointer_arithmetic(&insn);
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    dst.add_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_SUB => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_SUB;
+                    vm.check_pointer_arithmetic(&insn);
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    dst.sub_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_MUL => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_MUL;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    dst.mul_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_DIV => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_DIV;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    if insn.imm == 0 {
+                        vm.invalidate("Div by 0");
+                        break;
+                    }
+                    let width = width_of(matched);
+                    if insn.is_signed_division() {
+                        dst.signed_div_assign(src, width);
+                    } else {
+                        dst.safe_div_assign(src);
+                    }
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_MOD => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_MOD;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    if insn.imm == 0 {
+                        vm.invalidate("Div by 0");
+                        break;
+                    }
+                    let width = width_of(matched);
+                    if insn.is_signed_division() {
+                        dst.signed_rem_assign(src, width);
+                    } else {
+                        dst.safe_rem_assign(src);
+                    }
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_AND => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_AND;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    dst.bitand_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_OR => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_OR;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    dst.bitor_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_XOR => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_XOR;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    dst.bitxor_assign(src);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_MOV => {
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    *dst = src.clone();
+                    if let Some(width) = insn.is_sign_extending_move() {
+                        dst.sign_extend_assign(width);
+                    }
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_MOV => {
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    *dst = src.clone();
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_MOV => {
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    *dst = src.clone();
+                    if let Some(width) = insn.is_sign_extending_move() {
+                        dst.sign_extend_assign(width);
+                    }
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_MOV => {
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    *dst = src.clone();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_LSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_LSH;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let width = width_of(matched);
+                    dst.lower_half_assign();
+                    dst.l_shift(src, width);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_RSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_RSH;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let width = width_of(matched);
+                    dst.lower_half_assign();
+                    dst.r_shift(src, width);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_X_BPF_ARSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_X_BPF_ARSH;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let width = width_of(matched);
+                    dst.lower_half_assign();
+                    dst.signed_shr(src, width);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_LSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_LSH;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let width = width_of(matched);
+                    dst.lower_half_assign();
+                    dst.l_shift(src, width);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_RSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_RSH;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let width = width_of(matched);
+                    dst.lower_half_assign();
+                    dst.r_shift(src, width);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_ARSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU_BPF_K_BPF_ARSH;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let width = width_of(matched);
+                    dst.lower_half_assign();
+                    dst.signed_shr(src, width);
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_LSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_LSH;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let width = width_of(matched);
+                    dst.l_shift(src, width);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_RSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_RSH;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let width = width_of(matched);
+                    dst.r_shift(src, width);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_X_BPF_ARSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_X_BPF_ARSH;
+                    let dst_r = insn.dst_reg();
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
+                    let width = width_of(matched);
+                    dst.signed_shr(src, width);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_LSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_LSH;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let width = width_of(matched);
+                    dst.l_shift(src, width);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_RSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_RSH;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let width = width_of(matched);
+                    dst.r_shift(src, width);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_ARSH => {
+                    #[allow(unused_variables)]
+                    let matched: u8 = BPF_ALU64_BPF_K_BPF_ARSH;
+                    let dst_r = insn.dst_reg();
+                    let src = &mut Value::constantu32(insn.imm as u32);
+                    let dst = vm.reg(dst_r);
+                    let width = width_of(matched);
+                    dst.signed_shr(src, width);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_K_BPF_NEG => {
+                    let dst_r = insn.dst_reg();
+                    let dst = vm.reg(dst_r);
+                    dst.neg_assign();
+                    dst.zero_upper_half_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU64_BPF_K_BPF_NEG => {
+                    let dst_r = insn.dst_reg();
+                    let dst = vm.reg(dst_r);
+                    dst.neg_assign();
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_END_BPF_TO_LE => {
+                    let dst_r = insn.dst_reg();
+                    let dst = vm.reg(dst_r);
+                    dst.host_to_le(insn.imm);
+                    vm.update_reg(dst_r);
+                }
+                BPF_ALU_BPF_END_BPF_TO_BE => {
+                    let dst_r = insn.dst_reg();
+                    let dst = vm.reg(dst_r);
+                    dst.host_to_be(insn.imm);
+                    vm.update_reg(dst_r);
+                }
+                _ if (opcode & BPF_JMP32_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)
+                    == BPF_JMP32_BPF_X_BPF_JEQ_TO_BPF_JSGE
+                    && (ebpf_consts::BPF_JEQ..=ebpf_consts::BPF_JSGE)
+                        .contains(&(opcode & !BPF_JMP32_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)) =>
+                {
+                    #[allow(unused_variables)]
+                    let matched: u8 = opcode;
+                    let pc = *vm.pc();
+                    let width = width_of(matched);
+                    let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
+                    let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, src_r));
+                    let src_r = src_r as i8;
+                    let operation = matched & mask::BPF_OPCODE_JMP_MASK;
+                    let fork = Fork {
+                        target: pc.wrapping_add_signed(insn.off as isize),
+                        fall_through: pc,
+                    };
+                    let fork =
+                        if matches!(operation, BPF_JNE | BPF_JGT | BPF_JGE | BPF_JSGT | BPF_JSGE) {
+                            Fork {
+                                target: fork.fall_through,
+                                fall_through: fork.target,
+                            }
+                        } else {
+                            fork
+                        };
+                    let (dst, src) = ((dst_r as i8, dst), (src_r, src));
+                    let result = match operation {
+                        BPF_JEQ | BPF_JNE => vm_bak.jeq(dst, src, fork, width),
+                        BPF_JLT | BPF_JGE => vm_bak.jlt(dst, src, fork, width),
+                        BPF_JLE | BPF_JGT => vm_bak.jle(dst, src, fork, width),
+                        BPF_JSLT | BPF_JSGE => vm_bak.jslt(dst, src, fork, width),
+                        BPF_JSLE | BPF_JSGT => vm_bak.jsle(dst, src, fork, width),
+                        _ => vm_bak.jset(dst, src, fork, width),
+                    };
+                    *vm.pc() = *vm_bak.pc();
+                    vm.jumped(pc - 1);
+                    if let Some(branch) = result {
+                        branch.borrow_mut().jumped(pc - 1);
+                        context.add_pending_branch(branch);
+                    }
+                }
+                _ if (opcode & BPF_JMP32_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)
+                    == BPF_JMP32_BPF_X_BPF_JLT_TO_BPF_JSLE
+                    && (ebpf_consts::BPF_JLT..=ebpf_consts::BPF_JSLE)
+                        .contains(&(opcode & !BPF_JMP32_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)) =>
+                {
+                    #[allow(unused_variables)]
+                    let matched: u8 = opcode;
+                    let pc = *vm.pc();
+                    let width = width_of(matched);
+                    let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
+                    let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, src_r));
+                    let src_r = src_r as i8;
+                    let operation = matched & mask::BPF_OPCODE_JMP_MASK;
+                    let fork = Fork {
+                        target: pc.wrapping_add_signed(insn.off as isize),
+                        fall_through: pc,
+                    };
+                    let fork =
+                        if matches!(operation, BPF_JNE | BPF_JGT | BPF_JGE | BPF_JSGT | BPF_JSGE) {
+                            Fork {
+                                target: fork.fall_through,
+                                fall_through: fork.target,
+                            }
+                        } else {
+                            fork
+                        };
+                    let (dst, src) = ((dst_r as i8, dst), (src_r, src));
+                    let result = match operation {
+                        BPF_JEQ | BPF_JNE => vm_bak.jeq(dst, src, fork, width),
+                        BPF_JLT | BPF_JGE => vm_bak.jlt(dst, src, fork, width),
+                        BPF_JLE | BPF_JGT => vm_bak.jle(dst, src, fork, width),
+                        BPF_JSLT | BPF_JSGE => vm_bak.jslt(dst, src, fork, width),
+                        BPF_JSLE | BPF_JSGT => vm_bak.jsle(dst, src, fork, width),
+                        _ => vm_bak.jset(dst, src, fork, width),
+                    };
+                    *vm.pc() = *vm_bak.pc();
+                    vm.jumped(pc - 1);
+                    if let Some(branch) = result {
+                        branch.borrow_mut().jumped(pc - 1);
+                        context.add_pending_branch(branch);
+                    }
+                }
+                _ if (opcode & BPF_JMP32_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)
+                    == BPF_JMP32_BPF_K_BPF_JEQ_TO_BPF_JSGE
+                    && (ebpf_consts::BPF_JEQ..=ebpf_consts::BPF_JSGE)
+                        .contains(&(opcode & !BPF_JMP32_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)) =>
+                {
+                    #[allow(unused_variables)]
+                    let matched: u8 = opcode;
+                    let pc = *vm.pc();
+                    let width = width_of(matched);
+                    let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
+                    let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
+                    let _ = src_r;
+                    let src_r = -1i8;
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    let operation = matched & mask::BPF_OPCODE_JMP_MASK;
+                    let fork = Fork {
+                        target: pc.wrapping_add_signed(insn.off as isize),
+                        fall_through: pc,
+                    };
+                    let fork =
+                        if matches!(operation, BPF_JNE | BPF_JGT | BPF_JGE | BPF_JSGT | BPF_JSGE) {
+                            Fork {
+                                target: fork.fall_through,
+                                fall_through: fork.target,
+                            }
+                        } else {
+                            fork
+                        };
+                    let (dst, src) = ((dst_r as i8, dst), (src_r, src));
+                    let result = match operation {
+                        BPF_JEQ | BPF_JNE => vm_bak.jeq(dst, src, fork, width),
+                        BPF_JLT | BPF_JGE => vm_bak.jlt(dst, src, fork, width),
+                        BPF_JLE | BPF_JGT => vm_bak.jle(dst, src, fork, width),
+                        BPF_JSLT | BPF_JSGE => vm_bak.jslt(dst, src, fork, width),
+                        BPF_JSLE | BPF_JSGT => vm_bak.jsle(dst, src, fork, width),
+                        _ => vm_bak.jset(dst, src, fork, width),
+                    };
+                    *vm.pc() = *vm_bak.pc();
+                    vm.jumped(pc - 1);
+                    if let Some(branch) = result {
+                        branch.borrow_mut().jumped(pc - 1);
+                        context.add_pending_branch(branch);
+                    }
+                }
+                _ if (opcode & BPF_JMP32_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)
+                    == BPF_JMP32_BPF_K_BPF_JLT_TO_BPF_JSLE
+                    && (ebpf_consts::BPF_JLT..=ebpf_consts::BPF_JSLE)
+                        .contains(&(opcode & !BPF_JMP32_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)) =>
+                {
+                    #[allow(unused_variables)]
+                    let matched: u8 = opcode;
+                    let pc = *vm.pc();
+                    let width = width_of(matched);
+                    let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
+                    let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
+                    let _ = src_r;
+                    let src_r = -1i8;
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    let operation = matched & mask::BPF_OPCODE_JMP_MASK;
+                    let fork = Fork {
+                        target: pc.wrapping_add_signed(insn.off as isize),
+                        fall_through: pc,
+                    };
+                    let fork =
+                        if matches!(operation, BPF_JNE | BPF_JGT | BPF_JGE | BPF_JSGT | BPF_JSGE) {
+                            Fork {
+                                target: fork.fall_through,
+                                fall_through: fork.target,
+                            }
+                        } else {
+                            fork
+                        };
+                    let (dst, src) = ((dst_r as i8, dst), (src_r, src));
+                    let result = match operation {
+                        BPF_JEQ | BPF_JNE => vm_bak.jeq(dst, src, fork, width),
+                        BPF_JLT | BPF_JGE => vm_bak.jlt(dst, src, fork, width),
+                        BPF_JLE | BPF_JGT => vm_bak.jle(dst, src, fork, width),
+                        BPF_JSLT | BPF_JSGE => vm_bak.jslt(dst, src, fork, width),
+                        BPF_JSLE | BPF_JSGT => vm_bak.jsle(dst, src, fork, width),
+                        _ => vm_bak.jset(dst, src, fork, width),
+                    };
+                    *vm.pc() = *vm_bak.pc();
+                    vm.jumped(pc - 1);
+                    if let Some(branch) = result {
+                        branch.borrow_mut().jumped(pc - 1);
+                        context.add_pending_branch(branch);
+                    }
+                }
+                _ if (opcode & BPF_JMP_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)
+                    == BPF_JMP_BPF_X_BPF_JEQ_TO_BPF_JSGE
+                    && (ebpf_consts::BPF_JEQ..=ebpf_consts::BPF_JSGE)
+                        .contains(&(opcode & !BPF_JMP_BPF_X_BPF_JEQ_TO_BPF_JSGE_MASK)) =>
+                {
+                    #[allow(unused_variables)]
+                    let matched: u8 = opcode;
+                    let pc = *vm.pc();
+                    let width = width_of(matched);
+                    let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
+                    let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, src_r));
+                    let src_r = src_r as i8;
+                    let operation = matched & mask::BPF_OPCODE_JMP_MASK;
+                    let fork = Fork {
+                        target: pc.wrapping_add_signed(insn.off as isize),
+                        fall_through: pc,
+                    };
+                    let fork =
+                        if matches!(operation, BPF_JNE | BPF_JGT | BPF_JGE | BPF_JSGT | BPF_JSGE) {
+                            Fork {
+                                target: fork.fall_through,
+                                fall_through: fork.target,
+                            }
+                        } else {
+                            fork
+                        };
+                    let (dst, src) = ((dst_r as i8, dst), (src_r, src));
+                    let result = match operation {
+                        BPF_JEQ | BPF_JNE => vm_bak.jeq(dst, src, fork, width),
+                        BPF_JLT | BPF_JGE => vm_bak.jlt(dst, src, fork, width),
+                        BPF_JLE | BPF_JGT => vm_bak.jle(dst, src, fork, width),
+                        BPF_JSLT | BPF_JSGE => vm_bak.jslt(dst, src, fork, width),
+                        BPF_JSLE | BPF_JSGT => vm_bak.jsle(dst, src, fork, width),
+                        _ => vm_bak.jset(dst, src, fork, width),
+                    };
+                    *vm.pc() = *vm_bak.pc();
+                    vm.jumped(pc - 1);
+                    if let Some(branch) = result {
+                        branch.borrow_mut().jumped(pc - 1);
+                        context.add_pending_branch(branch);
+                    }
+                }
+                _ if (opcode & BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)
+                    == BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE
+                    && (ebpf_consts::BPF_JLT..=ebpf_consts::BPF_JSLE)
+                        .contains(&(opcode & !BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)) =>
+                {
+                    #[allow(unused_variables)]
+                    let matched: u8 = opcode;
+                    let pc = *vm.pc();
+                    let width = width_of(matched);
+                    let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
+                    let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
+                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, src_r));
+                    let src_r = src_r as i8;
+                    let operation = matched & mask::BPF_OPCODE_JMP_MASK;
+                    let fork = Fork {
+                        target: pc.wrapping_add_signed(insn.off as isize),
+                        fall_through: pc,
+                    };
+                    let fork =
+                        if matches!(operation, BPF_JNE | BPF_JGT | BPF_JGE | BPF_JSGT | BPF_JSGE) {
+                            Fork {
+                                target: fork.fall_through,
+                                fall_through: fork.target,
+                            }
+                        } else {
+                            fork
+                        };
+                    let (dst, src) = ((dst_r as i8, dst), (src_r, src));
+                    let result = match operation {
+                        BPF_JEQ | BPF_JNE => vm_bak.jeq(dst, src, fork, width),
+                        BPF_JLT | BPF_JGE => vm_bak.jlt(dst, src, fork, width),
+                        BPF_JLE | BPF_JGT => vm_bak.jle(dst, src, fork, width),
+                        BPF_JSLT | BPF_JSGE => vm_bak.jslt(dst, src, fork, width),
+                        BPF_JSLE | BPF_JSGT => vm_bak.jsle(dst, src, fork, width),
+                        _ => vm_bak.jset(dst, src, fork, width),
+                    };
+                    *vm.pc() = *vm_bak.pc();
+                    vm.jumped(pc - 1);
+                    if let Some(branch) = result {
+                        branch.borrow_mut().jumped(pc - 1);
+                        context.add_pending_branch(branch);
+                    }
+                }
+                _ if (opcode & BPF_JMP_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)
+                    == BPF_JMP_BPF_K_BPF_JEQ_TO_BPF_JSGE
+                    && (ebpf_consts::BPF_JEQ..=ebpf_consts::BPF_JSGE)
+                        .contains(&(opcode & !BPF_JMP_BPF_K_BPF_JEQ_TO_BPF_JSGE_MASK)) =>
+                {
+                    #[allow(unused_variables)]
+                    let matched: u8 = opcode;
+                    let pc = *vm.pc();
+                    let width = width_of(matched);
+                    let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
+                    let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
+                    let _ = src_r;
+                    let src_r = -1i8;
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    let operation = matched & mask::BPF_OPCODE_JMP_MASK;
+                    let fork = Fork {
+                        target: pc.wrapping_add_signed(insn.off as isize),
+                        fall_through: pc,
+                    };
+                    let fork =
+                        if matches!(operation, BPF_JNE | BPF_JGT | BPF_JGE | BPF_JSGT | BPF_JSGE) {
+                            Fork {
+                                target: fork.fall_through,
+                                fall_through: fork.target,
+                            }
+                        } else {
+                            fork
+                        };
+                    let (dst, src) = ((dst_r as i8, dst), (src_r, src));
+                    let result = match operation {
+                        BPF_JEQ | BPF_JNE => vm_bak.jeq(dst, src, fork, width),
+                        BPF_JLT | BPF_JGE => vm_bak.jlt(dst, src, fork, width),
+                        BPF_JLE | BPF_JGT => vm_bak.jle(dst, src, fork, width),
+                        BPF_JSLT | BPF_JSGE => vm_bak.jslt(dst, src, fork, width),
+                        BPF_JSLE | BPF_JSGT => vm_bak.jsle(dst, src, fork, width),
+                        _ => vm_bak.jset(dst, src, fork, width),
+                    };
+                    *vm.pc() = *vm_bak.pc();
+                    vm.jumped(pc - 1);
+                    if let Some(branch) = result {
+                        branch.borrow_mut().jumped(pc - 1);
+                        context.add_pending_branch(branch);
+                    }
+                }
+                _ if (opcode & BPF_JMP_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)
+                    == BPF_JMP_BPF_K_BPF_JLT_TO_BPF_JSLE
+                    && (ebpf_consts::BPF_JLT..=ebpf_consts::BPF_JSLE)
+                        .contains(&(opcode & !BPF_JMP_BPF_K_BPF_JLT_TO_BPF_JSLE_MASK)) =>
+                {
+                    #[allow(unused_variables)]
+                    let matched: u8 = opcode;
+                    let pc = *vm.pc();
+                    let width = width_of(matched);
+                    let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
+                    let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
+                    let _ = src_r;
+                    let src_r = -1i8;
+                    let src = &mut Value::constanti32(insn.imm);
+                    let dst = vm.reg(dst_r);
+                    let operation = matched & mask::BPF_OPCODE_JMP_MASK;
+                    let fork = Fork {
+                        target: pc.wrapping_add_signed(insn.off as isize),
+                        fall_through: pc,
+                    };
+                    let fork =
+                        if matches!(operation, BPF_JNE | BPF_JGT | BPF_JGE | BPF_JSGT | BPF_JSGE) {
+                            Fork {
+                                target: fork.fall_through,
+                                fall_through: fork.target,
+                            }
+                        } else {
+                            fork
+                        };
+                    let (dst, src) = ((dst_r as i8, dst), (src_r, src));
+                    let result = match operation {
+                        BPF_JEQ | BPF_JNE => vm_bak.jeq(dst, src, fork, width),
+                        BPF_JLT | BPF_JGE => vm_bak.jlt(dst, src, fork, width),
+                        BPF_JLE | BPF_JGT => vm_bak.jle(dst, src, fork, width),
+                        BPF_JSLT | BPF_JSGE => vm_bak.jslt(dst, src, fork, width),
+                        BPF_JSLE | BPF_JSGT => vm_bak.jsle(dst, src, fork, width),
+                        _ => vm_bak.jset(dst, src, fork, width),
+                    };
+                    *vm.pc() = *vm_bak.pc();
+                    vm.jumped(pc - 1);
+                    if let Some(branch) = result {
+                        branch.borrow_mut().jumped(pc - 1);
+                        context.add_pending_branch(branch);
+                    }
+                }
+                BPF_JMP_BPF_JA => {
+                    let offset = insn.off as isize;
+                    let from = *vm.pc() - 1;
+                    *vm.pc() = vm.pc().wrapping_add_signed(offset);
+                    vm.jumped(from);
+                }
+                BPF_JMP32_BPF_JA => {
+                    let offset = insn.imm as isize;
+                    let from = *vm.pc() - 1;
+                    *vm.pc() = vm.pc().wrapping_add_signed(offset);
+                    vm.jumped(from);
+                }
+                BPF_JMP_BPF_EXIT => {
+                    if vm.return_relative() {
+                        *vm.pc() = *vm.pc();
+                    } else {
+                        return;
+                    }
+                }
+                BPF_JMP_BPF_CALL => {
+                    run_call(insn, vm, context);
+                }
+                BPF_LDX_BPF_MEM_BPF_B => {
+                    const SIZE: usize = 1;
+                    let src = vm.ro_reg(insn.src_reg());
+                    let provenance = provenance_of(context, src, insn.off);
+                    if let Some(value) = unsafe { src.get_at(insn.off, SIZE) } {
+                        *vm.reg(insn.dst_reg()) = value;
+                        notify_load(context, provenance, SIZE);
+                    } else {
+                        vm.invalidate("Illegal access");
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_LDX_BPF_MEM_BPF_H => {
+                    const SIZE: usize = 2;
+                    let src = vm.ro_reg(insn.src_reg());
+                    let provenance = provenance_of(context, src, insn.off);
+                    if let Some(value) = unsafe { src.get_at(insn.off, SIZE) } {
+                        *vm.reg(insn.dst_reg()) = value;
+                        notify_load(context, provenance, SIZE);
+                    } else {
+                        vm.invalidate("Illegal access");
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_LDX_BPF_MEM_BPF_W => {
+                    const SIZE: usize = 4;
+                    let src = vm.ro_reg(insn.src_reg());
+                    let provenance = provenance_of(context, src, insn.off);
+                    if let Some(value) = unsafe { src.get_at(insn.off, SIZE) } {
+                        *vm.reg(insn.dst_reg()) = value;
+                        notify_load(context, provenance, SIZE);
+                    } else {
+                        vm.invalidate("Illegal access");
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_LDX_BPF_MEM_BPF_DW => {
+                    const SIZE: usize = 8;
+                    let src = vm.ro_reg(insn.src_reg());
+                    let provenance = provenance_of(context, src, insn.off);
+                    if let Some(value) = unsafe { src.get_at(insn.off, SIZE) } {
+                        *vm.reg(insn.dst_reg()) = value;
+                        notify_load(context, provenance, SIZE);
+                    } else {
+                        vm.invalidate("Illegal access");
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_STX_BPF_MEM_BPF_B => {
+                    const SIZE: usize = 1;
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    let src = vm.ro_reg(insn.src_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, src) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_STX_BPF_MEM_BPF_H => {
+                    const SIZE: usize = 2;
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    let src = vm.ro_reg(insn.src_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, src) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_STX_BPF_MEM_BPF_W => {
+                    const SIZE: usize = 4;
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    let src = vm.ro_reg(insn.src_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, src) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_STX_BPF_MEM_BPF_DW => {
+                    const SIZE: usize = 8;
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    let src = vm.ro_reg(insn.src_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, src) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.src_reg());
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_ST_BPF_MEM_BPF_B => {
+                    const SIZE: usize = 1;
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, &Value::constant64(insn.imm as u32 as u64)) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_ST_BPF_MEM_BPF_H => {
+                    const SIZE: usize = 2;
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, &Value::constant64(insn.imm as u32 as u64)) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_ST_BPF_MEM_BPF_W => {
+                    const SIZE: usize = 4;
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, &Value::constant64(insn.imm as u32 as u64)) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_ST_BPF_MEM_BPF_DW => {
+                    const SIZE: usize = 8;
+                    let dst = vm.ro_reg(insn.dst_reg());
+                    unsafe {
+                        if dst.set_at(insn.off, SIZE, &Value::constant64(insn.imm as u32 as u64)) {
+                            notify_store(context, provenance_of(context, dst, insn.off), SIZE);
+                        } else {
+                            vm.invalidate("Illegal access");
+                        }
+                    }
+                    vm.update_reg(insn.dst_reg());
+                }
+                BPF_LD_BPF_IMM_BPF_DW => {
+                    let next = code[*vm.pc()];
+                    match insn.src_reg() {
+                        BPF_IMM64_IMM => {
+                            let value = insn.imm as u32 as u64 | (next & 0xFFFF_FFFF_0000_0000);
+                            *vm.reg(insn.dst_reg()) = Value::constant64(value);
+                            vm.update_reg(insn.dst_reg());
+                        }
+                        _ => {
+                            if let Some(value) = vm.load_imm64(&insn, next) {
+                                *vm.reg(insn.dst_reg()) = value;
+                                vm.update_reg(insn.dst_reg());
+                            } else {
+                                vm.invalidate("Unsupported imm64 instruction");
+                                break;
+                            }
+                        }
+                    }
+                    *vm.pc() += 1;
+                }
+                BPF_STX_BPF_ATOMIC_BPF_B => {
+                    run_atomic(insn, vm, context, 1);
+                }
+                BPF_STX_BPF_ATOMIC_BPF_H => {
+                    run_atomic(insn, vm, context, 2);
+                }
+                #[cfg(feature = "atomic32")]
+                BPF_STX_BPF_ATOMIC_BPF_W => {
+                    run_atomic(insn, vm, context, 4);
+                }
+                #[cfg(feature = "atomic64")]
+                BPF_STX_BPF_ATOMIC_BPF_DW => {
+                    run_atomic(insn, vm, context, 8);
+                }
+                _ => {
+                    vm.invalidate("Unrecognized opcode");
+                    break;
+                }
+            }
+        };
+    }
+}
+
+/// The operand width of an ALU or a JMP instruction, 32 for `BPF_ALU` and `BPF_JMP32`
+fn width_of(opcode: u8) -> u8 {
+    match opcode & mask::BPF_OPCODE_CLASS_MASK {
+        BPF_ALU | BPF_JMP32 => 32,
+        _ => 64,
+    }
+}
+
+fn run_atomic<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
+    insn: Instruction,
+    vm: &mut RefMut<M>,
+    context: &mut C,
+    size: usize,
+) {
+    let provenance = provenance_of(context, vm.ro_reg(insn.dst_reg()), insn.off);
+    let atomic_code = insn.imm;
+    {
+        const BPF_ATOMIC_FETCH_BPF_ATOMIC_ADD: i32 =
+            ebpf_consts::BPF_ATOMIC_FETCH | ebpf_consts::BPF_ATOMIC_ADD;
+        const BPF_ATOMIC_FETCH_BPF_ATOMIC_OR: i32 =
+            ebpf_consts::BPF_ATOMIC_FETCH | ebpf_consts::BPF_ATOMIC_OR;
+        const BPF_ATOMIC_FETCH_BPF_ATOMIC_AND: i32 =
+            ebpf_consts::BPF_ATOMIC_FETCH | ebpf_consts::BPF_ATOMIC_AND;
+        const BPF_ATOMIC_FETCH_BPF_ATOMIC_XOR: i32 =
+            ebpf_consts::BPF_ATOMIC_FETCH | ebpf_consts::BPF_ATOMIC_XOR;
+        const BPF_ATOMIC_NO_FETCH_BPF_ATOMIC_ADD: i32 =
+            ebpf_consts::BPF_ATOMIC_NO_FETCH | ebpf_consts::BPF_ATOMIC_ADD;
+        const BPF_ATOMIC_NO_FETCH_BPF_ATOMIC_OR: i32 =
+            ebpf_consts::BPF_ATOMIC_NO_FETCH | ebpf_consts::BPF_ATOMIC_OR;
+        const BPF_ATOMIC_NO_FETCH_BPF_ATOMIC_AND: i32 =
+            ebpf_consts::BPF_ATOMIC_NO_FETCH | ebpf_consts::BPF_ATOMIC_AND;
+        const BPF_ATOMIC_NO_FETCH_BPF_ATOMIC_XOR: i32 =
+            ebpf_consts::BPF_ATOMIC_NO_FETCH | ebpf_consts::BPF_ATOMIC_XOR;
+        const BPF_ATOMIC_FETCH_BPF_ATOMIC_XCHG: i32 =
+            ebpf_consts::BPF_ATOMIC_FETCH | ebpf_consts::BPF_ATOMIC_XCHG;
+        const BPF_ATOMIC_FETCH_BPF_ATOMIC_CMPXCHG: i32 =
+            ebpf_consts::BPF_ATOMIC_FETCH | ebpf_consts::BPF_ATOMIC_CMPXCHG;
+        match atomic_code {
+            BPF_ATOMIC_FETCH_BPF_ATOMIC_ADD => {
+                let src_r = insn.src_reg();
+                let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
+                let result = dst.fetch_add(insn.off, src, size);
+                if let Err(err) = &result {
+                    vm.invalidate(err.message());
+                    return;
+                }
+                if let Ok(old) = result {
+                    *vm.reg(src_r) = old;
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(src_r);
+            }
+            BPF_ATOMIC_FETCH_BPF_ATOMIC_OR => {
+                let src_r = insn.src_reg();
+                let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
+                let result = dst.fetch_or(insn.off, src, size);
+                if let Err(err) = &result {
+                    vm.invalidate(err.message());
+                    return;
+                }
+                if let Ok(old) = result {
+                    *vm.reg(src_r) = old;
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(src_r);
+            }
+            BPF_ATOMIC_FETCH_BPF_ATOMIC_AND => {
+                let src_r = insn.src_reg();
+                let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
+                let result = dst.fetch_and(insn.off, src, size);
+                if let Err(err) = &result {
+                    vm.invalidate(err.message());
+                    return;
+                }
+                if let Ok(old) = result {
+                    *vm.reg(src_r) = old;
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(src_r);
+            }
+            BPF_ATOMIC_FETCH_BPF_ATOMIC_XOR => {
+                let src_r = insn.src_reg();
+                let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
+                let result = dst.fetch_xor(insn.off, src, size);
+                if let Err(err) = &result {
+                    vm.invalidate(err.message());
+                    return;
+                }
+                if let Ok(old) = result {
+                    *vm.reg(src_r) = old;
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(src_r);
+            }
+            BPF_ATOMIC_NO_FETCH_BPF_ATOMIC_ADD => {
+                let src_r = insn.src_reg();
+                let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
+                let result = dst.fetch_add(insn.off, src, size);
+                if let Err(err) = &result {
+                    vm.invalidate(err.message());
+                    return;
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(src_r);
+            }
+            BPF_ATOMIC_NO_FETCH_BPF_ATOMIC_OR => {
+                let src_r = insn.src_reg();
+                let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
+                let result = dst.fetch_or(insn.off, src, size);
+                if let Err(err) = &result {
+                    vm.invalidate(err.message());
+                    return;
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(src_r);
+            }
+            BPF_ATOMIC_NO_FETCH_BPF_ATOMIC_AND => {
+                let src_r = insn.src_reg();
+                let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
+                let result = dst.fetch_and(insn.off, src, size);
+                if let Err(err) = &result {
+                    vm.invalidate(err.message());
+                    return;
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(src_r);
+            }
+            BPF_ATOMIC_NO_FETCH_BPF_ATOMIC_XOR => {
+                let src_r = insn.src_reg();
+                let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
+                let result = dst.fetch_xor(insn.off, src, size);
+                if let Err(err) = &result {
+                    vm.invalidate(err.message());
+                    return;
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(src_r);
+            }
+            BPF_ATOMIC_FETCH_BPF_ATOMIC_XCHG => {
+                let src_r = insn.src_reg();
+                let (src, dst) = return_if_none!(vm.two_regs(src_r, insn.dst_reg()));
+                match dst.swap(insn.off, src, size) {
+                    Ok(old) => *vm.reg(src_r) = old,
+                    Err(err) => vm.invalidate(err.message()),
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(src_r);
+            }
+            BPF_ATOMIC_FETCH_BPF_ATOMIC_CMPXCHG => {
+                let src_r = insn.src_reg();
+                let (dst, src, expected) = return_if_none!(vm.three_regs(insn.dst_reg(), src_r, 0));
+                match dst.compare_exchange(insn.off, expected, src, size) {
+                    Ok(old) => *vm.reg(0) = old,
+                    Err(err) => vm.invalidate(err.message()),
+                }
+                vm.update_reg(insn.dst_reg());
+                vm.update_reg(0);
+                vm.update_reg(src_r);
+            }
+            _ => vm.invalidate("Atomic failed"),
+        }
+    };
+    if vm.is_valid() {
+        notify_load(context, provenance.clone(), size);
+        notify_store(context, provenance, size);
+    }
+}
@@ -2,7 +2,7 @@
 
 use num_traits::ToPrimitive;
 
-/// A callable function, used by [super::vm::UncheckedVm] as helper function pointers
+/// A callable function, used by [super::vm::UncheckedVm] and [super::vm::ConcreteVm] as helper function pointers
 pub type HelperPointer = fn(u64, u64, u64, u64, u64) -> u64;
 
 /// A collection of [HelperPointer]
@@ -1,25 +1,39 @@
-//! Implements a generic interpreter and a tiny VM.
+//! Implements a generic interpreter and tiny VMs, see [vm::ConcreteVm].
 //!
 //! The interpreter is designed to run eBPF intructions
 //! on a virtual [Vm], backed by a value implementation [VmValue],
 //! which can get transparently replaced from [u64] with
 //! a value type with verification purposes like [crate::branch::checked_value::CheckedValue].
 //!
-//! It also heavily uses the [opcode_match] macro, which messes around the code pieces.
+//! The instruction dispatch (see [run]) heavily uses the `opcode_match!` macro,
+//! which messes around the code pieces. With the `no-macros` feature (or without
+//! the default `macros` feature), a pre-expanded copy of it is used instead,
+//! so that the proc-macro dependency is not needed.
+//!
+//! The in-tree conformance harness (the `conformance` module) needs the `conformance` feature.
 
+#[cfg(feature = "conformance")]
+pub mod conformance;
 pub mod context;
 pub mod helper;
 pub mod value;
 pub mod vm;
 
-use core::cell::RefMut;
+use core::{
+    cell::{Cell, RefCell, RefMut},
+    ops::RangeInclusive,
+};
 
+use alloc::rc::Rc;
 use ebpf_consts::*;
-use opcode_macros::opcode_match;
 
-use crate::{interpreter::context::Fork, spec::Instruction};
+use crate::{spec::Instruction, track::id::Id};
 
-use self::{context::VmContext, value::VmValue, vm::Vm};
+use self::{
+    context::{AccessObserver, VmContext},
+    value::{Verifiable, VmValue},
+    vm::Vm,
+};
 
 macro_rules! break_if_none {
     ($value:expr) => {
@@ -40,367 +54,112 @@ macro_rules! return_if_none {
     };
 }
 
-/// Runs (or, interprets) the code on the given VM
-pub fn run<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
+#[cfg(all(feature = "macros", not(feature = "no-macros")))]
+mod dispatch;
+#[cfg(any(not(feature = "macros"), feature = "no-macros"))]
+#[path = "dispatch_expanded.rs"]
+mod dispatch;
+
+pub use self::dispatch::run;
+
+/// Runs the code like [run], but invalidates the VM if it does not exit within `budget` instructions
+///
+/// Unlike the instruction limit of the verifier, this limits actual executions,
+/// for example, of trusted programs with real loops.
+pub fn run_with_budget<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
     code: &[u64],
     vm: &mut RefMut<M>,
     context: &mut C,
+    budget: usize,
 ) {
-    while vm.is_valid() && context.is_valid() {
-        context.increment_pc();
-
-        let insn = Instruction::from_raw(code[*vm.pc()]);
-        *vm.pc() += 1;
-        let opcode = insn.opcode;
-        opcode_match! {
-            opcode in ebpf_consts,
-            // ALU / ALU64: Binary operators
-            [[BPF_ALU: ALU32, BPF_ALU64: ALU64], [BPF_X: X, BPF_K: K],
-             [
-                // Algebraic
-                BPF_ADD: add_assign,
-                BPF_SUB: sub_assign,
-                BPF_MUL: mul_assign,
-                BPF_DIV: safe_div_assign,
-                BPF_MOD: safe_rem_assign,
-                // Bitwise
-                BPF_AND: bitand_assign,
-                BPF_OR : bitor_assign,
-                BPF_XOR: bitxor_assign,
-             ]
-            ] => {
-                // Gettings the dst operant
-                let dst_r = insn.dst_reg();
-                // Gettings the src operant
-                #?((K))
-                    #?((ALU32))
-                        let src = &mut Value::constantu32(insn.imm as u32);
-                    ##
-                    #?((ALU64))
-                        let src = &mut Value::constanti32(insn.imm);
-                    ##
-                    let dst = vm.reg(dst_r);
-                ##
-                #?((X))
-                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
-                ##
-                #?((ALU32))
-                    let src = &src.lower_half();
-                ##
-
-                #?((ALU32))
-                    dst.lower_half_assign();
-                ##
-
-                #?((safe_div_assign,K)|(safe_rem_assign,K))
-                    if insn.imm == 0 {
-                        vm.invalidate("Div by 0");
-                        break;
-                    }
-                ##
-
-                dst.#=2(src);
-                #?((ALU32))
-                    dst.lower_half_assign();
-                ##
-                vm.update_reg(dst_r);
-            }
-            // BPF_ALU_MOV: Sign extending for BPF_K
-            [[BPF_ALU: ALU32, BPF_ALU64: ALU64], [BPF_X: X, BPF_K: K],
-             [BPF_MOV: mov]
-            ] => {
-                let dst_r = insn.dst_reg();
-                #?((K))
-                    #?((ALU32))
-                        let src = &mut Value::constantu32(insn.imm as u32);
-                    ##
-                    #?((ALU64))
-                        let src = &mut Value::constanti32(insn.imm);
-                    ##
-                    let dst = vm.reg(dst_r);
-                ##
-                #?((X))
-                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
-                ##
+    let mut budgeted = Budgeted {
+        context,
+        remaining: budget,
+        exhausted: Cell::new(false),
+    };
+    run(code, vm, &mut budgeted);
+    if budgeted.exhausted.get() {
+        vm.invalidate("Instruction budget exhausted");
+    }
+}
 
-                *dst = src.clone();
+/// Wraps a context, stopping the interpreter after a number of instructions
+struct Budgeted<'a, C> {
+    context: &'a mut C,
+    remaining: usize,
+    /// Set when the interpreter is stopped by us
+    exhausted: Cell<bool>,
+}
 
-                #?((ALU32))
-                    dst.zero_upper_half_assign();
-                ##
-                vm.update_reg(dst_r);
-            }
-            // Shifts: Width aware
-            [[BPF_ALU: ALU32, BPF_ALU64: ALU64], [BPF_X: X, BPF_K: K],
-             [
-                BPF_LSH: l_shift,
-                BPF_RSH: r_shift,
-                BPF_ARSH: signed_shr
-             ]
-            ] => {
-                // Gettings the dst operant
-                let dst_r = insn.dst_reg();
-                // Gettings the src operant
-                #?((K))
-                    let src = &mut Value::constantu32(insn.imm as u32);
-                    let dst = vm.reg(dst_r);
-                ##
-                #?((X))
-                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, insn.src_reg()));
-                ##
-                #?((ALU32))
-                    let width = 32;
-                ##
-                #?((ALU64))
-                    let width = 64;
-                ##
+impl<C: Verifiable> Verifiable for Budgeted<'_, C> {
+    fn is_valid(&self) -> bool {
+        if !self.context.is_valid() {
+            false
+        } else if self.remaining == 0 {
+            self.exhausted.set(true);
+            false
+        } else {
+            true
+        }
+    }
+}
 
-                #?((ALU32))
-                    dst.lower_half_assign();
-                ##
+impl<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>> VmContext<Value, M> for Budgeted<'_, C> {
+    fn add_pending_branch(&mut self, vm: Rc<RefCell<M>>) {
+        self.context.add_pending_branch(vm);
+    }
 
-                dst.#=2(src, width);
-                #?((ALU32))
-                    dst.lower_half_assign();
-                ##
-                vm.update_reg(dst_r);
-            }
-            // ALU / ALU64: Unary operators
-            [[BPF_ALU: ALU32, BPF_ALU64: ALU64], [BPF_K: K],
-             [
-                BPF_NEG: neg_assign,
-             ]
-            ] => {
-                let dst_r = insn.dst_reg();
-                let dst = vm.reg(dst_r);
-                dst.#=2();
-                #?((ALU32))
-                    dst.lower_half_assign();
-                ##
-                vm.update_reg(dst_r);
-            }
-            // ALU / ALU64: Byte swap
-            [[BPF_ALU: ALU32], [BPF_END: END],
-             [
-                BPF_TO_LE: host_to_le,
-                BPF_TO_BE: host_to_be,
-             ]
-            ] => {
-                let dst_r = insn.dst_reg();
-                let dst = vm.reg(dst_r);
-                dst.#=2(insn.imm);
-                vm.update_reg(dst_r);
-            }
-            // JMP32 / JMP: Conditional
-            [[BPF_JMP32: JMP32, BPF_JMP: JMP64], [BPF_X: X, BPF_K: K],
-             [
-                // Unsigned
-                BPF_JEQ: jeq,
-                BPF_JLT: jlt,
-                BPF_JLE: jle,
-                BPF_JSLT: jslt,
-                BPF_JSLE: jsle,
-                // Inverse
-                BPF_JNE: jeq,
-                BPF_JGT: jle,
-                BPF_JGE: jlt,
-                BPF_JSGT: jsle,
-                BPF_JSGE: jslt,
-                // Misc
-                BPF_JSET: jset,
-             ]
-            ] => {
-                let pc = *vm.pc();
-                #?((JMP32))
-                    let width = 32;
-                ##
-                #?((JMP64))
-                    let width = 64;
-                ##
+    fn increment_pc(&mut self) {
+        self.remaining -= 1;
+        self.context.increment_pc();
+    }
 
-                let vm_bak = unsafe { (vm.dup() as *mut M).as_mut().unwrap() };
-                let (dst_r, src_r) = (insn.dst_reg(), insn.src_reg());
-                #?((K))
-                    let _ = src_r;
-                    let src_r = -1i8;
-                    #?((BPF_JSGT)|(BPF_JSGE)|(BPF_JSLT)|(BPF_JSLE))
-                        let src = &mut Value::constanti32(insn.imm);
-                    ##
-                    #?((!BPF_JSGT,!BPF_JSGE,!BPF_JSLT,!BPF_JSLE))
-                        let src = &mut Value::constantu32(insn.imm as u32);
-                    ##
-                    let dst = vm.reg(dst_r);
-                ##
-                #?((X))
-                    let (dst, src) = break_if_none!(vm.two_regs(dst_r, src_r));
-                    let src_r = src_r as i8;
-                ##
-                let fork = Fork { target: pc.wrapping_add_signed(insn.off as isize), fall_through: pc };
-                #?((BPF_JNE)|(BPF_JGT)|(BPF_JGE)|(BPF_JSGT)|(BPF_JSGE))
-                    let fork = Fork { target: fork.fall_through, fall_through: fork.target };
-                ##
-                let result = vm_bak.#=2(
-                    (dst_r as i8, dst),
-                    (src_r, src),
-                    fork,
-                    width
-                );
-                *vm.pc() = *vm_bak.pc();
-                if let Some(branch) = result {
-                    context.add_pending_branch(branch);
-                }
-            }
-            // BPF_JA: Unconditional jump
-            [[BPF_JMP: JMP], [BPF_JA: JA]] => {
-                *vm.pc() = vm.pc().wrapping_add_signed(insn.off as isize);
-            }
-            // BPF_EXIT: Exits
-            [[BPF_JMP: JMP], [BPF_EXIT: EXIT]] => {
-                if vm.return_relative() {
-                    *vm.pc() = *vm.pc();
-                } else {
-                    return;
-                }
-            }
-            [[BPF_JMP: JMP], [BPF_CALL: CALL]] => {
-                run_call(insn, vm);
-            }
-            // Store / load
-            [[BPF_LDX: LDX, BPF_STX: STX, BPF_ST: ST], [BPF_MEM: MEM],
-             [
-                BPF_B: "1",
-                BPF_H: "2",
-                BPF_W: "4",
-                BPF_DW: "8",
-             ]
-            ] => {
-                const SIZE: usize = #=2;
-                #?((LDX))
-                    let src = vm.ro_reg(insn.src_reg());
-                    if let Some(value) = unsafe { src.get_at(insn.off, SIZE) } {
-                        *vm.reg(insn.dst_reg()) = value;
-                    } else {
-                        vm.invalidate("Illegal access");
-                    }
-                    vm.update_reg(insn.src_reg());
-                    vm.update_reg(insn.dst_reg());
-                ##
-                #?((STX))
-                    let dst = vm.ro_reg(insn.dst_reg());
-                    let src = vm.ro_reg(insn.src_reg());
-                    unsafe {
-                        if !dst.set_at(insn.off, SIZE, src) {
-                            vm.invalidate("Illegal access");
-                        }
-                    }
-                    vm.update_reg(insn.src_reg());
-                    vm.update_reg(insn.dst_reg());
-                ##
-                #?((ST))
-                    let dst = vm.ro_reg(insn.dst_reg());
-                    unsafe {
-                        if !dst.set_at(insn.off, SIZE, &Value::constant64(insn.imm as u32 as u64)) {
-                            vm.invalidate("Illegal access");
-                        }
-                    }
-                    vm.update_reg(insn.dst_reg());
-                ##
-            }
-            [[BPF_LD: LD], [BPF_IMM: IMM], [BPF_DW: DW]] => {
-                let next = code[*vm.pc()];
-                match insn.src_reg() {
-                    BPF_IMM64_IMM => {
-                        let value = insn.imm as u32 as u64 | (next & 0xFFFF_FFFF_0000_0000);
-                        *vm.reg(insn.dst_reg()) = Value::constant64(value);
-                        vm.update_reg(insn.dst_reg());
-                    }
-                    _ => {
-                        if let Some(value) = vm.load_imm64(&insn, next) {
-                            *vm.reg(insn.dst_reg()) = value;
-                            vm.update_reg(insn.dst_reg());
-                        } else {
-                            vm.invalidate("Unsupported imm64 instruction");
-                            break;
-                        }
-                    }
-                }
-                *vm.pc() += 1;
-            }
-            #[cfg(feature = "atomic32")]
-            [[BPF_STX: STX], [BPF_ATOMIC: ATOMIC], [BPF_W: W]] => {
-                run_atomic(insn, vm, 4);
-            }
-            #[cfg(feature = "atomic64")]
-            [[BPF_STX: STX], [BPF_ATOMIC: ATOMIC], [BPF_DW: DW]] => {
-                run_atomic(insn, vm, 8);
-            }
-            _ => {
-                vm.invalidate("Unrecognized opcode");
-                break;
-            }
-        };
+    fn observer(&self) -> Option<&dyn AccessObserver> {
+        self.context.observer()
     }
 }
 
-fn run_call<Value: VmValue, M: Vm<Value>>(insn: Instruction, vm: &mut RefMut<M>) {
+fn run_call<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
+    insn: Instruction,
+    vm: &mut RefMut<M>,
+    context: &mut C,
+) {
     match insn.src_reg() {
         BPF_CALL_HELPER => vm.call_helper(insn.imm),
         BPF_CALL_PSEUDO => vm.call_relative(insn.imm),
-        BPF_CALL_KFUNC => vm.invalidate("Unsupported BPF_CALL"),
+        BPF_CALL_KFUNC => {
+            context.prepare_kfunc(vm, insn.imm);
+            vm.call_kfunc(insn.imm);
+        }
         _ => vm.invalidate("Invalid BPF_CALL"),
     }
 }
 
-fn run_atomic<Value: VmValue, M: Vm<Value>>(insn: Instruction, vm: &mut RefMut<M>, size: usize) {
-    let atomic_code = insn.imm;
-    opcode_match! {
-        atomic_code as i32 in ebpf_consts,
-        [[BPF_ATOMIC_FETCH: FETCH, BPF_ATOMIC_NO_FETCH: NO_FETCH],
-         [
-            BPF_ATOMIC_ADD: "fetch_add",
-            BPF_ATOMIC_OR : "fetch_or",
-            BPF_ATOMIC_AND: "fetch_and",
-            BPF_ATOMIC_XOR: "fetch_xor",
-         ]
-        ] => {
-            let src_r = insn.src_reg();
-            let (dst, src) = return_if_none!(vm.two_regs(insn.dst_reg(), src_r));
-            let result = dst.#=1(insn.off, src, size);
-            if result.is_err() {
-                vm.invalidate("Atomic failed");
-                return;
-            }
-            #?((FETCH))
-                if let Ok(old) = result {
-                    *vm.reg(src_r) = old;
-                }
-            ##
-            vm.update_reg(insn.dst_reg());
-            vm.update_reg(src_r);
-        }
-        [[BPF_ATOMIC_FETCH: FETCH], [BPF_ATOMIC_XCHG: XCHG]] => {
-            let src_r =  insn.src_reg();
-            let (src, dst) = return_if_none!(vm.two_regs(src_r, insn.dst_reg()));
-            if let Ok(old) = dst.swap(insn.off, src, size) {
-                *vm.reg(src_r) = old;
-            } else {
-                vm.invalidate("Atomic failed");
-            }
-            vm.update_reg(insn.dst_reg());
-            vm.update_reg(src_r);
-        }
-        [[BPF_ATOMIC_FETCH: FETCH], [BPF_ATOMIC_CMPXCHG: CMPXCHG]] => {
-            let src_r = insn.src_reg();
-            let (dst, src, expected) = return_if_none!(vm.three_regs(insn.dst_reg(), src_r, 0));
-            if let Ok(old) = dst.compare_exchange(insn.off, expected, src, size) {
-                *vm.reg(0) = old;
-            } else {
-                vm.invalidate("Atomic failed");
-            }
-            vm.update_reg(insn.dst_reg());
-            vm.update_reg(0);
-            vm.update_reg(src_r);
-        }
-        _ => vm.invalidate("Atomic failed"),
-    };
+/// Fetches the provenance of a memory access only if there is an observer
+fn provenance_of<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
+    context: &C,
+    pointer: &Value,
+    offset: i16,
+) -> Option<(Id, RangeInclusive<i64>)> {
+    context.observer().and_then(|_| pointer.provenance(offset))
+}
+
+fn notify_load<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
+    context: &C,
+    provenance: Option<(Id, RangeInclusive<i64>)>,
+    size: usize,
+) {
+    if let (Some(observer), Some((region, offset))) = (context.observer(), provenance) {
+        observer.on_load(region, offset, size);
+    }
+}
+
+fn notify_store<Value: VmValue, M: Vm<Value>, C: VmContext<Value, M>>(
+    context: &C,
+    provenance: Option<(Id, RangeInclusive<i64>)>,
+    size: usize,
+) {
+    if let (Some(observer), Some((region, offset))) = (context.observer(), provenance) {
+        observer.on_store(region, offset, size);
+    }
 }
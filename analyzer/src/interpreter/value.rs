@@ -10,6 +10,8 @@ use core::ops::*;
 
 use ebpf_atomic::Atomic;
 
+use crate::track::id::Id;
+
 /// Representing casting between integer types
 ///
 /// It only handles truncation.
@@ -20,6 +22,10 @@ pub trait Castable {
     fn lower_half_assign(&mut self);
     /// Zeroes the upper half of self while keeping the lower half
     fn zero_upper_half_assign(&mut self);
+    /// Sign-extends the lowest `width` bits to 64 bits
+    ///
+    /// The bit `width` is either `8`, `16` or `32`.
+    fn sign_extend_assign(&mut self, width: u8);
 }
 
 impl Castable for u64 {
@@ -34,6 +40,15 @@ impl Castable for u64 {
     fn zero_upper_half_assign(&mut self) {
         *self &= 0xFFFF_FFFF
     }
+
+    fn sign_extend_assign(&mut self, width: u8) {
+        *self = match width {
+            8 => *self as i8 as i64 as u64,
+            16 => *self as i16 as i64 as u64,
+            32 => *self as i32 as i64 as u64,
+            _ => *self,
+        }
+    }
 }
 
 impl Castable for Wrapping<u64> {
@@ -48,6 +63,10 @@ impl Castable for Wrapping<u64> {
     fn zero_upper_half_assign(&mut self) {
         self.0.zero_upper_half_assign()
     }
+
+    fn sign_extend_assign(&mut self, width: u8) {
+        self.0.sign_extend_assign(width)
+    }
 }
 
 /// Used to validate a value
@@ -132,13 +151,13 @@ pub trait NegAssign {
 
 impl NegAssign for u64 {
     fn neg_assign(&mut self) {
-        *self = (-(*self as i64)) as u64
+        *self = self.wrapping_neg()
     }
 }
 
 impl NegAssign for Wrapping<u64> {
     fn neg_assign(&mut self) {
-        *self = Wrapping((-(self.0 as i64)) as u64)
+        *self = -*self
     }
 }
 
@@ -205,20 +224,28 @@ where
     /// It is only unsafe for interpreters.
     /// The verifier does not operate on raw pointers.
     unsafe fn set_at(&self, offset: i16, size: usize, value: &Self) -> bool;
+    /// Returns the region id and the possible offsets within the region
+    /// of the memory `offset` bytes away, used to notify [super::context::AccessObserver]s
+    ///
+    /// Values without provenance info (e.g., raw pointers) return `None`.
+    fn provenance(&self, _offset: i16) -> Option<(Id, RangeInclusive<i64>)> {
+        None
+    }
 }
 
 fn unchecked_add(x: u64, y: i16) -> u64 {
     x.wrapping_add_signed(y as i64)
 }
 
+// eBPF programs may access memory unaligned, e.g., packet fields
 impl Dereference for u64 {
     unsafe fn get_at(&self, offset: i16, size: usize) -> Option<Self> {
         let ptr = unchecked_add(*self, offset);
         Some(match size {
             1 => *(ptr as *const u8) as u64,
-            2 => *(ptr as *const u16) as u64,
-            4 => *(ptr as *const u32) as u64,
-            8 => *(ptr as *const u64),
+            2 => (ptr as *const u16).read_unaligned() as u64,
+            4 => (ptr as *const u32).read_unaligned() as u64,
+            8 => (ptr as *const u64).read_unaligned(),
             _ => 0,
         })
     }
@@ -227,9 +254,9 @@ impl Dereference for u64 {
         let ptr = unchecked_add(*self, offset);
         match size {
             1 => *(ptr as *mut u8) = *value as u8,
-            2 => *(ptr as *mut u16) = *value as u16,
-            4 => *(ptr as *mut u32) = *value as u32,
-            8 => *(ptr as *mut u64) = *value,
+            2 => (ptr as *mut u16).write_unaligned(*value as u16),
+            4 => (ptr as *mut u32).write_unaligned(*value as u32),
+            8 => (ptr as *mut u64).write_unaligned(*value),
             _ => return false,
         }
         true
@@ -256,6 +283,14 @@ pub trait SafeDivAssign<Rhs = Self>: DivAssign<Rhs> + RemAssign<Rhs> {
     fn safe_rem_assign(&mut self, rhs: Rhs) {
         self.rem_assign(rhs)
     }
+    /// Signed divisions with zero-division checking
+    ///
+    /// The bit `width` is either `32` or `64`.
+    fn signed_div_assign(&mut self, rhs: Rhs, width: u8);
+    /// Signed modulus with zero-division checking
+    ///
+    /// The bit `width` is either `32` or `64`.
+    fn signed_rem_assign(&mut self, rhs: Rhs, width: u8);
 }
 
 impl SafeDivAssign<&Self> for u64 {
@@ -274,6 +309,28 @@ impl SafeDivAssign<&Self> for u64 {
             self.rem_assign(rhs)
         }
     }
+
+    /// Signed division, wrapping `MIN / -1` around to `MIN`
+    fn signed_div_assign(&mut self, rhs: &Self, width: u8) {
+        if *rhs == 0 {
+            *self = 0
+        } else if width == 32 {
+            *self = (*self as i32).wrapping_div(*rhs as i32) as u32 as u64
+        } else {
+            *self = (*self as i64).wrapping_div(*rhs as i64) as u64
+        }
+    }
+
+    /// Signed modulus, with `MIN % -1` being zero
+    fn signed_rem_assign(&mut self, rhs: &Self, width: u8) {
+        if *rhs == 0 {
+            // Kept as is
+        } else if width == 32 {
+            *self = (*self as i32).wrapping_rem(*rhs as i32) as u32 as u64
+        } else {
+            *self = (*self as i64).wrapping_rem(*rhs as i64) as u64
+        }
+    }
 }
 
 impl SafeDivAssign<&Self> for Wrapping<u64> {
@@ -284,6 +341,14 @@ impl SafeDivAssign<&Self> for Wrapping<u64> {
     fn safe_rem_assign(&mut self, rhs: &Self) {
         self.0.safe_rem_assign(&rhs.0)
     }
+
+    fn signed_div_assign(&mut self, rhs: &Self, width: u8) {
+        self.0.signed_div_assign(&rhs.0, width)
+    }
+
+    fn signed_rem_assign(&mut self, rhs: &Self, width: u8) {
+        self.0.signed_rem_assign(&rhs.0, width)
+    }
 }
 
 /// Scalar operations
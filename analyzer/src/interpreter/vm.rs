@@ -1,9 +1,9 @@
 //! This file contains the [Vm] trait used by the interpreter
-//! as well as a VM implemtation [UncheckedVm] working as an interpreter.
+//! as well as VM implemtations [UncheckedVm] and [ConcreteVm] working as interpreters.
 
 use core::{cell::UnsafeCell, num::Wrapping, mem::swap};
 
-use alloc::vec::Vec;
+use alloc::{vec, vec::Vec};
 use ebpf_consts::{READABLE_REGISTER_COUNT, STACK_REGISTER, STACK_SIZE, WRITABLE_REGISTER_COUNT};
 
 use crate::{safe::{mut_borrow_items, safe_ref_unsafe_cell}, spec::Instruction};
@@ -51,14 +51,35 @@ pub trait Vm<Value: VmValue>: Forker<Value, Self> {
     fn update_reg(&mut self, reg: u8);
     /// Calls a helper function
     fn call_helper(&mut self, helper: i32);
+    /// Calls a kernel function (kfunc) by its BTF id
+    fn call_kfunc(&mut self, imm: i32);
     /// Calls a inner function
+    ///
+    /// The callee gets `r1` ~ `r5` as arguments and a new stack frame,
+    /// while the caller's `r6` ~ `r9` are saved in the call trace.
     fn call_relative(&mut self, imm: i32);
     /// Returns from a function
     ///
+    /// When returning to a caller, `r0` holds the return value, `r1` ~ `r5` are clobbered
+    /// and `r6` ~ `r10` are restored from the caller frame. Verifiers should mark the clobbered
+    /// registers as uninitialized, while interpreters may leave them as is.
+    ///
     /// It returns `false` if the stack frame is empty and the interpreter should now stop.
     fn return_relative(&mut self) -> bool;
     /// Loads an immediate value by relocation
     fn load_imm64(&mut self, insn: &Instruction, next: u64) -> Option<Value>;
+    /// Checks an addition or a subtraction before it is done, in case it moves a pointer
+    ///
+    /// Verifiers may invalidate the state if the pointer moves too far.
+    fn check_pointer_arithmetic(&self, _insn: &Instruction) {}
+    /// Notifies that the instruction at `pc` starts executing
+    ///
+    /// Verifiers may record it to locate errors.
+    fn executing(&mut self, _pc: usize) {}
+    /// Notifies that the jump instruction at `from` has set the pc, either jumping or not
+    ///
+    /// Verifiers may keep track of loops with it.
+    fn jumped(&mut self, _from: usize) {}
 }
 
 /// Saves the caller pc, callee saved registers and its stack
@@ -172,6 +193,10 @@ impl Vm<Wrapping<u64>> for UncheckedVm<Wrapping<u64>> {
         }
     }
 
+    fn call_kfunc(&mut self, _imm: i32) {
+        self.invalidate("Unsupported kfunc call");
+    }
+
     fn call_relative(&mut self, imm: i32) {
         let inner = self.0.get_mut();
         let mut new_stack: Vec<Value> = Vec::new();
@@ -226,7 +251,176 @@ impl<Value: VmValue> UncheckedVm<Value> {
         UncheckedVm(UnsafeCell::new(vm))
     }
 
+    /// Returns why the VM got invalidated, if it did
+    pub fn message(&self) -> Option<&'static str> {
+        self.inner().invalid
+    }
+
     fn inner(&self) -> &UncheckedInnerVm<Value> {
         safe_ref_unsafe_cell(&self.0)
     }
 }
+
+struct ConcreteInnerVm {
+    invalid: Option<&'static str>,
+    pc: usize,
+    call_trace: Vec<CallerContext<Value, Vec<u8>>>,
+    registers: [Value; READABLE_REGISTER_COUNT as usize],
+    stack: Vec<u8>,
+    helpers: HelperCollection,
+    /// Temporary value for double borrows, see [UncheckedInnerVm]
+    temp: Value,
+}
+
+impl ConcreteInnerVm {
+    fn frame_pointer(&self) -> Value {
+        Wrapping(self.stack.as_ptr() as u64 + STACK_SIZE as u64)
+    }
+}
+
+/// A VM backed by [Wrapping] [u64] values, executing programs for real
+///
+/// Unlike [UncheckedVm], each stack frame is a plain byte array, so that it behaves
+/// like the kernel one, making it suitable for differential testing against the JIT.
+/// Arithmetic wraps around like in eBPF.
+pub struct ConcreteVm(UnsafeCell<ConcreteInnerVm>);
+
+impl ConcreteVm {
+    /// Creates a zero-initialized VM
+    pub fn new(helpers: HelperCollection) -> Self {
+        let mut vm = ConcreteInnerVm {
+            invalid: None,
+            pc: 0,
+            call_trace: Vec::new(),
+            registers: Default::default(),
+            stack: vec![0; STACK_SIZE],
+            helpers,
+            temp: Wrapping(0),
+        };
+        vm.registers[STACK_REGISTER as usize] = vm.frame_pointer();
+        ConcreteVm(UnsafeCell::new(vm))
+    }
+
+    /// Returns why the VM got invalidated, if it did
+    pub fn message(&self) -> Option<&'static str> {
+        self.inner().invalid
+    }
+
+    /// Returns the current stack frame
+    pub fn stack(&self) -> &[u8] {
+        &self.inner().stack
+    }
+
+    fn inner(&self) -> &ConcreteInnerVm {
+        safe_ref_unsafe_cell(&self.0)
+    }
+}
+
+impl Vm<Value> for ConcreteVm {
+    fn is_valid(&self) -> bool {
+        self.inner().invalid.is_none()
+    }
+
+    fn invalidate(&self, message: &'static str) {
+        unsafe { (*self.0.get()).invalid = Some(message) }
+    }
+
+    fn pc(&mut self) -> &mut usize {
+        &mut self.0.get_mut().pc
+    }
+
+    fn reg(&mut self, i: u8) -> &mut Value {
+        if i < WRITABLE_REGISTER_COUNT {
+            &mut self.0.get_mut().registers[i as usize]
+        } else {
+            self.invalidate("Register not allowed");
+            &mut self.0.get_mut().registers[0]
+        }
+    }
+
+    fn ro_reg(&self, i: u8) -> &Value {
+        if i < READABLE_REGISTER_COUNT {
+            &self.inner().registers[i as usize]
+        } else {
+            self.invalidate("Register not allowed");
+            &self.inner().registers[0]
+        }
+    }
+
+    unsafe fn dup(&mut self) -> &mut Self {
+        (self as *mut Self).as_mut().unwrap()
+    }
+
+    fn two_regs(&mut self, i: u8, j: u8) -> Option<(&mut Value, &mut Value)> {
+        if i == j {
+            let inner = self.0.get_mut();
+            if i < WRITABLE_REGISTER_COUNT {
+                inner.temp = inner.registers[i as usize];
+                Some((&mut inner.registers[i as usize], &mut inner.temp))
+            } else {
+                None
+            }
+        } else {
+            mut_borrow_items!(self.0.get_mut().registers, [i as usize, j as usize], Value)
+        }
+    }
+
+    fn three_regs(&mut self, i: u8, j: u8, k: u8) -> Option<(&mut Value, &mut Value, &mut Value)> {
+        mut_borrow_items!(
+            self.0.get_mut().registers,
+            [i as usize, j as usize, k as usize],
+            Value
+        )
+    }
+
+    fn update_reg(&mut self, reg: u8) {
+        if !self.ro_reg(reg).is_valid() {
+            self.invalidate("Value invalid");
+        }
+    }
+
+    fn call_helper(&mut self, helper: i32) {
+        let inner = self.0.get_mut();
+        let [_, r1, r2, r3, r4, r5, ..] = inner.registers.map(|r| r.0);
+        if let Some(v) = inner.helpers.call_helper(helper, r1, r2, r3, r4, r5) {
+            inner.registers[0] = Wrapping(v);
+        } else {
+            self.invalidate("Helper not found");
+        }
+    }
+
+    fn call_kfunc(&mut self, _imm: i32) {
+        self.invalidate("Unsupported kfunc call");
+    }
+
+    fn call_relative(&mut self, imm: i32) {
+        let inner = self.0.get_mut();
+        let mut stack = vec![0; STACK_SIZE];
+        swap(&mut stack, &mut inner.stack);
+        let [.., r6, r7, r8, r9, _] = inner.registers;
+        inner.call_trace.push(CallerContext {
+            pc: inner.pc,
+            registers: [r6, r7, r8, r9],
+            stack,
+        });
+        inner.registers[10] = inner.frame_pointer();
+        inner.pc = inner.pc.wrapping_add_signed(imm as isize);
+    }
+
+    fn return_relative(&mut self) -> bool {
+        let inner = self.0.get_mut();
+        if let Some(caller) = inner.call_trace.pop() {
+            inner.pc = caller.pc;
+            inner.registers[6..=9].copy_from_slice(&caller.registers);
+            inner.stack = caller.stack;
+            inner.registers[10] = inner.frame_pointer();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn load_imm64(&mut self, _insn: &Instruction, _next: u64) -> Option<Value> {
+        None
+    }
+}
@@ -39,6 +39,7 @@ pub mod analyzer;
 pub mod blocks;
 pub mod branch;
 pub mod interpreter;
+pub mod loops;
 pub(crate) mod safe;
 pub mod spec;
 pub mod track;
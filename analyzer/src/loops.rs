@@ -0,0 +1,245 @@
+//! Natural loops and their induction variables, see [ProgramInfo::loops]
+
+use core::ops::Range;
+
+use alloc::{vec, vec::Vec};
+use ebpf_consts::{mask::*, *};
+
+use crate::{
+    blocks::{BlockId, FunctionBlock, ProgramInfo, TERMINAL_PSEUDO_BLOCK},
+    spec::{effect, instructions, CodeOffset, Instruction, ParsedInstruction},
+};
+
+/// The variable of a simple counted loop, stepped by a constant and compared against a bound
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InductionVariable {
+    /// The register holding the variable
+    pub register: u8,
+    /// The constant added to the register in each iteration, by the only instruction
+    /// in the loop writing to it (`BPF_ALU64 | BPF_ADD | BPF_K` or `BPF_SUB`)
+    pub step: i64,
+    /// The conditional jump comparing the register against an immediate,
+    /// being the only way out of the loop
+    pub exit: CodeOffset,
+    /// The opcode of the conditional jump
+    pub comparison: u8,
+    /// The immediate that the register is compared against
+    pub bound: i32,
+    /// Whether the loop goes on when the jump is taken, or otherwise when it falls through
+    pub continues_if_taken: bool,
+    /// Whether, starting from the loop header, the register gets stepped before compared
+    pub steps_first: bool,
+}
+
+/// A natural loop, formed by the back edges (see [FunctionBlock::back_edges]) to its header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loop {
+    /// The first instruction of the loop header
+    pub header: CodeOffset,
+    /// Instructions of the blocks in the loop, the header included
+    pub body: Vec<Range<CodeOffset>>,
+    /// The induction variable, if it is a simple counted loop
+    pub induction: Option<InductionVariable>,
+}
+
+impl Loop {
+    /// Whether the instruction is in the loop
+    pub fn contains(&self, pc: CodeOffset) -> bool {
+        self.body.iter().any(|range| range.contains(&pc))
+    }
+}
+
+impl ProgramInfo {
+    /// Finds the natural loops of all functions, sorted by their headers
+    ///
+    /// A loop is a simple counted loop (see [InductionVariable]) if:
+    /// - it has only one back edge, from a block called the latch;
+    /// - its only exit is a conditional jump, ending either the header or the latch,
+    ///   that compares a register against an immediate;
+    /// - that register is written only once in the loop, by adding (or subtracting)
+    ///   a constant in either the header or the latch.
+    ///
+    /// `code` should be the one this [ProgramInfo] is parsed from.
+    pub fn loops(&self, code: &[u64]) -> Vec<Loop> {
+        let mut loops = Vec::new();
+        for (i, function) in self.functions.iter().enumerate() {
+            let end = self
+                .functions
+                .get(i + 1)
+                .map_or(code.len(), |f| f.block_starts[0]);
+            let range = |block: BlockId| {
+                let next = function.block_starts.get(block + 1).copied();
+                function.block_starts[block]..next.unwrap_or(end)
+            };
+            let mut headers: Vec<(BlockId, Vec<BlockId>)> = Vec::new();
+            for (latch, header) in function.back_edges() {
+                match headers.iter_mut().find(|(h, _)| *h == header) {
+                    Some((_, latches)) => latches.push(latch),
+                    None => headers.push((header, vec![latch])),
+                }
+            }
+            for (header, latches) in headers {
+                let body = natural_loop(function, header, &latches);
+                let induction = induction_variable(code, function, &body, header, &latches, range);
+                let blocks = (0..function.block_count()).filter(|block| body[*block]);
+                loops.push(Loop {
+                    header: function.block_starts[header],
+                    body: blocks.map(range).collect(),
+                    induction,
+                });
+            }
+        }
+        loops.sort_unstable_by_key(|l| l.header);
+        loops
+    }
+}
+
+/// Marks the blocks reaching any of the latches without passing through the header
+fn natural_loop(function: &FunctionBlock, header: BlockId, latches: &[BlockId]) -> Vec<bool> {
+    let mut body = vec![false; function.block_count()];
+    body[header] = true;
+    let mut pending = latches.to_vec();
+    while let Some(block) = pending.pop() {
+        if !body[block] {
+            body[block] = true;
+            pending.extend(&function.to[block]);
+        }
+    }
+    body
+}
+
+/// Recognizes simple counted loops, see [ProgramInfo::loops]
+fn induction_variable(
+    code: &[u64],
+    function: &FunctionBlock,
+    body: &[bool],
+    header: BlockId,
+    latches: &[BlockId],
+    range: impl Fn(BlockId) -> Range<CodeOffset>,
+) -> Option<InductionVariable> {
+    let [latch] = latches else {
+        return None;
+    };
+    let in_loop =
+        |pc: CodeOffset| (0..body.len()).any(|block| body[block] && range(block).contains(&pc));
+    let mut exits = (0..body.len()).filter(|block| {
+        body[*block]
+            && function.from[*block]
+                .iter()
+                .any(|to| *to == TERMINAL_PSEUDO_BLOCK || !body[*to])
+    });
+    let exit_block = exits.next()?;
+    if exits.next().is_some() || (exit_block != header && exit_block != *latch) {
+        return None;
+    }
+
+    let exit = range(exit_block).end - 1;
+    let jump = Instruction::from_raw(code[exit]);
+    if !matches!(jump.opcode & BPF_OPCODE_CLASS_MASK, BPF_JMP | BPF_JMP32)
+        || jump.opcode & BPF_OPCODE_SRC_MASK != BPF_K
+        || matches!(
+            jump.opcode & BPF_OPCODE_JMP_MASK,
+            BPF_JA | BPF_CALL | BPF_EXIT
+        )
+    {
+        return None;
+    }
+    let register = jump.dst_reg();
+    let target = (exit + 1).wrapping_add_signed(jump.off as isize);
+
+    let mut writes = instructions(code).filter_map(|(pc, parsed)| {
+        let insn = match parsed {
+            ParsedInstruction::None => return None,
+            ParsedInstruction::Instruction(i) => i,
+            ParsedInstruction::WideInstruction(w) => w.instruction,
+        };
+        (in_loop(pc) && effect(&insn).writes(register)).then_some((pc, insn))
+    });
+    let (step_pc, step) = writes.next()?;
+    if writes.next().is_some() || step.imm == 0 {
+        return None;
+    }
+    let step = if step.opcode == BPF_ALU64 | BPF_ADD | BPF_K {
+        step.imm as i64
+    } else if step.opcode == BPF_ALU64 | BPF_SUB | BPF_K {
+        (step.imm as i64).wrapping_neg()
+    } else {
+        return None;
+    };
+    let step_block = if range(header).contains(&step_pc) {
+        header
+    } else if range(*latch).contains(&step_pc) {
+        *latch
+    } else {
+        return None;
+    };
+
+    Some(InductionVariable {
+        register,
+        step,
+        exit,
+        comparison: jump.opcode,
+        bound: jump.imm,
+        continues_if_taken: in_loop(target),
+        steps_first: !(exit_block == header && step_block == *latch && header != *latch),
+    })
+}
+
+#[test]
+fn test_loops() {
+    let exit = BPF_JMP_EXIT as u64;
+    let code: &[u64] = &[
+        // 0: r0 = 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        // 1: if r0 >= 10 goto +2
+        Instruction::pack(BPF_JMP | BPF_JGE | BPF_K, 0, 0, 2, 10),
+        // 2: r0 += 1
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+        // 3: goto -3
+        Instruction::pack(BPF_JMP | BPF_JA, 0, 0, -3, 0),
+        // 4: r1 = 5
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 5),
+        // 5: r1 -= 1
+        Instruction::pack(BPF_ALU64 | BPF_SUB | BPF_K, 0, 1, 0, 1),
+        // 6: if r1 == r0 goto +1
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_X, 0, 1, 1, 0),
+        // 7: if r1 s> 0 goto -3
+        Instruction::pack(BPF_JMP | BPF_JSGT | BPF_K, 0, 1, -3, 0),
+        // 8: exit
+        exit,
+    ];
+    let info = ProgramInfo::new(code).unwrap();
+    let loops = info.loops(code);
+    assert_eq!(loops.len(), 2);
+    assert_eq!(loops[0].header, 1);
+    assert_eq!(loops[0].body, [1..2, 2..4]);
+    assert!(loops[0].contains(3) && !loops[0].contains(4));
+    assert_eq!(
+        loops[0].induction,
+        Some(InductionVariable {
+            register: 0,
+            step: 1,
+            exit: 1,
+            comparison: BPF_JMP | BPF_JGE | BPF_K,
+            bound: 10,
+            continues_if_taken: false,
+            steps_first: false,
+        })
+    );
+    // Two ways out of the loop
+    assert_eq!(loops[1].header, 5);
+    assert_eq!(loops[1].body, [5..7, 7..8]);
+    assert_eq!(loops[1].induction, None);
+
+    // Without the early exit
+    let mut code = code.to_vec();
+    code[6] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 2, 0, 0);
+    let info = ProgramInfo::new(&code).unwrap();
+    let induction = info.loops(&code)[1].induction.unwrap();
+    assert_eq!((induction.register, induction.step), (1, -1));
+    assert!(induction.continues_if_taken && induction.steps_first);
+    // Writing the induction variable twice
+    code[6] = Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, 1);
+    let info = ProgramInfo::new(&code).unwrap();
+    assert_eq!(info.loops(&code)[1].induction, None);
+}
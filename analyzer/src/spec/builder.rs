@@ -0,0 +1,339 @@
+//! This module provides [ProgramBuilder] for assembling programs without hand-packing jumps.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::vec::Vec;
+use ebpf_consts::*;
+
+use super::Instruction;
+
+/// A jump target, created by [ProgramBuilder::label] and bound with [ProgramBuilder::bind]
+///
+/// It is only valid within the builder creating it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Label {
+    /// Id of the creating builder
+    builder: usize,
+    /// Index into the labels of the builder
+    index: usize,
+}
+
+/// Ids of builders, telling labels from different builders apart
+static BUILDER_IDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Errors from [ProgramBuilder::build]
+#[derive(Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// A label is used but never bound
+    UnboundLabel(Label),
+    /// A label created by another builder is used or bound
+    ForeignLabel(Label),
+    /// The jump at the instruction index cannot reach its target
+    OffsetOutOfRange(usize),
+}
+
+/// Where the relative offset to a label goes
+enum Fixup {
+    /// `off`, for jumps
+    Offset,
+    /// `imm`, for local function calls
+    Imm,
+}
+
+/// Assembles instructions, resolving relative offsets of jumps and local calls to labels
+///
+/// ```rust
+/// use ebpf_analyzer::spec::builder::ProgramBuilder;
+/// use ebpf_consts::*;
+///
+/// let mut builder = ProgramBuilder::new();
+/// let skip = builder.label();
+/// builder
+///     .alu64_imm(BPF_MOV, 0, 0)
+///     .jmp_imm(BPF_JEQ, 1, 0, skip)
+///     .alu64_imm(BPF_ADD, 0, 1)
+///     .bind(skip)
+///     .exit();
+/// assert_eq!(builder.build().unwrap().len(), 4);
+/// ```
+pub struct ProgramBuilder {
+    /// Id of the builder, see [Label]
+    id: usize,
+    code: Vec<u64>,
+    /// Instruction indices of bound labels
+    labels: Vec<Option<usize>>,
+    /// Instructions whose offsets are to be resolved
+    fixups: Vec<(usize, Label, Fixup)>,
+    /// The first foreign label passed to [ProgramBuilder::bind], reported on build
+    foreign: Option<Label>,
+}
+
+impl Default for ProgramBuilder {
+    fn default() -> Self {
+        ProgramBuilder {
+            id: BUILDER_IDS.fetch_add(1, Ordering::Relaxed),
+            code: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+            foreign: None,
+        }
+    }
+}
+
+impl ProgramBuilder {
+    /// Creates an empty builder
+    pub fn new() -> ProgramBuilder {
+        Self::default()
+    }
+
+    /// Number of instructions (wide ones counting as two) emitted so far
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Returns `true` if nothing is emitted yet
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    /// Creates a new label, to be bound later
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label {
+            builder: self.id,
+            index: self.labels.len() - 1,
+        }
+    }
+
+    /// Binds the label to the next instruction
+    ///
+    /// Labels from other builders are left unbound, failing [ProgramBuilder::build].
+    pub fn bind(&mut self, label: Label) -> &mut Self {
+        if label.builder == self.id {
+            self.labels[label.index] = Some(self.code.len());
+        } else {
+            self.foreign.get_or_insert(label);
+        }
+        self
+    }
+
+    /// Emits an encoded instruction as is
+    pub fn raw(&mut self, code: u64) -> &mut Self {
+        self.code.push(code);
+        self
+    }
+
+    fn emit(&mut self, opcode: u8, src_reg: u8, dst_reg: u8, offset: i16, imm: i32) -> &mut Self {
+        self.raw(Instruction::pack(opcode, src_reg, dst_reg, offset, imm))
+    }
+
+    fn emit_to(
+        &mut self,
+        label: Label,
+        fixup: Fixup,
+        opcode: u8,
+        src_reg: u8,
+        dst_reg: u8,
+        imm: i32,
+    ) -> &mut Self {
+        self.fixups.push((self.code.len(), label, fixup));
+        self.emit(opcode, src_reg, dst_reg, 0, imm)
+    }
+
+    /// `dst = dst op imm` (64-bit), `op` being `BPF_ADD`, `BPF_MOV`, etc.
+    pub fn alu64_imm(&mut self, op: u8, dst: u8, imm: i32) -> &mut Self {
+        self.emit(BPF_ALU64 | BPF_K | op, 0, dst, 0, imm)
+    }
+
+    /// `dst = dst op src` (64-bit)
+    pub fn alu64_reg(&mut self, op: u8, dst: u8, src: u8) -> &mut Self {
+        self.emit(BPF_ALU64 | BPF_X | op, src, dst, 0, 0)
+    }
+
+    /// `dst = dst op imm` (32-bit)
+    pub fn alu32_imm(&mut self, op: u8, dst: u8, imm: i32) -> &mut Self {
+        self.emit(BPF_ALU | BPF_K | op, 0, dst, 0, imm)
+    }
+
+    /// `dst = dst op src` (32-bit)
+    pub fn alu32_reg(&mut self, op: u8, dst: u8, src: u8) -> &mut Self {
+        self.emit(BPF_ALU | BPF_X | op, src, dst, 0, 0)
+    }
+
+    /// `dst = value`, emitting a wide instruction
+    pub fn ld_imm64(&mut self, dst: u8, value: u64) -> &mut Self {
+        self.emit(BPF_LD | BPF_DW | BPF_IMM, 0, dst, 0, value as i32)
+            .raw(value & 0xFFFF_FFFF_0000_0000)
+    }
+
+    /// `dst = *(size *)(src + off)`, `size` being `BPF_B`, `BPF_H`, `BPF_W` or `BPF_DW`
+    pub fn load(&mut self, size: u8, dst: u8, src: u8, off: i16) -> &mut Self {
+        self.emit(BPF_LDX | BPF_MEM | size, src, dst, off, 0)
+    }
+
+    /// `*(size *)(dst + off) = src`
+    pub fn store_reg(&mut self, size: u8, dst: u8, off: i16, src: u8) -> &mut Self {
+        self.emit(BPF_STX | BPF_MEM | size, src, dst, off, 0)
+    }
+
+    /// `*(size *)(dst + off) = imm`
+    pub fn store_imm(&mut self, size: u8, dst: u8, off: i16, imm: i32) -> &mut Self {
+        self.emit(BPF_ST | BPF_MEM | size, 0, dst, off, imm)
+    }
+
+    /// `goto label`
+    pub fn ja(&mut self, label: Label) -> &mut Self {
+        self.emit_to(label, Fixup::Offset, BPF_JMP | BPF_JA, 0, 0, 0)
+    }
+
+    /// `if dst cond imm goto label`, `cond` being `BPF_JEQ`, `BPF_JGT`, etc.
+    pub fn jmp_imm(&mut self, cond: u8, dst: u8, imm: i32, label: Label) -> &mut Self {
+        self.emit_to(label, Fixup::Offset, BPF_JMP | BPF_K | cond, 0, dst, imm)
+    }
+
+    /// `if dst cond src goto label`
+    pub fn jmp_reg(&mut self, cond: u8, dst: u8, src: u8, label: Label) -> &mut Self {
+        self.emit_to(label, Fixup::Offset, BPF_JMP | BPF_X | cond, src, dst, 0)
+    }
+
+    /// `if (u32) dst cond imm goto label`
+    pub fn jmp32_imm(&mut self, cond: u8, dst: u8, imm: i32, label: Label) -> &mut Self {
+        self.emit_to(label, Fixup::Offset, BPF_JMP32 | BPF_K | cond, 0, dst, imm)
+    }
+
+    /// `if (u32) dst cond (u32) src goto label`
+    pub fn jmp32_reg(&mut self, cond: u8, dst: u8, src: u8, label: Label) -> &mut Self {
+        self.emit_to(label, Fixup::Offset, BPF_JMP32 | BPF_X | cond, src, dst, 0)
+    }
+
+    /// Calls a helper function
+    pub fn call(&mut self, helper: i32) -> &mut Self {
+        self.emit(BPF_JMP_CALL, BPF_CALL_HELPER, 0, 0, helper)
+    }
+
+    /// Calls a local function starting at the label
+    pub fn call_local(&mut self, label: Label) -> &mut Self {
+        self.emit_to(label, Fixup::Imm, BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0)
+    }
+
+    /// `exit`
+    pub fn exit(&mut self) -> &mut Self {
+        self.emit(BPF_JMP_EXIT, 0, 0, 0, 0)
+    }
+
+    /// Resolves the labels and returns the code
+    pub fn build(&self) -> Result<Vec<u64>, BuildError> {
+        if let Some(label) = self.foreign {
+            return Err(BuildError::ForeignLabel(label));
+        }
+        let mut code = self.code.clone();
+        for (pc, label, fixup) in &self.fixups {
+            if label.builder != self.id {
+                return Err(BuildError::ForeignLabel(*label));
+            }
+            let target = self.labels[label.index].ok_or(BuildError::UnboundLabel(*label))?;
+            let relative = target as i64 - (*pc as i64 + 1);
+            let mut insn = Instruction::from_raw(code[*pc]);
+            match fixup {
+                Fixup::Offset => {
+                    insn.off =
+                        i16::try_from(relative).map_err(|_| BuildError::OffsetOutOfRange(*pc))?;
+                }
+                Fixup::Imm => {
+                    insn.imm =
+                        i32::try_from(relative).map_err(|_| BuildError::OffsetOutOfRange(*pc))?;
+                }
+            }
+            code[*pc] = Instruction::pack(
+                insn.opcode,
+                insn.src_reg(),
+                insn.dst_reg(),
+                insn.off,
+                insn.imm,
+            );
+        }
+        Ok(code)
+    }
+}
+
+#[test]
+fn test_complex_normal() {
+    use crate::blocks::ProgramInfo;
+
+    let mut builder = ProgramBuilder::new();
+    let (recur, test) = (builder.label(), builder.label());
+    builder
+        // main:
+        .call_local(test)
+        .alu32_imm(BPF_MOV, 0, 0)
+        .exit()
+        .bind(recur)
+        .alu32_imm(BPF_MOV, 0, 0)
+        .call_local(recur)
+        .exit()
+        .bind(test)
+        .call_local(recur)
+        .alu32_imm(BPF_MOV, 0, 0)
+        .exit();
+    let code = builder.build().unwrap();
+    assert_eq!(
+        code,
+        [
+            Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 5),
+            Instruction::pack(BPF_ALU | BPF_K | BPF_MOV, 0, 0, 0, 0),
+            Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+            Instruction::pack(BPF_ALU | BPF_K | BPF_MOV, 0, 0, 0, 0),
+            Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, -2),
+            Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+            Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, -4),
+            Instruction::pack(BPF_ALU | BPF_K | BPF_MOV, 0, 0, 0, 0),
+            Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+        ]
+    );
+    assert_eq!(ProgramInfo::new(&code).unwrap().functions.len(), 3);
+}
+
+#[test]
+fn test_builder_jumps() {
+    let mut builder = ProgramBuilder::new();
+    let (start, end) = (builder.label(), builder.label());
+    builder
+        .bind(start)
+        .ld_imm64(1, 0x1234_5678_9ABC_DEF0)
+        .jmp_reg(BPF_JGT, 1, 2, end)
+        .ja(start)
+        .bind(end)
+        .exit();
+    assert_eq!(
+        builder.build().unwrap(),
+        [
+            Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, 0, 1, 0, 0x9ABC_DEF0u32 as i32),
+            0x1234_5678_0000_0000,
+            Instruction::pack(BPF_JMP | BPF_X | BPF_JGT, 2, 1, 1, 0),
+            Instruction::pack(BPF_JMP | BPF_JA, 0, 0, -4, 0),
+            Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+        ]
+    );
+
+    let mut builder = ProgramBuilder::new();
+    let unbound = builder.label();
+    builder.ja(unbound);
+    assert_eq!(builder.build(), Err(BuildError::UnboundLabel(unbound)));
+
+    let mut builder = ProgramBuilder::new();
+    let far = builder.label();
+    builder.ja(far);
+    for _ in 0..0x8000 {
+        builder.exit();
+    }
+    builder.bind(far).exit();
+    assert_eq!(builder.build(), Err(BuildError::OffsetOutOfRange(0)));
+
+    // Labels only work within their own builders
+    let (mut first, mut second) = (ProgramBuilder::new(), ProgramBuilder::new());
+    let (label, other) = (first.label(), second.label());
+    first.bind(label).ja(other).exit();
+    assert_eq!(first.build(), Err(BuildError::ForeignLabel(other)));
+    second.bind(label).exit();
+    assert_eq!(second.build(), Err(BuildError::ForeignLabel(label)));
+}
@@ -0,0 +1,530 @@
+//! This module renders instructions into human-readable text.
+//!
+//! The syntax mostly follows what LLVM and the kernel verifier log print,
+//! for example `r1 += 0x10`, `if r2 > r3 goto +5` or `*(u32 *)(r1 + 8) = r0`.
+
+use alloc::string::{String, ToString};
+use core::fmt::{Display, Formatter, Result};
+
+use ebpf_consts::mask::*;
+use ebpf_consts::*;
+
+use super::{Instruction, ParsedInstruction, WideInstruction};
+
+/// A register, `r*` for 64-bit operations or `w*` for 32-bit ones
+struct Reg(u8, bool);
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}{}", if self.1 { 'w' } else { 'r' }, self.0)
+    }
+}
+
+/// An immediate number, in signed hexadecimal
+struct Imm(i64);
+
+impl Display for Imm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if self.0 < 0 {
+            write!(f, "-{:#x}", self.0.unsigned_abs())
+        } else {
+            write!(f, "{:#x}", self.0)
+        }
+    }
+}
+
+/// A relative jump offset
+struct Jump(i32);
+
+impl Display for Jump {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:+}", self.0)
+    }
+}
+
+/// The operand size of a load / store instruction
+fn size_in_bits(opcode: u8) -> u8 {
+    match opcode & BPF_OPCODE_SIZE_MASK {
+        BPF_B => 8,
+        BPF_H => 16,
+        BPF_W => 32,
+        _ => 64,
+    }
+}
+
+/// A memory operand like `(u32 *)(r1 + 8)`
+struct Mem {
+    opcode: u8,
+    reg: u8,
+    off: i16,
+    signed: bool,
+}
+
+impl Display for Mem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let bits = size_in_bits(self.opcode);
+        let sign = if self.signed { 's' } else { 'u' };
+        write!(f, "({sign}{bits} *)(r{}", self.reg)?;
+        match self.off {
+            0 => f.write_str(")"),
+            off if off < 0 => write!(f, " - {})", off.unsigned_abs()),
+            off => write!(f, " + {off})"),
+        }
+    }
+}
+
+impl Instruction {
+    /// Renders the instruction into human-readable text
+    ///
+    /// Wide instructions only show `insn0.imm` here. Use [WideInstruction::disassemble]
+    /// to get the full 64-bit immediate number.
+    pub fn disassemble(self) -> String {
+        self.to_string()
+    }
+
+    fn is_32_bit(self) -> bool {
+        matches!(self.opcode & BPF_OPCODE_CLASS_MASK, BPF_ALU | BPF_JMP32)
+    }
+
+    fn dst(self) -> Reg {
+        Reg(self.dst_reg(), self.is_32_bit())
+    }
+
+    fn src(self) -> Reg {
+        Reg(self.src_reg(), self.is_32_bit())
+    }
+
+    fn mem(self, reg: u8, signed: bool) -> Mem {
+        Mem {
+            opcode: self.opcode,
+            reg,
+            off: self.off,
+            signed,
+        }
+    }
+
+    /// Writes either `src_reg` or the immediate number
+    fn fmt_source(self, f: &mut Formatter<'_>) -> Result {
+        if (self.opcode & BPF_OPCODE_SRC_MASK) == BPF_X {
+            self.src().fmt(f)
+        } else {
+            Imm(self.imm as i64).fmt(f)
+        }
+    }
+
+    fn fmt_alu(self, f: &mut Formatter<'_>) -> Result {
+        let dst = self.dst();
+        let op = match self.opcode & BPF_OPCODE_ALU_MASK {
+            BPF_ADD => "+=",
+            BPF_SUB => "-=",
+            BPF_MUL => "*=",
+            BPF_DIV if self.is_signed_division() => "s/=",
+            BPF_DIV => "/=",
+            BPF_MOD if self.is_signed_division() => "s%=",
+            BPF_MOD => "%=",
+            BPF_OR => "|=",
+            BPF_AND => "&=",
+            BPF_LSH => "<<=",
+            BPF_RSH => ">>=",
+            BPF_ARSH => "s>>=",
+            BPF_XOR => "^=",
+            BPF_NEG => return write!(f, "{dst} = -{dst}"),
+            BPF_MOV => match self.is_sign_extending_move() {
+                Some(width) => return write!(f, "{dst} = (s{width}){}", self.src()),
+                None => "=",
+            },
+            BPF_END => {
+                let name = if (self.opcode & BPF_OPCODE_CLASS_MASK) == BPF_ALU64 {
+                    "bswap"
+                } else if (self.opcode & BPF_OPCODE_SRC_MASK) == BPF_TO_BE {
+                    "be"
+                } else {
+                    "le"
+                };
+                let dst = Reg(self.dst_reg(), false);
+                return write!(f, "{dst} = {name}{} {dst}", self.imm);
+            }
+            _ => return self.fmt_invalid(f),
+        };
+        write!(f, "{dst} {op} ")?;
+        self.fmt_source(f)
+    }
+
+    fn fmt_jump(self, f: &mut Formatter<'_>) -> Result {
+        let target = Jump(self.off as i32);
+        let op = match self.opcode & BPF_OPCODE_JMP_MASK {
+            BPF_JA if (self.opcode & BPF_OPCODE_CLASS_MASK) == BPF_JMP32 => {
+                return write!(f, "gotol {}", Jump(self.imm));
+            }
+            BPF_JA => return write!(f, "goto {target}"),
+            BPF_JCOND => return write!(f, "may_goto {target}"),
+            BPF_EXIT => return f.write_str("exit"),
+            BPF_CALL => {
+                return match self.src_reg() {
+                    BPF_CALL_PSEUDO => write!(f, "call pc{}", Jump(self.imm)),
+                    BPF_CALL_KFUNC => write!(f, "call kfunc {}", Imm(self.imm as i64)),
+                    _ => write!(f, "call {}", self.imm),
+                };
+            }
+            BPF_JEQ => "==",
+            BPF_JGT => ">",
+            BPF_JGE => ">=",
+            BPF_JSET => "&",
+            BPF_JNE => "!=",
+            BPF_JSGT => "s>",
+            BPF_JSGE => "s>=",
+            BPF_JLT => "<",
+            BPF_JLE => "<=",
+            BPF_JSLT => "s<",
+            BPF_JSLE => "s<=",
+            _ => return self.fmt_invalid(f),
+        };
+        write!(f, "if {} {op} ", self.dst())?;
+        self.fmt_source(f)?;
+        write!(f, " goto {target}")
+    }
+
+    fn fmt_load(self, f: &mut Formatter<'_>) -> Result {
+        let bits = size_in_bits(self.opcode);
+        match self.opcode & BPF_OPCODE_MODIFIER_MASK {
+            BPF_IMM if bits == 64 => self.fmt_wide(f, self.imm as i64, 0),
+            // Legacy packet access
+            BPF_ABS => write!(f, "r0 = *(u{bits} *)skb[{}]", Imm(self.imm as i64)),
+            BPF_IND => write!(
+                f,
+                "r0 = *(u{bits} *)skb[{} + {}]",
+                Reg(self.src_reg(), false),
+                Imm(self.imm as i64)
+            ),
+            _ => self.fmt_invalid(f),
+        }
+    }
+
+    /// Writes a wide instruction given the 64-bit immediate number and `insn1.imm`
+    fn fmt_wide(self, f: &mut Formatter<'_>, imm64: i64, imm1: i32) -> Result {
+        let dst = self.dst();
+        match self.src_reg() {
+            BPF_IMM64_IMM => write!(f, "{dst} = ll {}", Imm(imm64)),
+            BPF_IMM64_MAP_FD => write!(f, "{dst} = map_fd {}", self.imm),
+            BPF_IMM64_MAP_IDX => write!(f, "{dst} = map_idx {}", self.imm),
+            BPF_IMM64_MAP_VALUE => {
+                write!(f, "{dst} = map_value {} + {}", self.imm, Imm(imm1 as i64))
+            }
+            BPF_IMM64_MAP_IDX_VALUE => {
+                write!(
+                    f,
+                    "{dst} = map_idx_value {} + {}",
+                    self.imm,
+                    Imm(imm1 as i64)
+                )
+            }
+            BPF_IMM64_BTF_ID => write!(f, "{dst} = btf_id {}", self.imm),
+            BPF_IMM64_FUNC => write!(f, "{dst} = func pc{}", Jump(self.imm)),
+            _ => self.fmt_invalid(f),
+        }
+    }
+
+    fn fmt_memory(self, f: &mut Formatter<'_>) -> Result {
+        let class = self.opcode & BPF_OPCODE_CLASS_MASK;
+        match (class, self.opcode & BPF_OPCODE_MODIFIER_MASK) {
+            (BPF_LDX, BPF_MEM) => write!(
+                f,
+                "{} = *{}",
+                Reg(self.dst_reg(), false),
+                self.mem(self.src_reg(), false)
+            ),
+            (BPF_LDX, BPF_MEMSX) => write!(
+                f,
+                "{} = *{}",
+                Reg(self.dst_reg(), false),
+                self.mem(self.src_reg(), true)
+            ),
+            (BPF_ST, BPF_MEM) => write!(
+                f,
+                "*{} = {}",
+                self.mem(self.dst_reg(), false),
+                Imm(self.imm as i64)
+            ),
+            (BPF_STX, BPF_MEM) => write!(
+                f,
+                "*{} = {}",
+                self.mem(self.dst_reg(), false),
+                Reg(self.src_reg(), false)
+            ),
+            (BPF_STX, BPF_ATOMIC) => self.fmt_atomic(f),
+            _ => self.fmt_invalid(f),
+        }
+    }
+
+    fn fmt_atomic(self, f: &mut Formatter<'_>) -> Result {
+        let mem = self.mem(self.dst_reg(), false);
+        let src = Reg(
+            self.src_reg(),
+            (self.opcode & BPF_OPCODE_SIZE_MASK) == BPF_W,
+        );
+        let (op, name) = match self.imm & !BPF_ATOMIC_FETCH {
+            BPF_ATOMIC_ADD => ("+=", "add"),
+            BPF_ATOMIC_OR => ("|=", "or"),
+            BPF_ATOMIC_AND => ("&=", "and"),
+            BPF_ATOMIC_XOR => ("^=", "xor"),
+            BPF_ATOMIC_XCHG_NO_FETCH if self.imm == BPF_ATOMIC_XCHG => {
+                return write!(f, "{src} = xchg({mem}, {src})");
+            }
+            BPF_ATOMIC_CMPXCHG_NO_FETCH if self.imm == BPF_ATOMIC_CMPXCHG => {
+                let r0 = Reg(0, src.1);
+                return write!(f, "{r0} = cmpxchg({mem}, {r0}, {src})");
+            }
+            _ => return self.fmt_invalid(f),
+        };
+        if (self.imm & BPF_ATOMIC_FETCH) == 0 {
+            write!(f, "lock *{mem} {op} {src}")
+        } else {
+            write!(f, "{src} = atomic_fetch_{name}({mem}, {src})")
+        }
+    }
+
+    fn fmt_invalid(self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "invalid {self:?}")
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self.opcode & BPF_OPCODE_CLASS_MASK {
+            BPF_ALU | BPF_ALU64 => self.fmt_alu(f),
+            BPF_JMP | BPF_JMP32 => self.fmt_jump(f),
+            BPF_LD => self.fmt_load(f),
+            _ => self.fmt_memory(f),
+        }
+    }
+}
+
+impl WideInstruction {
+    /// Renders the instruction into human-readable text, e.g., `r0 = ll 0x100000000`
+    pub fn disassemble(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for WideInstruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        self.instruction
+            .fmt_wide(f, self.imm64() as i64, self.imm1())
+    }
+}
+
+impl Display for ParsedInstruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            ParsedInstruction::None => f.write_str("invalid"),
+            ParsedInstruction::Instruction(i) => i.fmt(f),
+            ParsedInstruction::WideInstruction(w) => w.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+fn disassemble(opcode: u8, src: u8, dst: u8, off: i16, imm: i32) -> String {
+    Instruction::from_raw(Instruction::pack(opcode, src, dst, off, imm)).disassemble()
+}
+
+#[test]
+fn test_alu() {
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, 0x10),
+        "r1 += 0x10"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_SUB | BPF_K, 0, 1, 0, -1),
+        "r1 -= -0x1"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU | BPF_MUL | BPF_X, 2, 1, 0, 0),
+        "w1 *= w2"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_DIV | BPF_X, 2, 1, 0, 0),
+        "r1 /= r2"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_DIV | BPF_X, 2, 1, 1, 0),
+        "r1 s/= r2"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU | BPF_MOD | BPF_K, 0, 1, 1, 3),
+        "w1 s%= 0x3"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_OR | BPF_X, 2, 1, 0, 0),
+        "r1 |= r2"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_AND | BPF_K, 0, 1, 0, 0xFF),
+        "r1 &= 0xff"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_LSH | BPF_K, 0, 1, 0, 2),
+        "r1 <<= 0x2"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU | BPF_RSH | BPF_X, 2, 1, 0, 0),
+        "w1 >>= w2"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_ARSH | BPF_K, 0, 1, 0, 63),
+        "r1 s>>= 0x3f"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_XOR | BPF_X, 1, 1, 0, 0),
+        "r1 ^= r1"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_NEG | BPF_K, 0, 3, 0, 0),
+        "r3 = -r3"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+        "r1 = r10"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        "w0 = 0x0"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_MOV | BPF_X, 2, 1, 32, 0),
+        "r1 = (s32)r2"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU | BPF_MOV | BPF_X, 2, 1, 8, 0),
+        "w1 = (s8)w2"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU | BPF_END | BPF_TO_BE, 0, 1, 0, 16),
+        "r1 = be16 r1"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU | BPF_END | BPF_TO_LE, 0, 1, 0, 64),
+        "r1 = le64 r1"
+    );
+    assert_eq!(
+        disassemble(BPF_ALU64 | BPF_END, 0, 1, 0, 32),
+        "r1 = bswap32 r1"
+    );
+    assert!(disassemble(BPF_ALU64 | 0xE0, 0, 1, 0, 0).starts_with("invalid"));
+}
+
+#[test]
+fn test_jumps() {
+    assert_eq!(
+        disassemble(BPF_JMP | BPF_JGT | BPF_X, 3, 2, 5, 0),
+        "if r2 > r3 goto +5"
+    );
+    assert_eq!(
+        disassemble(BPF_JMP32 | BPF_JSLE | BPF_K, 0, 2, -3, -1),
+        "if w2 s<= -0x1 goto -3"
+    );
+    assert_eq!(
+        disassemble(BPF_JMP | BPF_JSET | BPF_K, 0, 1, 0, 8),
+        "if r1 & 0x8 goto +0"
+    );
+    assert_eq!(disassemble(BPF_JMP | BPF_JA, 0, 0, 2, 0), "goto +2");
+    assert_eq!(
+        disassemble(BPF_JMP32 | BPF_JA, 0, 0, 0, -70000),
+        "gotol -70000"
+    );
+    assert_eq!(disassemble(BPF_JMP | BPF_JCOND, 0, 0, 4, 0), "may_goto +4");
+    assert_eq!(disassemble(BPF_JMP_CALL, 0, 0, 0, 7), "call 7");
+    assert_eq!(
+        disassemble(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 3),
+        "call pc+3"
+    );
+    assert_eq!(
+        disassemble(BPF_JMP_CALL, BPF_CALL_KFUNC, 0, 0, 0x20),
+        "call kfunc 0x20"
+    );
+    assert_eq!(disassemble(BPF_JMP_EXIT, 0, 0, 0, 0), "exit");
+}
+
+#[test]
+fn test_memory() {
+    assert_eq!(
+        disassemble(BPF_LDX | BPF_MEM | BPF_W, 1, 0, 8, 0),
+        "r0 = *(u32 *)(r1 + 8)"
+    );
+    assert_eq!(
+        disassemble(BPF_LDX | BPF_MEMSX | BPF_B, 10, 0, -1, 0),
+        "r0 = *(s8 *)(r10 - 1)"
+    );
+    assert_eq!(
+        disassemble(BPF_ST | BPF_MEM | BPF_H, 0, 10, -2, 1),
+        "*(u16 *)(r10 - 2) = 0x1"
+    );
+    assert_eq!(
+        disassemble(BPF_STX | BPF_MEM | BPF_W, 0, 1, 8, 0),
+        "*(u32 *)(r1 + 8) = r0"
+    );
+    assert_eq!(
+        disassemble(BPF_STX | BPF_MEM | BPF_DW, 2, 1, 0, 0),
+        "*(u64 *)(r1) = r2"
+    );
+    assert_eq!(
+        disassemble(BPF_STX | BPF_ATOMIC | BPF_DW, 2, 1, 8, BPF_ATOMIC_ADD),
+        "lock *(u64 *)(r1 + 8) += r2"
+    );
+    assert_eq!(
+        disassemble(
+            BPF_STX | BPF_ATOMIC | BPF_W,
+            2,
+            1,
+            0,
+            BPF_ATOMIC_AND | BPF_ATOMIC_FETCH
+        ),
+        "w2 = atomic_fetch_and((u32 *)(r1), w2)"
+    );
+    assert_eq!(
+        disassemble(BPF_STX | BPF_ATOMIC | BPF_DW, 2, 1, 0, BPF_ATOMIC_XCHG),
+        "r2 = xchg((u64 *)(r1), r2)"
+    );
+    assert_eq!(
+        disassemble(BPF_STX | BPF_ATOMIC | BPF_DW, 2, 1, 0, BPF_ATOMIC_CMPXCHG),
+        "r0 = cmpxchg((u64 *)(r1), r0, r2)"
+    );
+    assert!(disassemble(
+        BPF_STX | BPF_ATOMIC | BPF_DW,
+        2,
+        1,
+        0,
+        BPF_ATOMIC_XCHG_NO_FETCH
+    )
+    .starts_with("invalid"));
+    // Legacy packet access
+    assert_eq!(
+        disassemble(BPF_LD | BPF_ABS | BPF_H, 0, 0, 0, 12),
+        "r0 = *(u16 *)skb[0xc]"
+    );
+    assert_eq!(
+        disassemble(BPF_LD | BPF_IND | BPF_B, 3, 0, 0, 1),
+        "r0 = *(u8 *)skb[r3 + 0x1]"
+    );
+}
+
+#[test]
+fn test_wide() {
+    let code = [
+        Instruction::pack(BPF_LD | BPF_IMM | BPF_DW, 0, 0, 0, 0x1234),
+        Instruction::pack(0, 0, 0, 0, 1),
+        Instruction::pack(BPF_LD | BPF_IMM | BPF_DW, BPF_IMM64_MAP_VALUE, 1, 0, 3),
+        Instruction::pack(0, 0, 0, 0, 8),
+        Instruction::pack(BPF_LD | BPF_IMM | BPF_DW, BPF_IMM64_FUNC, 2, 0, 5),
+        0,
+    ];
+    let disassemble = |pc| Instruction::from(&code, pc).to_string();
+    assert_eq!(disassemble(0), "r0 = ll 0x100001234");
+    assert_eq!(disassemble(2), "r1 = map_value 3 + 0x8");
+    assert_eq!(disassemble(4), "r2 = func pc+5");
+    assert_eq!(Instruction::from(&code[..1], 0).to_string(), "invalid");
+    assert_eq!(
+        Instruction::from_raw(code[0]).disassemble(),
+        "r0 = ll 0x1234"
+    );
+}
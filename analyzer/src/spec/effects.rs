@@ -0,0 +1,251 @@
+//! See [effect] and [live_registers].
+
+use alloc::vec::Vec;
+use ebpf_consts::mask::*;
+use ebpf_consts::*;
+
+use super::{instructions, CodeOffset, Instruction, JumpInstruction, ParsedInstruction};
+
+/// Registers `r1 ~ r5`, used for arguments of calls
+const ARGUMENT_REGISTERS: u16 = 0b11_1110;
+/// Registers `r0 ~ r5`, clobbered by calls
+const CALLER_SAVED_REGISTERS: u16 = 0b11_1111;
+
+/// A summary of what an instruction does, derived from its opcode only
+///
+/// Calls to helpers, kfuncs or subprograms read their arguments (`r1 ~ r5`),
+/// clobber `r0 ~ r5` and are assumed to both read and write memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InstructionEffect {
+    /// Registers read, bit `i` standing for `ri`
+    pub read_registers: u16,
+    /// Registers written, bit `i` standing for `ri`
+    pub written_registers: u16,
+    /// Whether it reads memory (other than the registers)
+    pub reads_memory: bool,
+    /// Whether it writes memory (other than the registers)
+    pub writes_memory: bool,
+    /// Whether it may continue at another instruction than the next one (calls and exits excluded)
+    pub branches: bool,
+}
+
+impl InstructionEffect {
+    /// Whether the register is read
+    pub fn reads(&self, reg: u8) -> bool {
+        self.read_registers & (1 << reg) != 0
+    }
+
+    /// Whether the register is written
+    pub fn writes(&self, reg: u8) -> bool {
+        self.written_registers & (1 << reg) != 0
+    }
+}
+
+/// Summarizes the effect of an instruction
+///
+/// For wide instructions, only the first half is needed.
+/// The instruction is not validated, see [Instruction::validate] for that.
+pub fn effect(insn: &Instruction) -> InstructionEffect {
+    let opcode = insn.opcode;
+    let dst = 1 << insn.dst_reg();
+    let src = 1 << insn.src_reg();
+    let uses_src = opcode & BPF_OPCODE_SRC_MASK == BPF_X;
+    let mut effect = InstructionEffect::default();
+    match opcode & BPF_OPCODE_CLASS_MASK {
+        BPF_ALU | BPF_ALU64 => {
+            effect.read_registers = match opcode & BPF_OPCODE_ALU_MASK {
+                BPF_MOV if uses_src => src,
+                BPF_MOV => 0,
+                BPF_NEG | BPF_END => dst,
+                _ if uses_src => dst | src,
+                _ => dst,
+            };
+            effect.written_registers = dst;
+        }
+        BPF_LD => match opcode & BPF_OPCODE_MODIFIER_MASK {
+            // Legacy packet access, with the context implicitly in r6
+            BPF_ABS | BPF_IND => {
+                effect.read_registers = if opcode & BPF_OPCODE_MODIFIER_MASK == BPF_IND {
+                    (1 << 6) | src
+                } else {
+                    1 << 6
+                };
+                effect.written_registers = CALLER_SAVED_REGISTERS;
+                effect.reads_memory = true;
+            }
+            _ => effect.written_registers = dst,
+        },
+        BPF_LDX => {
+            effect.read_registers = src;
+            effect.written_registers = dst;
+            effect.reads_memory = true;
+        }
+        BPF_ST => {
+            effect.read_registers = dst;
+            effect.writes_memory = true;
+        }
+        BPF_STX => {
+            effect.read_registers = dst | src;
+            effect.writes_memory = true;
+            if opcode & BPF_OPCODE_MODIFIER_MASK == BPF_ATOMIC {
+                effect.reads_memory = true;
+                if insn.imm == BPF_ATOMIC_CMPXCHG {
+                    effect.read_registers |= 1;
+                    effect.written_registers = 1;
+                } else if insn.imm & BPF_ATOMIC_FETCH != 0 {
+                    effect.written_registers = src;
+                }
+            }
+        }
+        _ => match opcode & BPF_OPCODE_JMP_MASK {
+            BPF_JA => effect.branches = true,
+            BPF_EXIT => effect.read_registers = 1,
+            BPF_CALL => {
+                effect.read_registers = ARGUMENT_REGISTERS;
+                effect.written_registers = CALLER_SAVED_REGISTERS;
+                effect.reads_memory = true;
+                effect.writes_memory = true;
+            }
+            _ => {
+                effect.read_registers = if uses_src { dst | src } else { dst };
+                effect.branches = true;
+            }
+        },
+    }
+    effect
+}
+
+/// Computes the registers live before each instruction, bit `i` standing for `ri`
+///
+/// A register is live if it may be read before being written on some path from the
+/// instruction. Calls fall through to the next instruction and exits end the path,
+/// so the registers of a subprogram are only tracked until it returns.
+/// Slots that do not parse (see [ParsedInstruction::None]) have all registers live.
+pub fn live_registers(code: &[u64]) -> Vec<u16> {
+    let parsed: Vec<(CodeOffset, ParsedInstruction)> = instructions(code).collect();
+    let mut live = alloc::vec![0u16; code.len()];
+    let at = |live: &[u16], pc: i64| {
+        usize::try_from(pc)
+            .ok()
+            .and_then(|pc| live.get(pc).copied())
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (pc, insn) in parsed.iter().rev() {
+            let (insn, width) = match insn {
+                ParsedInstruction::None => {
+                    live[*pc..].fill(u16::MAX);
+                    continue;
+                }
+                ParsedInstruction::Instruction(insn) => (*insn, 1),
+                ParsedInstruction::WideInstruction(wide) => (wide.instruction, 2),
+            };
+            let next = *pc as i64 + width;
+            let after = match insn.jumps_to() {
+                Some(JumpInstruction::Exit) => 0,
+                Some(JumpInstruction::Unconditional(off)) => {
+                    at(&live, next + off as i64).unwrap_or(0)
+                }
+                Some(JumpInstruction::Conditional(off)) => {
+                    at(&live, next).unwrap_or(0) | at(&live, next + off as i64).unwrap_or(0)
+                }
+                None => at(&live, next).unwrap_or(0),
+            };
+            let summary = effect(&insn);
+            let before = (after & !summary.written_registers) | summary.read_registers;
+            if before != live[*pc] {
+                changed = true;
+                live[*pc..*pc + width as usize].fill(before);
+            }
+        }
+    }
+    live
+}
+
+#[test]
+fn test_alu_effect() {
+    let add = Instruction::from_raw(Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 2, 1, 0, 0));
+    let summary = effect(&add);
+    assert!(summary.reads(1) && summary.reads(2));
+    assert!(summary.writes(1) && !summary.writes(2));
+    assert!(!summary.reads_memory && !summary.writes_memory && !summary.branches);
+
+    let mov = Instruction::from_raw(Instruction::pack(BPF_ALU | BPF_MOV | BPF_K, 0, 3, 0, 1));
+    assert_eq!(effect(&mov).read_registers, 0);
+    assert_eq!(effect(&mov).written_registers, 1 << 3);
+}
+
+#[test]
+fn test_memory_effect() {
+    let store = Instruction::from_raw(Instruction::pack(BPF_STX | BPF_MEM | BPF_DW, 1, 10, -8, 0));
+    let summary = effect(&store);
+    assert!(summary.reads(1) && summary.reads(10));
+    assert_eq!(summary.written_registers, 0);
+    assert!(summary.writes_memory && !summary.reads_memory && !summary.branches);
+
+    let load = Instruction::from_raw(Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 10, 0, -4, 0));
+    let summary = effect(&load);
+    assert!(summary.reads(10) && summary.writes(0));
+    assert!(summary.reads_memory && !summary.writes_memory);
+
+    let cmpxchg = Instruction::from_raw(Instruction::pack(
+        BPF_STX | BPF_ATOMIC | BPF_DW,
+        2,
+        1,
+        0,
+        BPF_ATOMIC_CMPXCHG,
+    ));
+    let summary = effect(&cmpxchg);
+    assert!(summary.reads(0) && summary.reads(1) && summary.reads(2));
+    assert_eq!(summary.written_registers, 1);
+    assert!(summary.reads_memory && summary.writes_memory);
+}
+
+#[test]
+fn test_jump_effect() {
+    let jeq = Instruction::from_raw(Instruction::pack(BPF_JMP32 | BPF_JEQ | BPF_X, 2, 1, 3, 0));
+    let summary = effect(&jeq);
+    assert!(summary.reads(1) && summary.reads(2));
+    assert_eq!(summary.written_registers, 0);
+    assert!(summary.branches);
+
+    let exit = Instruction::from_raw(BPF_JMP_EXIT as u64);
+    assert_eq!(effect(&exit).read_registers, 1);
+    assert!(!effect(&exit).branches);
+
+    let call = Instruction::from_raw(Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1));
+    let summary = effect(&call);
+    assert!(summary.reads(5) && !summary.reads(0));
+    assert!(summary.writes(0) && summary.writes(5) && !summary.writes(6));
+    assert!(!summary.branches);
+}
+
+#[test]
+fn test_live_registers() {
+    let code = [
+        // r6 = 0; r7 = 1
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 6, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 7, 0, 1),
+        // loop: r7 = 2; r6 += 1; if r6 < 4 goto loop
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 7, 0, 2),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 6, 0, 1),
+        Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 6, -3, 4),
+        // r0 = r6; exit
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let live = live_registers(&code);
+    assert_eq!(live[0], 0);
+    assert_eq!(live[1], 1 << 6);
+    assert_eq!(live[2], 1 << 6);
+    assert_eq!(live[5], 1 << 6);
+    assert_eq!(live[6], 1);
+
+    let wide = [
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, 0, 0, 0, 1),
+        0,
+        BPF_JMP_EXIT as u64,
+    ];
+    assert_eq!(live_registers(&wide), [0, 0, 1]);
+}
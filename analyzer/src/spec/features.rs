@@ -0,0 +1,77 @@
+//! This module describes instruction set extensions that a program may require.
+
+use bitflags::bitflags;
+use ebpf_consts::mask::*;
+use ebpf_consts::*;
+
+use super::Instruction;
+
+bitflags! {
+    /// A set of instruction set extensions
+    ///
+    /// Use it either to describe what a program requires (see
+    /// [crate::blocks::ProgramInfo::required_features]) or what a target supports.
+    pub struct FeatureSet: u8 {
+        /// 32-bit jumps (`BPF_JMP32`)
+        const JMP32    = 0b00000001;
+        /// 32-bit arithmetic (`BPF_ALU`)
+        const ALU32    = 0b00000010;
+        /// Sign-extending loads (`BPF_LDX | BPF_MEMSX`)
+        const LDSX     = 0b00000100;
+        /// Signed division and modulo (`BPF_DIV` / `BPF_MOD` with `off == 1`)
+        const SDIV     = 0b00001000;
+        /// Atomic instructions (`BPF_STX | BPF_ATOMIC`)
+        const ATOMICS  = 0b00010000;
+        /// The `may_goto` instruction (`BPF_JMP | BPF_JCOND`)
+        const MAY_GOTO = 0b00100000;
+    }
+}
+
+impl Instruction {
+    /// Returns the instruction set extensions this instruction requires
+    ///
+    /// It does not check for instruction validity.
+    pub fn required_features(self) -> FeatureSet {
+        let signed_division = || {
+            if self.is_signed_division() {
+                FeatureSet::SDIV
+            } else {
+                FeatureSet::empty()
+            }
+        };
+        match self.opcode & BPF_OPCODE_CLASS_MASK {
+            BPF_ALU => FeatureSet::ALU32 | signed_division(),
+            BPF_ALU64 => signed_division(),
+            BPF_JMP32 => FeatureSet::JMP32,
+            BPF_JMP if (self.opcode & BPF_OPCODE_JMP_MASK) == BPF_JCOND => FeatureSet::MAY_GOTO,
+            BPF_LDX if (self.opcode & BPF_OPCODE_MODIFIER_MASK) == BPF_MEMSX => FeatureSet::LDSX,
+            BPF_STX if (self.opcode & BPF_OPCODE_MODIFIER_MASK) == BPF_ATOMIC => {
+                FeatureSet::ATOMICS
+            }
+            _ => FeatureSet::empty(),
+        }
+    }
+}
+
+#[test]
+fn test_instruction_features() {
+    let features = |opcode: u8, off: i16| {
+        Instruction::from_raw(Instruction::pack(opcode, 0, 0, off, 0)).required_features()
+    };
+    assert_eq!(features(BPF_ALU64 | BPF_ADD | BPF_K, 0), FeatureSet::empty());
+    assert_eq!(features(BPF_ALU | BPF_ADD | BPF_K, 0), FeatureSet::ALU32);
+    assert_eq!(features(BPF_ALU64 | BPF_DIV | BPF_X, 0), FeatureSet::empty());
+    assert_eq!(features(BPF_ALU64 | BPF_DIV | BPF_X, 1), FeatureSet::SDIV);
+    assert_eq!(
+        features(BPF_ALU | BPF_MOD | BPF_K, 1),
+        FeatureSet::ALU32 | FeatureSet::SDIV
+    );
+    assert_eq!(features(BPF_JMP32 | BPF_JEQ | BPF_K, 0), FeatureSet::JMP32);
+    assert_eq!(features(BPF_JMP | BPF_JCOND, 0), FeatureSet::MAY_GOTO);
+    assert_eq!(features(BPF_LDX | BPF_MEMSX | BPF_W, 0), FeatureSet::LDSX);
+    assert_eq!(features(BPF_LDX | BPF_MEM | BPF_W, 0), FeatureSet::empty());
+    assert_eq!(
+        features(BPF_STX | BPF_ATOMIC | BPF_DW, 0),
+        FeatureSet::ATOMICS
+    );
+}
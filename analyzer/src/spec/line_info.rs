@@ -0,0 +1,237 @@
+//! This module reads line info from the `.BTF.ext` section, see [LineInfo].
+
+use alloc::{string::String, vec::Vec};
+
+use super::CodeOffset;
+
+/// Magic number of both `.BTF` and `.BTF.ext` headers
+const BTF_MAGIC: u16 = 0xEB9F;
+/// Size of `struct bpf_line_info`
+const MIN_LINE_INFO_RECORD_SIZE: usize = 16;
+
+/// Errors when reading malformed BTF info
+#[derive(Debug, PartialEq, Eq)]
+pub enum BtfError {
+    /// Bad magic number, unsupported version or header length
+    InvalidHeader,
+    /// Sections or records reaching beyond the end of data
+    Truncated,
+    /// Line info records smaller than `struct bpf_line_info`
+    InvalidRecordSize,
+    /// String offsets out of the string table or strings not valid UTF-8
+    InvalidString,
+    /// Instruction offsets not aligned to instructions or not strictly increasing
+    InvalidInstructionOffset,
+    /// No line info for the requested ELF section
+    SectionNotFound,
+}
+
+/// Reads little-endian or big-endian fields
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the magic number and the version, detecting the endianness
+    fn new(data: &'a [u8]) -> Result<Self, BtfError> {
+        let magic = data.get(0..2).ok_or(BtfError::InvalidHeader)?;
+        let big_endian = match [magic[0], magic[1]] {
+            m if u16::from_le_bytes(m) == BTF_MAGIC => false,
+            m if u16::from_be_bytes(m) == BTF_MAGIC => true,
+            _ => return Err(BtfError::InvalidHeader),
+        };
+        if data.get(2) != Some(&1) {
+            return Err(BtfError::InvalidHeader);
+        }
+        Ok(Self { data, big_endian })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, BtfError> {
+        let bytes = offset
+            .checked_add(4)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or(BtfError::Truncated)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn usize(&self, offset: usize) -> Result<usize, BtfError> {
+        Ok(self.u32(offset)? as usize)
+    }
+
+    /// Returns the sub-slice `[start + offset, start + offset + len)`, both read from the header
+    fn section(&self, start: usize, offset: usize, len: usize) -> Result<&'a [u8], BtfError> {
+        let begin = self.usize(start)?.checked_add(self.usize(offset)?);
+        let end = begin.and_then(|begin| begin.checked_add(self.usize(len).ok()?));
+        match (begin, end) {
+            (Some(begin), Some(end)) => self.data.get(begin..end).ok_or(BtfError::Truncated),
+            _ => Err(BtfError::Truncated),
+        }
+    }
+}
+
+/// Reads a NUL-terminated string from the BTF string table
+fn string(strings: &[u8], offset: u32) -> Result<&str, BtfError> {
+    let tail = strings
+        .get(offset as usize..)
+        .ok_or(BtfError::InvalidString)?;
+    let len = tail
+        .iter()
+        .position(|c| *c == 0)
+        .ok_or(BtfError::InvalidString)?;
+    core::str::from_utf8(&tail[..len]).map_err(|_| BtfError::InvalidString)
+}
+
+/// Source lines of instructions in a program, read from `.BTF.ext`
+///
+/// Compilers emit line info for some instructions only, so an instruction
+/// without its own record belongs to the line of the closest record before it.
+#[derive(Clone, Debug, Default)]
+pub struct LineInfo {
+    /// Instruction offsets, file names and line numbers, sorted by offsets
+    lines: Vec<(CodeOffset, String, u32)>,
+}
+
+impl LineInfo {
+    /// Reads the line info of the ELF section `section` (e.g., `xdp`)
+    ///
+    /// `btf` and `btf_ext` are the contents of the `.BTF` and `.BTF.ext` sections,
+    /// the former holding the strings that the latter refers to.
+    pub fn parse(btf: &[u8], btf_ext: &[u8], section: &str) -> Result<Self, BtfError> {
+        // struct btf_header: magic, version, flags, hdr_len, type_off, type_len, str_off, str_len
+        let header = Reader::new(btf)?;
+        let strings = header.section(4, 16, 20)?;
+
+        // struct btf_ext_header: magic, version, flags, hdr_len,
+        //                        func_info_off, func_info_len, line_info_off, line_info_len
+        let ext = Reader::new(btf_ext)?;
+        if ext.usize(4)? < 24 {
+            return Err(BtfError::InvalidHeader);
+        }
+        let records = Reader {
+            data: ext.section(4, 16, 20)?,
+            big_endian: ext.big_endian,
+        };
+
+        let record_size = records.usize(0)?;
+        if record_size < MIN_LINE_INFO_RECORD_SIZE {
+            return Err(BtfError::InvalidRecordSize);
+        }
+        let mut offset = 4;
+        while offset < records.data.len() {
+            // struct btf_ext_info_sec: sec_name_off, num_info, followed by the records
+            let name = string(strings, records.u32(offset)?)?;
+            let count = records.usize(offset + 4)?;
+            offset += 8;
+            // `num_info` is untrusted: checks it against the data before reserving records
+            let end = count
+                .checked_mul(record_size)
+                .and_then(|size| size.checked_add(offset))
+                .filter(|end| *end <= records.data.len())
+                .ok_or(BtfError::Truncated)?;
+            if name != section {
+                offset = end;
+                continue;
+            }
+
+            let mut lines: Vec<(CodeOffset, String, u32)> = Vec::with_capacity(count);
+            for i in 0..count {
+                // struct bpf_line_info: insn_off, file_name_off, line_off, line_col
+                let record = offset + i * record_size;
+                let insn_off = records.usize(record)?;
+                if insn_off % 8 != 0 || lines.last().is_some_and(|last| last.0 >= insn_off / 8) {
+                    return Err(BtfError::InvalidInstructionOffset);
+                }
+                let file = string(strings, records.u32(record + 4)?)?;
+                string(strings, records.u32(record + 8)?)?;
+                let line = records.u32(record + 12)? >> 10;
+                lines.push((insn_off / 8, file.into(), line));
+            }
+            return Ok(Self { lines });
+        }
+        Err(BtfError::SectionNotFound)
+    }
+
+    /// Returns the file name and the line number of the instruction
+    pub fn line_info(&self, pc: CodeOffset) -> Option<(String, u32)> {
+        let i = match self
+            .lines
+            .binary_search_by_key(&pc, |(offset, _, _)| *offset)
+        {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (_, file, line) = &self.lines[i];
+        Some((file.clone(), *line))
+    }
+}
+
+#[cfg(test)]
+fn header(hdr_len: u32, fields: &[u32]) -> Vec<u8> {
+    let mut data = Vec::from([0x9F, 0xEB, 1, 0]);
+    data.extend_from_slice(&hdr_len.to_le_bytes());
+    for field in fields {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data
+}
+
+#[test]
+fn test_line_info() {
+    let strings = b"\0xdp\0a.c\0\0";
+    let mut btf = header(24, &[0, 0, 0, strings.len() as u32]);
+    btf.extend_from_slice(strings);
+
+    let records: [&[u32]; 4] = [
+        // Record size
+        &[16],
+        // Section "xdp" with two records
+        &[1, 2],
+        // Instruction 0 at line 3, instruction 2 at line 7
+        &[0, 5, 9, 3 << 10 | 5],
+        &[16, 5, 9, 7 << 10 | 1],
+    ];
+    let records = records.concat();
+    let mut ext = header(24, &[0, 0, 0, records.len() as u32 * 4]);
+    for field in records {
+        ext.extend_from_slice(&field.to_le_bytes());
+    }
+
+    let info = LineInfo::parse(&btf, &ext, "xdp").unwrap();
+    assert_eq!(info.line_info(0), Some(("a.c".into(), 3)));
+    assert_eq!(info.line_info(1), Some(("a.c".into(), 3)));
+    assert_eq!(info.line_info(5), Some(("a.c".into(), 7)));
+    assert_eq!(
+        LineInfo::parse(&btf, &ext, "tc").unwrap_err(),
+        BtfError::SectionNotFound
+    );
+    assert_eq!(
+        LineInfo::parse(&btf, &ext[..ext.len() - 1], "xdp").unwrap_err(),
+        BtfError::Truncated
+    );
+
+    // Way more records than the data holds
+    let mut huge = ext.clone();
+    huge[24 + 8..24 + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(
+        LineInfo::parse(&btf, &huge, "xdp").unwrap_err(),
+        BtfError::Truncated
+    );
+
+    // Unaligned instruction offset
+    ext[24 + 12] = 4;
+    assert_eq!(
+        LineInfo::parse(&btf, &ext, "xdp").unwrap_err(),
+        BtfError::InvalidInstructionOffset
+    );
+    assert_eq!(
+        LineInfo::parse(&btf[1..], &ext, "xdp").unwrap_err(),
+        BtfError::InvalidHeader
+    );
+}
@@ -1,13 +1,22 @@
 //! This module contains the instruction verification according to the instruction set specification.
 
+pub mod builder;
+pub mod disasm;
+pub mod effects;
+pub mod features;
+pub mod line_info;
+pub mod program_type;
 pub mod proto;
 
 use core::fmt::Debug;
 use core::hint::unreachable_unchecked;
 
+use ebpf_atomic::AtomicError;
 use ebpf_consts::mask::*;
 use ebpf_consts::*;
 
+pub use effects::{effect, live_registers, InstructionEffect};
+
 /// Instruction offset
 pub type CodeOffset = usize;
 
@@ -33,7 +42,8 @@ pub struct WideInstruction {
     pub instruction: Instruction,
     /// The following 64-bit value
     ///
-    /// No, this is not `imm64`.
+    /// No, this is not `imm64`. It is encoded the same way as [Instruction::from_raw] expects,
+    /// that is, `insn1.imm` lies in the higher 32 bits regardless of the host endianness.
     pub imm: u64,
 }
 
@@ -50,7 +60,9 @@ pub enum ParsedInstruction {
 /// Basic jump instruction classification
 pub enum JumpInstruction {
     /// An unconditional jump
-    Unconditional(i16),
+    ///
+    /// The offset is wider than `i16` for `BPF_JMP32 | BPF_JA` (`gotol`), which uses `imm`.
+    Unconditional(i32),
     /// A conditional jump
     Conditional(i16),
     /// Exits
@@ -70,8 +82,8 @@ pub enum IllegalInstruction {
     LegacyInstruction,
     /// Unused fields must be zeroed
     UnusedFieldNotZeroed,
-    /// Atomic width other than 32 and 64
-    UnsupportedAtomicWidth,
+    /// Atomic width other than 32 and 64, with [AtomicError::UnsupportedBitness] naming the size
+    UnsupportedAtomicWidth(AtomicError),
     /// Tries to jump right into an 128-bit instruction
     UnalignedJump,
     /// Jumps out of the whole program
@@ -82,6 +94,17 @@ pub enum IllegalInstruction {
     MapFdNotAvailable,
 }
 
+/// Fields that do not fit into an instruction, see [Instruction::try_pack]
+#[derive(Debug, PartialEq, Eq)]
+pub enum PackError {
+    /// Register number larger than 15
+    RegisterOutOfRange(u8),
+    /// Offset out of the range of `i16`
+    OffsetOutOfRange(i64),
+    /// Immediate value fitting neither `i32` nor `u32`
+    ImmediateOutOfRange(i64),
+}
+
 impl ParsedInstruction {
     /// Validates the instruction
     pub fn validate(&self) -> Result<(), IllegalInstruction> {
@@ -140,6 +163,28 @@ impl WideInstruction {
     }
 }
 
+/// Iterates over the instructions in the code, along with their offsets
+///
+/// Wide instructions take up two slots and are yielded once. A trailing half of a wide
+/// instruction is yielded as [ParsedInstruction::None], after which the iteration stops.
+/// It does not check for instruction validity.
+pub fn instructions(code: &[u64]) -> impl Iterator<Item = (CodeOffset, ParsedInstruction)> + '_ {
+    let mut pc = 0 as CodeOffset;
+    core::iter::from_fn(move || {
+        if pc >= code.len() {
+            return None;
+        }
+        let parsed = Instruction::from(code, pc);
+        let current = pc;
+        pc += match parsed {
+            ParsedInstruction::None => code.len(),
+            ParsedInstruction::Instruction(_) => 1,
+            ParsedInstruction::WideInstruction(_) => 2,
+        };
+        Some((current, parsed))
+    })
+}
+
 impl Instruction {
     /// Packs fields into a `u64` instruction
     pub fn pack(opcode: u8, src_reg: u8, dst_reg: u8, offset: i16, imm: i32) -> u64 {
@@ -152,6 +197,30 @@ impl Instruction {
         opcode | (dst_reg << 8) | (src_reg << (8 + 4)) | (offset << 16) | (imm << 32)
     }
 
+    /// Packs fields into a `u64` instruction, checking that every field fits
+    ///
+    /// Unlike [Instruction::pack], which silently truncates its inputs, it rejects
+    /// registers out of `0..=15`, offsets out of the `i16` range and immediate values
+    /// out of both the `i32` and the `u32` range (e.g., both `-1` and `0xFFFF_FFFF` are fine).
+    pub fn try_pack(
+        opcode: u8,
+        src_reg: u8,
+        dst_reg: u8,
+        offset: i64,
+        imm: i64,
+    ) -> Result<u64, PackError> {
+        for reg in [src_reg, dst_reg] {
+            if reg > 0xF {
+                return Err(PackError::RegisterOutOfRange(reg));
+            }
+        }
+        let offset = i16::try_from(offset).map_err(|_| PackError::OffsetOutOfRange(offset))?;
+        let imm = i32::try_from(imm)
+            .or_else(|_| u32::try_from(imm).map(|imm| imm as i32))
+            .map_err(|_| PackError::ImmediateOutOfRange(imm))?;
+        Ok(Instruction::pack(opcode, src_reg, dst_reg, offset, imm))
+    }
+
     /// Gets the opcode
     pub fn opcode(code: u64) -> u8 {
         (code & BPF_OPCODE_MASK) as u8
@@ -177,6 +246,11 @@ impl Instruction {
     }
 
     /// Constructs an instruction from a encoded code
+    ///
+    /// The encoded code is the value of the instruction read as a little-endian `u64`,
+    /// the same as what [Instruction::pack] produces. Only shifts are used so that
+    /// it decodes the same on any host. To decode raw bytes, see [Instruction::from_le_bytes]
+    /// and, with the `big-endian` feature, `Instruction::from_be_bytes`.
     pub fn from_raw(encoded: u64) -> Instruction {
         Instruction {
             opcode: Self::opcode(encoded),
@@ -186,6 +260,47 @@ impl Instruction {
         }
     }
 
+    /// Encodes the instruction into the form accepted by [Instruction::from_raw]
+    pub fn to_raw(self) -> u64 {
+        Self::pack(
+            self.opcode,
+            self.src_reg(),
+            self.dst_reg(),
+            self.off,
+            self.imm,
+        )
+    }
+
+    /// Decodes an instruction encoded for a little-endian kernel
+    pub fn from_le_bytes(bytes: [u8; 8]) -> Instruction {
+        Self::from_raw(u64::from_le_bytes(bytes))
+    }
+
+    /// Decodes an instruction encoded for a big-endian kernel
+    ///
+    /// On big-endian kernels, `off` and `imm` are stored in big-endian
+    /// and `dst_reg` takes the higher four bits of the register byte.
+    #[cfg(feature = "big-endian")]
+    pub fn from_be_bytes(bytes: [u8; 8]) -> Instruction {
+        Instruction {
+            opcode: bytes[0],
+            regs: bytes[1].rotate_left(4),
+            off: i16::from_be_bytes([bytes[2], bytes[3]]),
+            imm: i32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+
+    /// Decodes an instruction encoded for the host kernel
+    ///
+    /// Big-endian hosts need the `big-endian` feature.
+    #[cfg(any(target_endian = "little", feature = "big-endian"))]
+    pub fn from_ne_bytes(bytes: [u8; 8]) -> Instruction {
+        #[cfg(target_endian = "big")]
+        return Self::from_be_bytes(bytes);
+        #[cfg(target_endian = "little")]
+        return Self::from_le_bytes(bytes);
+    }
+
     /// Checks whether an instruction is a valid one
     ///
     /// The following checks are performed:
@@ -233,7 +348,7 @@ impl Instruction {
         if is_jump(self.opcode) {
             let operation = self.opcode & BPF_OPCODE_JMP_MASK;
             if operation == BPF_JA {
-                Some(JumpInstruction::Unconditional(self.off))
+                Some(JumpInstruction::Unconditional(self.jump_offset()))
             } else if operation == BPF_EXIT {
                 Some(JumpInstruction::Exit)
             } else if operation == BPF_CALL {
@@ -246,6 +361,20 @@ impl Instruction {
         }
     }
 
+    /// Returns the offset of a jump instruction, taken from `imm` for `BPF_JMP32 | BPF_JA`
+    fn jump_offset(self) -> i32 {
+        if self.is_long_jump() {
+            self.imm
+        } else {
+            self.off as i32
+        }
+    }
+
+    /// Whether it is a `BPF_JMP32 | BPF_JA` (`gotol`), with its offset in `imm`
+    pub fn is_long_jump(self) -> bool {
+        self.opcode == BPF_JMP32 | BPF_JA
+    }
+
     /// Returns `Some(offset)` if it is a call instruction with [BPF_CALL_PSEUDO]
     pub fn is_pseudo_call(self) -> Option<i32> {
         if self.opcode == BPF_JMP_CALL && self.src_reg() == BPF_CALL_PSEUDO {
@@ -255,6 +384,40 @@ impl Instruction {
         }
     }
 
+    /// Returns `Some(btf_id)` if it is a call instruction with [BPF_CALL_KFUNC]
+    pub fn is_kfunc_call(self) -> Option<i32> {
+        if self.opcode == BPF_JMP_CALL && self.src_reg() == BPF_CALL_KFUNC {
+            Some(self.imm)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if it is a `BPF_DIV` with [BPF_SDIV] or a `BPF_MOD` with [BPF_SMOD]
+    pub fn is_signed_division(self) -> bool {
+        match self.opcode & BPF_OPCODE_CLASS_MASK {
+            BPF_ALU | BPF_ALU64 => match self.opcode & BPF_OPCODE_ALU_MASK {
+                BPF_DIV => self.off == BPF_SDIV,
+                BPF_MOD => self.off == BPF_SMOD,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns `Some(width)` if it is a sign-extending move (`BPF_MOV | BPF_X` with a non-zero offset)
+    ///
+    /// The source register is sign-extended from `width` bits (8 or 16, and also 32 for `BPF_ALU64`).
+    pub fn is_sign_extending_move(self) -> Option<u8> {
+        if (self.opcode & !BPF_OPCODE_CLASS_MASK) != (BPF_MOV | BPF_X) {
+            return None;
+        }
+        match (self.opcode & BPF_OPCODE_CLASS_MASK, self.off) {
+            (BPF_ALU | BPF_ALU64, 8 | 16) | (BPF_ALU64, 32) => Some(self.off as u8),
+            _ => None,
+        }
+    }
+
     /// Returns `Some(offset)` if it is a wide instruction with [BPF_IMM64_FUNC]
     pub fn is_ldimm64_func(self) -> Option<i32> {
         if self.is_wide() && self.src_reg() == BPF_IMM64_FUNC {
@@ -324,7 +487,7 @@ impl Instruction {
     ///
     /// 1. BPF_EXIT does not use any other fields;
     /// 2. BPF_CALL calls the function specified in the immediate number;
-    /// 3. BPF_JA jumps to the offset unconditionally;
+    /// 3. BPF_JA jumps to the offset unconditionally, or to `imm` for BPF_JMP32;
     /// 4. Other instructions either:
     ///    a) compares dst_reg against the immediate number;
     ///    b) or compares dst_reg against the src_reg.
@@ -333,9 +496,13 @@ impl Instruction {
             0xE0 => Err(IllegalInstruction::IllegalOpCode),
             0xF0 => Err(IllegalInstruction::IllegalOpCode),
             BPF_JA => {
-                if XLEN == 32 {
-                    Err(IllegalInstruction::IllegalInstruction)
-                } else if self.regs == 0 && self.imm == 0 {
+                // `gotol` (BPF_JMP32) takes its offset from `imm` instead of `off`
+                let unused = if XLEN == 32 {
+                    self.off as i32
+                } else {
+                    self.imm
+                };
+                if self.regs == 0 && unused == 0 {
                     Ok(())
                 } else {
                     Err(IllegalInstruction::UnusedFieldNotZeroed)
@@ -367,13 +534,14 @@ impl Instruction {
 
     /// Checks if a jump instruction is valid
     ///
-    /// 1. None of them uses the offset;
+    /// 1. None of them uses the offset, except for signed `BPF_DIV` / `BPF_MOD`
+    ///    and sign-extending `BPF_MOV`;
     /// 2. All of them writes to the dst_reg;
     /// 3. BPF_ALU_END operates on dst_reg according to the immediate number, requiring BPF_ALU;
     /// 4. BPF_NEG reads and writes to from dst_reg, requiring BPF_K;
     /// 5. Others read from either src_reg or the immediate number.
     fn is_arithmetic_valid(self) -> Result<(), IllegalInstruction> {
-        if self.off != 0 {
+        if self.off != 0 && !self.is_signed_division() && self.is_sign_extending_move().is_none() {
             return Err(IllegalInstruction::UnusedFieldNotZeroed);
         }
 
@@ -447,22 +615,34 @@ impl Instruction {
     /// 1. BPF_XCHG: Exchanges the original value into src_reg;
     /// 2. BPF_CMPXCHG: The value is stored into R0, src_reg not modified.
     /// 3. Other instructions store into src_reg if the BPF_FETCH flag is set.
+    ///
+    /// As is with the kernel, BPF_XCHG and BPF_CMPXCHG always come with the BPF_FETCH flag.
     fn is_atomic_store_valid(self) -> Result<(), IllegalInstruction> {
-        let operant_size: u8 = self.opcode & BPF_OPCODE_SIZE_MASK;
-        if !((cfg!(feature = "atomic64") && operant_size == BPF_DW)
-            || (cfg!(feature = "atomic32") && operant_size == BPF_W))
-        {
-            return Err(IllegalInstruction::UnsupportedAtomicWidth);
-        }
+        let operant_size = match self.opcode & BPF_OPCODE_SIZE_MASK {
+            BPF_B => 1,
+            BPF_H => 2,
+            BPF_W => 4,
+            _ => 8,
+        };
+        ebpf_atomic::check_size(operant_size)
+            .map_err(IllegalInstruction::UnsupportedAtomicWidth)?;
 
         if self.dst_reg() >= READABLE_REGISTER_COUNT {
             return Err(IllegalInstruction::IllegalRegister);
         }
 
-        let src_limit = if self.imm == BPF_ATOMIC_CMPXCHG || (self.imm & BPF_ATOMIC_FETCH) == 0 {
-            READABLE_REGISTER_COUNT
-        } else {
-            WRITABLE_REGISTER_COUNT
+        let fetch = (self.imm & BPF_ATOMIC_FETCH) != 0;
+        let src_limit = match self.imm & !BPF_ATOMIC_FETCH {
+            BPF_ATOMIC_XCHG_NO_FETCH if fetch => WRITABLE_REGISTER_COUNT,
+            BPF_ATOMIC_CMPXCHG_NO_FETCH if fetch => READABLE_REGISTER_COUNT,
+            BPF_ATOMIC_ADD | BPF_ATOMIC_OR | BPF_ATOMIC_AND | BPF_ATOMIC_XOR => {
+                if fetch {
+                    WRITABLE_REGISTER_COUNT
+                } else {
+                    READABLE_REGISTER_COUNT
+                }
+            }
+            _ => return Err(IllegalInstruction::IllegalOpCode),
         };
 
         if self.src_reg() >= src_limit {
@@ -506,6 +686,92 @@ fn test_misc() {
     std::dbg!(IllegalInstruction::IllegalInstruction);
 }
 
+#[test]
+fn test_signed_division_validation() {
+    let validate = |opcode: u8, off: i16| {
+        Instruction::from_raw(Instruction::pack(opcode, 1, 0, off, 0)).validate()
+    };
+    assert!(validate(BPF_ALU64 | BPF_DIV | BPF_X, BPF_SDIV).is_ok());
+    assert!(validate(BPF_ALU | BPF_DIV | BPF_X, BPF_SDIV).is_ok());
+    assert!(validate(BPF_ALU64 | BPF_MOD | BPF_X, BPF_SMOD).is_ok());
+    assert!(validate(BPF_ALU | BPF_MOD | BPF_X, BPF_SMOD).is_ok());
+    assert!(matches!(
+        validate(BPF_ALU64 | BPF_DIV | BPF_X, 2),
+        Err(IllegalInstruction::UnusedFieldNotZeroed)
+    ));
+    assert!(matches!(
+        validate(BPF_ALU64 | BPF_ADD | BPF_X, 1),
+        Err(IllegalInstruction::UnusedFieldNotZeroed)
+    ));
+    assert!(matches!(
+        validate(BPF_ALU64 | BPF_MUL | BPF_X, 1),
+        Err(IllegalInstruction::UnusedFieldNotZeroed)
+    ));
+}
+
+#[test]
+fn test_sign_extending_move_validation() {
+    let validate = |opcode: u8, off: i16| {
+        Instruction::from_raw(Instruction::pack(opcode, 1, 0, off, 0)).validate()
+    };
+    for off in [8, 16, 32] {
+        assert!(validate(BPF_ALU64 | BPF_MOV | BPF_X, off).is_ok());
+    }
+    assert!(validate(BPF_ALU | BPF_MOV | BPF_X, 8).is_ok());
+    assert!(validate(BPF_ALU | BPF_MOV | BPF_X, 16).is_ok());
+    for (opcode, off) in [
+        // Only 8-bit and 16-bit operands are extended into 32-bit ones
+        (BPF_ALU | BPF_MOV | BPF_X, 32),
+        (BPF_ALU64 | BPF_MOV | BPF_X, 1),
+        (BPF_ALU64 | BPF_MOV | BPF_X, 64),
+        (BPF_ALU64 | BPF_MOV | BPF_X, -8),
+        (BPF_ALU64 | BPF_MOV | BPF_K, 8),
+        (BPF_ALU64 | BPF_ADD | BPF_X, 8),
+    ] {
+        assert!(matches!(
+            validate(opcode, off),
+            Err(IllegalInstruction::UnusedFieldNotZeroed)
+        ));
+    }
+}
+
+#[test]
+fn test_try_pack() {
+    let opcode = BPF_ALU64 | BPF_MOV | BPF_X;
+    assert_eq!(
+        Instruction::try_pack(opcode, 1, 15, -1, 0),
+        Ok(Instruction::pack(opcode, 1, 15, -1, 0))
+    );
+    assert_eq!(
+        Instruction::try_pack(opcode, 0, 0, 0, 0xFFFF_FFFF),
+        Ok(Instruction::pack(opcode, 0, 0, 0, -1))
+    );
+    assert_eq!(
+        Instruction::try_pack(opcode, 0, 0, 0, i32::MIN as i64),
+        Ok(Instruction::pack(opcode, 0, 0, 0, i32::MIN))
+    );
+    assert_eq!(
+        Instruction::try_pack(opcode, 16, 0, 0, 0),
+        Err(PackError::RegisterOutOfRange(16))
+    );
+    assert_eq!(
+        Instruction::try_pack(opcode, 0, 16, 0, 0),
+        Err(PackError::RegisterOutOfRange(16))
+    );
+    assert_eq!(
+        Instruction::try_pack(opcode, 0, 0, 0x8000, 0),
+        Err(PackError::OffsetOutOfRange(0x8000))
+    );
+    assert_eq!(
+        Instruction::try_pack(opcode, 0, 0, 0, 0x1_0000_0000),
+        Err(PackError::ImmediateOutOfRange(0x1_0000_0000))
+    );
+    assert_eq!(
+        Instruction::try_pack(opcode, 0, 0, 0, i32::MIN as i64 - 1),
+        Err(PackError::ImmediateOutOfRange(i32::MIN as i64 - 1))
+    );
+}
+
 #[test]
 fn test_parsing() {
     let code: &[u64] = &[
@@ -540,6 +806,102 @@ fn test_parsing() {
     }
 }
 
+#[test]
+fn test_instructions() {
+    let code: &[u64] = &[
+        (BPF_ALU64 | BPF_ADD | BPF_K) as u64,
+        (BPF_LD | BPF_DW | BPF_IMM) as u64,
+        0,
+        (BPF_ALU64 | BPF_ADD | BPF_K) as u64,
+        (BPF_LD | BPF_DW | BPF_IMM) as u64,
+        0,
+        (BPF_LD | BPF_DW | BPF_IMM) as u64,
+        0,
+        BPF_JMP_EXIT as u64,
+    ];
+    let offsets = |code| {
+        instructions(code)
+            .map(|(pc, parsed)| (pc, matches!(parsed, ParsedInstruction::WideInstruction(_))))
+            .collect::<alloc::vec::Vec<_>>()
+    };
+    assert_eq!(
+        offsets(code),
+        [
+            (0, false),
+            (1, true),
+            (3, false),
+            (4, true),
+            (6, true),
+            (8, false)
+        ]
+    );
+    assert!(offsets(&[]).is_empty());
+
+    // Stops at the trailing half
+    let truncated = &code[..7];
+    assert_eq!(offsets(truncated).last(), Some(&(6, false)));
+    assert!(matches!(
+        instructions(truncated).last(),
+        Some((6, ParsedInstruction::None))
+    ));
+}
+
+#[test]
+fn test_endianness() {
+    fn decode(code: [[u8; 8]; 2], from_bytes: fn([u8; 8]) -> Instruction) -> [u64; 2] {
+        code.map(|bytes| from_bytes(bytes).to_raw())
+    }
+    fn check_imm64(code: &[u64]) {
+        match Instruction::from(code, 0) {
+            ParsedInstruction::WideInstruction(w) => {
+                assert_eq!(w.instruction.opcode, BPF_LD | BPF_DW | BPF_IMM);
+                assert_eq!(w.instruction.dst_reg(), 1);
+                assert_eq!(w.instruction.src_reg(), 0);
+                assert_eq!(w.imm0(), 0xCAFE_BABEu32 as i32);
+                assert_eq!(w.imm1(), 0xDEAD_BEEFu32 as i32);
+                assert_eq!(w.imm64(), 0xDEAD_BEEF_CAFE_BABE);
+                assert!(w.validate().is_ok());
+            }
+            _ => panic!(),
+        }
+    }
+
+    // r1 = 0xDEADBEEF_CAFEBABE
+    let le: [[u8; 8]; 2] = [
+        [0x18, 0x01, 0x00, 0x00, 0xBE, 0xBA, 0xFE, 0xCA],
+        [0x00, 0x00, 0x00, 0x00, 0xEF, 0xBE, 0xAD, 0xDE],
+    ];
+    check_imm64(&decode(le, Instruction::from_le_bytes));
+    let insn =
+        Instruction::from_le_bytes([BPF_STX | BPF_MEM | BPF_DW, 0x1A, 0xF8, 0xFF, 0, 0, 0, 0]);
+    assert_eq!((insn.dst_reg(), insn.src_reg(), insn.off), (10, 1, -8));
+    assert_eq!(Instruction::from_raw(insn.to_raw()).regs, insn.regs);
+
+    #[cfg(target_endian = "little")]
+    assert_eq!(
+        decode(le, Instruction::from_ne_bytes),
+        decode(le, Instruction::from_le_bytes)
+    );
+
+    #[cfg(feature = "big-endian")]
+    {
+        let be: [[u8; 8]; 2] = [
+            [0x18, 0x10, 0x00, 0x00, 0xCA, 0xFE, 0xBA, 0xBE],
+            [0x00, 0x00, 0x00, 0x00, 0xDE, 0xAD, 0xBE, 0xEF],
+        ];
+        check_imm64(&decode(be, Instruction::from_be_bytes));
+        let insn =
+            Instruction::from_be_bytes([BPF_STX | BPF_MEM | BPF_DW, 0xA1, 0xFF, 0xF8, 0, 0, 0, 0]);
+        assert_eq!((insn.dst_reg(), insn.src_reg(), insn.off), (10, 1, -8));
+
+        let native = if cfg!(target_endian = "big") { be } else { le };
+        assert_eq!(
+            decode(native, Instruction::from_ne_bytes),
+            decode(le, Instruction::from_le_bytes)
+        );
+    }
+}
+
 #[test]
 fn test_wide_validation() {
     let mut w = WideInstruction {
@@ -595,7 +957,16 @@ fn test_atomic_validation() {
     ));
     assert!(matches!(
         i.is_atomic_store_valid(),
-        Err(IllegalInstruction::UnsupportedAtomicWidth)
+        Err(IllegalInstruction::UnsupportedAtomicWidth(
+            AtomicError::UnsupportedBitness { size: 1 }
+        ))
+    ));
+    i.opcode = BPF_ATOMIC | BPF_H | BPF_STX;
+    assert!(matches!(
+        i.is_atomic_store_valid(),
+        Err(IllegalInstruction::UnsupportedAtomicWidth(
+            AtomicError::UnsupportedBitness { size: 2 }
+        ))
     ));
     i.opcode = BPF_ATOMIC | BPF_DW | BPF_STX;
     assert!(i.is_atomic_store_valid().is_ok());
@@ -620,4 +991,67 @@ fn test_atomic_validation() {
     ))
     .validate()
     .is_ok());
+
+    // Whether src_reg may be the read-only R10
+    let with_r10 = |imm: i32| {
+        Instruction::from_raw(Instruction::pack(
+            BPF_ATOMIC | BPF_DW | BPF_STX,
+            10,
+            1,
+            0,
+            imm,
+        ))
+        .validate()
+    };
+    for imm in [
+        BPF_ATOMIC_ADD,
+        BPF_ATOMIC_OR,
+        BPF_ATOMIC_AND,
+        BPF_ATOMIC_XOR,
+        BPF_ATOMIC_CMPXCHG,
+    ] {
+        assert!(with_r10(imm).is_ok());
+    }
+    for imm in [
+        BPF_ATOMIC_ADD | BPF_ATOMIC_FETCH,
+        BPF_ATOMIC_OR | BPF_ATOMIC_FETCH,
+        BPF_ATOMIC_AND | BPF_ATOMIC_FETCH,
+        BPF_ATOMIC_XOR | BPF_ATOMIC_FETCH,
+        BPF_ATOMIC_XCHG,
+    ] {
+        assert!(matches!(
+            with_r10(imm),
+            Err(IllegalInstruction::IllegalRegister)
+        ));
+    }
+    // No non-fetching BPF_XCHG or BPF_CMPXCHG
+    for imm in [BPF_ATOMIC_XCHG_NO_FETCH, BPF_ATOMIC_CMPXCHG_NO_FETCH, 0x10] {
+        assert!(matches!(
+            with_r10(imm),
+            Err(IllegalInstruction::IllegalOpCode)
+        ));
+    }
+}
+
+#[test]
+fn test_long_jump_validation() {
+    let validate = |opcode: u8, src: u8, off: i16, imm: i32| {
+        Instruction::from_raw(Instruction::pack(opcode, src, 0, off, imm)).validate()
+    };
+    assert!(validate(BPF_JMP32 | BPF_JA, 0, 0, 40000).is_ok());
+    assert!(validate(BPF_JMP | BPF_JA, 0, 1000, 0).is_ok());
+    for (opcode, src, off, imm) in [
+        (BPF_JMP32 | BPF_JA, 0, 1, 40000),
+        (BPF_JMP32 | BPF_JA, 1, 0, 40000),
+        (BPF_JMP | BPF_JA, 0, 1000, 1),
+    ] {
+        assert!(matches!(
+            validate(opcode, src, off, imm),
+            Err(IllegalInstruction::UnusedFieldNotZeroed)
+        ));
+    }
+    assert!(matches!(
+        Instruction::from_raw(Instruction::pack(BPF_JMP32 | BPF_JA, 0, 0, 0, -3)).jumps_to(),
+        Some(JumpInstruction::Unconditional(-3))
+    ));
 }
@@ -0,0 +1,143 @@
+//! This module bundles per-program-type policies into [ProgramType] descriptors.
+
+use core::ops::RangeInclusive;
+
+use ebpf_consts::programs::ProgType;
+
+use crate::{
+    branch::vm::{BranchState, StaticHelpers},
+    interpreter::vm::Vm,
+    track::{
+        pointees::{
+            dyn_region::DynamicRegion,
+            packet_region::PacketRegion,
+            pointed,
+            struct_region::{Field, FieldType, StructRegion},
+        },
+        pointer::{Pointer, PointerAttributes},
+        scalar::Scalar,
+    },
+};
+
+use super::{
+    features::FeatureSet,
+    proto::helpers::{HELPERS, NETWORKING_HELPERS},
+};
+
+/// Built-in program types
+///
+/// Each of them describes the defaults (allowed helpers, context, expected return values
+/// and allowed instruction set extensions) that [crate::analyzer::AnalyzerConfig] falls back to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgramType {
+    /// `BPF_PROG_TYPE_SOCKET_FILTER`, with `struct __sk_buff` as its context
+    SocketFilter,
+    /// `BPF_PROG_TYPE_KPROBE`, with `struct pt_regs` as its context
+    Kprobe,
+    /// `BPF_PROG_TYPE_SCHED_CLS`, with `struct __sk_buff` as its context
+    SkbTc,
+    /// `BPF_PROG_TYPE_XDP`, with `struct xdp_md` as its context
+    Xdp,
+    /// `BPF_PROG_TYPE_RAW_TRACEPOINT`, with its context (the raw arguments) opaque to the program
+    Raw,
+}
+
+/// `sizeof(struct __sk_buff)`
+const SK_BUFF_SIZE: usize = 192;
+/// `sizeof(struct pt_regs)` on x86_64
+const PT_REGS_SIZE: usize = 168;
+/// `sizeof(struct xdp_md)`
+const XDP_MD_SIZE: usize = 24;
+/// Fields of `struct xdp_md`, with `data` and `data_end` read as `u32`
+const XDP_MD_FIELDS: [Field; 6] = [
+    (0, 4, FieldType::PacketStart),
+    (4, 4, FieldType::PacketEnd),
+    // `data_meta` is not tracked
+    (8, 4, FieldType::Forbidden),
+    (12, 4, FieldType::Scalar),
+    (16, 4, FieldType::Scalar),
+    (20, 4, FieldType::Scalar),
+];
+
+impl ProgramType {
+    /// The program type as is in [ebpf_consts::programs]
+    pub fn kind(&self) -> ProgType {
+        match self {
+            ProgramType::SocketFilter => ProgType::SocketFilter,
+            ProgramType::Kprobe => ProgType::Kprobe,
+            ProgramType::SkbTc => ProgType::SchedCls,
+            ProgramType::Xdp => ProgType::Xdp,
+            ProgramType::Raw => ProgType::RawTracepoint,
+        }
+    }
+
+    /// Helpers available to the program type
+    ///
+    /// Tracing programs get all of [HELPERS], while networking ones
+    /// get [NETWORKING_HELPERS] only.
+    pub fn helpers(&self) -> StaticHelpers {
+        match self {
+            ProgramType::Kprobe | ProgramType::Raw => HELPERS,
+            ProgramType::SocketFilter | ProgramType::SkbTc | ProgramType::Xdp => NETWORKING_HELPERS,
+        }
+    }
+
+    /// Size (in bytes) of the read-only context passed in `r1`, zero if it is opaque
+    pub fn context_size(&self) -> usize {
+        match self {
+            ProgramType::SocketFilter | ProgramType::SkbTc => SK_BUFF_SIZE,
+            ProgramType::Kprobe => PT_REGS_SIZE,
+            ProgramType::Xdp => XDP_MD_SIZE,
+            ProgramType::Raw => 0,
+        }
+    }
+
+    /// Allowed return values, `None` if unrestricted
+    pub fn expected_return(&self) -> Option<RangeInclusive<i64>> {
+        match self {
+            // XDP_ABORTED, XDP_DROP, XDP_PASS, XDP_TX, XDP_REDIRECT
+            ProgramType::Xdp => Some(0..=4),
+            _ => None,
+        }
+    }
+
+    /// Allowed instruction set extensions
+    ///
+    /// The kernel accepts the same extensions for all program types, so this is
+    /// [FeatureSet::all]. Older targets are narrowed through
+    /// [crate::analyzer::AnalyzerConfig::features] instead.
+    pub fn features(&self) -> FeatureSet {
+        FeatureSet::all()
+    }
+
+    /// Sets up the context in `r1`
+    ///
+    /// - For XDP, `xdp_md->data` and `xdp_md->data_end` point into a [PacketRegion];
+    /// - For raw tracepoints, `r1` is an unknown scalar;
+    /// - Otherwise, it is a read-only region of [ProgramType::context_size] bytes.
+    ///
+    /// It fails if the context layout is malformed.
+    pub fn setup(&self, vm: &mut BranchState) -> Result<(), &'static str> {
+        let context = match self {
+            ProgramType::Raw => {
+                *vm.reg(1) = Scalar::unknown().into();
+                return Ok(());
+            }
+            ProgramType::Xdp => {
+                let packet = pointed(PacketRegion::default());
+                vm.add_external_resource(packet.clone());
+                let context = StructRegion::from_fields(XDP_MD_SIZE, &XDP_MD_FIELDS, Some(packet))
+                    .ok_or("xdp_md fields out of xdp_md")?;
+                pointed(context)
+            }
+            _ => pointed(DynamicRegion::new(self.context_size())),
+        };
+        vm.add_external_resource(context.clone());
+        *vm.reg(1) = Pointer::new(
+            PointerAttributes::NON_NULL | PointerAttributes::READABLE,
+            context,
+        )
+        .into();
+        Ok(())
+    }
+}
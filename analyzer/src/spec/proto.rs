@@ -2,12 +2,14 @@
 
 use core::ops::RangeInclusive;
 
+#[cfg(test)]
+use crate::{branch::vm::StaticHelpers, spec::Instruction};
 use crate::{
     branch::{checked_value::CheckedValue, vm::BranchState},
     interpreter::{value::VmValue, vm::Vm},
     track::{
-        pointees::{pointed, simple_resource::SimpleResource, AnyType},
-        pointer::Pointer,
+        pointees::{pointed, simple_resource::SimpleResource, typed_region::TypedRegion, AnyType},
+        pointer::{Pointer, PointerAttributes},
         scalar::Scalar,
         TrackError, TrackedValue,
     },
@@ -30,12 +32,30 @@ pub enum IllegalFunctionCall {
     IllegalResource,
     /// Rejected, either not implemented or not allowed
     Rejected,
+    /// Acquiring a held lock or releasing a free one
+    IllegalLockState,
+    /// A map key or value buffer smaller than the key or value size of the map
+    MapBufferSizeMismatch,
+    /// Tail calls into a program array of another program type
+    TailCallTypeMismatch,
 }
 
 /// Function prototype information
 pub trait VerifiableCall<Value: VmValue, M: Vm<Value>> {
     /// Verifies the function call
     fn call(&self, vm: &mut M) -> Result<Value, IllegalFunctionCall>;
+
+    /// Whether the function may get called while holding a spin lock
+    ///
+    /// As is with the kernel, most helpers (including all sleepable ones) are not.
+    fn allowed_while_locked(&self) -> bool {
+        false
+    }
+
+    /// The argument types if the function has a static prototype, see [HelperSet::validate]
+    fn arguments(&self) -> Option<&Arguments> {
+        None
+    }
 }
 
 /// Describes what the function do to a resource
@@ -44,6 +64,8 @@ pub enum ResourceOperation {
     /// Probably unimportant operation
     Unknown,
     /// Deallocates the region
+    ///
+    /// The pointer must point to the start of the region (with a zero offset).
     Deallocates,
 }
 
@@ -63,8 +85,36 @@ pub enum ArgumentType {
     FixedMemory(usize),
     /// Ranged memory, with its size specified by another register
     DynamicMemory(u8),
+    /// Readable memory that must be fully initialized, see [MemoryExtent]
+    ///
+    /// Unlike [ArgumentType::FixedMemory], the function only reads from it.
+    InitializedMemory(MemoryExtent),
+    /// Destination memory of a copy: `(source register, size register)`
+    ///
+    /// It models `(dst, size, src)` helpers: the same `size` must be writable
+    /// in the destination and readable from the source.
+    CopyDestination((u8, u8)),
     /// Resource pointer (not null, readable & writable)
     ResourceType((AnyType, ResourceOperation)),
+    /// Pointer to an 8-byte slot, into which the function stores a resource pointer
+    ///
+    /// It models `void **` outputs: after the call, the slot holds a valid
+    /// (not null, readable & writable) pointer to a resource of the given type.
+    OutPointer(AnyType),
+    /// Address of a subprogram, see [crate::track::TrackedValue::FuncPointer]
+    Callback,
+}
+
+/// How many bytes an [ArgumentType::InitializedMemory] argument covers
+#[derive(Clone)]
+pub enum MemoryExtent {
+    /// A fixed count of bytes
+    Fixed(usize),
+    /// All bytes from the pointer up to the frame pointer (`r10`)
+    ///
+    /// The pointer must point into the current stack frame,
+    /// at or below the deepest written byte (see [BranchState::stack_depth]).
+    StackFrame,
 }
 
 /// Describes what the function returns
@@ -73,15 +123,31 @@ pub enum ReturnType {
     None,
     /// (Unknown) scalar value
     Scalar,
-    /// Allocated resource (nullable)
+    /// Allocated resource (nullable until checked, with only the non-null branch to release it)
     AllocatedResource(AnyType),
-    /// External resource (nullable)
+    /// External resource (nullable until checked)
     ExternalResource(AnyType),
+    /// Allocated memory of the size in the register, nullable until checked
+    ///
+    /// It models `bpf_ringbuf_reserve`: the program must compare the returned pointer
+    /// against null before using it, and only the null branch is free of the allocation.
+    AllocatedMemory((AnyType, u8)),
+    /// Read-only pointer to a kernel object of the type, e.g., a `struct task_struct`
+    ///
+    /// The layout of the type is looked up in [crate::analyzer::AnalyzerConfig::kernel_structs],
+    /// with only the fields listed there readable. No arithmetic is allowed on the pointer,
+    /// while helpers taking [ArgumentType::ResourceType] of the type accept it.
+    TypedKernelPointer(AnyType),
 }
 
 /// Specifies the arguments
 pub type Arguments = [ArgumentType; 5];
 
+/// Prototype of a kernel function (kfunc), checked the same way as helper functions
+///
+/// See [crate::analyzer::AnalyzerConfig::kfunc_resolver].
+pub type KfuncPrototype = StaticFunctionCall;
+
 /// Verifies the call basing on static information
 pub struct StaticFunctionCall {
     arguments: Arguments,
@@ -124,6 +190,10 @@ impl StaticFunctionCall {
 }
 
 impl VerifiableCall<CheckedValue, BranchState> for StaticFunctionCall {
+    fn arguments(&self) -> Option<&Arguments> {
+        Some(&self.arguments)
+    }
+
     fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
         for i in 1..=5u8 {
             let arg = self.arguments[(i - 1) as usize].clone();
@@ -141,6 +211,18 @@ impl VerifiableCall<CheckedValue, BranchState> for StaticFunctionCall {
                     let (a, b) = vm.two_regs(i, reg).unwrap();
                     a.check_arg_type(&arg, Some(b))?;
                 }
+                ArgumentType::InitializedMemory(_) => {
+                    if vm.is_invalid_resource(i) {
+                        return Err(IllegalFunctionCall::IllegalResource);
+                    }
+                    vm.ro_reg(i).check_arg_type(&arg, Some(vm.ro_reg(10)))?;
+                }
+                ArgumentType::CopyDestination((src, size)) => {
+                    if vm.is_invalid_resource(i) || vm.is_invalid_resource(src) {
+                        return Err(IllegalFunctionCall::IllegalResource);
+                    }
+                    vm.ro_reg(i).check_copy(vm.ro_reg(src), vm.ro_reg(size))?;
+                }
                 ArgumentType::ResourceType((_, ref op)) => {
                     if vm.is_invalid_resource(i) {
                         return Err(IllegalFunctionCall::IllegalResource);
@@ -153,6 +235,18 @@ impl VerifiableCall<CheckedValue, BranchState> for StaticFunctionCall {
                         }
                     }
                 }
+                ArgumentType::OutPointer(type_id) => {
+                    if vm.is_invalid_resource(i) {
+                        return Err(IllegalFunctionCall::IllegalResource);
+                    }
+                    vm.ro_reg(i).check_arg_type(&arg, None)?;
+                    let resource = pointed(SimpleResource::new(type_id));
+                    vm.add_external_resource(resource.clone());
+                    if let Some(TrackedValue::Pointer(p)) = vm.ro_reg(i).inner() {
+                        p.set(8, &TrackedValue::Pointer(Pointer::nrw(resource)))
+                            .map_err(IllegalFunctionCall::IllegalPointer)?;
+                    }
+                }
                 _ => {
                     vm.ro_reg(i).check_arg_type(&arg, None)?;
                 }
@@ -164,17 +258,127 @@ impl VerifiableCall<CheckedValue, BranchState> for StaticFunctionCall {
             ReturnType::AllocatedResource(type_id) => {
                 let resource = pointed(SimpleResource::new(type_id));
                 vm.add_allocated_resource(resource.clone());
-                Ok(Pointer::nrw(resource).into())
+                Ok(Pointer::rw(resource).into())
             }
             ReturnType::ExternalResource(type_id) => {
                 let resource = pointed(SimpleResource::new(type_id));
                 vm.add_external_resource(resource.clone());
-                Ok(Pointer::nrw(resource).into())
+                Ok(Pointer::rw(resource).into())
+            }
+            ReturnType::AllocatedMemory((type_id, reg)) => {
+                let size = vm.ro_reg(reg).constant_size()?;
+                let resource = pointed(TypedRegion::new(type_id, size));
+                vm.add_allocated_resource(resource.clone());
+                Ok(Pointer::rwa(resource).into())
+            }
+            ReturnType::TypedKernelPointer(type_id) => {
+                let region = vm.kernel_region(type_id);
+                let region = pointed(region.ok_or(IllegalFunctionCall::Rejected)?);
+                vm.add_external_resource(region.clone());
+                let attributes = PointerAttributes::NON_NULL | PointerAttributes::READABLE;
+                Ok(Pointer::new(attributes, region).into())
             }
         }
     }
 }
 
+/// Inconsistent helper prototypes
+///
+/// `helper` is the index of the helper and `argument` the register (`1..=5`) of the argument.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PrototypeError {
+    /// Referring to a register other than the remaining argument registers
+    InvalidRegister {
+        /// The helper index
+        helper: usize,
+        /// The argument register
+        argument: u8,
+    },
+    /// Expecting a constant from an empty range
+    EmptyRange {
+        /// The helper index
+        helper: usize,
+        /// The argument register
+        argument: u8,
+    },
+}
+
+/// A helper table indexed by helper ids
+pub trait HelperSet {
+    /// Checks the static prototypes for internal consistency
+    ///
+    /// It is meant to be called once when building the table,
+    /// instead of having misconfigured prototypes fail in the middle of verification.
+    fn validate(&self) -> Result<(), PrototypeError>;
+}
+
+impl HelperSet for [&dyn VerifiableCall<CheckedValue, BranchState>] {
+    fn validate(&self) -> Result<(), PrototypeError> {
+        for (helper, call) in self.iter().enumerate() {
+            let Some(arguments) = call.arguments() else {
+                continue;
+            };
+            for (argument, arg) in (1..=5u8).zip(arguments) {
+                let valid = |reg: u8| (1..=5).contains(&reg) && reg != argument;
+                match arg {
+                    ArgumentType::DynamicMemory(reg) if !valid(*reg) => {
+                        return Err(PrototypeError::InvalidRegister { helper, argument });
+                    }
+                    ArgumentType::CopyDestination((src, size)) if !valid(*src) || !valid(*size) => {
+                        return Err(PrototypeError::InvalidRegister { helper, argument });
+                    }
+                    ArgumentType::Constant(range) if range.is_empty() => {
+                        return Err(PrototypeError::EmptyRange { helper, argument });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_validate_prototypes() {
+    assert!(helpers::HELPERS.validate().is_ok());
+    assert!(helpers::NETWORKING_HELPERS.validate().is_ok());
+    let prototype = |arguments: Arguments| StaticFunctionCall::new(arguments, ReturnType::Scalar);
+    let memory_by_r7 = prototype([
+        ArgumentType::DynamicMemory(7),
+        ArgumentType::Scalar,
+        ArgumentType::Any,
+        ArgumentType::Any,
+        ArgumentType::Any,
+    ]);
+    let inverted = prototype([
+        ArgumentType::Any,
+        ArgumentType::Any,
+        ArgumentType::Constant(RangeInclusive::new(1, 0)),
+        ArgumentType::Any,
+        ArgumentType::Any,
+    ]);
+    let table: &[&dyn VerifiableCall<CheckedValue, BranchState>] = &[
+        helpers::BPF_HELPER_INVALID,
+        helpers::BPF_HELPER_DYN2,
+        &memory_by_r7,
+    ];
+    assert_eq!(
+        table.validate(),
+        Err(PrototypeError::InvalidRegister {
+            helper: 2,
+            argument: 1
+        })
+    );
+    let table: &[&dyn VerifiableCall<CheckedValue, BranchState>] = &[&inverted];
+    assert_eq!(
+        table.validate(),
+        Err(PrototypeError::EmptyRange {
+            helper: 0,
+            argument: 3
+        })
+    );
+}
+
 #[test]
 fn test_arg_check() {
     let v: CheckedValue = Scalar::unknown().into();
@@ -210,10 +414,13 @@ fn test_arg_check() {
     assert!(v
         .check_arg_type(&ArgumentType::FixedMemory(8), None)
         .is_err());
+    let initialized = ArgumentType::InitializedMemory(MemoryExtent::Fixed(8));
+    assert!(v.check_arg_type(&initialized, None).is_err());
     assert!(ptr.set_all(8).is_ok());
     assert!(v
         .check_arg_type(&ArgumentType::FixedMemory(8), None)
         .is_ok());
+    assert!(v.check_arg_type(&initialized, None).is_ok());
     assert!(v
         .check_arg_type(
             &ArgumentType::DynamicMemory(2),
@@ -235,9 +442,385 @@ fn test_arg_check() {
         .is_err());
 }
 
+#[test]
+fn test_out_pointer() {
+    use crate::analyzer::{Analyzer, AnalyzerConfig};
+    use ebpf_consts::*;
+    const OUT_HELPERS: StaticHelpers = &[
+        helpers::BPF_HELPER_INVALID,
+        // (1) stores a pointer to resource 1 into *r1
+        &StaticFunctionCall::new(
+            [
+                ArgumentType::OutPointer(1),
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        ),
+        // (2) uses resource 1
+        &StaticFunctionCall::new(
+            [
+                ArgumentType::ResourceType((1, ResourceOperation::Unknown)),
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::None,
+        ),
+    ];
+    let config = AnalyzerConfig {
+        helpers: OUT_HELPERS,
+        ..Default::default()
+    };
+    let code = [
+        // r1 = r10 - 8
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -8),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        // r1 = *(u64 *)(r10 - 8)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 10, 1, -8, 0),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(Analyzer::analyze(&code, &config).is_ok());
+
+    // Without the call, the slot is never initialized
+    let mut uninit = code;
+    uninit[2] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0);
+    assert!(Analyzer::analyze(&uninit, &config).is_err());
+
+    // The slot must be 8-byte large
+    let mut small = code;
+    small[1] = Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -4);
+    assert!(Analyzer::analyze(&small, &config).is_err());
+}
+
+#[test]
+fn test_copy_bounds() {
+    use crate::analyzer::{Analyzer, AnalyzerConfig};
+    use ebpf_consts::*;
+    const COPY_HELPERS: StaticHelpers = &[
+        helpers::BPF_HELPER_INVALID,
+        // (1) copies r2 bytes from r3 into r1
+        &StaticFunctionCall::new(
+            [
+                ArgumentType::CopyDestination((3, 2)),
+                ArgumentType::Scalar,
+                ArgumentType::Some,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        ),
+        // (2) returns an unknown scalar
+        helpers::BPF_HELPER_GET_SCALAR,
+    ];
+    let config = AnalyzerConfig {
+        helpers: COPY_HELPERS,
+        ..Default::default()
+    };
+    let code = [
+        // *(u64 *)(r10 - 32) = 0, *(u64 *)(r10 - 24) = 0
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -32, 0),
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -24, 0),
+        // r1 = r10 - 16, r2 = 16, r3 = r10 - 32
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -16),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 16),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 3, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 3, 0, -32),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        // The destination is initialized by the copy
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 10, 0, -8, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(Analyzer::analyze(&code, &config).is_ok());
+
+    // The destination buffer (r10 - 8) is smaller than the size
+    let mut small_dst = code;
+    small_dst[3] = Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -8);
+    assert!(Analyzer::analyze(&small_dst, &config).is_err());
+
+    // The source (r10 - 24) is only partly initialized
+    let mut small_src = code;
+    small_src[6] = Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 3, 0, -24);
+    assert!(Analyzer::analyze(&small_src, &config).is_err());
+
+    // The size must be known, even when it fits in both buffers
+    let unknown_size = [
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -32, 0),
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -24, 0),
+        // r2 = get_scalar() & 15
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 2, 0, 15),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -16),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 3, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 3, 0, -32),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(Analyzer::analyze(&unknown_size, &config).is_err());
+    // The same program passes once the size is pinned to a constant
+    let mut known_size = unknown_size;
+    known_size[4] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 15);
+    assert!(Analyzer::analyze(&known_size, &config).is_ok());
+}
+
+#[test]
+fn test_deallocate_at_base() {
+    use crate::analyzer::{Analyzer, AnalyzerConfig};
+    use ebpf_consts::*;
+    /// Returns an allocated resource of type 2 that allows pointer arithmetic
+    struct ReserveCall;
+    impl VerifiableCall<CheckedValue, BranchState> for ReserveCall {
+        fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+            let resource = pointed(SimpleResource::new(2));
+            vm.add_allocated_resource(resource.clone());
+            Ok(Pointer::nrwa(resource).into())
+        }
+    }
+    const RESERVE_HELPERS: StaticHelpers = &[
+        helpers::BPF_HELPER_INVALID,
+        &ReserveCall {},
+        // (2) releases resource 2, like `bpf_ringbuf_submit`
+        &StaticFunctionCall::new(
+            [
+                ArgumentType::ResourceType((2, ResourceOperation::Deallocates)),
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::None,
+        ),
+    ];
+    let config = AnalyzerConfig {
+        helpers: RESERVE_HELPERS,
+        ..Default::default()
+    };
+    let code = |offset| {
+        [
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+            // r1 = r0 + offset
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 1, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, offset),
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    assert!(Analyzer::analyze(&code(0), &config).is_ok());
+    match Analyzer::analyze(&code(8), &config) {
+        Err(crate::analyzer::VerificationError::IllegalStateChange(branch)) => {
+            assert_eq!(branch.borrow().messages()[0], "Function call failed");
+        }
+        _ => panic!("mid-resource pointers should not release the resource"),
+    }
+}
+
+#[test]
+fn test_nullable_resource() {
+    use crate::analyzer::{Analyzer, AnalyzerConfig};
+    use ebpf_consts::*;
+    const NULLABLE_HELPERS: StaticHelpers = &[
+        helpers::BPF_HELPER_INVALID,
+        // (1) allocates resource 1, or fails with null
+        &StaticFunctionCall::new(
+            [
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::AllocatedResource(1),
+        ),
+        // (2) releases resource 1
+        &StaticFunctionCall::new(
+            [
+                ArgumentType::ResourceType((1, ResourceOperation::Deallocates)),
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::None,
+        ),
+    ];
+    let config = AnalyzerConfig {
+        helpers: NULLABLE_HELPERS,
+        ..Default::default()
+    };
+    let code = [
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        // if r0 == 0 goto +2
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 2, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 1, 0, 0),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(Analyzer::analyze(&code, &config).is_ok());
+
+    let mut unchecked = code;
+    unchecked[1] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 0, 0, 0);
+    assert!(Analyzer::analyze(&unchecked, &config).is_err());
+}
+
+#[test]
+fn test_ringbuf() {
+    use crate::analyzer::{Analyzer, AnalyzerConfig, MapInfo, VerificationError};
+    use ebpf_consts::{maps::MapType, *};
+    const RINGBUF_HELPERS: StaticHelpers = &[
+        helpers::BPF_HELPER_INVALID,
+        helpers::BPF_HELPER_RINGBUF_RESERVE,
+        helpers::BPF_HELPER_RINGBUF_SUBMIT,
+    ];
+    let config = AnalyzerConfig {
+        helpers: RINGBUF_HELPERS,
+        map_fd_collector: &|_| {
+            Some(MapInfo {
+                map_type: MapType::RingBuf,
+                max_size: 4096,
+                key_size: 0,
+                value_size: 0,
+                readonly: false,
+                initial_value: None,
+                program_type: None,
+            })
+        },
+        ..Default::default()
+    };
+    let rejected = |code: &[u64], message: &str| match Analyzer::analyze(code, &config) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            let messages = branch.borrow().messages().join(";");
+            assert!(messages.contains(message), "{messages}");
+        }
+        _ => panic!("expecting rejection: {message}"),
+    };
+    let nop = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 0, 0, 0);
+    let code = [
+        // r0 = bpf_ringbuf_reserve(&ringbuf, 16, 0)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 16),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, 0),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        // if r0 == 0 goto exit
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 4, 0),
+        // *(u64 *)(r0 + 8) = 42
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 0, 8, 42),
+        // bpf_ringbuf_submit(r0, 0)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 0),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2),
+        // exit: return 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(Analyzer::analyze(&code, &config).is_ok());
+
+    // Writing beyond the reserved size
+    let mut beyond = code;
+    beyond[6] = Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 0, 16, 42);
+    assert!(Analyzer::analyze(&beyond, &config).is_err());
+
+    // Writing to or submitting the record without a null check
+    let mut unchecked = code;
+    unchecked[5] = nop;
+    assert!(Analyzer::analyze(&unchecked, &config).is_err());
+    unchecked[6] = nop;
+    rejected(&unchecked, "Function call failed");
+
+    // Never submitted
+    let mut leaked = code;
+    leaked[9] = nop;
+    rejected(&leaked, "Resource not cleaned up");
+
+    // Reserving from a map other than a ring buffer
+    let config = AnalyzerConfig {
+        map_fd_collector: &|_| {
+            Some(MapInfo {
+                map_type: MapType::Array,
+                max_size: 1,
+                key_size: 4,
+                value_size: 16,
+                readonly: false,
+                initial_value: None,
+                program_type: None,
+            })
+        },
+        ..config
+    };
+    match Analyzer::analyze(&code, &config) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            let messages = branch.borrow().messages().join(";");
+            assert!(messages.contains("Function call failed"), "{messages}");
+        }
+        _ => panic!("reserving from an array map"),
+    }
+}
+
+#[test]
+fn test_spin_lock() {
+    use crate::{
+        analyzer::{Analyzer, AnalyzerConfig, VerificationError},
+        track::pointees::dyn_region::DynamicRegion,
+    };
+    use ebpf_consts::*;
+    const LOCK_HELPERS: StaticHelpers = &[
+        helpers::BPF_HELPER_INVALID,
+        helpers::BPF_HELPER_SPIN_LOCK,
+        helpers::BPF_HELPER_SPIN_UNLOCK,
+        helpers::BPF_HELPER_GET_SCALAR,
+    ];
+    let config = AnalyzerConfig {
+        helpers: LOCK_HELPERS,
+        setup: &|vm| {
+            // r1 = &value->lock
+            let value = pointed(DynamicRegion::new(8));
+            vm.add_external_resource(value.clone());
+            *vm.reg(1) = Pointer::nrwa(value).into();
+        },
+        ..Default::default()
+    };
+    let rejected = |code: &[u64], message: &str| match Analyzer::analyze(code, &config) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            let messages = branch.borrow().messages().join(";");
+            assert!(messages.contains(message), "{messages}");
+        }
+        _ => panic!("expecting rejection: {message}"),
+    };
+    let save_r1 = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 6, 0, 0);
+    let restore_r1 = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 1, 0, 0);
+    let lock = Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1);
+    let unlock = Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2);
+    let exit = BPF_JMP_EXIT as u64;
+
+    assert!(Analyzer::analyze(&[save_r1, lock, restore_r1, unlock, exit], &config).is_ok());
+    rejected(&[lock, exit], "Lock not released");
+    rejected(&[save_r1, lock, restore_r1, lock, exit], "Function call failed");
+    rejected(&[unlock, exit], "Function call failed");
+    rejected(
+        &[save_r1, lock, Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 3), exit],
+        "Helper call while holding a lock",
+    );
+}
+
 /// The module defines some commonly used helper function prototypes.
 pub mod helpers {
-    use crate::track::pointees::map_resource::{MapDeleteCall, MapLookupCall, MapUpdateCall};
+    use crate::track::pointees::dynptr::{DynptrDataCall, DynptrFromMemCall};
+    use crate::track::pointees::kernel_region::TASK_STRUCT_TYPE_ID;
+    use crate::track::pointees::map_resource::{
+        ForEachMapElemCall, MapDeleteCall, MapLookupCall, MapUpdateCall, RingbufReserveCall,
+        TailCall, TimerInitCall, TimerSetCallbackCall, TimerStartCall, RINGBUF_RECORD_TYPE_ID,
+    };
 
     use super::*;
 
@@ -250,6 +833,47 @@ pub mod helpers {
         }
     }
 
+    /// The `bpf_spin_lock` helper function (helper id `93`)
+    ///
+    /// It takes a pointer to a `struct bpf_spin_lock` and fails if the lock is already held.
+    pub struct SpinLockCall;
+
+    impl VerifiableCall<CheckedValue, BranchState> for SpinLockCall {
+        fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+            vm.ro_reg(1).check_arg_type(&ArgumentType::FixedMemory(4), None)?;
+            if vm.lock() {
+                Ok(Scalar::constant64(0).into())
+            } else {
+                Err(IllegalFunctionCall::IllegalLockState)
+            }
+        }
+
+        fn allowed_while_locked(&self) -> bool {
+            // So that double locking gets reported as such
+            true
+        }
+    }
+
+    /// The `bpf_spin_unlock` helper function (helper id `94`)
+    ///
+    /// It takes a pointer to a `struct bpf_spin_lock` and fails if no lock is held.
+    pub struct SpinUnlockCall;
+
+    impl VerifiableCall<CheckedValue, BranchState> for SpinUnlockCall {
+        fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+            vm.ro_reg(1).check_arg_type(&ArgumentType::FixedMemory(4), None)?;
+            if vm.unlock() {
+                Ok(Scalar::constant64(0).into())
+            } else {
+                Err(IllegalFunctionCall::IllegalLockState)
+            }
+        }
+
+        fn allowed_while_locked(&self) -> bool {
+            true
+        }
+    }
+
     /// A helper function that accepts [ArgumentType::Any] and returns [ReturnType::None]
     pub const BPF_HELPER_NOP: &StaticFunctionCall = &StaticFunctionCall::nop();
 
@@ -298,6 +922,36 @@ pub mod helpers {
         ReturnType::Scalar,
     );
 
+    /// A helper function `(pointer into the stack) -> error code`, reading the whole frame
+    ///
+    /// It models debugging helpers that dump the current stack frame,
+    /// see [MemoryExtent::StackFrame].
+    pub const BPF_HELPER_READ_FRAME: &StaticFunctionCall = &StaticFunctionCall::new(
+        [
+            ArgumentType::InitializedMemory(MemoryExtent::StackFrame),
+            ArgumentType::Any,
+            ArgumentType::Any,
+            ArgumentType::Any,
+            ArgumentType::Any,
+        ],
+        ReturnType::Scalar,
+    );
+
+    /// The `bpf_get_current_task_btf` helper function `() -> task`
+    ///
+    /// It needs the layout of [TASK_STRUCT_TYPE_ID] in
+    /// [crate::analyzer::AnalyzerConfig::kernel_structs].
+    pub const BPF_HELPER_GET_CURRENT_TASK_BTF: &StaticFunctionCall = &StaticFunctionCall::new(
+        [
+            ArgumentType::Any,
+            ArgumentType::Any,
+            ArgumentType::Any,
+            ArgumentType::Any,
+            ArgumentType::Any,
+        ],
+        ReturnType::TypedKernelPointer(TASK_STRUCT_TYPE_ID),
+    );
+
     /// A helper function [BPF_HELPER_DYN2]
     pub const BPF_HELPER_TRACE_PRINTK: &StaticFunctionCall = BPF_HELPER_DYN2;
 
@@ -313,6 +967,51 @@ pub mod helpers {
     /// The `bpf_map_delete_elem` helper function
     pub const BPF_HELPER_MAP_DELETE_ELEM: &MapDeleteCall = &MapDeleteCall {};
 
+    /// The `bpf_tail_call` helper function
+    pub const BPF_HELPER_TAIL_CALL: &TailCall = &TailCall {};
+
+    /// The `bpf_for_each_map_elem` helper function [ForEachMapElemCall]
+    pub const BPF_HELPER_FOR_EACH_MAP_ELEM: &ForEachMapElemCall = &ForEachMapElemCall {};
+
+    /// The `bpf_timer_init` helper function [TimerInitCall]
+    pub const BPF_HELPER_TIMER_INIT: &TimerInitCall = &TimerInitCall {};
+
+    /// The `bpf_timer_set_callback` helper function [TimerSetCallbackCall]
+    pub const BPF_HELPER_TIMER_SET_CALLBACK: &TimerSetCallbackCall = &TimerSetCallbackCall {};
+
+    /// The `bpf_timer_start` helper function [TimerStartCall]
+    pub const BPF_HELPER_TIMER_START: &TimerStartCall = &TimerStartCall {};
+
+    /// The `bpf_spin_lock` helper function [SpinLockCall]
+    pub const BPF_HELPER_SPIN_LOCK: &SpinLockCall = &SpinLockCall {};
+
+    /// The `bpf_spin_unlock` helper function [SpinUnlockCall]
+    pub const BPF_HELPER_SPIN_UNLOCK: &SpinUnlockCall = &SpinUnlockCall {};
+
+    /// The `bpf_ringbuf_reserve` helper function `(ringbuf, size, flags) -> nullable record`
+    pub const BPF_HELPER_RINGBUF_RESERVE: &RingbufReserveCall = &RingbufReserveCall {};
+
+    /// The `bpf_ringbuf_submit` helper function `(record, flags)`, releasing the record
+    pub const BPF_HELPER_RINGBUF_SUBMIT: &StaticFunctionCall = &StaticFunctionCall::new(
+        [
+            ArgumentType::ResourceType((RINGBUF_RECORD_TYPE_ID, ResourceOperation::Deallocates)),
+            ArgumentType::Scalar,
+            ArgumentType::Any,
+            ArgumentType::Any,
+            ArgumentType::Any,
+        ],
+        ReturnType::None,
+    );
+
+    /// The `bpf_ringbuf_discard` helper function [BPF_HELPER_RINGBUF_SUBMIT]
+    pub const BPF_HELPER_RINGBUF_DISCARD: &StaticFunctionCall = BPF_HELPER_RINGBUF_SUBMIT;
+
+    /// The `bpf_dynptr_from_mem` helper function [DynptrFromMemCall]
+    pub const BPF_HELPER_DYNPTR_FROM_MEM: &DynptrFromMemCall = &DynptrFromMemCall {};
+
+    /// The `bpf_dynptr_data` helper function [DynptrDataCall]
+    pub const BPF_HELPER_DYNPTR_DATA: &DynptrDataCall = &DynptrDataCall {};
+
     /// A typical helper collection for [crate::analyzer::Analyzer]
     pub const HELPERS: &[&dyn VerifiableCall<CheckedValue, BranchState>; 17] = &[
         BPF_HELPER_INVALID,
@@ -328,19 +1027,41 @@ pub mod helpers {
         BPF_HELPER_INVALID,
         BPF_HELPER_INVALID,
         BPF_HELPER_INVALID,
-        // TODO: Support tail call
-        BPF_HELPER_INVALID,
+        BPF_HELPER_TAIL_CALL,
+        // TODO: Support skb
         BPF_HELPER_INVALID,
         BPF_HELPER_GET_CURRENT_PID_TGID,
         BPF_HELPER_GET_CURRENT_UID_GID,
         BPF_HELPER_GET_CURRENT_COMM,
     ];
+
+    /// Helpers for networking programs, that is, [HELPERS] without tracing ones
+    ///
+    /// As is with the kernel, helpers reading kernel memory (`bpf_probe_read`)
+    /// or the current task are reserved for tracing programs.
+    pub const NETWORKING_HELPERS: &[&dyn VerifiableCall<CheckedValue, BranchState>; 13] = &[
+        BPF_HELPER_INVALID,
+        BPF_HELPER_MAP_LOOKUP_ELEM,
+        BPF_HELPER_MAP_UPDATE_ELEM,
+        BPF_HELPER_MAP_DELETE_ELEM,
+        // bpf_probe_read
+        BPF_HELPER_INVALID,
+        BPF_HELPER_KTIME_GET_NS,
+        BPF_HELPER_TRACE_PRINTK,
+        BPF_HELPER_GET_PRANDOM_U32,
+        BPF_HELPER_GET_SMP_PROCESSOR_ID,
+        // TODO: Support skb
+        BPF_HELPER_INVALID,
+        BPF_HELPER_INVALID,
+        BPF_HELPER_INVALID,
+        BPF_HELPER_TAIL_CALL,
+    ];
 }
 
 #[test]
 fn test_invalid_call() {
     use ebpf_consts::*;
-    let invalid: &[u64] = &[0, 9, 10, 11, 12, 13];
+    let invalid: &[u64] = &[0, 9, 10, 11, 13];
     let zero_arg: &[u64] = &[5, 7, 8, 14, 15];
     for i in 0..helpers::HELPERS.len() {
         let result = crate::analyzer::Analyzer::analyze(&[
@@ -349,9 +1070,8 @@ fn test_invalid_call() {
             BPF_JMP_EXIT as u64,
         ], &crate::analyzer::AnalyzerConfig {
             helpers: helpers::HELPERS,
-            setup: &|_| {},
             processed_instruction_limit: 10,
-            map_fd_collector: &|_| None,
+            ..Default::default()
         });
         if invalid.contains(&(i as u64)) {
             assert!(matches!(result, Err(crate::analyzer::VerificationError::IllegalStateChange(_))));
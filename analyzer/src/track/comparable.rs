@@ -1,6 +1,6 @@
 //! This module makes [Scalar] [Comparable], generating a [ComparisonResult].
 
-use super::scalar::Scalar;
+use super::{range::RangePair, scalar::Scalar};
 
 /// The predicted comparison result
 pub enum ComparisonResult<T> {
@@ -46,12 +46,43 @@ pub trait Comparable: Sized {
     comparable_ops!(eq, set, lt, le, slt, sle);
 }
 
+/// Syncs both the narrowed `(lhs, rhs)` for `true` and the pair for `false`
+///
+/// If either branch turns out impossible, `(lhs, rhs)` are restored to the `original` values,
+/// yielding `Always` or `Never` instead.
+fn settle(
+    lhs: &mut Scalar,
+    rhs: &mut Scalar,
+    original: (Scalar, Scalar),
+    (mut false_lhs, mut false_rhs): (Scalar, Scalar),
+) -> ComparisonResult<Scalar> {
+    let may_be_true = lhs.try_sync_bounds() && rhs.try_sync_bounds();
+    let may_be_false = false_lhs.try_sync_bounds() && false_rhs.try_sync_bounds();
+    match (may_be_true, may_be_false) {
+        (true, true) => ComparisonResult::Perhaps((false_lhs, false_rhs)),
+        (true, false) => {
+            (*lhs, *rhs) = original;
+            ComparisonResult::Always
+        }
+        (false, true) => {
+            (*lhs, *rhs) = original;
+            ComparisonResult::Never
+        }
+        (false, false) => {
+            // Only possible with inconsistent inputs, about which we know nothing
+            (*lhs, *rhs) = original.clone();
+            ComparisonResult::Perhaps(original)
+        }
+    }
+}
+
 /// Passing the `le` calls to [RangePair]
 ///
 /// Use `swap` to get a `gt` result.
 /// See actual usages below to see what things are swapped.
 macro_rules! yield_le {
-    ($self:ident, $rhs:ident, $range:ident, $swap:expr) => {
+    ($self:ident, $rhs:ident, $range:ident, $swap:expr) => {{
+        let original = ($self.clone(), $rhs.clone());
         match $self.$range.le(&mut $rhs.$range) {
             ComparisonResult::Always => {
                 if $swap {
@@ -78,18 +109,14 @@ macro_rules! yield_le {
                     s1.$range = gt1;
                     s2.$range = gt2;
                 }
-                $self.sync_bounds();
-                $rhs.sync_bounds();
-                s1.sync_bounds();
-                s2.sync_bounds();
                 if $swap {
-                    ComparisonResult::Perhaps((s2, s1))
+                    settle($rhs, $self, (original.1, original.0), (s2, s1))
                 } else {
-                    ComparisonResult::Perhaps((s1, s2))
+                    settle($self, $rhs, original, (s1, s2))
                 }
             }
         }
-    };
+    }};
 }
 
 impl Comparable for Scalar {
@@ -108,14 +135,12 @@ impl Comparable for Scalar {
                     let icommon = self.$irange.intersects(&rhs.$irange);
                     let ucommon = self.$urange.intersects(&rhs.$urange);
                     if icommon.is_valid() && ucommon.is_valid() {
-                        let other = ComparisonResult::Perhaps((self.clone(), rhs.clone()));
+                        let original = (self.clone(), rhs.clone());
                         self.$irange = icommon;
                         rhs.$irange = icommon;
                         self.$urange = ucommon;
                         rhs.$urange = ucommon;
-                        self.sync_bounds();
-                        rhs.sync_bounds();
-                        other
+                        settle(self, rhs, original.clone(), original)
                     } else {
                         // Must not eq
                         ComparisonResult::Never
@@ -140,7 +165,7 @@ impl Comparable for Scalar {
 
     fn set(&mut self, rhs: &mut Self, width: u8) -> ComparisonResult<Self> {
         macro_rules! yield_set {
-            ($width:expr, $self_bits:expr, $rhs_bits:expr) => {
+            ($width:expr, $self_bits:expr, $rhs_bits:expr, $urange:ident, $utype:ident) => {
                 {
                     let sbits = $self_bits;
                     let rbits = $rhs_bits;
@@ -152,14 +177,24 @@ impl Comparable for Scalar {
                         } else {
                             if !sbits.is_constant() && rbits.is_constant() {
                                 // We can deduce more info for each branch only if either of them is constant
+                                let original = (self.clone(), rhs.clone());
                                 let mut other = self.clone();
+                                // No masked bits set: all of them are zero, bounding the range
                                 other.bits = other.bits & !rbits;
-                                other.sync_bounds();
+                                let cleared = sbits & !rbits;
+                                let urange = other.$urange.intersects(&RangePair::new(
+                                    cleared.min() as $utype,
+                                    cleared.max() as $utype,
+                                ));
+                                if !urange.is_valid() {
+                                    // Some masked bits are always set
+                                    return ComparisonResult::Always;
+                                }
+                                other.$urange = urange;
                                 if rbits.value().count_ones() == 1 {
                                     self.bits = self.bits | rbits;
-                                    self.sync_bounds();
                                 }
-                                ComparisonResult::Perhaps((other, rhs.clone()))
+                                settle(self, rhs, original, (other, rhs.clone()))
                             } else if sbits.is_constant() && !rbits.is_constant() {
                                 match rhs.set(self, $width) {
                                     ComparisonResult::Always => ComparisonResult::Always,
@@ -180,9 +215,15 @@ impl Comparable for Scalar {
 
         debug_assert!(width == 32 || width == 64);
         if width == 32 {
-            yield_set!(32, self.bits.lower_half(), rhs.bits.lower_half())
+            yield_set!(
+                32,
+                self.bits.lower_half(),
+                rhs.bits.lower_half(),
+                urange32,
+                u32
+            )
         } else {
-            yield_set!(64, self.bits, rhs.bits)
+            yield_set!(64, self.bits, rhs.bits, urange, u64)
         }
     }
 
@@ -382,3 +423,68 @@ fn test_ranged_scalars() {
         ComparisonResult::Never => panic!("N"),
     }
 }
+
+#[test]
+fn test_set_none_branch() {
+    // if r1 <= 0xFF
+    let mut s = Scalar::unknown();
+    let _ = s.le(&mut Scalar::constant64(0xFF), 64);
+    assert!(s.urange.max == 0xFF);
+    for width in [32, 64] {
+        // if (r1 & 0xF0) == 0
+        match s.clone().set(&mut Scalar::constant64(0xF0), width) {
+            ComparisonResult::Perhaps((none_set, _)) => {
+                assert!(none_set.urange.max == 0x0F);
+                assert!(none_set.urange32.max == 0x0F);
+                assert!(none_set.irange.max == 0x0F);
+                assert!(none_set.bits.max() == 0x0F);
+            }
+            _ => panic!("both branches are possible"),
+        }
+    }
+
+    // if 0x10 <= r1 <= 0xFF, the masked bits can never be all zero
+    let mut s = Scalar::unknown();
+    let _ = s.le(&mut Scalar::constant64(0xFF), 64);
+    let _ = Scalar::constant64(0x10).le(&mut s, 64);
+    assert!(s.urange.min == 0x10);
+    assert!(matches!(
+        s.set(&mut Scalar::constant64(0xF0), 64),
+        ComparisonResult::Always
+    ));
+}
+
+#[test]
+fn test_contradicting_branches() {
+    // One of 0x05, 0x15, 0x25 and 0x35, whose range contains 0x29 nonetheless
+    let mut s = Scalar::from_tnum(0x30, 0x05);
+    assert!(!s.contains(0x29u64));
+    for width in [32, 64] {
+        let original = s.clone();
+        assert!(matches!(
+            s.eq(&mut Scalar::constant64(0x29), width),
+            ComparisonResult::Never
+        ));
+        assert!(s.is_subset_of(&original) && original.is_subset_of(&s));
+    }
+
+    // Not a constant, but either 0x26 or 0x27, with bit 1 set
+    let mut t = Scalar::unknown();
+    let _ = Scalar::constant64(0x26).le(&mut t, 64);
+    let _ = t.le(&mut Scalar::constant64(0x27), 64);
+    let original = (s.clone(), t.clone());
+    assert!(matches!(s.eq(&mut t, 64), ComparisonResult::Never));
+    assert!(s.is_subset_of(&original.0) && original.0.is_subset_of(&s));
+    assert!(t.is_subset_of(&original.1) && original.1.is_subset_of(&t));
+
+    // Both branches possible: only the narrowed values get synced
+    let mut u = Scalar::from_tnum(0x30, 0x05);
+    match u.lt(&mut Scalar::constant64(0x20), 64) {
+        ComparisonResult::Perhaps((mut ge, _)) => {
+            assert_eq!(u.unsigned_range(), 0x05..=0x15);
+            assert!(ge.try_sync_bounds());
+            assert_eq!(ge.unsigned_range(), 0x25..=0x35);
+        }
+        _ => panic!("both branches are possible"),
+    }
+}
@@ -0,0 +1,224 @@
+//! A soundness cross-check of [Scalar] tracking against concrete values, see [check_soundness].
+
+use alloc::vec::Vec;
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+
+use crate::interpreter::value::{
+    ByteSwap, Castable, NegAssign, SafeDivAssign, ShiftAssign as ConcreteShift,
+};
+
+use super::{
+    comparable::{Comparable, ComparisonResult},
+    range::RangePair,
+    scalar::{Scalar, ShiftAssign},
+};
+
+/// A binary operation on scalars, with the bit width
+type ScalarOp = fn(&mut Scalar, &Scalar, u8);
+/// The same operation on concrete values, as is done by the interpreter
+type ConcreteOp = fn(&mut u64, u64, u8);
+
+/// Binary operations, applied the way the interpreter does (see `interpreter::dispatch`)
+fn binary_ops() -> [(&'static str, ScalarOp, ConcreteOp); 14] {
+    [
+        ("add", |s, t, _| *s += t, |u, v, _| *u = u.wrapping_add(v)),
+        ("sub", |s, t, _| *s -= t, |u, v, _| *u = u.wrapping_sub(v)),
+        ("mul", |s, t, _| *s *= t, |u, v, _| *u = u.wrapping_mul(v)),
+        ("and", |s, t, _| *s &= t, |u, v, _| *u &= v),
+        ("or", |s, t, _| *s |= t, |u, v, _| *u |= v),
+        ("xor", |s, t, _| *s ^= t, |u, v, _| *u ^= v),
+        ("div", |s, t, _| *s /= t, |u, v, _| u.safe_div_assign(&v)),
+        ("mod", |s, t, _| *s %= t, |u, v, _| u.safe_rem_assign(&v)),
+        (
+            "sdiv",
+            |s, t, w| match w {
+                32 => s.signed_div::<32>(t),
+                _ => s.signed_div::<64>(t),
+            },
+            |u, v, w| u.signed_div_assign(&v, w),
+        ),
+        (
+            "smod",
+            |s, t, w| match w {
+                32 => s.signed_rem::<32>(t),
+                _ => s.signed_rem::<64>(t),
+            },
+            |u, v, w| u.signed_rem_assign(&v, w),
+        ),
+        (
+            "lsh",
+            |s, t, w| match w {
+                32 => ShiftAssign::<32, &Scalar>::shl_assign(s, t),
+                _ => ShiftAssign::<64, &Scalar>::shl_assign(s, t),
+            },
+            |u, v, w| u.l_shift(&v, w),
+        ),
+        (
+            "rsh",
+            |s, t, w| match w {
+                32 => ShiftAssign::<32, &Scalar>::shr_assign(s, t),
+                _ => ShiftAssign::<64, &Scalar>::shr_assign(s, t),
+            },
+            |u, v, w| u.r_shift(&v, w),
+        ),
+        (
+            "arsh",
+            |s, t, w| match w {
+                32 => ShiftAssign::<32, &Scalar>::ashr_assign(s, t),
+                _ => ShiftAssign::<64, &Scalar>::ashr_assign(s, t),
+            },
+            |u, v, w| u.signed_shr(&v, w),
+        ),
+        ("neg", |s, _, _| s.neg_assign(), |u, _, _| u.neg_assign()),
+    ]
+}
+
+/// Generates a random scalar along with a concrete value within it
+fn operand(rng: &mut ThreadRng) -> (Scalar, u64) {
+    match rng.gen_range(0..4) {
+        0 => {
+            let value = rng.gen();
+            (Scalar::constant64(value), value)
+        }
+        1 => {
+            let value = rng.gen_range(0..64);
+            (Scalar::constant64(value), value)
+        }
+        2 => {
+            let mask = rng.gen::<u64>() & rng.gen::<u64>();
+            let scalar = Scalar::from_tnum(mask, rng.gen());
+            let value = scalar.bits.value() | (rng.gen::<u64>() & mask);
+            (scalar, value)
+        }
+        _ => {
+            let min = rng.gen_range(-256..256);
+            let max = min + rng.gen_range(0..256);
+            let mut scalar = Scalar::unknown();
+            scalar.irange = RangePair::new(min, max);
+            scalar.sync_bounds();
+            (scalar, rng.gen_range(min..=max) as u64)
+        }
+    }
+}
+
+/// Applies a binary operation in 32 or 64 bits, like the interpreter does
+fn apply(
+    (s, u): (&mut Scalar, &mut u64),
+    (t, v): (&Scalar, u64),
+    width: u8,
+    (scalar_op, concrete_op): (ScalarOp, ConcreteOp),
+) {
+    if width == 32 {
+        let mut t = t.clone();
+        t.mark_upper_half_unknown();
+        s.mark_upper_half_unknown();
+        u.lower_half_assign();
+        scalar_op(s, &t, width);
+        concrete_op(u, v.lower_half(), width);
+        s.lower_half();
+        u.zero_upper_half_assign();
+    } else {
+        scalar_op(s, t, width);
+        concrete_op(u, v, width);
+    }
+}
+
+/// Compares the scalars, keeping the branch that the concrete values take
+///
+/// Comparisons are `eq`, `set`, `lt`, `le`, `slt` and `sle`, selected by `op`.
+fn compare((s, u): (&mut Scalar, u64), (t, v): (&mut Scalar, u64), op: usize, width: u8) {
+    let (u32, v32) = (u as u32, v as u32);
+    let holds = match (op, width) {
+        (0, 32) => u32 == v32,
+        (0, _) => u == v,
+        (1, 32) => u32 & v32 != 0,
+        (1, _) => u & v != 0,
+        (2, 32) => u32 < v32,
+        (2, _) => u < v,
+        (3, 32) => u32 <= v32,
+        (3, _) => u <= v,
+        (4, 32) => (u32 as i32) < (v32 as i32),
+        (4, _) => (u as i64) < (v as i64),
+        (_, 32) => (u32 as i32) <= (v32 as i32),
+        (_, _) => (u as i64) <= (v as i64),
+    };
+    let result = match op {
+        0 => Comparable::eq(s, t, width),
+        1 => s.set(t, width),
+        2 => s.lt(t, width),
+        3 => s.le(t, width),
+        4 => s.slt(t, width),
+        _ => s.sle(t, width),
+    };
+    match result {
+        ComparisonResult::Always => assert!(holds, "Always: 0x{u:x} #{op} 0x{v:x}"),
+        ComparisonResult::Never => assert!(!holds, "Never: 0x{u:x} #{op} 0x{v:x}"),
+        ComparisonResult::Perhaps((s_false, t_false)) => {
+            if !holds {
+                *s = s_false;
+                *t = t_false;
+            }
+        }
+    }
+}
+
+/// Applies random operation sequences to both a [Scalar] and a concrete `u64` within it
+///
+/// It panics with the applied operations if the scalar ever loses track of the concrete value.
+pub fn check_soundness(iterations: usize) {
+    let mut rng = thread_rng();
+    let ops = binary_ops();
+    for _ in 0..iterations {
+        let (mut s, mut u) = operand(&mut rng);
+        let mut trace = Vec::new();
+        for _ in 0..rng.gen_range(1..32) {
+            let (mut t, v) = operand(&mut rng);
+            let width = if rng.gen_bool(0.5) { 32 } else { 64 };
+            let op = rng.gen_range(0..ops.len() + 3);
+            trace.push((op, width, t.clone(), v, s.clone(), u));
+            match op {
+                _ if op < ops.len() => {
+                    let (_, scalar_op, concrete_op) = ops[op];
+                    apply((&mut s, &mut u), (&t, v), width, (scalar_op, concrete_op));
+                }
+                _ if op == ops.len() => {
+                    compare((&mut s, u), (&mut t, v), rng.gen_range(0..6), width);
+                    assert!(t.contains(v), "{t:?} lost 0x{v:x}");
+                }
+                _ if op == ops.len() + 1 => {
+                    let width = [16, 32, 64][rng.gen_range(0..3)];
+                    if rng.gen_bool(0.5) {
+                        s.host_to_be(width);
+                        u.host_to_be(width);
+                    } else {
+                        s.host_to_le(width);
+                        u.host_to_le(width);
+                    }
+                }
+                _ => {
+                    s.lower_half();
+                    u.zero_upper_half_assign();
+                }
+            }
+            assert!(
+                s.contains(u)
+                    && s.contains(u as i64)
+                    && s.contains(u as u32)
+                    && s.contains(u as i32),
+                "{s:?} lost 0x{u:x} after {:?}",
+                trace
+                    .iter()
+                    .map(|(op, width, t, v, s, u)| {
+                        let name = ops.get(*op).map(|(name, _, _)| *name).unwrap_or("misc");
+                        alloc::format!("{s:?}(0x{u:x}) {name}{width} {t:?}(0x{v:x})")
+                    })
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_soundness() {
+    check_soundness(20000);
+}
@@ -1,7 +1,10 @@
 //! This modules contains all kinds of structs and traits for tracking values.
+//!
+//! The soundness cross-check of [Scalar] (the `fuzz` module) needs the `fuzz` feature.
 
 use self::{scalar::Scalar, pointer::Pointer};
 
+pub mod id;
 pub mod pointer;
 pub mod scalar;
 
@@ -9,6 +12,8 @@ mod range;
 mod tnum;
 pub mod pointees;
 pub mod comparable;
+#[cfg(any(test, feature = "fuzz"))]
+pub mod fuzz;
 
 /// A `Value` implementation
 #[derive(Clone, Debug)]
@@ -17,6 +22,10 @@ pub enum TrackedValue {
     Pointer(Pointer),
     /// Scalar values
     Scalar(Scalar),
+    /// Address of a subprogram (by its index), only usable as a callback argument
+    ///
+    /// See [crate::spec::proto::ArgumentType::Callback].
+    FuncPointer(usize),
 }
 
 impl From<Scalar> for TrackedValue {
@@ -31,6 +40,18 @@ impl From<Pointer> for TrackedValue {
     }
 }
 
+impl TrackedValue {
+    /// Checks whether all values this one may be are also possible for `other`
+    pub fn is_subset_of(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TrackedValue::Pointer(p), TrackedValue::Pointer(q)) => p.is_subset_of(q),
+            (TrackedValue::Scalar(s), TrackedValue::Scalar(t)) => s.is_subset_of(t),
+            (TrackedValue::FuncPointer(f), TrackedValue::FuncPointer(g)) => f == g,
+            _ => false,
+        }
+    }
+}
+
 /// Error during tracking
 #[derive(Debug)]
 pub enum TrackError {
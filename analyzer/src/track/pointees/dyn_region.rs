@@ -1,11 +1,9 @@
 //! A dynamic region
 
+use alloc::rc::Rc;
 use num_traits::ToPrimitive;
 
-use crate::{
-    branch::id::Id,
-    track::{scalar::Scalar, TrackError, TrackedValue},
-};
+use crate::track::{id::Id, scalar::Scalar, TrackError, TrackedValue};
 
 use super::{is_access_in_range, InnerRegion, MemoryRegion, Pointee, SafeClone, pointed};
 
@@ -17,25 +15,160 @@ pub struct DynamicRegion {
     /// A user-defined upper-limit for the limit,
     /// preventing some nasty limits like `u64::MAX`, causing an overflow
     upper_limit: usize,
+    /// Known (and immutable) contents at the start of the region
+    contents: Option<Rc<[u8]>>,
+    /// The pointer offset corresponding to the start of the region
+    base: i64,
+    /// Whether it holds packet data, see [DynamicRegion::set_packet]
+    packet: bool,
 }
 
 impl DynamicRegion {
     /// Creates a region with fixed size (in bytes)
     pub fn new(size: usize) -> Self {
-        Self { id: 0, limit: size, upper_limit: size }
+        Self {
+            id: 0,
+            limit: size,
+            upper_limit: size,
+            contents: None,
+            base: 0,
+            packet: false,
+        }
+    }
+
+    /// Sets the pointer offset at which the region starts
+    ///
+    /// By default, pointers to the region start at offset zero. With a base of `-len`,
+    /// a pointer with offset zero points to the end of the region instead,
+    /// and the region spans offsets `[-len, 0)`, like the stack does.
+    pub fn set_base(&mut self, base: i64) {
+        self.base = base;
+    }
+
+    /// The pointer offset at which the region starts, see [DynamicRegion::set_base]
+    pub fn base(&self) -> i64 {
+        self.base
+    }
+
+    /// Marks the region as packet data, with its end only known at runtime
+    ///
+    /// It is done by [crate::track::pointer::Pointer::end].
+    pub fn set_packet(&mut self) {
+        self.packet = true;
+    }
+
+    /// Converts a pointer offset into an offset from the start of the region
+    fn rebase(&self, offset: &Scalar) -> Scalar {
+        let mut offset = offset.clone();
+        if self.base != 0 {
+            offset -= &Scalar::constant64(self.base as u64);
+        }
+        offset
+    }
+
+    /// Sets the known contents of the region
+    ///
+    /// Reads of 1, 2, 4 or 8 bytes at constant offsets within the contents
+    /// yield constants, in the byte order of the target like the stack. The caller should ensure that the region is never written to.
+    pub fn set_contents(&mut self, contents: Rc<[u8]>) {
+        self.contents = Some(contents);
+    }
+
+    /// Reads from the known contents, assuming the access is in range
+    fn read_contents(&self, offset: &Scalar, size: u8) -> Scalar {
+        if let (Some(contents), Some(offset)) = (&self.contents, offset.value64()) {
+            let start = offset as usize;
+            if let (1 | 2 | 4 | 8, Some(bytes)) = (size, contents.get(start..start + size as usize))
+            {
+                let mut value = [0u8; 8];
+                let value = if cfg!(target_endian = "big") {
+                    value[8 - bytes.len()..].copy_from_slice(bytes);
+                    u64::from_be_bytes(value)
+                } else {
+                    value[..bytes.len()].copy_from_slice(bytes);
+                    u64::from_le_bytes(value)
+                };
+                return Scalar::constant64(value);
+            }
+        }
+        Scalar::unknown()
     }
 
     /// Sets the size limit of this region
+    ///
+    /// For a dynamic `limit`, only its lower bound is recorded here.
+    /// See [crate::track::pointer::Pointer::set_window] for dynamic-length accesses.
     pub fn set_limit(&mut self, limit: &Scalar) {
-        self.limit = self.limit.max(match limit.value64() {
-            Some(limit) => limit.to_usize().unwrap_or(0),
-            None => 0,
+        self.limit = self.limit.max(if self.is_bounded(limit) {
+            limit.unsigned_range().start().to_usize().unwrap_or(0)
+        } else {
+            0
         });
         if self.limit > self.upper_limit {
             self.limit = 0;
         }
     }
 
+    /// Checks if the offset is never negative and never exceeds the upper limit,
+    /// so that `start + offset` never overflows
+    pub fn is_bounded(&self, offset: &Scalar) -> bool {
+        offset
+            .is_signed_in_sync()
+            .is_some_and(|(min, _)| min >= 0)
+            && *offset.unsigned_range().end() <= self.upper_limit as u64
+    }
+
+    /// Reads through a pointer that has `window` bytes left before the end of the region
+    pub fn get_within(
+        &mut self,
+        offset: &Scalar,
+        size: u8,
+        window: usize,
+    ) -> Result<TrackedValue, TrackError> {
+        let offset = &self.rebase(offset);
+        is_access_in_range(offset, size, self.limit_within(offset, window))?;
+        Ok(self.read_contents(offset, size).into())
+    }
+
+    /// Writes through a pointer that has `window` bytes left before the end of the region
+    pub fn set_within(
+        &mut self,
+        offset: &Scalar,
+        size: u8,
+        value: &TrackedValue,
+        window: usize,
+    ) -> Result<(), TrackError> {
+        match value {
+            TrackedValue::Pointer(_) | TrackedValue::FuncPointer(_) => {
+                Err(TrackError::PointeeNotWritable)
+            }
+            TrackedValue::Scalar(_) => {
+                let offset = &self.rebase(offset);
+                is_access_in_range(offset, size, self.limit_within(offset, window))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// The end of the region as is seen from a pointer with `window` bytes left
+    fn limit_within(&self, offset: &Scalar, window: usize) -> usize {
+        match offset.is_signed_in_sync() {
+            Some((_, max)) if max >= 0 => self.limit.max(max as usize + window),
+            _ => self.limit,
+        }
+    }
+
+    /// Checks whether the region is subsumed by `other`
+    ///
+    /// The region should be at least as large as `other`, with the same contents.
+    pub fn is_subset_of(&self, other: &DynamicRegion) -> bool {
+        self.limit >= other.limit
+            && self.upper_limit == other.upper_limit
+            && self.base == other.base
+            && self.packet == other.packet
+            && self.contents == other.contents
+    }
+
     /// Sets an upper limit for the length of the region
     ///
     /// This prevents some malicious code generating nasty limits like `u64::MAX`,
@@ -63,15 +196,18 @@ impl SafeClone for DynamicRegion {
 
 impl MemoryRegion for DynamicRegion {
     fn get(&mut self, offset: &Scalar, size: u8) -> Result<TrackedValue, TrackError> {
+        let offset = &self.rebase(offset);
         is_access_in_range(offset, size, self.limit)?;
-        Ok(Scalar::unknown().into())
+        Ok(self.read_contents(offset, size).into())
     }
 
     fn set(&mut self, offset: &Scalar, size: u8, value: &TrackedValue) -> Result<(), TrackError> {
         match value {
-            TrackedValue::Pointer(_) => Err(TrackError::PointeeNotWritable),
+            TrackedValue::Pointer(_) | TrackedValue::FuncPointer(_) => {
+                Err(TrackError::PointeeNotWritable)
+            }
             TrackedValue::Scalar(_) => {
-                is_access_in_range(offset, size, self.limit)?;
+                is_access_in_range(&self.rebase(offset), size, self.limit)?;
                 Ok(())
             }
         }
@@ -80,6 +216,17 @@ impl MemoryRegion for DynamicRegion {
     fn inner(&mut self) -> InnerRegion {
         InnerRegion::Dyn(self)
     }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        match other.inner() {
+            InnerRegion::Dyn(other) => DynamicRegion::is_subset_of(self, other),
+            _ => false,
+        }
+    }
+
+    fn is_packet(&self) -> bool {
+        self.packet
+    }
 }
 
 impl Default for DynamicRegion {
@@ -88,6 +235,9 @@ impl Default for DynamicRegion {
             id: Default::default(),
             limit: Default::default(),
             upper_limit: 64 * 1024,
+            contents: None,
+            base: 0,
+            packet: false,
         }
     }
 }
@@ -120,3 +270,62 @@ fn test_dyn_region() {
         )
         .is_err());
 }
+
+#[test]
+fn test_dyn_region_contents() {
+    let mut region = DynamicRegion::new(16);
+    region.set_contents(Rc::from(&[1u8, 2, 3, 4, 5, 6, 7, 8, 9, 10][..]));
+    let read = |region: &mut DynamicRegion, offset: u64, size: u8| {
+        let value = region.get(&Scalar::constant64(offset), size);
+        match value {
+            Ok(TrackedValue::Scalar(s)) => s.value64(),
+            _ => panic!(),
+        }
+    };
+    assert_eq!(read(&mut region, 0, 1), Some(1));
+    assert_eq!(
+        read(&mut region, 2, 2),
+        Some(u16::from_ne_bytes([3, 4]) as u64)
+    );
+    assert_eq!(
+        read(&mut region, 4, 4),
+        Some(u32::from_ne_bytes([5, 6, 7, 8]) as u64)
+    );
+    assert_eq!(
+        read(&mut region, 1, 8),
+        Some(u64::from_ne_bytes([2, 3, 4, 5, 6, 7, 8, 9]))
+    );
+    // Beyond the known contents
+    assert_eq!(read(&mut region, 8, 4), None);
+    assert_eq!(read(&mut region, 12, 4), None);
+    // Unknown offsets
+    assert!(matches!(
+        region.get(&Scalar::from_tnum(1, 0), 1),
+        Ok(TrackedValue::Scalar(s)) if s.value64().is_none()
+    ));
+}
+
+#[test]
+fn test_dyn_region_base() {
+    let mut region = DynamicRegion::new(16);
+    region.set_contents(Rc::from(&[1u8, 2, 3, 4][..]));
+    region.set_base(-16);
+    let at = |offset: i64| Scalar::constant64(offset as u64);
+    let value = Scalar::unknown().into();
+    assert!(matches!(
+        region.get(&at(-16), 1),
+        Ok(TrackedValue::Scalar(s)) if s.value64() == Some(1)
+    ));
+    assert!(region.get(&at(-1), 1).is_ok());
+    assert!(region.set(&at(-8), 8, &value).is_ok());
+    assert!(region.get(&at(0), 1).is_err());
+    assert!(region.get(&at(-17), 1).is_err());
+    assert!(region.set(&at(-4), 8, &value).is_err());
+
+    // Variable offsets and windows
+    let mut offset = Scalar::from_tnum(0xF, 0);
+    offset -= &at(16);
+    assert!(region.get(&offset, 1).is_ok());
+    assert!(region.get_within(&offset, 4, 0).is_err());
+    assert!(region.get_within(&at(-4), 4, 4).is_ok());
+}
@@ -0,0 +1,174 @@
+//! See [Dynptr].
+
+use crate::{
+    branch::{checked_value::CheckedValue, vm::BranchState},
+    interpreter::vm::Vm,
+    spec::proto::{
+        ArgumentType, IllegalFunctionCall, ReturnType, StaticFunctionCall, VerifiableCall,
+    },
+    track::{id::Id, pointer::Pointer, scalar::Scalar, TrackError, TrackedValue},
+};
+
+use super::{downcast_region, pointed, AnyType, InnerRegion, MemoryRegion, Pointee, SafeClone};
+
+/// The type id for dynamic pointers
+pub const DYNPTR_TYPE_ID: AnyType = -4i32;
+
+/// Size of `struct bpf_dynptr`
+pub const DYNPTR_SIZE: usize = 16;
+
+/// A dynamic pointer (`struct bpf_dynptr`) to a buffer of known size
+///
+/// Programs only see it through the stack slot it gets initialized in,
+/// which holds a pointer to this resource (see [DynptrFromMemCall]).
+#[derive(Clone, Debug)]
+pub struct Dynptr {
+    id: Id,
+    /// Start of the underlying buffer
+    data: Pointer,
+    size: usize,
+}
+
+impl Dynptr {
+    /// Creates a dynamic pointer to `size` bytes starting at `data`
+    pub fn new(data: Pointer, size: usize) -> Self {
+        Self { id: 0, data, size }
+    }
+
+    /// Returns a nullable pointer to `len` bytes at `offset` in the buffer
+    pub fn slice(&self, offset: usize, len: usize) -> Result<Pointer, IllegalFunctionCall> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.size => {
+                let mut slice = self.data.clone();
+                slice += &Scalar::constant64(offset as u64);
+                slice.set_nullable();
+                Ok(slice)
+            }
+            _ => Err(IllegalFunctionCall::OutofRange),
+        }
+    }
+}
+
+impl SafeClone for Dynptr {
+    fn get_id(&self) -> Id {
+        self.id
+    }
+
+    fn set_id(&mut self, id: Id) {
+        self.id = id
+    }
+
+    fn safe_clone(&self) -> Pointee {
+        pointed(self.clone())
+    }
+
+    fn redirects(&mut self, mapper: &dyn Fn(Id) -> Option<Pointee>) {
+        if let Some(region) = mapper(self.data.get_pointing_to()) {
+            self.data.redirect(region);
+        }
+    }
+}
+
+impl MemoryRegion for Dynptr {
+    fn get(&mut self, _offset: &Scalar, _size: u8) -> Result<TrackedValue, TrackError> {
+        Err(TrackError::PointeeNotReadable)
+    }
+
+    fn set(
+        &mut self,
+        _offset: &Scalar,
+        _size: u8,
+        _value: &TrackedValue,
+    ) -> Result<(), TrackError> {
+        Err(TrackError::PointeeNotWritable)
+    }
+
+    fn inner(&mut self) -> InnerRegion<'_> {
+        InnerRegion::Any((DYNPTR_TYPE_ID, self))
+    }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        downcast_region::<Dynptr>(other, DYNPTR_TYPE_ID)
+            .is_some_and(|other| self.size == other.size && self.data.is_subset_of(&other.data))
+    }
+}
+
+/// Returns the stack slot for a `struct bpf_dynptr` in the register
+fn dynptr_slot(vm: &BranchState, reg: u8) -> Result<Pointer, IllegalFunctionCall> {
+    if vm.is_invalid_resource(reg) {
+        return Err(IllegalFunctionCall::IllegalResource);
+    }
+    if let Some(TrackedValue::Pointer(p)) = vm.ro_reg(reg).inner() {
+        if matches!(
+            p.get_pointing_region().borrow_mut().inner(),
+            InnerRegion::Stack(_)
+        ) {
+            return Ok(p.clone());
+        }
+    }
+    Err(IllegalFunctionCall::TypeMismatch)
+}
+
+/// bpf_dynptr_from_mem
+///
+/// Initializes the dynptr in the stack slot `r4` with `r2` (a constant) bytes at `r1`.
+/// The flags (`r3`) must be zero.
+pub struct DynptrFromMemCall;
+
+impl VerifiableCall<CheckedValue, BranchState> for DynptrFromMemCall {
+    fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+        let slot = dynptr_slot(vm, 4)?;
+        slot.set_all(DYNPTR_SIZE)
+            .map_err(IllegalFunctionCall::IllegalPointer)?;
+        let result = StaticFunctionCall::new(
+            [
+                ArgumentType::DynamicMemory(2),
+                ArgumentType::Scalar,
+                ArgumentType::Constant(0..=0),
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        )
+        .call(vm)?;
+        let size = vm.ro_reg(2).constant_size()?;
+        let Some(TrackedValue::Pointer(data)) = vm.ro_reg(1).inner() else {
+            return Err(IllegalFunctionCall::TypeMismatch);
+        };
+        let dynptr = pointed(Dynptr::new(data.clone(), size));
+        vm.add_external_resource(dynptr.clone());
+        slot.set(8, &Pointer::nrw(dynptr).into())
+            .map_err(IllegalFunctionCall::IllegalPointer)?;
+        let mut rest = slot;
+        rest += &Scalar::constant64(8);
+        rest.set(8, &Scalar::unknown().into())
+            .map_err(IllegalFunctionCall::IllegalPointer)?;
+        Ok(result)
+    }
+}
+
+/// bpf_dynptr_data
+///
+/// Returns a nullable pointer to `r3` bytes at offset `r2` of the dynptr in the stack slot `r1`,
+/// both being constants within the size of the dynptr.
+pub struct DynptrDataCall;
+
+impl VerifiableCall<CheckedValue, BranchState> for DynptrDataCall {
+    fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+        let slot = dynptr_slot(vm, 1)?;
+        let offset = vm.ro_reg(2).constant_size()?;
+        let len = vm.ro_reg(3).constant_size()?;
+        if let Ok(TrackedValue::Pointer(p)) = slot.get(8) {
+            if p.non_null() {
+                let region = p.get_pointing_region();
+                let mut region = region.borrow_mut();
+                if let InnerRegion::Any((DYNPTR_TYPE_ID, dynptr)) = region.inner() {
+                    if let Some(dynptr) = dynptr.downcast_ref::<Dynptr>() {
+                        return Ok(dynptr.slice(offset, len)?.into());
+                    }
+                }
+            }
+        }
+        Err(IllegalFunctionCall::IllegalResource)
+    }
+}
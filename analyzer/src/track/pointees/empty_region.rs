@@ -1,8 +1,8 @@
 //! See [EmptyRegion].
 
-use crate::{track::{scalar::Scalar, TrackedValue, TrackError}, branch::id::Id};
+use crate::track::{id::Id, scalar::Scalar, TrackedValue, TrackError};
 
-use super::{MemoryRegion, SafeClone, Pointee, pointed};
+use super::{MemoryRegion, SafeClone, Pointee, pointed, InnerRegion};
 
 /// Not a valid region
 ///
@@ -26,6 +26,10 @@ impl MemoryRegion for EmptyRegion {
     fn set(&mut self, _offset: &Scalar, _size: u8, _value: &TrackedValue) -> Result<(), TrackError> {
         Err(crate::track::TrackError::PointeeNotWritable)
     }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        matches!(other.inner(), InnerRegion::None)
+    }
 }
 
 impl SafeClone for EmptyRegion {
@@ -0,0 +1,117 @@
+//! See [KernelRegion].
+
+use crate::track::{id::Id, scalar::Scalar, TrackError, TrackedValue};
+
+use super::{
+    downcast_region, pointed,
+    struct_region::{Field, StructRegion},
+    AnyType, InnerRegion, MemoryRegion, Pointee, SafeClone,
+};
+
+/// Type id for `struct task_struct`, see [crate::spec::proto::helpers::BPF_HELPER_GET_CURRENT_TASK_BTF]
+pub const TASK_STRUCT_TYPE_ID: AnyType = -5i32;
+
+/// Layout of a kernel struct, as is described by its BTF info
+///
+/// See [crate::analyzer::AnalyzerConfig::kernel_structs].
+#[derive(Clone, Copy, Debug)]
+pub struct KernelStruct<'a> {
+    /// The type id
+    pub type_id: AnyType,
+    /// Size of the struct in bytes
+    pub size: usize,
+    /// Fields allowed to be read, with only [FieldType::Scalar] or [FieldType::Forbidden] ones
+    ///
+    /// [FieldType::Scalar]: super::struct_region::FieldType::Scalar
+    /// [FieldType::Forbidden]: super::struct_region::FieldType::Forbidden
+    pub fields: &'a [Field],
+}
+
+/// A read-only kernel object, e.g., a `struct task_struct`
+///
+/// Only the fields listed in its [KernelStruct] are readable. It exposes itself
+/// as [InnerRegion::Any] so that only helpers expecting the very type accept it.
+#[derive(Clone, Debug)]
+pub struct KernelRegion {
+    type_id: AnyType,
+    region: StructRegion,
+}
+
+impl KernelRegion {
+    /// Creates a region with the layout, or `None` if it has packet fields or invalid fields
+    ///
+    /// See [StructRegion::from_fields] for the validation.
+    pub fn new(layout: &KernelStruct) -> Option<Self> {
+        Some(Self {
+            type_id: layout.type_id,
+            region: StructRegion::from_fields(layout.size, layout.fields, None)?,
+        })
+    }
+}
+
+impl SafeClone for KernelRegion {
+    fn get_id(&self) -> Id {
+        self.region.get_id()
+    }
+
+    fn set_id(&mut self, id: Id) {
+        self.region.set_id(id)
+    }
+
+    fn safe_clone(&self) -> Pointee {
+        pointed(self.clone())
+    }
+
+    fn redirects(&mut self, _mapper: &dyn Fn(Id) -> Option<Pointee>) {}
+}
+
+impl MemoryRegion for KernelRegion {
+    fn get(&mut self, offset: &Scalar, size: u8) -> Result<TrackedValue, TrackError> {
+        self.region.get(offset, size)
+    }
+
+    fn set(&mut self, _: &Scalar, _: u8, _: &TrackedValue) -> Result<(), TrackError> {
+        Err(TrackError::PointeeNotWritable)
+    }
+
+    fn inner(&mut self) -> InnerRegion<'_> {
+        InnerRegion::Any((self.type_id, self))
+    }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        // Kernel objects are read-only
+        downcast_region::<KernelRegion>(other, self.type_id).is_some()
+    }
+}
+
+#[test]
+fn test_kernel_region() {
+    use super::struct_region::FieldType;
+    const TASK: KernelStruct = KernelStruct {
+        type_id: TASK_STRUCT_TYPE_ID,
+        size: 16,
+        fields: &[(8, 4, FieldType::Scalar)],
+    };
+    let mut region = KernelRegion::new(&TASK).unwrap();
+    assert!(region.get(&Scalar::constant64(8), 4).is_ok());
+    assert!(region.get(&Scalar::constant64(0), 4).is_err());
+    assert!(region.get(&Scalar::constant64(8), 8).is_err());
+    assert!(region
+        .set(&Scalar::constant64(8), 4, &Scalar::unknown().into())
+        .is_err());
+    assert!(matches!(
+        region.inner(),
+        InnerRegion::Any((TASK_STRUCT_TYPE_ID, _))
+    ));
+
+    let packet = KernelStruct {
+        fields: &[(0, 4, FieldType::PacketStart)],
+        ..TASK
+    };
+    assert!(KernelRegion::new(&packet).is_none());
+    let overflowing = KernelStruct {
+        fields: &[(12, 8, FieldType::Scalar)],
+        ..TASK
+    };
+    assert!(KernelRegion::new(&overflowing).is_none());
+}
@@ -1,24 +1,33 @@
 //! A simplistic map resource
 
-use alloc::vec::Vec;
-use ebpf_consts::maps::MapType;
+use alloc::{rc::Rc, vec::Vec};
+use ebpf_consts::{maps::MapType, programs::ProgType};
 
 use crate::{
-    branch::{checked_value::CheckedValue, id::Id, vm::BranchState},
+    branch::{checked_value::CheckedValue, vm::BranchState},
+    interpreter::vm::Vm,
     spec::proto::{
         ArgumentType, IllegalFunctionCall, ReturnType, StaticFunctionCall, VerifiableCall,
     },
-    track::{pointer::Pointer, scalar::Scalar, TrackError, TrackedValue},
+    track::{
+        id::Id,
+        pointer::{Pointer, PointerAttributes},
+        scalar::Scalar,
+        TrackError, TrackedValue,
+    },
 };
 
 use super::{
-    dyn_region::DynamicRegion, pointed, AnyType, MemoryRegion, Pointee, SafeClone, with_resource,
+    downcast_region, dyn_region::DynamicRegion, pointed, with_resource, AnyType, MemoryRegion,
+    Pointee, SafeClone,
 };
 
 /// The type id for maps
 pub const MAP_TYPE_ID: AnyType = -1i32;
 /// The type id for map values
 pub const MAP_VALUE_TYPE_ID: AnyType = -2i32;
+/// The type id for records reserved in ring buffers, see [ReturnType::AllocatedMemory]
+pub const RINGBUF_RECORD_TYPE_ID: AnyType = -3i32;
 
 /// A simple map used via helper functions
 #[derive(Clone, Debug)]
@@ -28,20 +37,33 @@ pub struct SimpleMap {
     max_size: usize,
     key_size: usize,
     value_size: usize,
+    readonly: bool,
+    initial_value: Option<Rc<[u8]>>,
+    program_type: Option<ProgType>,
     values: Vec<Pointee>,
 }
 
 impl SimpleMap {
     /// Creates a map
     ///
-    /// Sizes should be in bytes.
-    pub fn new(map_type: MapType, max_size: usize, key_size: usize, value_size: usize) -> Self {
+    /// Sizes should be in bytes. Values of a `readonly` map (e.g. `.rodata`)
+    /// are not writable from within programs.
+    pub fn new(
+        map_type: MapType,
+        max_size: usize,
+        key_size: usize,
+        value_size: usize,
+        readonly: bool,
+    ) -> Self {
         Self {
             id: 0,
             map_type,
             max_size,
             key_size,
             value_size,
+            readonly,
+            initial_value: None,
+            program_type: None,
             values: Vec::new(),
         }
     }
@@ -66,12 +88,51 @@ impl SimpleMap {
         self.max_size
     }
 
+    /// Sets the initial contents of map values
+    ///
+    /// Contents are only used for read-only maps, whose values never change,
+    /// so that constant reads of them yield constants.
+    pub fn set_initial_value(&mut self, value: Vec<u8>) {
+        self.initial_value = Some(value.into());
+    }
+
+    /// Sets the program type shared by all programs in a [MapType::ProgArray]
+    pub fn set_program_type(&mut self, program_type: ProgType) {
+        self.program_type = Some(program_type);
+    }
+
+    /// Returns the program type of a [MapType::ProgArray], `None` if unknown
+    pub fn program_type(&self) -> Option<ProgType> {
+        self.program_type
+    }
+
+    /// Returns whether map values are read-only to programs
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
     /// Returns a region of a map value (nullable, readable, writable, allowing arithmetic)
+    ///
+    /// Values of read-only maps are not writable. The VM gets invalidated if too many map values
+    /// are live, see [BranchState::add_map_value_resource].
     pub fn get_value(&mut self, vm: &mut BranchState) -> Pointer {
-        let value = pointed(DynamicRegion::new(self.value_size));
-        vm.add_external_resource(value.clone());
+        let mut region = DynamicRegion::new(self.value_size);
+        if let (true, Some(contents)) = (self.readonly, &self.initial_value) {
+            region.set_contents(contents.clone());
+        }
+        let value = pointed(region);
+        if !vm.add_map_value_resource(value.clone()) {
+            vm.invalidate("Too many live map values");
+        }
         self.values.push(value.clone());
-        Pointer::rwa(value)
+        if self.readonly {
+            Pointer::new(
+                PointerAttributes::READABLE | PointerAttributes::ARITHMETIC,
+                value,
+            )
+        } else {
+            Pointer::rwa(value)
+        }
     }
 
     /// Invalidates all value regions in this map
@@ -115,6 +176,15 @@ impl MemoryRegion for SimpleMap {
     fn inner(&mut self) -> super::InnerRegion {
         super::InnerRegion::Any((MAP_TYPE_ID, self))
     }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        // Only the looked-up values change, which should be the very same regions
+        let id = |value: &Pointee| value.try_borrow().map(|v| v.get_id()).ok();
+        downcast_region::<SimpleMap>(other, MAP_TYPE_ID).is_some_and(|other| {
+            self.values.len() == other.values.len()
+                && self.values.iter().map(id).eq(other.values.iter().map(id))
+        })
+    }
 }
 
 /// Retrieves map info from `r1`
@@ -125,15 +195,35 @@ fn for_map<T>(
     with_resource(MAP_TYPE_ID, 1, vm, action)
 }
 
+/// Checks that the buffer in the register is large enough for a map key or value
+///
+/// Only out-of-bound buffers are rejected here, with a clearer error than the generic
+/// [ArgumentType::FixedMemory] check, which still follows.
+fn check_buffer_size(vm: &BranchState, reg: u8, size: usize) -> Result<(), IllegalFunctionCall> {
+    if let Some(TrackedValue::Pointer(p)) = vm.ro_reg(reg).inner() {
+        if let Err(TrackError::PointerOutOfBound) = p.get_all(size) {
+            return Err(IllegalFunctionCall::MapBufferSizeMismatch);
+        }
+    }
+    Ok(())
+}
+
 /// bpf_map_update_elem
 pub struct MapUpdateCall;
 
 impl VerifiableCall<CheckedValue, BranchState> for MapUpdateCall {
     fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
-        let (key_size, value_size) = for_map(vm, |map, vm| {
+        let (key_size, value_size, readonly) = for_map(vm, |map, vm| {
             map.invalidate_values(vm);
-            (map.key_size, map.value_size)
+            (map.key_size, map.value_size, map.readonly)
         })?;
+        if readonly {
+            return Err(IllegalFunctionCall::IllegalPointer(
+                TrackError::PointeeNotWritable,
+            ));
+        }
+        check_buffer_size(vm, 2, key_size)?;
+        check_buffer_size(vm, 3, value_size)?;
         StaticFunctionCall::new(
             [
                 ArgumentType::Any,
@@ -154,6 +244,7 @@ pub struct MapLookupCall;
 impl VerifiableCall<CheckedValue, BranchState> for MapLookupCall {
     fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
         let (key_size, value) = for_map(vm, |map, vm| (map.key_size, map.get_value(vm)))?;
+        check_buffer_size(vm, 2, key_size)?;
         StaticFunctionCall::new(
             [
                 ArgumentType::Any,
@@ -174,10 +265,16 @@ pub struct MapDeleteCall;
 
 impl VerifiableCall<CheckedValue, BranchState> for MapDeleteCall {
     fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
-        let key_size = for_map(vm, |map, vm| {
+        let (key_size, readonly) = for_map(vm, |map, vm| {
             map.invalidate_values(vm);
-            map.key_size
+            (map.key_size, map.readonly)
         })?;
+        if readonly {
+            return Err(IllegalFunctionCall::IllegalPointer(
+                TrackError::PointeeNotWritable,
+            ));
+        }
+        check_buffer_size(vm, 2, key_size)?;
         StaticFunctionCall::new(
             [
                 ArgumentType::Any,
@@ -192,6 +289,232 @@ impl VerifiableCall<CheckedValue, BranchState> for MapDeleteCall {
     }
 }
 
+/// bpf_tail_call
+///
+/// A successful tail call never returns, so we only verify the failing path,
+/// where the program continues with an error code.
+///
+/// Tail calls only work between programs of the same type, so the program type of the array
+/// (if known) must match [BranchState::program_type].
+pub struct TailCall;
+
+impl VerifiableCall<CheckedValue, BranchState> for TailCall {
+    fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+        let (is_prog_array, program_type) =
+            with_resource(MAP_TYPE_ID, 2, vm, |map: &mut SimpleMap, _| {
+                (matches!(map.map_type, MapType::ProgArray), map.program_type)
+            })?;
+        if !is_prog_array {
+            return Err(IllegalFunctionCall::TypeMismatch);
+        }
+        if let (Some(expected), Some(actual)) = (program_type, vm.program_type()) {
+            if expected != actual {
+                return Err(IllegalFunctionCall::TailCallTypeMismatch);
+            }
+        }
+        StaticFunctionCall::new(
+            [
+                ArgumentType::Some,
+                ArgumentType::Any,
+                ArgumentType::Scalar,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        )
+        .call(vm)
+    }
+}
+
+/// bpf_ringbuf_reserve, only reserving records from [MapType::RingBuf] maps
+pub struct RingbufReserveCall;
+
+impl VerifiableCall<CheckedValue, BranchState> for RingbufReserveCall {
+    fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+        let is_ringbuf = with_resource(MAP_TYPE_ID, 1, vm, |map: &mut SimpleMap, _| {
+            matches!(map.map_type, MapType::RingBuf)
+        })?;
+        if !is_ringbuf {
+            return Err(IllegalFunctionCall::TypeMismatch);
+        }
+        StaticFunctionCall::new(
+            [
+                ArgumentType::Any,
+                ArgumentType::Constant(1..=u32::MAX as u64),
+                ArgumentType::Constant(0..=0),
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::AllocatedMemory((RINGBUF_RECORD_TYPE_ID, 2)),
+        )
+        .call(vm)
+    }
+}
+
+/// bpf_for_each_map_elem
+///
+/// The callback `(map, key, value, ctx) -> 0 (continue) or 1 (stop)` gets verified
+/// right after the call, with the key and the value of a synthesized element.
+pub struct ForEachMapElemCall;
+
+impl VerifiableCall<CheckedValue, BranchState> for ForEachMapElemCall {
+    fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+        let (key_size, mut value) = for_map(vm, |map, vm| (map.key_size, map.get_value(vm)))?;
+        let result = StaticFunctionCall::new(
+            [
+                ArgumentType::Any,
+                ArgumentType::Callback,
+                ArgumentType::Some,
+                ArgumentType::Constant(0..=0),
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        )
+        .call(vm)?;
+
+        let key = pointed(DynamicRegion::new(key_size));
+        vm.add_external_resource(key.clone());
+        value.set_non_null();
+        let args = [
+            vm.ro_reg(1).clone(),
+            Pointer::new(
+                PointerAttributes::NON_NULL | PointerAttributes::READABLE,
+                key,
+            )
+            .into(),
+            value.into(),
+            vm.ro_reg(3).clone(),
+            CheckedValue::default(),
+        ];
+        let callback = vm.ro_reg(2).clone();
+        if vm.schedule_callback(&callback, args) {
+            Ok(result)
+        } else {
+            Err(IllegalFunctionCall::TypeMismatch)
+        }
+    }
+}
+
+/// `sizeof(struct bpf_timer)`
+const TIMER_SIZE: usize = 16;
+
+/// Locates the `bpf_timer` that `r1` points to, returning the map value and the offset
+fn timer_of(vm: &mut BranchState) -> Result<(Pointer, u64), IllegalFunctionCall> {
+    if vm.is_invalid_resource(1) {
+        return Err(IllegalFunctionCall::IllegalResource);
+    }
+    vm.ro_reg(1)
+        .check_arg_type(&ArgumentType::FixedMemory(TIMER_SIZE), None)?;
+    if let Some(TrackedValue::Pointer(p)) = vm.ro_reg(1).inner() {
+        if !vm.is_map_value(p.get_pointing_to()) {
+            return Err(IllegalFunctionCall::TypeMismatch);
+        }
+        let offset = p
+            .offset()
+            .value64()
+            .ok_or(IllegalFunctionCall::NotAConstant)?;
+        if offset % 8 != 0 {
+            return Err(IllegalFunctionCall::OutofRange);
+        }
+        Ok((Pointer::rwa(p.get_pointing_region()), offset))
+    } else {
+        Err(IllegalFunctionCall::TypeMismatch)
+    }
+}
+
+/// Ensures that the `bpf_timer` in `r1` is initialized, returning the map value
+fn initialized_timer(vm: &mut BranchState) -> Result<Pointer, IllegalFunctionCall> {
+    let (value, offset) = timer_of(vm)?;
+    if vm.is_timer_initialized(value.get_pointing_to(), offset) {
+        Ok(value)
+    } else {
+        Err(IllegalFunctionCall::IllegalResource)
+    }
+}
+
+/// bpf_timer_init
+///
+/// The timer (in `r1`) must lie in a map value.
+/// Other timer helpers only accept timers initialized by this one.
+pub struct TimerInitCall;
+
+impl VerifiableCall<CheckedValue, BranchState> for TimerInitCall {
+    fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+        let (value, offset) = timer_of(vm)?;
+        with_resource(MAP_TYPE_ID, 2, vm, |_: &mut SimpleMap, _| ())?;
+        let result = StaticFunctionCall::new(
+            [
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Scalar,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        )
+        .call(vm)?;
+        vm.init_timer(value.get_pointing_to(), offset);
+        Ok(result)
+    }
+}
+
+/// bpf_timer_set_callback
+///
+/// The callback `(map, key, value) -> 0` gets verified right after the call,
+/// with the map value containing the timer. The map and the key are not available.
+pub struct TimerSetCallbackCall;
+
+impl VerifiableCall<CheckedValue, BranchState> for TimerSetCallbackCall {
+    fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+        let mut value = initialized_timer(vm)?;
+        let result = StaticFunctionCall::new(
+            [
+                ArgumentType::Any,
+                ArgumentType::Callback,
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        )
+        .call(vm)?;
+        value.set_non_null();
+        let args = [
+            CheckedValue::default(),
+            CheckedValue::default(),
+            value.into(),
+            CheckedValue::default(),
+            CheckedValue::default(),
+        ];
+        let callback = vm.ro_reg(2).clone();
+        if vm.schedule_callback(&callback, args) {
+            Ok(result)
+        } else {
+            Err(IllegalFunctionCall::TypeMismatch)
+        }
+    }
+}
+
+/// bpf_timer_start
+pub struct TimerStartCall;
+
+impl VerifiableCall<CheckedValue, BranchState> for TimerStartCall {
+    fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+        initialized_timer(vm)?;
+        StaticFunctionCall::new(
+            [
+                ArgumentType::Any,
+                ArgumentType::Scalar,
+                ArgumentType::Scalar,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        )
+        .call(vm)
+    }
+}
+
 #[cfg(test)]
 fn get_map_info(vm: &mut BranchState) -> Result<(usize, usize), IllegalFunctionCall> {
     for_map(vm, |map, _| (map.key_size, map.value_size))
@@ -200,8 +523,7 @@ fn get_map_info(vm: &mut BranchState) -> Result<(usize, usize), IllegalFunctionC
 #[test]
 fn test_map_helpers() {
     use alloc::vec::Vec;
-    use crate::interpreter::vm::Vm;
-    let map = pointed(SimpleMap::new(MapType::Unspec, 1, 8, 8));
+    let map = pointed(SimpleMap::new(MapType::Unspec, 1, 8, 8, false));
     let mut vm = BranchState::new(&[], Vec::new());
     vm.add_external_resource(map.clone());
 
@@ -286,3 +608,40 @@ fn test_map_helpers() {
     assert!(unsafe { vm.reg(2).set_at(0, 8, &Pointer::rwa(map).into()) });
     assert!(delete.call(&mut vm).is_err());
 }
+
+#[test]
+fn test_map_buffer_sizes() {
+    use crate::interpreter::value::Dereference;
+    use core::ops::SubAssign;
+
+    let map = pointed(SimpleMap::new(MapType::Hash, 1, 8, 16, false));
+    let mut vm = BranchState::new(&[], Vec::new());
+    vm.add_external_resource(map.clone());
+    *vm.reg(1) = Pointer::nrwa(map).into();
+
+    // A 4-byte key for an 8-byte-key map
+    *vm.reg(2) = vm.ro_reg(10).clone();
+    vm.reg(2).sub_assign(&Scalar::constant64(4).into());
+    assert!(unsafe { vm.reg(2).set_at(0, 4, &Scalar::constant64(0).into()) });
+    assert!(matches!(
+        MapLookupCall {}.call(&mut vm),
+        Err(IllegalFunctionCall::MapBufferSizeMismatch)
+    ));
+    assert!(matches!(
+        MapDeleteCall {}.call(&mut vm),
+        Err(IllegalFunctionCall::MapBufferSizeMismatch)
+    ));
+
+    // An 8-byte key with an 8-byte value for a 16-byte-value map
+    vm.reg(2).sub_assign(&Scalar::constant64(12).into());
+    assert!(unsafe { vm.reg(2).set_at(0, 8, &Scalar::constant64(0).into()) });
+    assert!(MapLookupCall {}.call(&mut vm).is_ok());
+    *vm.reg(3) = vm.ro_reg(10).clone();
+    vm.reg(3).sub_assign(&Scalar::constant64(8).into());
+    assert!(unsafe { vm.reg(3).set_at(0, 8, &Scalar::constant64(0).into()) });
+    *vm.reg(4) = Scalar::constant64(0).into();
+    assert!(matches!(
+        MapUpdateCall {}.call(&mut vm),
+        Err(IllegalFunctionCall::MapBufferSizeMismatch)
+    ));
+}
@@ -4,22 +4,22 @@ use core::{any::Any, cell::RefCell, fmt::Debug};
 
 use alloc::rc::Rc;
 
-use crate::{
-    branch::{id::Id, vm::BranchState},
-    interpreter::vm::Vm,
-    spec::proto::IllegalFunctionCall,
-};
+use crate::{branch::vm::BranchState, interpreter::vm::Vm, spec::proto::IllegalFunctionCall};
 
-use self::{dyn_region::DynamicRegion, stack_region::StackRegion};
+use self::{dyn_region::DynamicRegion, packet_region::PacketRegion, stack_region::StackRegion};
 
-use super::{scalar::Scalar, TrackError, TrackedValue};
+use super::{id::Id, scalar::Scalar, TrackError, TrackedValue};
 
 pub mod dyn_region;
+pub mod dynptr;
 pub mod empty_region;
+pub mod kernel_region;
 pub mod map_resource;
+pub mod packet_region;
 pub mod simple_resource;
 pub mod stack_region;
 pub mod struct_region;
+pub mod typed_region;
 
 /// Type id for user-defined types
 ///
@@ -31,6 +31,8 @@ pub type AnyType = i32;
 pub enum InnerRegion<'a> {
     /// A dynamic range
     Dyn(&'a mut DynamicRegion),
+    /// A network packet
+    Packet(&'a mut PacketRegion),
     /// A stack
     Stack(&'a mut StackRegion),
     /// Anything, allowing for user-defined types
@@ -89,6 +91,17 @@ pub trait MemoryRegion: SafeClone + Debug {
     fn inner(&mut self) -> InnerRegion {
         InnerRegion::None
     }
+    /// Checks whether the region is subsumed by `other`, one of the same id in another branch
+    ///
+    /// Any access permitted in `other` should be permitted here, yielding subsumed values.
+    /// It defaults to `false`, which is always safe but prevents pruning the branch.
+    fn is_subset_of(&self, _other: &mut dyn MemoryRegion) -> bool {
+        false
+    }
+    /// Whether the region holds packet data, which need not be accessed with alignment
+    fn is_packet(&self) -> bool {
+        false
+    }
 }
 
 /// Reference to a memory region
@@ -131,6 +144,14 @@ fn is_access_in_range(
     }
 }
 
+/// Retrieves the concrete region behind `other` if it is an [InnerRegion::Any] of type `t`
+fn downcast_region<T: 'static>(other: &mut dyn MemoryRegion, t: AnyType) -> Option<&mut T> {
+    match other.inner() {
+        InnerRegion::Any((type_id, region)) if type_id == t => region.downcast_mut::<T>(),
+        _ => None,
+    }
+}
+
 /// Wraps something into [Pointee]
 pub fn pointed<T: MemoryRegion + 'static>(region: T) -> Pointee {
     Rc::new(RefCell::new(region)) as Pointee
@@ -0,0 +1,142 @@
+//! See [PacketRegion].
+
+use num_traits::ToPrimitive;
+
+use crate::track::{comparable::Comparable, id::Id, scalar::Scalar, TrackError, TrackedValue};
+
+use super::{is_access_in_range, pointed, InnerRegion, MemoryRegion, Pointee, SafeClone};
+
+/// A network packet (`skb->data` or `xdp_md->data`), whose end (`data_end`) is only known at runtime
+///
+/// The region tracks the packet length (that is, `data_end - data`) as a [Scalar].
+/// Only bytes below the lower bound of the length are accessible,
+/// which a program raises by comparing pointers against `data_end`
+/// (see [PacketRegion::check_access]).
+#[derive(Clone, Debug)]
+pub struct PacketRegion {
+    id: Id,
+    /// The packet length
+    len: Scalar,
+}
+
+impl PacketRegion {
+    /// Creates a packet region with its length within the range of `len`
+    pub fn new(len: Scalar) -> Self {
+        Self { id: 0, len }
+    }
+
+    /// The packet length, that is, `data_end - data`
+    pub fn len(&self) -> &Scalar {
+        &self.len
+    }
+
+    /// Number of bytes that are known to be within the packet
+    pub fn readable(&self) -> usize {
+        self.len.unsigned_range().start().to_usize().unwrap_or(0)
+    }
+
+    /// Records that `data + offset <= data_end` holds
+    ///
+    /// Offsets that might be negative are ignored.
+    pub fn check_access(&mut self, offset: &Scalar) {
+        if !offset.is_signed_in_sync().is_some_and(|(min, _)| min >= 0) {
+            return;
+        }
+        // When it is `Perhaps`, `self.len` gets narrowed in place so that `offset <= len`
+        let _ = offset.clone().le(&mut self.len, 64);
+    }
+}
+
+impl Default for PacketRegion {
+    /// A packet of at most 64 KiB
+    fn default() -> Self {
+        Self::new(Scalar::from_tnum(0xFFFF, 0))
+    }
+}
+
+impl SafeClone for PacketRegion {
+    fn get_id(&self) -> Id {
+        self.id
+    }
+
+    fn set_id(&mut self, id: Id) {
+        self.id = id
+    }
+
+    fn safe_clone(&self) -> Pointee {
+        pointed(self.clone())
+    }
+
+    fn redirects(&mut self, _mapper: &dyn Fn(Id) -> Option<Pointee>) {}
+}
+
+impl MemoryRegion for PacketRegion {
+    fn get(&mut self, offset: &Scalar, size: u8) -> Result<TrackedValue, TrackError> {
+        is_access_in_range(offset, size, self.readable())?;
+        Ok(Scalar::unknown().into())
+    }
+
+    fn set(&mut self, offset: &Scalar, size: u8, value: &TrackedValue) -> Result<(), TrackError> {
+        match value {
+            TrackedValue::Pointer(_) | TrackedValue::FuncPointer(_) => {
+                Err(TrackError::PointeeNotWritable)
+            }
+            TrackedValue::Scalar(_) => {
+                is_access_in_range(offset, size, self.readable())?;
+                Ok(())
+            }
+        }
+    }
+
+    fn inner(&mut self) -> InnerRegion<'_> {
+        InnerRegion::Packet(self)
+    }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        match other.inner() {
+            InnerRegion::Packet(other) => self.len.is_subset_of(&other.len),
+            _ => false,
+        }
+    }
+
+    fn is_packet(&self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_packet_region() {
+    let mut region = PacketRegion::default();
+    assert_eq!(region.readable(), 0);
+    assert!(region.get(&Scalar::constant64(0), 1).is_err());
+
+    // if data + 14 <= data_end
+    region.check_access(&Scalar::constant64(14));
+    assert_eq!(region.readable(), 14);
+    assert!(region.get(&Scalar::constant64(6), 8).is_ok());
+    assert!(region.get(&Scalar::constant64(8), 8).is_err());
+    assert!(region
+        .set(&Scalar::constant64(12), 2, &Scalar::unknown().into())
+        .is_ok());
+
+    // Smaller or possibly negative offsets never shrink the window
+    region.check_access(&Scalar::constant64(4));
+    region.check_access(&Scalar::constant64(-100i64 as u64));
+    assert_eq!(region.readable(), 14);
+
+    // Beyond the maximum packet length
+    region.check_access(&Scalar::constant64(0x10000));
+    assert_eq!(region.readable(), 14);
+}
+
+#[test]
+fn test_packet_region_subset() {
+    use super::dyn_region::DynamicRegion;
+    let mut explored = PacketRegion::default();
+    let mut region = PacketRegion::default();
+    region.check_access(&Scalar::constant64(14));
+    // A longer packet permits more accesses
+    assert!(region.is_subset_of(&mut explored));
+    assert!(!explored.is_subset_of(&mut region));
+    assert!(!region.is_subset_of(&mut DynamicRegion::new(14)));
+}
@@ -1,8 +1,8 @@
 //! A simple resource pointee
 
-use crate::{branch::id::Id, track::{scalar::Scalar, TrackedValue, TrackError}};
+use crate::track::{id::Id, scalar::Scalar, TrackedValue, TrackError};
 
-use super::{AnyType, MemoryRegion, SafeClone, Pointee, InnerRegion, pointed};
+use super::{AnyType, MemoryRegion, SafeClone, Pointee, InnerRegion, pointed, downcast_region};
 
 /// A resource with a type
 #[derive(Clone, Debug)]
@@ -30,6 +30,10 @@ impl MemoryRegion for SimpleResource {
     fn inner(&mut self) -> InnerRegion {
         InnerRegion::Any((self.type_id, self))
     }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        downcast_region::<SimpleResource>(other, self.type_id).is_some()
+    }
 }
 
 impl SafeClone for SimpleResource {
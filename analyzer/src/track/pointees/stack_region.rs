@@ -3,10 +3,7 @@
 use alloc::vec::Vec;
 use ebpf_consts::STACK_SIZE;
 
-use crate::{
-    branch::id::Id,
-    track::{pointer::Pointer, scalar::Scalar, TrackError, TrackedValue},
-};
+use crate::track::{id::Id, scalar::Scalar, TrackError, TrackedValue};
 
 use super::{is_access_in_range, pointed, InnerRegion, MemoryRegion, Pointee, SafeClone};
 
@@ -27,10 +24,10 @@ enum StackSlot {
 /// - `0`: Not readable, either uninitialized or pointer residue
 /// - `1`: Initailized, part of a scalar or part of a pointer
 ///
-/// Although it tries to keep values precise,
-/// given that we are unaware of the machine endianness (at least not yet),
-/// the precision is limited:
+/// Although it tries to keep values precise, the precision is limited:
 /// - Storage slots are 64-bit, keeping two 32-bit ones or (mutual exclusive) a precise 64-bit value.
+/// - Aligned narrower reads from a 64-bit scalar extract the corresponding bits,
+///   assuming the host endianness.
 /// - Any other unaligned read gets unknown values.
 /// - Any other unaligned write sets the overlapping values to unknown.
 ///
@@ -64,6 +61,8 @@ pub struct StackRegion {
     ///
     /// The highest byte on the stack is mapped to the least significant bits in the first byte.
     map: [u8; BIT_MAP_BYTES],
+    /// The deepest written byte, counting from the frame pointer
+    depth: usize,
 }
 
 impl StackRegion {
@@ -73,9 +72,17 @@ impl StackRegion {
             id: 0,
             map: [0; BIT_MAP_BYTES],
             values: Vec::new(),
+            depth: 0,
         }
     }
 
+    /// Returns the deepest written byte, counting from the frame pointer
+    ///
+    /// For example, after writing to `r10 - 8`, the depth is 8.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
     /// Maps a on-stack byte offset into bit offset
     fn bitmap_offset(offset: usize) -> (usize, usize) {
         if offset == 0 {
@@ -141,12 +148,68 @@ impl StackRegion {
         STACK_SIZE / 8 - 1 - offset / 8
     }
 
-    /// Updates pointers to the same region as non null if the pointer is non null
-    pub fn update_pointers(&mut self, pointer: &mut Pointer) {
-        let id = pointer.get_pointing_to();
+    /// Extracts the `size` bytes at `offset` of a stored 64-bit scalar
+    fn extract(value: &TrackedValue, offset: usize, size: u8) -> TrackedValue {
+        let TrackedValue::Scalar(value) = value else {
+            return TrackedValue::Scalar(Scalar::unknown());
+        };
+        let byte = offset % 8;
+        let shift = if cfg!(target_endian = "big") {
+            8 - byte - size as usize
+        } else {
+            byte
+        };
+        let mut value = value.clone();
+        value.shr::<64>(shift as u64 * 8);
+        value &= &Scalar::constant64(u64::MAX >> (64 - size as u64 * 8));
+        TrackedValue::Scalar(value)
+    }
+
+    /// Checks whether the stack is subsumed by `other`
+    ///
+    /// Bytes readable in `other` must be readable here, with values
+    /// (of the same slot layout) subsumed by those in `other`.
+    pub fn is_subset_of(&self, other: &StackRegion) -> bool {
+        if self.depth > other.depth
+            || self.map.iter().zip(&other.map).any(|(a, b)| b & !a != 0)
+        {
+            return false;
+        }
+        let unknown = StackSlot::Value64(TrackedValue::Scalar(Scalar::unknown()));
+        for (index, slot) in other.values.iter().enumerate() {
+            let offset = STACK_SIZE - (index + 1) * 8;
+            let is_pointer = matches!(
+                slot,
+                StackSlot::Value64(TrackedValue::Pointer(_) | TrackedValue::FuncPointer(_))
+            );
+            if !is_pointer && other.map[offset / 8] == 0 {
+                // Never read in `other`
+                continue;
+            }
+            let subsumed = match (self.values.get(index).unwrap_or(&unknown), slot) {
+                (StackSlot::Value64(v), StackSlot::Value64(w)) => v.is_subset_of(w),
+                (StackSlot::Scalar32((a, b)), StackSlot::Scalar32((c, d))) => {
+                    a.is_subset_of(c) && b.is_subset_of(d)
+                }
+                _ => false,
+            };
+            if !subsumed {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Updates pointers to the region `id` as non null, after a pointer to it is checked
+    pub fn update_pointers(&mut self, id: Id) {
         for slot in &mut self.values {
             if let StackSlot::Value64(TrackedValue::Pointer(p)) = slot {
-                if p.is_pointing_to(id) {
+                // Pointers into this very stack (borrowed by the caller) are never null anyway
+                let region = p.get_pointing_region();
+                if region
+                    .try_borrow()
+                    .is_ok_and(|region| region.get_id() == id)
+                {
                     p.set_non_null();
                 }
             }
@@ -176,7 +239,7 @@ impl MemoryRegion for StackRegion {
                 } else if size == 4 && start % 4 == 0 {
                     let index = Self::o2i(start);
                     match &self.values[index] {
-                        StackSlot::Value64(_) => Ok(TrackedValue::Scalar(Scalar::unknown())),
+                        StackSlot::Value64(v) => Ok(Self::extract(v, start, size)),
                         StackSlot::Scalar32((lower, higher)) => {
                             let mut value = if start % 8 == 0 {
                                 lower.clone()
@@ -187,6 +250,12 @@ impl MemoryRegion for StackRegion {
                             Ok(TrackedValue::Scalar(value))
                         }
                     }
+                } else if (size == 1 || size == 2) && start % size as usize == 0 {
+                    let index = Self::o2i(start);
+                    match &self.values[index] {
+                        StackSlot::Value64(v) => Ok(Self::extract(v, start, size)),
+                        StackSlot::Scalar32(_) => Ok(TrackedValue::Scalar(Scalar::unknown())),
+                    }
                 } else {
                     Ok(TrackedValue::Scalar(Scalar::unknown()))
                 }
@@ -198,8 +267,11 @@ impl MemoryRegion for StackRegion {
             if end - start == 8 && start % 8 == 0 {
                 let index = Self::o2i(start);
                 if index < self.values.len() {
-                    if let StackSlot::Value64(TrackedValue::Pointer(p)) = &self.values[index] {
-                        return Ok(TrackedValue::Pointer(p.clone()));
+                    if let StackSlot::Value64(
+                        value @ (TrackedValue::Pointer(_) | TrackedValue::FuncPointer(_)),
+                    ) = &self.values[index]
+                    {
+                        return Ok(value.clone());
                     }
                 }
             }
@@ -211,13 +283,13 @@ impl MemoryRegion for StackRegion {
         let (start, end) = is_access_in_range(offset, size, STACK_SIZE)?;
         if end - start == size as usize {
             // Constant offset
+            self.depth = self.depth.max(STACK_SIZE - start);
             match value {
-                TrackedValue::Pointer(pointer) => {
+                TrackedValue::Pointer(_) | TrackedValue::FuncPointer(_) => {
                     if size == 8 && start % 8 == 0 {
                         let index = Self::o2i(start);
                         self.reserve(index);
-                        self.values[index] =
-                            StackSlot::Value64(TrackedValue::Pointer(pointer.clone()));
+                        self.values[index] = StackSlot::Value64(value.clone());
                         self.mark_as_type(start, end, false);
                         Ok(())
                     } else {
@@ -271,6 +343,13 @@ impl MemoryRegion for StackRegion {
     fn inner(&mut self) -> InnerRegion {
         InnerRegion::Stack(self)
     }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        match other.inner() {
+            InnerRegion::Stack(other) => StackRegion::is_subset_of(self, other),
+            _ => false,
+        }
+    }
 }
 
 impl SafeClone for StackRegion {
@@ -298,7 +377,10 @@ impl SafeClone for StackRegion {
 }
 
 #[cfg(test)]
-use super::{super::pointer::PointerAttributes, empty_region::EmptyRegion};
+use super::{
+    super::pointer::{Pointer, PointerAttributes},
+    empty_region::EmptyRegion,
+};
 
 #[cfg(test)]
 use rand::{thread_rng, Rng};
@@ -332,6 +414,43 @@ pub fn test_clone() {
     };
 }
 
+#[test]
+pub fn test_sub_width_read() {
+    let mut stack = StackRegion::new();
+    let value = 0x1122_3344_5566_7788u64;
+    assert!(stack
+        .set(
+            &Scalar::constant64(496),
+            8,
+            &Scalar::constant64(value).into()
+        )
+        .is_ok());
+    let bytes = value.to_ne_bytes();
+    for (size, offset) in [(1, 496), (1, 503), (2, 496), (2, 502), (4, 496), (4, 500)] {
+        let start = offset - 496;
+        let mut expected = [0u8; 8];
+        expected[..size].copy_from_slice(&bytes[start..start + size]);
+        let expected = if cfg!(target_endian = "big") {
+            u64::from_be_bytes(expected) >> (64 - size * 8)
+        } else {
+            u64::from_le_bytes(expected)
+        };
+        match stack.get(&Scalar::constant64(offset as u64), size as u8) {
+            Ok(TrackedValue::Scalar(s)) => assert_eq!(s.value64(), Some(expected)),
+            _ => panic!(),
+        }
+    }
+
+    // Bounded by the width of the read
+    assert!(stack
+        .set(&Scalar::constant64(488), 8, &Scalar::unknown().into())
+        .is_ok());
+    match stack.get(&Scalar::constant64(490), 2) {
+        Ok(TrackedValue::Scalar(s)) => assert_eq!(s.unsigned_range(), 0..=0xFFFF),
+        _ => panic!(),
+    }
+}
+
 #[test]
 pub fn test_stack_access() {
     let mut stack = StackRegion::new();
@@ -340,6 +459,7 @@ pub fn test_stack_access() {
             assert!(stack.get(&Scalar::constant64(offset), size).is_err());
         }
     }
+    assert_eq!(stack.depth(), 0);
     assert!(stack
         .set(
             &Scalar::constant64(504),
@@ -361,6 +481,7 @@ pub fn test_stack_access() {
         Ok(TrackedValue::Scalar(s)) => assert!(s.is_constant::<64>().unwrap_or(false)),
         _ => panic!(),
     }
+    assert_eq!(stack.depth(), 16);
     assert!(stack.get(&Scalar::constant64(500), 8).is_err());
     assert!(stack.get(&Scalar::constant64(508), 4).is_err());
     assert!(stack
@@ -374,6 +495,27 @@ pub fn test_stack_access() {
     assert!(stack.get(&Scalar::constant64(504), 4).is_ok());
 }
 
+#[test]
+pub fn test_byte_initialization() {
+    let init = |skipped: Option<u64>| {
+        let mut stack = StackRegion::new();
+        // Partially overwritten pointers are no longer pointers
+        let pointer = Pointer::new(PointerAttributes::empty(), EmptyRegion::instance());
+        assert!(stack
+            .set(&Scalar::constant64(504), 8, &TrackedValue::Pointer(pointer))
+            .is_ok());
+        for offset in (496..512).filter(|&offset| Some(offset) != skipped) {
+            let value = TrackedValue::Scalar(Scalar::constant64(offset));
+            assert!(stack.set(&Scalar::constant64(offset), 1, &value).is_ok());
+        }
+        stack
+    };
+    assert!(init(None).get_all(496, 16).is_ok());
+    for skipped in [496, 503, 504, 511] {
+        assert!(init(Some(skipped)).get_all(496, 16).is_err());
+    }
+}
+
 #[test]
 pub fn test_stack_random_access() {
     const UNINIT: u8 = 0;
@@ -406,7 +548,7 @@ pub fn test_stack_random_access() {
             }
             if ok {
                 let type_info = match &value {
-                    TrackedValue::Pointer(_) => POINTER,
+                    TrackedValue::Pointer(_) | TrackedValue::FuncPointer(_) => POINTER,
                     TrackedValue::Scalar(_) => SCALAR,
                 };
                 for v in map.iter_mut().skip(offset * 8 + off as usize).take(8) {
@@ -448,3 +590,38 @@ pub fn test_stack_random_access() {
         }
     }
 }
+
+#[test]
+fn test_stack_subset() {
+    let offset = Scalar::constant64(504);
+    let mut explored = StackRegion::new();
+    let mut stack = StackRegion::new();
+    assert!(stack.is_subset_of(&explored));
+
+    let mut range = Scalar::unknown();
+    range &= &Scalar::constant64(0xF);
+    explored.set(&offset, 8, &range.into()).unwrap();
+    assert!(!stack.is_subset_of(&explored));
+    stack.set(&offset, 8, &Scalar::constant64(1).into()).unwrap();
+    assert!(stack.is_subset_of(&explored));
+    assert!(!explored.is_subset_of(&stack));
+
+    stack.set(&offset, 8, &Scalar::constant64(16).into()).unwrap();
+    assert!(!stack.is_subset_of(&explored));
+
+    // Pointers should point to the same region
+    let pointer = Pointer::new(PointerAttributes::NON_NULL, EmptyRegion::instance());
+    stack.set(&offset, 8, &pointer.clone().into()).unwrap();
+    explored.set(&offset, 8, &pointer.into()).unwrap();
+    assert!(stack.is_subset_of(&explored));
+    let other = EmptyRegion::instance();
+    other.borrow_mut().set_id(1);
+    let pointer = Pointer::new(PointerAttributes::NON_NULL, other);
+    stack.set(&offset, 8, &pointer.into()).unwrap();
+    assert!(!stack.is_subset_of(&explored));
+
+    // Deeper stacks are not subsumed
+    let mut deeper = explored.clone();
+    deeper.set(&Scalar::constant64(0), 8, &Scalar::constant64(0).into()).unwrap();
+    assert!(!deeper.is_subset_of(&explored));
+}
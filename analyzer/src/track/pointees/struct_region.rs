@@ -1,13 +1,10 @@
 //! See [StructRegion].
 
-use alloc::vec::Vec;
+use alloc::{rc::Rc, vec::Vec};
 
-use crate::{
-    branch::id::Id,
-    track::{pointer::Pointer, scalar::Scalar, TrackError, TrackedValue},
-};
+use crate::track::{id::Id, pointer::Pointer, scalar::Scalar, TrackError, TrackedValue};
 
-use super::{is_access_in_range, MemoryRegion, Pointee, SafeClone, pointed};
+use super::{is_access_in_range, InnerRegion, MemoryRegion, Pointee, SafeClone, pointed};
 
 /// A memory region of a struct instance
 ///
@@ -26,16 +23,36 @@ use super::{is_access_in_range, MemoryRegion, Pointee, SafeClone, pointed};
 /// - `0` means that it is part of a scalar value
 /// - `-1` means that it is read-only
 /// - `-2` means that it is write-only
+/// - `-3` means that it is neither readable nor writable
 ///
-/// Currently, it represents a struct known at compile time,
-/// and requires a `'static` slice representing its structure.
+/// The byte-map is either given as a `'static` slice representing a struct known
+/// at compile time, or built from a field table (see [StructRegion::from_fields]).
 #[derive(Clone, Debug)]
 pub struct StructRegion {
     id: Id,
     pointers: Vec<Pointer>,
-    map: &'static [i8],
+    map: Rc<[i8]>,
 }
 
+/// What a field in a [StructRegion] yields, see [StructRegion::from_fields]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldType {
+    /// A read-only scalar
+    Scalar,
+    /// A pointer to the start of the packet (e.g., `skb->data`)
+    PacketStart,
+    /// A pointer to the end of the packet (e.g., `skb->data_end`)
+    PacketEnd,
+    /// Neither readable nor writable
+    Forbidden,
+}
+
+/// A field of `size` bytes at `offset`: `(offset, size, type)`
+pub type Field = (usize, u8, FieldType);
+
+/// Byte-map value for bytes neither readable nor writable
+const FORBIDDEN: i8 = -3;
+
 impl StructRegion {
     /// Creates a new struct region from the pointers and the map
     ///
@@ -45,10 +62,50 @@ impl StructRegion {
         StructRegion {
             id: 0,
             pointers,
-            map: region_map,
+            map: region_map.into(),
         }
     }
 
+    /// Creates a struct region of `size` bytes from a field table
+    ///
+    /// Bytes not covered by any field are [FieldType::Forbidden].
+    /// The packet fields point to `packet`. It returns `None` if the struct is too large,
+    /// a field lies out of the struct, there are too many packet fields,
+    /// or there are packet fields without a `packet`.
+    pub fn from_fields(
+        size: usize,
+        fields: &[Field],
+        packet: Option<Pointee>,
+    ) -> Option<StructRegion> {
+        if size > i32::MAX as usize {
+            return None;
+        }
+        let mut map = alloc::vec![FORBIDDEN; size];
+        let mut pointers = Vec::new();
+        for (offset, size, field) in fields {
+            let value = match field {
+                FieldType::Scalar => -1,
+                FieldType::Forbidden => FORBIDDEN,
+                FieldType::PacketStart | FieldType::PacketEnd => {
+                    let packet = packet.clone()?;
+                    pointers.push(if *field == FieldType::PacketStart {
+                        Pointer::nrwa(packet)
+                    } else {
+                        Pointer::end(packet)
+                    });
+                    i8::try_from(pointers.len()).ok()?
+                }
+            };
+            let end = offset.checked_add(*size as usize)?;
+            map.get_mut(*offset..end)?.fill(value);
+        }
+        Some(StructRegion {
+            id: 0,
+            pointers,
+            map: map.into(),
+        })
+    }
+
     fn is_readable(i: i8) -> bool {
         i == 0 || i == -1
     }
@@ -97,6 +154,11 @@ impl MemoryRegion for StructRegion {
         }
         Ok(())
     }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        // Fields never change after creation, with pointers compared along with their regions
+        matches!(other.inner(), InnerRegion::None)
+    }
 }
 
 impl SafeClone for StructRegion {
@@ -242,3 +304,57 @@ pub fn test_pointer() {
         _ => panic!(),
     };
 }
+
+#[test]
+pub fn test_fields() {
+    use super::packet_region::PacketRegion;
+    let packet = pointed(PacketRegion::default());
+    packet.borrow_mut().set_id(1);
+    let mut region = StructRegion::from_fields(
+        16,
+        &[
+            (0, 4, FieldType::PacketStart),
+            (4, 4, FieldType::PacketEnd),
+            (8, 4, FieldType::Scalar),
+            (12, 2, FieldType::Forbidden),
+        ],
+        Some(packet.clone()),
+    )
+    .unwrap();
+    let value = TrackedValue::Scalar(Scalar::constant64(0));
+
+    // Permitted scalar fields are read-only
+    assert!(matches!(
+        region.get(&Scalar::constant64(8), 4),
+        Ok(TrackedValue::Scalar(_))
+    ));
+    assert!(region.get(&Scalar::constant64(10), 2).is_ok());
+    assert!(region.set(&Scalar::constant64(8), 4, &value).is_err());
+
+    // Forbidden or uncovered bytes
+    assert_all_err(&mut region, 12, 4);
+    assert_all_err(&mut region, 14, 2);
+    assert!(region.get(&Scalar::constant64(8), 8).is_err());
+
+    match region.get(&Scalar::constant64(0), 4) {
+        Ok(TrackedValue::Pointer(pointer)) => {
+            assert!(pointer.is_pointing_to(1) && !pointer.is_end_pointer())
+        }
+        _ => panic!(),
+    }
+    match region.get(&Scalar::constant64(4), 4) {
+        Ok(TrackedValue::Pointer(pointer)) => assert!(pointer.is_end_pointer()),
+        _ => panic!(),
+    }
+    assert!(region.get(&Scalar::constant64(0), 8).is_err());
+
+    // Invalid field tables
+    let scalar = |offset, size| (offset, size, FieldType::Scalar);
+    assert!(StructRegion::from_fields(16, &[scalar(12, 8)], None).is_none());
+    assert!(StructRegion::from_fields(16, &[scalar(usize::MAX, 8)], None).is_none());
+    assert!(StructRegion::from_fields(16, &[(0, 4, FieldType::PacketStart)], None).is_none());
+    assert!(StructRegion::from_fields(usize::MAX, &[], None).is_none());
+    let packets = [(0, 0, FieldType::PacketEnd); 128];
+    assert!(StructRegion::from_fields(16, &packets[..127], Some(packet.clone())).is_some());
+    assert!(StructRegion::from_fields(16, &packets, Some(packet)).is_none());
+}
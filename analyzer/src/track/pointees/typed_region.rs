@@ -0,0 +1,75 @@
+//! See [TypedRegion].
+
+use crate::track::{id::Id, scalar::Scalar, TrackError, TrackedValue};
+
+use super::{
+    downcast_region, dyn_region::DynamicRegion, pointed, AnyType, InnerRegion, MemoryRegion,
+    Pointee, SafeClone,
+};
+
+/// A fixed-size memory region tagged with a type id, e.g., a reserved ring buffer record
+///
+/// It is read and written like a [DynamicRegion], while exposing itself as [InnerRegion::Any]
+/// so that only helpers expecting the very type accept it
+/// (see [crate::spec::proto::ArgumentType::ResourceType]).
+#[derive(Clone, Debug)]
+pub struct TypedRegion {
+    type_id: AnyType,
+    region: DynamicRegion,
+}
+
+impl TypedRegion {
+    /// Creates a region of `size` bytes
+    pub fn new(type_id: AnyType, size: usize) -> Self {
+        Self {
+            type_id,
+            region: DynamicRegion::new(size),
+        }
+    }
+}
+
+impl SafeClone for TypedRegion {
+    fn get_id(&self) -> Id {
+        self.region.get_id()
+    }
+
+    fn set_id(&mut self, id: Id) {
+        self.region.set_id(id)
+    }
+
+    fn safe_clone(&self) -> Pointee {
+        pointed(self.clone())
+    }
+
+    fn redirects(&mut self, _mapper: &dyn Fn(Id) -> Option<Pointee>) {}
+}
+
+impl MemoryRegion for TypedRegion {
+    fn get(&mut self, offset: &Scalar, size: u8) -> Result<TrackedValue, TrackError> {
+        self.region.get(offset, size)
+    }
+
+    fn set(&mut self, offset: &Scalar, size: u8, value: &TrackedValue) -> Result<(), TrackError> {
+        self.region.set(offset, size, value)
+    }
+
+    fn inner(&mut self) -> InnerRegion<'_> {
+        InnerRegion::Any((self.type_id, self))
+    }
+
+    fn is_subset_of(&self, other: &mut dyn MemoryRegion) -> bool {
+        downcast_region::<TypedRegion>(other, self.type_id)
+            .is_some_and(|other| self.region.is_subset_of(&other.region))
+    }
+}
+
+#[test]
+fn test_typed_region() {
+    let mut region = TypedRegion::new(1, 8);
+    assert!(region.get(&Scalar::constant64(0), 8).is_ok());
+    assert!(region.get(&Scalar::constant64(4), 8).is_err());
+    assert!(region
+        .set(&Scalar::constant64(4), 4, &Scalar::unknown().into())
+        .is_ok());
+    assert!(matches!(region.inner(), InnerRegion::Any((1, _))));
+}
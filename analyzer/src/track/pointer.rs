@@ -8,9 +8,12 @@ use core::{
 use bitflags::bitflags;
 use num_traits::ToPrimitive;
 
-use crate::branch::id::Id;
-
-use super::{pointees::Pointee, scalar::Scalar, TrackError, TrackedValue};
+use super::{
+    id::Id,
+    pointees::{InnerRegion, MemoryRegion, Pointee},
+    scalar::Scalar,
+    TrackError, TrackedValue,
+};
 
 bitflags! {
     /// Attributes of the pointer
@@ -43,8 +46,15 @@ pub struct Pointer {
     attributes: PointerAttributes,
     offset: Scalar,
     pointee: Pointee,
+    /// Bytes known to be accessible from where the pointer is, whatever its offset is
+    ///
+    /// It is only used with [super::pointees::dyn_region::DynamicRegion]s, relating a dynamic offset to the region end.
+    window: Option<i64>,
 }
 
+/// The alignment assumed for the start of any region
+pub const MAX_ALIGNMENT: u64 = 8;
+
 impl Pointer {
     /// Creates a non-null, readable, mutable pointer
     pub fn nrw(pointee: Pointee) -> Pointer {
@@ -54,6 +64,16 @@ impl Pointer {
         )
     }
 
+    /// Creates a nullable, readable, mutable pointer
+    ///
+    /// It needs to be checked against null (see [Pointer::set_non_null]) before use.
+    pub fn rw(pointee: Pointee) -> Pointer {
+        Self::new(
+            PointerAttributes::READABLE | PointerAttributes::MUTABLE,
+            pointee,
+        )
+    }
+
     /// Creates a non-null, readable, mutable pointer that allows arithmetic operations
     pub fn nrwa(pointee: Pointee) -> Pointer {
         Self::new(
@@ -76,7 +96,13 @@ impl Pointer {
     }
 
     /// Creates a non-null, end-marking pointer
+    ///
+    /// A [super::pointees::dyn_region::DynamicRegion] with its end exposed this way
+    /// is marked as packet data.
     pub fn end(pointee: Pointee) -> Pointer {
+        if let InnerRegion::Dyn(region) = pointee.borrow_mut().inner() {
+            region.set_packet();
+        }
         Self::new(
             PointerAttributes::NON_NULL | PointerAttributes::DATA_END,
             pointee,
@@ -93,6 +119,10 @@ impl Pointer {
     pub fn set_non_null(&mut self) {
         self.attributes.set(PointerAttributes::NON_NULL, true)
     }
+    /// Sets the pointer as possibly null, e.g., when returned by a helper that may fail
+    pub fn set_nullable(&mut self) {
+        self.attributes.set(PointerAttributes::NON_NULL, false)
+    }
     /// Returns `true` if the memory region pointed to by this pointer is readable
     pub fn is_readable(&self) -> bool {
         self.attributes.contains(PointerAttributes::READABLE)
@@ -121,16 +151,82 @@ impl Pointer {
             attributes,
             offset: Scalar::constant64(0),
             pointee,
+            window: None,
         }
     }
 
+    /// Gets the known alignment of the pointer, a power of two no greater than [MAX_ALIGNMENT]
+    ///
+    /// Regions are assumed to start at [MAX_ALIGNMENT]-aligned addresses, which pointers see
+    /// at offset `base` (see [super::pointees::dyn_region::DynamicRegion::set_base]).
+    /// So it follows the known trailing zeros of the distance from the start,
+    /// refined as the pointer moves.
+    pub fn alignment(&self, base: i64) -> u64 {
+        let mut offset = self.offset.clone();
+        if base != 0 {
+            offset -= &Scalar::constant64(base as u64);
+        }
+        // Bits that might be set
+        let bits = offset.bits.value() | offset.bits.mask();
+        if bits == 0 {
+            MAX_ALIGNMENT
+        } else {
+            (bits & bits.wrapping_neg()).min(MAX_ALIGNMENT)
+        }
+    }
+
+    /// Checks that an access of `size` bytes into the region is aligned
+    ///
+    /// Packet accesses are not checked (see [MemoryRegion::is_packet]): packet data only
+    /// starts at a 2-byte boundary (`NET_IP_ALIGN`), and the kernel permits unaligned
+    /// packet accesses by default.
+    fn check_alignment(&self, pointee: &mut dyn MemoryRegion, size: u8) -> Result<(), TrackError> {
+        if pointee.is_packet() {
+            return Ok(());
+        }
+        let base = match pointee.inner() {
+            InnerRegion::Dyn(region) => region.base(),
+            _ => 0,
+        };
+        // Both being powers of two
+        if self.alignment(base) >= size as u64 {
+            Ok(())
+        } else {
+            Err(TrackError::PointerOffsetMisaligned)
+        }
+    }
+
+    /// Marks that the region is known to extend at least `window` bytes past the pointer
+    ///
+    /// After a successful `start + len <= end` check against a [super::pointees::dyn_region::DynamicRegion],
+    /// `start + len` has a window of zero bytes, licensing accesses below it
+    /// even if `len` is not constant. Moving the pointer by a constant updates the window,
+    /// while moving it by a non-constant discards the window.
+    pub fn set_window(&mut self, window: i64) {
+        self.window = Some(window);
+    }
+
+    /// Returns the window if it licenses accesses of `size` bytes
+    fn window_for(&self, size: usize) -> Option<usize> {
+        self.window
+            .and_then(|window| window.to_usize())
+            .filter(|window| size <= *window)
+    }
+
     /// Tries to read from the pointed memory
     ///
     /// - `size`: in bytes
     pub fn get(&self, size: u8) -> Result<TrackedValue, TrackError> {
         if self.non_null() {
             if self.is_readable() {
-                self.pointee.borrow_mut().get(&self.offset, size)
+                let mut pointee = self.pointee.borrow_mut();
+                self.check_alignment(&mut *pointee, size)?;
+                if let Some(window) = self.window_for(size as usize) {
+                    if let InnerRegion::Dyn(region) = pointee.inner() {
+                        return region.get_within(&self.offset, size, window);
+                    }
+                }
+                pointee.get(&self.offset, size)
             } else {
                 Err(TrackError::PointeeNotReadable)
             }
@@ -145,7 +241,14 @@ impl Pointer {
     pub fn set(&self, size: u8, value: &TrackedValue) -> Result<(), TrackError> {
         if self.non_null() {
             if self.is_mutable() {
-                self.pointee.borrow_mut().set(&self.offset, size, value)
+                let mut pointee = self.pointee.borrow_mut();
+                self.check_alignment(&mut *pointee, size)?;
+                if let Some(window) = self.window_for(size as usize) {
+                    if let InnerRegion::Dyn(region) = pointee.inner() {
+                        return region.set_within(&self.offset, size, value, window);
+                    }
+                }
+                pointee.set(&self.offset, size, value)
             } else {
                 Err(TrackError::PointeeNotWritable)
             }
@@ -207,6 +310,20 @@ impl Pointer {
         self.pointee.clone()
     }
 
+    /// Checks whether this pointer is at least as restricted as `other`
+    ///
+    /// Both should point to regions of the same id with the same attributes,
+    /// with possible offsets of this one among those of `other`.
+    /// Pointers into regions that are currently mutably borrowed are
+    /// considered to point to the same region if both of them are borrowed.
+    pub fn is_subset_of(&self, other: &Self) -> bool {
+        let id = |p: &Pointer| p.pointee.try_borrow().map(|r| r.get_id()).ok();
+        self.attributes == other.attributes
+            && self.window == other.window
+            && id(self) == id(other)
+            && self.offset.is_subset_of(&other.offset)
+    }
+
     /// Sets the pointer to point to another region
     pub fn redirect(&mut self, region: Pointee) {
         self.pointee = region;
@@ -216,12 +333,20 @@ impl Pointer {
 impl AddAssign<&Scalar> for Pointer {
     fn add_assign(&mut self, rhs: &Scalar) {
         self.offset += rhs;
+        self.window = self
+            .window
+            .zip(rhs.value64())
+            .and_then(|(window, value)| window.checked_sub(value as i64));
     }
 }
 
 impl SubAssign<&Scalar> for Pointer {
     fn sub_assign(&mut self, rhs: &Scalar) {
         self.offset -= rhs;
+        self.window = self
+            .window
+            .zip(rhs.value64())
+            .and_then(|(window, value)| window.checked_add(value as i64));
     }
 }
 
@@ -63,6 +63,11 @@ impl<Int: RangeItem> RangePair<Int> {
         self.min == self.max
     }
 
+    /// Tells whether the range lies within `other`
+    pub fn is_subset_of(&self, other: &Self) -> bool {
+        other.min <= self.min && self.max <= other.max
+    }
+
     /// Returns the intersection with rhs
     ///
     /// It may incur an invalid state, which the caller is responsible to check.
@@ -70,6 +75,11 @@ impl<Int: RangeItem> RangePair<Int> {
         Self::new(self.min.max(rhs.min), self.max.min(rhs.max))
     }
 
+    /// Returns the smallest range covering both `self` and `rhs`
+    pub fn union(&self, rhs: &Self) -> Self {
+        Self::new(self.min.min(rhs.min), self.max.max(rhs.max))
+    }
+
     /// Modifies self and rhs
     pub fn le(&mut self, rhs: &mut Self) -> ComparisonResult<Self> {
         if self.max <= rhs.min {
@@ -2,12 +2,15 @@
 
 use core::{
     fmt::Debug,
-    ops::{AddAssign, BitAndAssign, BitOrAssign, BitXorAssign, MulAssign, SubAssign},
+    ops::{
+        AddAssign, BitAndAssign, BitOrAssign, BitXorAssign, DivAssign, MulAssign, RangeInclusive,
+        RemAssign, SubAssign,
+    },
 };
 
 use num_traits::{AsPrimitive, PrimInt};
 
-use crate::interpreter::value::{NegAssign, ByteSwap};
+use crate::interpreter::value::{ByteSwap, NegAssign, SafeDivAssign};
 
 use super::{
     range::{RangePair, SyncFromUpper},
@@ -40,31 +43,25 @@ pub trait ShiftAssign<const WIDTH: u8, Rhs = Self> {
 }
 
 macro_rules! impl_shift_assign {
-    ($width:expr, $urange:ident) => {
+    ($width:expr) => {
         impl ShiftAssign<$width, &Self> for Scalar {
             fn shl_assign(&mut self, rhs: &Self) {
-                if self.require_constant::<$width>(rhs) {
-                    self.shl::<$width>(rhs.$urange.max as u64);
-                }
+                self.shift_by_range::<$width>(rhs, Self::shl::<$width>);
             }
 
             fn shr_assign(&mut self, rhs: &Self) {
-                if self.require_constant::<$width>(rhs) {
-                    self.shl::<$width>(rhs.$urange.max as u64);
-                }
+                self.shift_by_range::<$width>(rhs, Self::shr::<$width>);
             }
 
             fn ashr_assign(&mut self, rhs: &Self) {
-                if self.require_constant::<$width>(rhs) {
-                    self.shl::<$width>(rhs.$urange.max as u64);
-                }
+                self.shift_by_range::<$width>(rhs, Self::ashr::<$width>);
             }
         }
     };
 }
 
-impl_shift_assign!(32, urange32);
-impl_shift_assign!(64, urange);
+impl_shift_assign!(32);
+impl_shift_assign!(64);
 
 impl Scalar {
     fn mark_as_known(&mut self, value: u64) {
@@ -112,6 +109,16 @@ impl Scalar {
         }
     }
 
+    /// Returns the possible values, as signed 64-bit integers
+    pub fn signed_range(&self) -> RangeInclusive<i64> {
+        self.irange.min..=self.irange.max
+    }
+
+    /// Returns the possible values, as unsigned 64-bit integers
+    pub fn unsigned_range(&self) -> RangeInclusive<u64> {
+        self.urange.min..=self.urange.max
+    }
+
     /// Returns `None` if the state invalid, or `Some(true_if_constant)`
     pub fn is_constant<const WIDTH: u8>(&self) -> Option<bool> {
         debug_assert!(WIDTH == 32 || WIDTH == 64);
@@ -159,6 +166,54 @@ impl Scalar {
         }
     }
 
+    /// Shifts by every amount that `rhs` might be, joining the results
+    ///
+    /// Joining the results for only the smallest and the largest amounts is not enough,
+    /// since the known bits differ for each amount.
+    /// The result is unknown if the amount might be `>= WIDTH`.
+    fn shift_by_range<const WIDTH: u8>(&mut self, rhs: &Self, shift: fn(&mut Self, u64)) {
+        let (bits, amounts) = if WIDTH == 32 {
+            (
+                rhs.bits.lower_half(),
+                rhs.urange32.min as u64..=rhs.urange32.max as u64,
+            )
+        } else {
+            (rhs.bits, rhs.urange.min..=rhs.urange.max)
+        };
+        if rhs.is_constant::<WIDTH>().is_none() || *amounts.end() >= WIDTH as u64 {
+            self.mark_as_unknown();
+            return;
+        }
+
+        let original = self.clone();
+        let mut result: Option<Scalar> = None;
+        for amount in amounts.filter(|&amount| bits.contains(amount)) {
+            let mut shifted = original.clone();
+            shift(&mut shifted, amount);
+            result = Some(match result {
+                Some(result) => result.union(&shifted),
+                None => shifted,
+            });
+        }
+        match result {
+            Some(result) => *self = result,
+            None => self.mark_as_unknown(),
+        }
+    }
+
+    /// Returns a scalar containing all values of both `self` and `rhs`
+    fn union(&self, rhs: &Self) -> Self {
+        let mut result = Scalar {
+            bits: self.bits.union(rhs.bits),
+            irange: self.irange.union(&rhs.irange),
+            irange32: self.irange32.union(&rhs.irange32),
+            urange: self.urange.union(&rhs.urange),
+            urange32: self.urange32.union(&rhs.urange32),
+        };
+        result.sync_bounds();
+        result
+    }
+
     /// Returns true if irange and irange32 are of the same range
     pub fn is_signed_in_sync(&self) -> Option<(i32, i32)> {
         if self.irange32.min as i64 == self.irange.min
@@ -217,30 +272,20 @@ impl Scalar {
         sync_range_bounds!(irange, urange, i64, u64);
     }
 
-    /// Syncs bits using range info
-    ///
-    /// TODO: Replace `unreachable` with less aggressive error reporting.
-    fn sync_bits(&mut self) {
-        if let Some(bits) = self
+    /// Syncs bits using range info, returning `false` if they contradict each other
+    fn sync_bits(&mut self) -> bool {
+        let bits = self
             .bits
-            .intersects(NumBits::range(self.urange.min, self.urange.max))
-        {
-            if let Some(bits32) = self.bits.lower_half().intersects(NumBits::range(
-                self.urange32.min as u64,
-                self.urange32.max as u64,
-            )) {
-                self.bits = bits.upper_half() | bits32;
-            } else {
-                unreachable!(
-                    "Ranges and bits are out of sync: {:?} {:?}",
-                    self.bits, self.urange32
-                );
-            }
+            .intersects(NumBits::range(self.urange.min, self.urange.max));
+        let bits32 = self.bits.lower_half().intersects(NumBits::range(
+            self.urange32.min as u64,
+            self.urange32.max as u64,
+        ));
+        if let (Some(bits), Some(bits32)) = (bits, bits32) {
+            self.bits = bits.upper_half() | bits32;
+            true
         } else {
-            unreachable!(
-                "Ranges and bits are out of sync: {:?} {:?}",
-                self.bits, self.urange
-            );
+            false
         }
     }
 
@@ -253,14 +298,39 @@ impl Scalar {
     ///
     /// This function must be called to sync the sign bit info between
     /// `iranges` and `bits` after changes in the sign bit.
+    ///
+    /// TODO: Replace `unreachable` with less aggressive error reporting.
     pub(super) fn sync_bounds(&mut self) {
         self.narrow_bounds();
         self.sync_from_upper();
         self.sync_sign_bounds();
-        self.sync_bits();
+        if !self.sync_bits() {
+            unreachable!("Ranges and bits are out of sync: {:?}", self);
+        }
         self.narrow_bounds();
     }
 
+    /// Syncs like [Scalar::sync_bounds], returning `false` if no value satisfies all the bounds
+    ///
+    /// Narrowing for branches may well lead to contradictions, which means that
+    /// the branch is never taken.
+    pub(super) fn try_sync_bounds(&mut self) -> bool {
+        let valid = |s: &Self| {
+            s.irange.is_valid()
+                && s.urange.is_valid()
+                && s.irange32.is_valid()
+                && s.urange32.is_valid()
+        };
+        self.narrow_bounds();
+        self.sync_from_upper();
+        self.sync_sign_bounds();
+        if !valid(self) || !self.sync_bits() {
+            return false;
+        }
+        self.narrow_bounds();
+        valid(self)
+    }
+
     /// Left-shifts
     ///
     /// Unlike other operations, shift verification depends on the value width,
@@ -275,7 +345,7 @@ impl Scalar {
                 if shift >= $width as u64 {
                     // Undefined shifts
                     self.$urange.mark_as_unknown();
-                } else if shift != 0 && max > (1 << ($width as u64 - shift)) {
+                } else if shift != 0 && max >= (1 << ($width as u64 - shift)) {
                     // Some bits are shifted off
                     self.$urange.mark_as_unknown();
                 } else {
@@ -392,16 +462,171 @@ impl Scalar {
         self.sync_bounds();
     }
 
+    /// Signed division, with `x / 0 == 0` and `MIN / -1 == MIN`
+    pub fn signed_div<const WIDTH: u8>(&mut self, rhs: &Self) {
+        debug_assert!(WIDTH == 32 || WIDTH == 64);
+
+        if WIDTH == 64 {
+            if let (Some(mut value), Some(divisor)) = (self.value64(), rhs.value64()) {
+                value.signed_div_assign(&divisor, 64);
+                *self = Scalar::constant64(value);
+                return;
+            }
+        }
+
+        macro_rules! signed_div_range {
+            ($irange:ident, $itype:ident) => {{
+                let (a, b) = (self.$irange, rhs.$irange);
+                if b.is_constant() {
+                    let divisor = b.min;
+                    if divisor == 0 {
+                        Some(RangePair::exact(0))
+                    } else if divisor == -1 {
+                        if a.min == $itype::MIN && !a.is_constant() {
+                            // Both MIN and -MIN wrap around
+                            None
+                        } else {
+                            Some(RangePair::new(a.max.wrapping_neg(), a.min.wrapping_neg()))
+                        }
+                    } else {
+                        // Truncating division is monotonic with a fixed divisor
+                        let (min, max) = (a.min / divisor, a.max / divisor);
+                        Some(RangePair::new(min.min(max), min.max(max)))
+                    }
+                } else {
+                    // |a / b| <= |a|, which does not hold only for MIN / -1
+                    let magnitude = a.min.unsigned_abs().max(a.max.unsigned_abs());
+                    if magnitude > $itype::MAX.unsigned_abs() {
+                        None
+                    } else {
+                        let magnitude = magnitude as $itype;
+                        Some(RangePair::new(-magnitude, magnitude))
+                    }
+                }
+            }};
+        }
+
+        if WIDTH == 32 {
+            let result = signed_div_range!(irange32, i32);
+            self.set_signed_range32(result);
+        } else {
+            let result = signed_div_range!(irange, i64);
+            self.set_signed_range64(result);
+        }
+    }
+
+    /// Signed modulus, with `x % 0 == x` and `MIN % -1 == 0`
+    ///
+    /// The result takes the sign of the dividend.
+    pub fn signed_rem<const WIDTH: u8>(&mut self, rhs: &Self) {
+        debug_assert!(WIDTH == 32 || WIDTH == 64);
+
+        if WIDTH == 64 {
+            if let (Some(mut value), Some(divisor)) = (self.value64(), rhs.value64()) {
+                value.signed_rem_assign(&divisor, 64);
+                *self = Scalar::constant64(value);
+                return;
+            }
+        }
+
+        macro_rules! signed_rem_range {
+            ($irange:ident, $itype:ident) => {{
+                let (a, b) = (self.$irange, rhs.$irange);
+                let mut result = RangePair::new(a.min.min(0), a.max.max(0));
+                if b.min > 0 || b.max < 0 {
+                    // |a % b| < |b| when b is never zero
+                    let bound = (b.min.unsigned_abs().max(b.max.unsigned_abs()) - 1) as $itype;
+                    result.min = result.min.max(-bound);
+                    result.max = result.max.min(bound);
+                }
+                result
+            }};
+        }
+
+        if WIDTH == 32 {
+            if rhs.irange32.is_constant() && rhs.irange32.min == 0 {
+                return;
+            }
+            let result = signed_rem_range!(irange32, i32);
+            self.set_signed_range32(Some(result));
+        } else {
+            if rhs.irange.is_constant() && rhs.irange.min == 0 {
+                return;
+            }
+            let result = signed_rem_range!(irange, i64);
+            self.set_signed_range64(Some(result));
+        }
+    }
+
+    /// Discards everything but the lower 32-bit signed range, `None` for unknown
+    fn set_signed_range32(&mut self, range: Option<RangePair<i32>>) {
+        self.mark_as_unknown();
+        if let Some(range) = range {
+            self.irange32 = range;
+        }
+        self.sync_bounds();
+    }
+
+    /// Discards everything but the signed range, `None` for unknown
+    fn set_signed_range64(&mut self, range: Option<RangePair<i64>>) {
+        self.mark_as_unknown();
+        if let Some(range) = range {
+            self.irange = range;
+        }
+        self.sync_bounds();
+    }
+
     /// Zeroes the upper half
+    ///
+    /// The result is always non-negative when interpreted as a signed 64-bit integer.
     pub fn lower_half(&mut self) {
         self.bits = self.bits.lower_half();
-        self.irange.mark_as_unknown();
-        self.irange.min = 0;
+        self.irange = RangePair::new(0, u32::MAX as i64);
         self.urange.min = self.urange32.min as u64;
         self.urange.max = self.urange32.max as u64;
         self.sync_bounds();
     }
 
+    /// Sign-extends the lowest `width` (8, 16 or 32) bits to 64 bits
+    pub fn sign_extend(&mut self, width: u8) {
+        debug_assert!(width == 8 || width == 16 || width == 32);
+
+        let low_mask = u64::MAX >> (64 - width);
+        let sign = 1u64 << (width - 1);
+
+        // Truncation
+        let low = NumBits::pruned(self.bits.mask() & low_mask, self.bits.value() & low_mask);
+        let (min, max) = if self.urange.max <= low_mask {
+            (self.urange.min, self.urange.max)
+        } else if self.urange32.max as u64 <= low_mask {
+            (self.urange32.min as u64, self.urange32.max as u64)
+        } else {
+            (0, low_mask)
+        };
+        let (min, max) = (min.max(low.min()), max.min(low.max()));
+
+        // Extension
+        let irange = if max < sign {
+            RangePair::new(min as i64, max as i64)
+        } else if min >= sign {
+            RangePair::new((min | !low_mask) as i64, (max | !low_mask) as i64)
+        } else {
+            RangePair::new(-(sign as i64), (sign - 1) as i64)
+        };
+        let bits = if low.mask() & sign != 0 {
+            NumBits::pruned(low.mask() | !low_mask, low.value())
+        } else if low.value() & sign != 0 {
+            NumBits::pruned(low.mask(), low.value() | !low_mask)
+        } else {
+            low
+        };
+
+        self.mark_as_unknown();
+        self.bits = bits;
+        self.irange = irange;
+        self.sync_bounds();
+    }
+
     /// Updates the irange/irange32 field for bit operations (`and`, `or` and `xor`)
     fn update_irange<const WIDTH: u8>(&mut self, rhs: &Self) {
         debug_assert!(WIDTH == 32 || WIDTH == 64);
@@ -458,6 +683,23 @@ impl Scalar {
         }
     }
 
+    /// Creates a scalar from known bits, `mask` marking the unknown ones
+    pub fn from_tnum(mask: u64, value: u64) -> Self {
+        let mut result = Scalar::unknown();
+        result.bits = NumBits::pruned(mask, value);
+        result.sync_bounds();
+        result
+    }
+
+    /// Determines if every value this scalar might be is also possible for `other`
+    pub fn is_subset_of(&self, other: &Self) -> bool {
+        self.bits.is_subset_of(other.bits)
+            && self.irange.is_subset_of(&other.irange)
+            && self.irange32.is_subset_of(&other.irange32)
+            && self.urange.is_subset_of(&other.urange)
+            && self.urange32.is_subset_of(&other.urange32)
+    }
+
     /// Determines if this scalar might match that certain integer
     pub fn contains<
         Int: PrimInt + AsPrimitive<i64> + AsPrimitive<u64> + AsPrimitive<i32> + AsPrimitive<u32>,
@@ -502,13 +744,40 @@ impl NegAssign for Scalar {
     }
 }
 
+impl Scalar {
+    /// Swaps the bytes (see [ByteSwap]), to big endian if `to_be` or to little endian otherwise
+    ///
+    /// Constants are swapped precisely. Otherwise, the value is kept as is
+    /// if the host endianness already matches, or becomes unknown,
+    /// with the upper bits zeroed in both cases.
+    fn byte_swap(&mut self, width: i32, to_be: bool) {
+        if let Some(mut value) = self.value64() {
+            if to_be {
+                value.host_to_be(width);
+            } else {
+                value.host_to_le(width);
+            }
+            *self = Scalar::constant64(value);
+            return;
+        }
+        if cfg!(target_endian = "big") != to_be {
+            self.mark_as_unknown();
+        }
+        match width {
+            64 => {}
+            16 | 32 => *self &= &Scalar::constant64(u64::MAX >> (64 - width)),
+            _ => *self = Scalar::constant64(0),
+        }
+    }
+}
+
 impl ByteSwap for Scalar {
-    fn host_to_le(&mut self, _width: i32) {
-        self.mark_as_unknown()
+    fn host_to_le(&mut self, width: i32) {
+        self.byte_swap(width, false)
     }
 
-    fn host_to_be(&mut self, _width: i32) {
-        self.mark_as_unknown()
+    fn host_to_be(&mut self, width: i32) {
+        self.byte_swap(width, true)
     }
 }
 
@@ -547,6 +816,58 @@ impl MulAssign<&Self> for Scalar {
     }
 }
 
+/// Unsigned division, with `x / 0 == 0`
+///
+/// Only divisions by constants are tracked.
+impl DivAssign<&Self> for Scalar {
+    fn div_assign(&mut self, rhs: &Self) {
+        if let (Some(mut value), Some(divisor)) = (self.value64(), rhs.value64()) {
+            value.safe_div_assign(&divisor);
+            *self = Scalar::constant64(value);
+            return;
+        }
+        match rhs.value64() {
+            Some(0) => *self = Scalar::constant64(0),
+            Some(divisor) if divisor.is_power_of_two() => {
+                self.shr::<64>(divisor.trailing_zeros() as u64)
+            }
+            Some(divisor) => {
+                let range = RangePair::new(self.urange.min / divisor, self.urange.max / divisor);
+                self.mark_as_unknown();
+                self.urange = range;
+                self.sync_bounds();
+            }
+            None => self.mark_as_unknown(),
+        }
+    }
+}
+
+/// Unsigned modulus, with `x % 0 == x`
+///
+/// Only modulus by constants are tracked.
+impl RemAssign<&Self> for Scalar {
+    fn rem_assign(&mut self, rhs: &Self) {
+        if let (Some(mut value), Some(divisor)) = (self.value64(), rhs.value64()) {
+            value.safe_rem_assign(&divisor);
+            *self = Scalar::constant64(value);
+            return;
+        }
+        match rhs.value64() {
+            Some(0) => {}
+            Some(divisor) if self.urange.max < divisor => {}
+            Some(divisor) if divisor.is_power_of_two() => {
+                *self &= &Scalar::constant64(divisor - 1)
+            }
+            Some(divisor) => {
+                self.mark_as_unknown();
+                self.urange = RangePair::new(0, divisor - 1);
+                self.sync_bounds();
+            }
+            None => self.mark_as_unknown(),
+        }
+    }
+}
+
 impl BitAndAssign<&Self> for Scalar {
     fn bitand_assign(&mut self, rhs: &Self) {
         self.bits = self.bits & rhs.bits;
@@ -576,10 +897,6 @@ impl BitAndAssign<&Self> for Scalar {
 
 impl BitOrAssign<&Self> for Scalar {
     fn bitor_assign(&mut self, rhs: &Self) {
-        if !self.require_constant::<64>(rhs) {
-            return;
-        }
-
         self.bits = self.bits | rhs.bits;
         if self.bits.is_constant() {
             self.mark_as_known(self.bits.value());
@@ -607,10 +924,6 @@ impl BitOrAssign<&Self> for Scalar {
 
 impl BitXorAssign<&Self> for Scalar {
     fn bitxor_assign(&mut self, rhs: &Self) {
-        if !self.require_constant::<64>(rhs) {
-            return;
-        }
-
         self.bits = self.bits ^ rhs.bits;
         if self.bits.is_constant() {
             self.mark_as_known(self.bits.value());
@@ -758,14 +1071,19 @@ pub fn test_unknown() {
     result *= &un;
     assert_unknown(&result);
 
+    // Bitwise operations track non-constant operands
     let mut result = s.clone();
     result |= &un;
-    assert_unknown(&result);
+    assert_eq!(result.unsigned_range(), 1..=5);
+    assert!(!result.contains(3u64));
 
     let mut result = s.clone();
     result ^= &un;
-    assert_unknown(&result);
+    assert_eq!(result.unsigned_range(), 1..=5);
+    assert!(!result.contains(3u64));
 
+    // Shift amounts that are possibly out of range
+    let un = unknown(6);
     let mut result = s.clone();
     ShiftAssign::<32, &Scalar>::shl_assign(&mut result, &un);
     assert_unknown(&result);
@@ -787,6 +1105,73 @@ pub fn test_unknown() {
     assert_unknown(&result);
 }
 
+#[test]
+pub fn test_from_tnum() {
+    let s = Scalar::from_tnum(0xF, 0x1230);
+    assert_eq!(s.unsigned_range(), 0x1230..=0x123F);
+    assert_eq!(s.signed_range(), 0x1230..=0x123F);
+    assert_eq!(s.urange32.min, 0x1230);
+    assert_eq!(s.urange32.max, 0x123F);
+    assert!(s.contains(0x1235u64));
+    assert!(!s.contains(0x1240u64));
+
+    // Unknown bits in the value are ignored
+    let s = Scalar::from_tnum(0xF, 0x123F);
+    assert_eq!(s.unsigned_range(), 0x1230..=0x123F);
+
+    let s = Scalar::from_tnum(0, 0x1234);
+    assert_eq!(s.value64(), Some(0x1234));
+}
+
+#[test]
+pub fn test_sign_extend() {
+    let mut s = Scalar::constant64(0x1234_5680);
+    s.sign_extend(8);
+    assert_eq!(s.value64(), Some(-0x80i64 as u64));
+
+    let mut s = Scalar::constant64(0xFFFF_FFFF_0000_7FFF);
+    s.sign_extend(16);
+    assert_eq!(s.value64(), Some(0x7FFF));
+
+    let mut s = Scalar::constant64(0x8000_0000);
+    s.sign_extend(32);
+    assert_eq!(s.value64(), Some(0xFFFF_FFFF_8000_0000));
+    s.lower_half();
+    assert_eq!(s.value64(), Some(0x8000_0000));
+
+    // Sign bit known to be set
+    let mut s = Scalar::from_tnum(0xF, 0xF0);
+    s.sign_extend(8);
+    assert_eq!(s.signed_range(), -16..=-1);
+
+    // Sign bit unknown
+    let mut s = Scalar::from_tnum(0xFF, 0);
+    s.sign_extend(8);
+    assert_eq!(s.signed_range(), -128..=127);
+    assert!(s.contains(-1i64));
+    assert!(s.contains(127i64));
+
+    let mut s = Scalar::unknown();
+    s.sign_extend(32);
+    assert_eq!(s.signed_range(), (i32::MIN as i64)..=(i32::MAX as i64));
+}
+
+#[test]
+pub fn test_lower_half() {
+    let mut s = Scalar::unknown();
+    s.lower_half();
+    assert_eq!(s.signed_range(), 0..=u32::MAX as i64);
+    assert_eq!(s.unsigned_range(), 0..=u32::MAX as u64);
+
+    let mut s = Scalar::constant64(-1i64 as u64);
+    s.lower_half();
+    assert_eq!(s.value64(), Some(u32::MAX as u64));
+
+    let mut s = Scalar::from_tnum(0xFFFF_FFFF_8000_00FF, 0);
+    s.lower_half();
+    assert_eq!(s.signed_range(), 0..=0x8000_00FF);
+}
+
 #[test]
 pub fn test_shl() {
     let mut s = Scalar::constant64(0x2);
@@ -799,6 +1184,112 @@ pub fn test_shl() {
 
     s.shl::<32>(70);
     assert!(!s.is_constant::<64>().unwrap_or(true));
+
+    // The maximum shifted off, while smaller values are not
+    let mut s = Scalar::unknown();
+    s.urange = RangePair::new(0, 1 << 30);
+    s.sync_bounds();
+    s.shl::<32>(2);
+    assert!(s.contains(1u32 << 30));
+    let mut s = Scalar::unknown();
+    s.urange = RangePair::new(0, 1 << 62);
+    s.sync_bounds();
+    s.shl::<64>(2);
+    assert!(s.contains(1u64 << 62));
+}
+
+#[test]
+pub fn test_try_sync_bounds() {
+    // Consistent bounds are synced just like with `sync_bounds`
+    let mut s = Scalar::unknown();
+    s.urange = RangePair::new(0x10, 0x1F);
+    let mut synced = s.clone();
+    synced.sync_bounds();
+    assert!(s.try_sync_bounds());
+    assert_eq!(s.unsigned_range(), synced.unsigned_range());
+    assert_eq!(s.signed_range(), synced.signed_range());
+    assert!(s.is_subset_of(&synced) && synced.is_subset_of(&s));
+
+    // One of 0x05, 0x15, 0x25 and 0x35, yet either 0x26 or 0x27
+    let mut s = Scalar::from_tnum(0x30, 0x05);
+    s.urange = RangePair::new(0x26, 0x27);
+    assert!(!s.try_sync_bounds());
+
+    // Negative, yet below 10 when unsigned
+    let mut s = Scalar::unknown();
+    s.irange = RangePair::new(-10, -1);
+    s.urange = RangePair::new(0, 10);
+    assert!(!s.try_sync_bounds());
+}
+
+#[test]
+pub fn test_bounded_shifts() {
+    let amounts = |low: u64, high: u64| {
+        let mut rhs = Scalar::unknown();
+        rhs.urange = RangePair::new(low, high);
+        rhs.sync_bounds();
+        rhs
+    };
+    let rhs = amounts(0, 3);
+    let mut s = Scalar::constant64(1);
+    ShiftAssign::<64, &Scalar>::shl_assign(&mut s, &rhs);
+    assert_eq!(s.unsigned_range(), 1..=8);
+    let mut s = Scalar::constant64(0x80);
+    ShiftAssign::<32, &Scalar>::shr_assign(&mut s, &rhs);
+    assert_eq!(s.unsigned_range(), 0x10..=0x80);
+
+    // Possibly out-of-range shifts
+    let rhs = amounts(0, 32);
+    let mut s = Scalar::constant64(1);
+    ShiftAssign::<32, &Scalar>::shl_assign(&mut s, &rhs);
+    assert_unknown(&s);
+
+    let mut rng = thread_rng();
+    for _ in 0..100000 {
+        let mask = rng.gen::<u64>() & rng.gen::<u64>();
+        let lhs = Scalar::from_tnum(mask, rng.gen());
+        let value = lhs.bits.value() | (rng.gen::<u64>() & mask);
+        let low = rng.gen_range(0..64);
+        let high = rng.gen_range(low..64);
+        let shift = rng.gen_range(low..=high);
+        let rhs = amounts(low, high);
+        assert!(rhs.contains(shift));
+
+        macro_rules! check_shift {
+            ($width:expr, $op:ident, $expected:expr, $($int:ty),+) => {
+                let mut result = lhs.clone();
+                ShiftAssign::<$width, &Scalar>::$op(&mut result, &rhs);
+                if high >= $width {
+                    assert_unknown(&result);
+                } else {
+                    $(
+                        assert!(
+                            result.contains($expected as $int),
+                            "{:?} {} ({}..={}): {:?}",
+                            lhs,
+                            stringify!($op),
+                            low,
+                            high,
+                            result
+                        );
+                    )+
+                }
+            };
+        }
+        check_shift!(64, shl_assign, value << shift, u64, i64);
+        check_shift!(64, shr_assign, value >> shift, u64, i64);
+        check_shift!(64, ashr_assign, (value as i64) >> shift, u64, i64);
+        let (value, shift) = (value as u32, shift as u32);
+        check_shift!(32, shl_assign, value.wrapping_shl(shift), u32, i32);
+        check_shift!(32, shr_assign, value.wrapping_shr(shift), u32, i32);
+        check_shift!(
+            32,
+            ashr_assign,
+            (value as i32).wrapping_shr(shift),
+            u32,
+            i32
+        );
+    }
 }
 
 #[cfg(test)]
@@ -870,15 +1361,15 @@ pub fn test_random_ops() {
                 }
                 7 => {
                     ShiftAssign::<32, &Scalar>::shl_assign(&mut a, &b);
-                    result = result.wrapping_shl(rhs.max(u32::MAX as u64) as u32);
+                    result = result.wrapping_shl(rhs as u32);
                 }
                 8 => {
                     ShiftAssign::<32, &Scalar>::shr_assign(&mut a, &b);
-                    result = (result as u32).wrapping_shr(rhs.max(u32::MAX as u64) as u32) as i32;
+                    result = (result as u32).wrapping_shr(rhs as u32) as i32;
                 }
                 9 => {
                     ShiftAssign::<32, &Scalar>::ashr_assign(&mut a, &b);
-                    result = result.wrapping_shr(rhs.max(u32::MAX as u64) as u32);
+                    result = result.wrapping_shr(rhs as u32);
                 }
                 _ => {}
             }
@@ -887,3 +1378,281 @@ pub fn test_random_ops() {
         }
     }
 }
+
+#[test]
+pub fn test_random_division() {
+    for _ in 0..200000 {
+        let (a, value) = if thread_rng().gen_bool(0.5) {
+            let shift = thread_rng().gen_range(0..64);
+            // Either 0 or 1 << shift
+            let value = if thread_rng().gen_bool(0.5) { 0 } else { 1 << shift };
+            (unknown(shift), value)
+        } else {
+            let value: u64 = thread_rng().gen();
+            (Scalar::constant64(value), value)
+        };
+        let divisor = match thread_rng().gen_range(0..4) {
+            0 => 0,
+            1 => 1 << thread_rng().gen_range(0..64),
+            2 => thread_rng().gen_range(1..1000),
+            _ => thread_rng().gen(),
+        };
+        let b = Scalar::constant64(divisor);
+
+        let mut quotient = a.clone();
+        quotient /= &b;
+        let expected = value.checked_div(divisor).unwrap_or(0);
+        assert!(quotient.contains(expected), "{a:?} / {divisor}: {quotient:?}");
+        let mut remainder = a.clone();
+        remainder %= &b;
+        let expected = value.checked_rem(divisor).unwrap_or(value);
+        assert!(remainder.contains(expected), "{a:?} % {divisor}: {remainder:?}");
+        if divisor != 0 {
+            assert!(remainder.urange.max < divisor);
+        }
+    }
+
+    let mut s = Scalar::constant64(100);
+    s /= &Scalar::constant64(7);
+    assert_exact(&s, 14);
+    let mut s = Scalar::constant64(100);
+    s %= &Scalar::constant64(7);
+    assert_exact(&s, 2);
+    let mut s = unknown(10);
+    s /= &Scalar::constant64(10);
+    assert_eq!(s.unsigned_range(), 0..=102);
+    let mut s = unknown(10);
+    s /= &unknown(2);
+    assert_unknown(&s);
+}
+
+#[test]
+pub fn test_signed_division() {
+    let edges = [
+        i64::MIN,
+        i64::MIN + 1,
+        i32::MIN as i64,
+        -7,
+        -1,
+        0,
+        1,
+        2,
+        7,
+        i32::MAX as i64,
+        i64::MAX,
+    ];
+    let range = |min: i64, max: i64| {
+        let mut s = Scalar::unknown();
+        s.irange = RangePair::new(min, max);
+        s.sync_bounds();
+        s
+    };
+    let random: [(i64, i64); 64] = core::array::from_fn(|_| {
+        let (a, b) = (
+            thread_rng().gen_range(0..edges.len()),
+            thread_rng().gen_range(0..edges.len()),
+        );
+        (edges[a.min(b)], edges[a.max(b)])
+    });
+    let ranges = random.iter().copied().chain(edges.iter().map(|&v| (v, v)));
+    for (a_min, a_max) in ranges.clone() {
+        for (b_min, b_max) in ranges.clone() {
+            let (a, b) = (range(a_min, a_max), range(b_min, b_max));
+            let mut quotient = a.clone();
+            quotient.signed_div::<64>(&b);
+            let mut remainder = a.clone();
+            remainder.signed_rem::<64>(&b);
+            let (mut quotient32, mut remainder32) = (a.clone(), a.clone());
+            quotient32.signed_div::<32>(&b);
+            remainder32.signed_rem::<32>(&b);
+            for x in edges.iter().chain([a_min, a_max].iter()) {
+                for y in edges.iter().chain([b_min, b_max].iter()) {
+                    if !(a_min..=a_max).contains(x) || !(b_min..=b_max).contains(y) {
+                        continue;
+                    }
+                    let (q, r) = if *y == 0 {
+                        (0, *x)
+                    } else {
+                        (x.wrapping_div(*y), x.wrapping_rem(*y))
+                    };
+                    assert!(quotient.contains(q as u64), "{x} / {y}: {quotient:?}");
+                    assert!(remainder.contains(r as u64), "{x} % {y}: {remainder:?}");
+                    let (x, y) = (*x as i32, *y as i32);
+                    if !a.irange32.contains(x) || !b.irange32.contains(y) {
+                        continue;
+                    }
+                    let (q, r) = if y == 0 {
+                        (0, x)
+                    } else {
+                        (x.wrapping_div(y), x.wrapping_rem(y))
+                    };
+                    assert!(quotient32.irange32.contains(q), "{x} / {y}: {quotient32:?}");
+                    assert!(remainder32.irange32.contains(r), "{x} % {y}: {remainder32:?}");
+                }
+            }
+        }
+    }
+
+    let mut s = Scalar::constant64(i64::MIN as u64);
+    s.signed_div::<64>(&Scalar::constant64(-1i64 as u64));
+    assert_exact(&s, i64::MIN);
+    let mut s = range(-100, 50);
+    s.signed_div::<64>(&Scalar::constant64(-10i64 as u64));
+    assert_eq!(s.signed_range(), -5..=10);
+    let mut s = range(-100, 50);
+    s.signed_rem::<64>(&range(1, 8));
+    assert_eq!(s.signed_range(), -7..=7);
+}
+
+#[cfg(test)]
+fn assert_exact(s: &Scalar, value: i64) {
+    assert_eq!(s.value64(), Some(value as u64), "{s:?}");
+    assert_eq!(s.value32(), Some(value as u32), "{s:?}");
+    assert_eq!(s.signed_range(), value..=value, "{s:?}");
+    assert_eq!(s.unsigned_range(), value as u64..=value as u64, "{s:?}");
+    assert_eq!(s.irange32.min, value as i32, "{s:?}");
+    assert_eq!(s.irange32.max, value as i32, "{s:?}");
+    assert!(s.bits.is_constant(), "{s:?}");
+}
+
+#[test]
+pub fn test_negative_constants() {
+    let constants = [
+        -1i64,
+        -5,
+        -0x7F,
+        -0x80,
+        i32::MIN as i64,
+        i32::MIN as i64 - 1,
+        -0x1_0000_0000,
+        -0x1_0000_0005,
+        i64::MIN,
+        i64::MIN + 5,
+    ];
+    for value in constants {
+        let mut s = Scalar::constant64(value as u64);
+        assert_exact(&s, value);
+        s.sync_bounds();
+        assert_exact(&s, value);
+
+        for other in constants.iter().chain([0, 1, 5, i64::MAX].iter()) {
+            let rhs = Scalar::constant64(*other as u64);
+            macro_rules! check_op {
+                ($op:tt, $f:expr) => {
+                    let mut result = s.clone();
+                    result $op &rhs;
+                    assert_exact(&result, $f(value, *other));
+                };
+            }
+            check_op!(+=, i64::wrapping_add);
+            check_op!(-=, i64::wrapping_sub);
+            check_op!(*=, i64::wrapping_mul);
+            check_op!(&=, |a, b| a & b);
+            check_op!(|=, |a, b| a | b);
+            check_op!(^=, |a, b| a ^ b);
+        }
+
+        let mut lower = s.clone();
+        lower.lower_half();
+        assert_exact(&lower, value as u32 as i64);
+        for shift in [0, 1, 4, 31, 32, 63] {
+            let mut result = s.clone();
+            result.shl::<64>(shift);
+            assert_exact(&result, value << shift);
+            let mut result = s.clone();
+            result.shr::<64>(shift);
+            assert_exact(&result, (value as u64 >> shift) as i64);
+            let mut result = s.clone();
+            result.ashr::<64>(shift);
+            assert_exact(&result, value >> shift);
+        }
+        for shift in [0, 1, 4, 31] {
+            let mut result = s.clone();
+            result.ashr::<32>(shift);
+            assert_exact(&result, ((value as i32) >> shift) as u32 as i64);
+        }
+    }
+}
+
+#[test]
+pub fn test_byte_swap() {
+    let mut s = Scalar::constant64(0x0304_0102);
+    s.host_to_be(16);
+    assert_eq!(s.value64(), Some((0x0102u16).to_be() as u64));
+
+    for width in [16, 32] {
+        let mut s = Scalar::unknown();
+        s.host_to_be(width);
+        assert_eq!(s.unsigned_range(), 0..=(u64::MAX >> (64 - width)));
+        let mut s = Scalar::unknown();
+        s.host_to_le(width);
+        assert_eq!(s.unsigned_range(), 0..=(u64::MAX >> (64 - width)));
+    }
+
+    // Already in the target endianness
+    let mut s = Scalar::from_tnum(0xF0, 0x100);
+    if cfg!(target_endian = "big") {
+        s.host_to_be(16);
+    } else {
+        s.host_to_le(16);
+    }
+    assert_eq!(s.unsigned_range(), 0x100..=0x1F0);
+}
+
+#[test]
+fn test_branchless_select() {
+    let range = |min: i64, max: i64| {
+        let mut s = Scalar::unknown();
+        s.irange = RangePair::new(min, max);
+        s.sync_bounds();
+        s
+    };
+    for _ in 0..10000 {
+        let (a_min, b_min): (i64, i64) = (
+            thread_rng().gen_range(-64..64),
+            thread_rng().gen_range(-64..64),
+        );
+        let (a_max, b_max) = (
+            a_min + thread_rng().gen_range(0..64),
+            b_min + thread_rng().gen_range(0..64),
+        );
+        let (a, b) = (range(a_min, a_max), range(b_min, b_max));
+        let (x, y) = (
+            thread_rng().gen_range(a_min..=a_max),
+            thread_rng().gen_range(b_min..=b_max),
+        );
+
+        // mask = cond - 1; result = (mask & a) | (~mask & b)
+        let mut mask = range(0, 1);
+        mask -= &Scalar::constant64(1);
+        let mut result = mask.clone();
+        result &= &a;
+        let mut other = mask;
+        other ^= &Scalar::constant64(u64::MAX);
+        other &= &b;
+        result |= &other;
+        assert!(result.contains(x), "{a:?}, {b:?}: {result:?}");
+        assert!(result.contains(y), "{a:?}, {b:?}: {result:?}");
+
+        // min(a, b) = b ^ ((a ^ b) & ((a - b) s>> 63))
+        let mut mask = a.clone();
+        mask -= &b;
+        ShiftAssign::<64, &Scalar>::ashr_assign(&mut mask, &Scalar::constant64(63));
+        let mut min = a.clone();
+        min ^= &b;
+        min &= &mask;
+        min ^= &b;
+        assert!(min.contains(x.min(y)), "min({a:?}, {b:?}): {min:?}");
+    }
+
+    // Non-negative candidates stay bounded
+    let mut mask = range(0, 1);
+    mask -= &Scalar::constant64(1);
+    let mut result = mask.clone();
+    result &= &Scalar::constant64(3);
+    mask ^= &Scalar::constant64(u64::MAX);
+    mask &= &Scalar::constant64(10);
+    result |= &mask;
+    assert_eq!(result.unsigned_range(), 0..=11);
+    assert_eq!(result.signed_range(), 0..=11);
+}
@@ -61,6 +61,12 @@ impl NumBits {
         Self { mask, value: value & !mask }
     }
 
+    /// Tells whether every value matching `self` also matches `other`
+    pub fn is_subset_of(self, other: Self) -> bool {
+        let known = !other.mask;
+        (self.mask & known) == 0 && (self.value & known) == (other.value & known)
+    }
+
     /// Tells whether the bits may match the value
     pub fn contains(self, value: u64) -> bool {
         let known = !self.mask;
@@ -98,6 +104,12 @@ impl NumBits {
         }
     }
 
+    /// Returns bits matching all values matched by either `self` or `rhs`
+    pub fn union(self, rhs: Self) -> Self {
+        let mask = self.mask | rhs.mask | (self.value ^ rhs.value);
+        Self::pruned(mask, self.value)
+    }
+
     /// Casts to the least significant `bytes`
     pub fn cast(self, bytes: u8) -> Self {
         let mask =  (1u64 << (bytes * 8)) - 1;
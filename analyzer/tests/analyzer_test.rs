@@ -1,20 +1,32 @@
+use std::{
+    cell::{Cell, RefCell},
+    ops::RangeInclusive,
+};
+
 use ebpf_analyzer::{
     analyzer::{Analyzer, AnalyzerConfig, MapInfo, VerificationError},
+    blocks::ProgramInfo,
+    branch::context::PruneReason,
+    spec::{features::FeatureSet, line_info::LineInfo, program_type::ProgramType, Instruction},
     branch::{checked_value::CheckedValue, vm::BranchState},
-    interpreter::vm::Vm,
+    interpreter::{context::AccessObserver, vm::Vm},
     spec::proto::{
         ArgumentType, IllegalFunctionCall, ResourceOperation, ReturnType, StaticFunctionCall,
         VerifiableCall,
     },
     track::{
-        pointees::{dyn_region::DynamicRegion, pointed, struct_region::StructRegion},
+        id::Id,
+        pointees::{
+            dyn_region::DynamicRegion, packet_region::PacketRegion, pointed,
+            simple_resource::SimpleResource, struct_region::StructRegion,
+        },
         pointer::{Pointer, PointerAttributes},
         scalar::Scalar,
         TrackedValue,
     },
 };
-use ebpf_consts::maps::MapType;
-use llvm_util::parse_llvm_dump;
+use ebpf_consts::{maps::MapType, programs::ProgType, *};
+use llvm_util::{elf, parse_llvm_dump};
 
 struct AssertFunc;
 
@@ -40,6 +52,17 @@ impl VerifiableCall<CheckedValue, BranchState> for AsIsFunc {
     }
 }
 
+/// Allocates resource 1, which, unlike [ReturnType::AllocatedResource], is never null
+struct NewResourceFunc;
+
+impl VerifiableCall<CheckedValue, BranchState> for NewResourceFunc {
+    fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+        let resource = pointed(SimpleResource::new(1));
+        vm.add_allocated_resource(resource.clone());
+        Ok(Pointer::nrw(resource).into())
+    }
+}
+
 const HELPERS: AnalyzerConfig = AnalyzerConfig {
     processed_instruction_limit: 40_000_000,
     helpers: &[
@@ -102,6 +125,8 @@ const HELPERS: AnalyzerConfig = AnalyzerConfig {
             ],
             ReturnType::None,
         ),
+        // (7) allocates resource 1, never null
+        &NewResourceFunc {},
     ],
     setup: &|vm| {
         let region = pointed(DynamicRegion::default());
@@ -124,7 +149,7 @@ const HELPERS: AnalyzerConfig = AnalyzerConfig {
         )
         .into();
     },
-    map_fd_collector: &|_| None,
+    ..AnalyzerConfig::DEFAULT
 };
 
 macro_rules! define_test {
@@ -178,6 +203,88 @@ define_test!(
     { std::println!("Captured: {branch:?}") }
 );
 
+#[test]
+fn test_conditional_free() {
+    let config = AnalyzerConfig {
+        helpers: HELPERS.helpers,
+        setup: &|vm| *vm.reg(1) = Scalar::unknown().into(),
+        ..Default::default()
+    };
+    let call = |helper| Instruction::pack(BPF_JMP_CALL, BPF_CALL_HELPER, 0, 0, helper);
+    let mov = |dst, src| Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, src, dst, 0, 0);
+    let ret = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0);
+    let exit = BPF_JMP_EXIT as u64;
+
+    let leaking = [
+        // r6 = r1; r0 = new_resource()
+        mov(6, 1),
+        call(7),
+        // if r6 == 0 goto +2
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 6, 2, 0),
+        // del_resource(r0)
+        mov(1, 0),
+        call(5),
+        ret,
+        exit,
+    ];
+    match Analyzer::analyze(&leaking, &config) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert!(branch.borrow_mut().messages()[0].contains("Resource not cleaned up"));
+        }
+        _ => panic!("the leak is not detected"),
+    }
+
+    let freeing = [
+        // r6 = r1; r7 = new_resource()
+        mov(6, 1),
+        call(7),
+        mov(7, 0),
+        // if r6 == 0 goto +5
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 6, 5, 0),
+        // use_resource(r7); del_resource(r7); goto +2
+        mov(1, 7),
+        call(4),
+        mov(1, 7),
+        call(5),
+        Instruction::pack(BPF_JMP | BPF_JA, 0, 0, 2, 0),
+        // del_resource(r7)
+        mov(1, 7),
+        call(5),
+        ret,
+        exit,
+    ];
+    assert!(Analyzer::analyze(&freeing, &config).is_ok());
+}
+
+#[test]
+fn test_deterministic_debug() {
+    let config = AnalyzerConfig {
+        helpers: HELPERS.helpers,
+        setup: &|vm| *vm.reg(1) = Scalar::unknown().into(),
+        ..Default::default()
+    };
+    let call = |helper| Instruction::pack(BPF_JMP_CALL, BPF_CALL_HELPER, 0, 0, helper);
+    let code = [
+        // r6 = new_resource(); r7 = new_resource()
+        call(7),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 6, 0, 0),
+        call(7),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 7, 0, 0),
+        // if r1 == 0 goto +0, cloning the state
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 1, 0, 0),
+        // Leaking both
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let dump = || match Analyzer::analyze(&code, &config) {
+        Err(VerificationError::IllegalStateChange(branch)) => format!("{:?}", branch.borrow()),
+        _ => panic!("the leak is not detected"),
+    };
+    let first = dump();
+    assert!(first.contains("regions: [0, 2, 3]"), "{first}");
+    assert_eq!(first, dump());
+}
+
 define_test!(test_printk, "bpf-src/printk.txt", Ok(_), {});
 define_test!(
     test_printk_fail,
@@ -191,7 +298,6 @@ define_test!(
 
 const MAP_HELPERS: &AnalyzerConfig = &AnalyzerConfig {
     helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
-    setup: &|_| {},
     processed_instruction_limit: 4_000,
     map_fd_collector: &|fd| {
         if (fd >> 16) == 0 {
@@ -200,11 +306,15 @@ const MAP_HELPERS: &AnalyzerConfig = &AnalyzerConfig {
                 max_size: 1,
                 key_size: (fd as u32 >> 8) & 0xff,
                 value_size: fd as u32 & 0xff,
+                readonly: false,
+                initial_value: None,
+                program_type: None,
             })
         } else {
             None
         }
     },
+    ..AnalyzerConfig::DEFAULT
 };
 
 define_test!(
@@ -224,3 +334,2387 @@ define_test!(
     },
     MAP_HELPERS
 );
+
+#[test]
+fn test_map_info() {
+    let load_map =
+        |dst, fd| Instruction::pack(BPF_LD | BPF_IMM | BPF_DW, BPF_IMM64_MAP_FD, dst, 0, fd);
+    let code = [
+        // r1 = map (fd 0x0108); r2 = map (fd 0x0410); r3 = map (fd 0x0108)
+        load_map(1, 0x0108),
+        0,
+        load_map(2, 0x0410),
+        0,
+        load_map(3, 0x0108),
+        0,
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let info = Analyzer::analyze(&code, MAP_HELPERS).unwrap();
+    let sizes: Vec<_> = info
+        .maps
+        .iter()
+        .map(|(fd, map)| (*fd, map.key_size, map.value_size))
+        .collect();
+    assert_eq!(sizes, [(0x0108, 1, 8), (0x0410, 4, 16)]);
+    assert_eq!(info.map_references, [(0, 0x0108), (2, 0x0410), (4, 0x0108)]);
+}
+
+#[test]
+fn test_tail_call_types() {
+    let code = [
+        // r2 = prog_array (fd 1)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 2, 0, 1),
+        0,
+        // tail_call(r1 = ctx, r2, r3 = 0)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, 0),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 12),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |code: &[u64], program_type| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+                program_type: Some(program_type),
+                map_fd_collector: &|_| {
+                    Some(MapInfo {
+                        map_type: MapType::ProgArray,
+                        max_size: 4,
+                        key_size: 4,
+                        value_size: 4,
+                        readonly: false,
+                        initial_value: None,
+                        program_type: Some(ProgType::Xdp),
+                    })
+                },
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(&code, ProgramType::Xdp).is_ok());
+    match analyze(&code, ProgramType::Kprobe) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert!(branch.borrow_mut().messages()[0].contains("Function call failed"));
+        }
+        _ => panic!("tail call into another program type accepted"),
+    }
+    // Only tail calls are checked, not other uses of the map
+    let mut passed = code;
+    passed[4] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 2, 1, 0, 0);
+    assert!(analyze(&passed, ProgramType::Kprobe).is_ok());
+}
+
+#[test]
+fn test_readonly_map() {
+    let code = [
+        // r1 = map (fd 1)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        // *(u32 *)(r10 - 4) = 0
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 10, -4, 0),
+        // r0 = map_lookup_elem(r1, r2 = r10 - 4)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        // if r0 == 0 goto +2
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 2, 0),
+        // r1 = *(u64 *)(r0 + 0)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 0, 1, 0, 0),
+        // *(u64 *)(r0 + 0) = r1
+        Instruction::pack(BPF_STX | BPF_MEM | BPF_DW, 1, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |code: &[u64], readonly| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+                map_fd_collector: &|_| {
+                    Some(MapInfo {
+                        map_type: MapType::Array,
+                        max_size: 1,
+                        key_size: 4,
+                        value_size: 8,
+                        readonly,
+                        initial_value: None,
+                        program_type: None,
+                    })
+                },
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(&code, false).is_ok());
+    match analyze(&code, true) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert!(branch.borrow_mut().messages()[0].contains("Illegal access"));
+        }
+        _ => panic!("writes to read-only map values should be rejected"),
+    }
+
+    // Reading alone is fine
+    let mut read_only = code;
+    read_only[8] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 0);
+    assert!(analyze(&read_only, true).is_ok());
+}
+
+#[test]
+fn test_signed_division_index() {
+    let code = [
+        // r1 = map (fd 1)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        // r0 = map_lookup_elem(r1, r2 = r10 - 4)
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 10, -4, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        // if r0 == 0 goto out
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 6, 0),
+        // r6 s/= 3
+        Instruction::pack(BPF_ALU64 | BPF_DIV | BPF_K, 0, 6, 1, 3),
+        // if r6 s< 0 goto out
+        Instruction::pack(BPF_JMP | BPF_JSLT | BPF_K, 0, 6, 4, 0),
+        // if r6 s>= 8 goto out
+        Instruction::pack(BPF_JMP | BPF_JSGE | BPF_K, 0, 6, 3, 8),
+        // r1 = *(u64 *)(r0 + r6 * 8)
+        Instruction::pack(BPF_ALU64 | BPF_LSH | BPF_K, 0, 6, 0, 3),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 6, 0, 0, 0),
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 0, 1, 0, 0),
+        // out:
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let config = AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        setup: &|vm| *vm.reg(6) = Scalar::unknown().into(),
+        map_fd_collector: &|_| {
+            Some(MapInfo {
+                map_type: MapType::Array,
+                max_size: 1,
+                key_size: 4,
+                value_size: 64,
+                readonly: false,
+                initial_value: None,
+                program_type: None,
+            })
+        },
+        ..Default::default()
+    };
+    assert!(Analyzer::analyze(&code, &config).is_ok());
+
+    // Either bound alone is not enough
+    let nop = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 0);
+    for i in [8, 9] {
+        let mut unbounded = code;
+        unbounded[i] = nop;
+        assert!(Analyzer::analyze(&unbounded, &config).is_err(), "{i}");
+    }
+}
+
+#[test]
+fn test_rodata_constants() {
+    let code = [
+        // r1 = &map_value(fd 1) + 4
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_VALUE, 1, 0, 1),
+        4 << 32,
+        // r0 = *(u32 *)(r1 + 0)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 1, 0, 0, 0),
+        // if r0 == 7 goto +1
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1, 7),
+        // r0 = *(u64 *)(r10 - 8), reading uninitialized stack
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 10, 0, -8, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |readonly, initial_value: Option<[u8; 8]>| {
+        Analyzer::analyze(
+            &code,
+            &AnalyzerConfig {
+                map_fd_collector: &|_| {
+                    Some(MapInfo {
+                        map_type: MapType::Array,
+                        max_size: 1,
+                        key_size: 4,
+                        value_size: 8,
+                        readonly,
+                        initial_value: initial_value.map(|value| value.to_vec()),
+                        program_type: None,
+                    })
+                },
+                ..Default::default()
+            },
+        )
+    };
+    let mut rodata = [0; 8];
+    rodata[4..].copy_from_slice(&7u32.to_ne_bytes());
+    assert!(analyze(true, Some(rodata)).is_ok());
+    // The value is unknown without initial contents, or if it may change
+    assert!(analyze(true, None).is_err());
+    assert!(analyze(false, Some(rodata)).is_err());
+    // The other branch gets explored
+    assert!(analyze(true, Some([0; 8])).is_err());
+}
+
+#[test]
+fn test_expected_return() {
+    let code = [
+        // r0 = get_prandom_u32()
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+        // if r0 > 4 goto +1
+        Instruction::pack(BPF_JMP | BPF_JGT | BPF_K, 0, 0, 1, 4),
+        BPF_JMP_EXIT as u64,
+        // r0 = 99
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 99),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |code: &[u64], expected_return| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+                expected_return,
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(&code, None).is_ok());
+    assert!(matches!(
+        analyze(&code, Some(0..=4)),
+        Err(VerificationError::InvalidReturnValue(_))
+    ));
+    assert!(analyze(&code, Some(0..=99)).is_ok());
+    // r0 = 4 instead
+    let mut fixed = code;
+    fixed[3] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 4);
+    assert!(analyze(&fixed, Some(0..=4)).is_ok());
+}
+
+#[test]
+fn test_program_type_defaults() {
+    let code = [
+        // r0 = *(u32 *)(r1 + 12), reading xdp_md->ingress_ifindex
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 1, 0, 12, 0),
+        // if r0 > 4 goto +1
+        Instruction::pack(BPF_JMP | BPF_JGT | BPF_K, 0, 0, 1, 4),
+        BPF_JMP_EXIT as u64,
+        // r0 = 99
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 99),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |code: &[u64]| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                program_type: Some(ProgramType::Xdp),
+                ..Default::default()
+            },
+        )
+    };
+    assert!(matches!(
+        analyze(&code),
+        Err(VerificationError::InvalidReturnValue(_))
+    ));
+    let mut fixed = code;
+    fixed[3] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 2);
+    assert!(analyze(&fixed).is_ok());
+    // The context is read-only
+    fixed[0] = Instruction::pack(BPF_STX | BPF_MEM | BPF_W, 0, 1, 12, 0);
+    assert!(analyze(&fixed).is_err());
+    // Reads out of the context
+    fixed[0] = Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 1, 0, 24, 0);
+    assert!(analyze(&fixed).is_err());
+    // Without the program type, r1 is never initialized
+    assert!(Analyzer::analyze(&code, &AnalyzerConfig::default()).is_err());
+}
+
+#[test]
+fn test_allowed_features() {
+    let code = [
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        // if w0 == 1 goto +0
+        Instruction::pack(BPF_JMP32 | BPF_JEQ | BPF_K, 0, 0, 0, 1),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |features| {
+        Analyzer::analyze(
+            &code,
+            &AnalyzerConfig {
+                program_type: Some(ProgramType::Raw),
+                features,
+                ..Default::default()
+            },
+        )
+    };
+    // Program types allow all extensions
+    assert!(analyze(None).is_ok());
+    assert!(analyze(Some(FeatureSet::JMP32)).is_ok());
+    let Err(err) = analyze(Some(FeatureSet::ALU32)) else {
+        panic!("JMP32 instructions accepted");
+    };
+    assert_eq!(err.pc(), Some(1));
+    assert!(matches!(
+        err,
+        VerificationError::UnsupportedFeatures((_, features)) if features == FeatureSet::JMP32
+    ));
+}
+
+#[test]
+fn test_program_type_context() {
+    let analyze = |code: &[u64], program_type| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                program_type: Some(program_type),
+                expected_return: Some(i64::MIN..=i64::MAX),
+                ..Default::default()
+            },
+        )
+    };
+
+    let xdp = |checked: i32, read: i16| {
+        [
+            // r2 = xdp_md->data, r3 = xdp_md->data_end
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 1, 2, 0, 0),
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 1, 3, 4, 0),
+            // if data + checked > data_end goto drop
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 2, 4, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 4, 0, checked),
+            Instruction::pack(BPF_JMP | BPF_JGT | BPF_X, 3, 4, 2, 0),
+            // r0 = *(u8 *) (data + read)
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_B, 2, 0, read, 0),
+            BPF_JMP_EXIT as u64,
+            // drop: r0 = 1
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 1),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    assert!(analyze(&xdp(14, 13), ProgramType::Xdp).is_ok());
+    assert!(analyze(&xdp(14, 14), ProgramType::Xdp).is_err());
+    // Packet accesses need not be aligned, e.g., reading the IPv4 source address
+    let mut saddr = xdp(30, 26);
+    saddr[5] = Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 2, 0, 26, 0);
+    assert!(analyze(&saddr, ProgramType::Xdp).is_ok());
+    saddr[5] = Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 2, 0, 27, 0);
+    assert!(analyze(&saddr, ProgramType::Xdp).is_err());
+    // Without a packet, r1 is unknown to raw tracepoint programs
+    assert!(analyze(&xdp(14, 13), ProgramType::Raw).is_err());
+
+    let raw = [
+        // r0 = r1 & 1
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 0, 0, 1),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(analyze(&raw, ProgramType::Raw).is_ok());
+    assert!(analyze(&raw, ProgramType::Kprobe).is_err());
+}
+
+#[test]
+fn test_program_type_helpers() {
+    let code = [
+        // r0 = get_current_pid_tgid(), only available to tracing programs
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 14),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |program_type| {
+        Analyzer::analyze(
+            &code,
+            &AnalyzerConfig {
+                program_type: Some(program_type),
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(ProgramType::Kprobe).is_ok());
+    assert!(analyze(ProgramType::Raw).is_ok());
+    for program_type in [ProgramType::Xdp, ProgramType::SocketFilter, ProgramType::SkbTc] {
+        assert!(matches!(
+            analyze(program_type),
+            Err(VerificationError::IllegalStateChange(_))
+        ));
+    }
+}
+
+type Access = (&'static str, Id, RangeInclusive<i64>, usize);
+
+#[derive(Default)]
+struct RecordingObserver(RefCell<Vec<Access>>);
+
+impl AccessObserver for RecordingObserver {
+    fn on_load(&self, region: Id, offset: RangeInclusive<i64>, size: usize) {
+        self.0.borrow_mut().push(("load", region, offset, size));
+    }
+
+    fn on_store(&self, region: Id, offset: RangeInclusive<i64>, size: usize) {
+        self.0.borrow_mut().push(("store", region, offset, size));
+    }
+}
+
+#[test]
+fn test_access_observer() {
+    let code = [
+        // r2 = 7
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 7),
+        // *(u32 *)(r10 - 4) = r2
+        Instruction::pack(BPF_STX | BPF_MEM | BPF_W, 2, 10, -4, 0),
+        // r0 = *(u32 *)(r10 - 4)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 10, 0, -4, 0),
+        // *(u8 *)(r1 + 2) = 1
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_B, 0, 1, 2, 1),
+        BPF_JMP_EXIT as u64,
+    ];
+    let context_id = Cell::new(0);
+    let observer = RecordingObserver::default();
+    assert!(Analyzer::analyze(
+        &code,
+        &AnalyzerConfig {
+            setup: &|vm| {
+                let context = pointed(DynamicRegion::new(16));
+                vm.add_external_resource(context.clone());
+                context_id.set(context.borrow().get_id());
+                *vm.reg(1) = Pointer::nrwa(context).into();
+            },
+            access_observer: Some(&observer),
+            ..Default::default()
+        },
+    )
+    .is_ok());
+    // The frame pointer points to the end of the 512-byte stack (region 1)
+    assert_eq!(
+        observer.0.into_inner(),
+        vec![
+            ("store", 1, 508..=508, 4),
+            ("load", 1, 508..=508, 4),
+            ("store", context_id.get(), 2..=2, 1),
+        ]
+    );
+}
+
+#[test]
+fn test_and_narrowed_map_index() {
+    let code = |mask| {
+        [
+            // r6 = get_prandom_u32() & mask
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 6, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 6, 0, mask),
+            // r0 = map_lookup_elem(fd 1, &(u32) 0)
+            Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 10, -4, 0),
+            Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+            0,
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+            Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 3, 0),
+            // r0 = ((u32 *) r0)[r6]
+            Instruction::pack(BPF_ALU64 | BPF_LSH | BPF_K, 0, 6, 0, 2),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 6, 0, 0, 0),
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 0, 0, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let analyze = |code: &[u64]| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+                map_fd_collector: &|_| {
+                    // A map value holding `u32[4]`
+                    Some(MapInfo {
+                        map_type: MapType::Array,
+                        max_size: 1,
+                        key_size: 4,
+                        value_size: 16,
+                        readonly: false,
+                        initial_value: None,
+                        program_type: None,
+                    })
+                },
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(&code(0x3)).is_ok());
+    match analyze(&code(0x7)) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert_eq!(branch.borrow().messages()[0], "Illegal access");
+        }
+        _ => panic!("r6 & 0x7 may index out of the map value"),
+    }
+}
+
+#[test]
+fn test_kfunc_resolver() {
+    // kfunc 42: (scalar) -> scalar
+    let resolved = core::cell::RefCell::new(Vec::new());
+    let resolver = |id| {
+        resolved.borrow_mut().push(id);
+        if id == 42 {
+            Some(StaticFunctionCall::new(
+                [
+                    ArgumentType::Scalar,
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                ],
+                ReturnType::Scalar,
+            ))
+        } else {
+            None
+        }
+    };
+    let analyze = |code: &[u64]| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                kfunc_resolver: Some(&resolver),
+                ..Default::default()
+            },
+        )
+    };
+    let code = |id, arg| {
+        [
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, arg, 0, 1),
+            Instruction::pack(BPF_JMP_CALL, BPF_CALL_KFUNC, 0, 0, id),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    assert!(analyze(&code(42, 1)).is_ok());
+    // r1 not initialized
+    assert!(matches!(
+        analyze(&code(42, 2)),
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+    match analyze(&code(43, 1)) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert_eq!(branch.borrow().messages(), ["Kfunc not available"]);
+        }
+        _ => panic!("kfunc 43 is not resolved"),
+    }
+
+    // Resolved once when first called, on either branch
+    resolved.borrow_mut().clear();
+    let branching = [
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 1),
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_KFUNC, 0, 0, 42),
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 2, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 1),
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_KFUNC, 0, 0, 42),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(analyze(&branching).is_ok());
+    assert_eq!(*resolved.borrow(), [42]);
+    // and never if the call is never reached
+    resolved.borrow_mut().clear();
+    let unreachable = [
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1, 0),
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_KFUNC, 0, 0, 43),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(analyze(&unreachable).is_ok());
+    assert!(resolved.borrow().is_empty());
+}
+
+#[test]
+fn test_static_kfuncs() {
+    let code = |id| {
+        [
+            Instruction::pack(BPF_JMP_CALL, BPF_CALL_KFUNC, 0, 0, id),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let analyze = |code: &[u64]| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                kfuncs: &[
+                    ebpf_analyzer::spec::proto::helpers::BPF_HELPER_INVALID,
+                    ebpf_analyzer::spec::proto::helpers::BPF_HELPER_GET_SCALAR,
+                ],
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(&code(1)).is_ok());
+    assert!(matches!(
+        analyze(&code(0)),
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+    match analyze(&code(2)) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert_eq!(branch.borrow().messages(), ["Kfunc not available"]);
+        }
+        _ => panic!("kfunc 2 is not registered"),
+    }
+}
+
+#[test]
+fn test_caller_stack_pointer() {
+    let code = |off, store: bool| {
+        let store = |value| {
+            if store {
+                Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 6, off, value)
+            } else {
+                Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, value)
+            }
+        };
+        [
+            // r1 = r10 - 8
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -8),
+            Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 3),
+            // return *(u64 *) (r10 - 8)
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 10, 0, -8, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+            BPF_JMP_EXIT as u64,
+            // subprog: *(u64 *) (r1 + off) = 1 or 2
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 6, 0, 0),
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 2, 0),
+            store(1),
+            BPF_JMP_EXIT as u64,
+            store(2),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let analyze = |code: &[u64]| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(&code(0, true)).is_ok());
+    // Beyond the caller frame
+    assert!(matches!(
+        analyze(&code(8, true)),
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+    // The caller stack slot is left uninitialized
+    assert!(matches!(
+        analyze(&code(0, false)),
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+}
+
+#[test]
+fn test_max_stack_depth() {
+    let store = |off| Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, off, 0);
+    let ret = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0);
+    let exit = BPF_JMP_EXIT as u64;
+    let config = AnalyzerConfig::default();
+
+    // *(u64 *) (r10 - 8) = 0
+    let info = Analyzer::analyze(&[store(-8), ret, exit], &config).unwrap();
+    assert_eq!(info.max_stack_depth, [8]);
+
+    let code = |main_off, sub_off| {
+        [
+            store(main_off),
+            Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 2),
+            ret,
+            exit,
+            // subprog
+            store(sub_off),
+            ret,
+            exit,
+        ]
+    };
+    let info = Analyzer::analyze(&code(-8, -16), &config).unwrap();
+    assert_eq!(info.max_stack_depth, [8, 16]);
+    // Frames along the call chain are limited to 512 bytes in total
+    assert!(Analyzer::analyze(&code(-256, -256), &config).is_ok());
+    assert!(matches!(
+        Analyzer::analyze(&code(-512, -8), &config),
+        Err(VerificationError::StackTooDeep(chain)) if chain == [0, 1]
+    ));
+    // Even if the caller only writes deeper after the call returns
+    let code = [
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 3),
+        store(-512),
+        ret,
+        exit,
+        // subprog
+        store(-8),
+        ret,
+        exit,
+    ];
+    assert!(matches!(
+        Analyzer::analyze(&code, &config),
+        Err(VerificationError::StackTooDeep(chain)) if chain == [0, 1]
+    ));
+}
+
+#[test]
+fn test_cmpxchg_spinlock() {
+    let code = |bounded: bool| {
+        let mut code = vec![
+            // *(u64 *) (r10 - 8) = prandom(): the lock, in an unknown state
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_STX | BPF_MEM | BPF_DW, 0, 10, -8, 0),
+            // r6 = 0
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 6, 0, 0),
+            // retry: r0 = 0; r1 = 1
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 1),
+            // r0 = cmpxchg_64(r10 - 8, r0, r1)
+            Instruction::pack(
+                BPF_STX | BPF_ATOMIC | BPF_DW,
+                1,
+                10,
+                -8,
+                BPF_ATOMIC_CMPXCHG,
+            ),
+            // if r0 == 0 goto acquired
+            Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 4, 0),
+            // r6 += 1; if r6 < 8 goto retry
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 6, 0, 1),
+            Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 6, -6, 8),
+            // return 1
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 1),
+            BPF_JMP_EXIT as u64,
+            // acquired: *(u64 *) (r10 - 8) = 0; return 0
+            Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -8, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        if !bounded {
+            // Spins without a bound: if r0 != 0 goto retry
+            code[8] = Instruction::pack(BPF_JMP | BPF_JNE | BPF_K, 0, 0, -6, 0);
+        }
+        code
+    };
+    let config = AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        processed_instruction_limit: 10_000,
+        ..Default::default()
+    };
+    let result = Analyzer::analyze(&code(true), &config);
+    assert!(result.is_ok(), "{:?}", result.err());
+    assert!(matches!(
+        Analyzer::analyze(&code(false), &config),
+        Err(VerificationError::IllegalContext(_))
+    ));
+}
+
+#[test]
+fn test_dynamic_packet_length() {
+    let code = |guarded: bool| {
+        let (success, failure) = if guarded {
+            (
+                Instruction::pack(BPF_LDX | BPF_MEM | BPF_B, 8, 0, -1, 0),
+                Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            )
+        } else {
+            (
+                Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+                Instruction::pack(BPF_LDX | BPF_MEM | BPF_B, 8, 0, -1, 0),
+            )
+        };
+        [
+            // r6 = data, r7 = data_end
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 1, 6, 0, 0),
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 1, 7, 8, 0),
+            // len = (random & 0xF) + 1
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 0, 0, 0xF),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+            // if data + len > data_end goto failure
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 8, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 0, 8, 0, 0),
+            Instruction::pack(BPF_JMP | BPF_JGT | BPF_X, 7, 8, 2, 0),
+            // r0 = *(u8 *) (data + len - 1)
+            success,
+            BPF_JMP_EXIT as u64,
+            failure,
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let analyze = |code: &[u64]| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+                ..HELPERS
+            },
+        )
+    };
+    assert!(analyze(&code(true)).is_ok());
+    assert!(matches!(
+        analyze(&code(false)),
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+
+    // Packet data need not be aligned: r0 = *(u32 *) (data + len - 4), with len >= 4
+    let mut unaligned = code(true);
+    unaligned[4] = Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 4);
+    unaligned[8] = Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 8, 0, -4, 0);
+    assert!(analyze(&unaligned).is_ok());
+}
+
+#[test]
+fn test_packet_bounds_check() {
+    const PACKET: AnalyzerConfig = AnalyzerConfig {
+        setup: &|vm| {
+            let packet = pointed(PacketRegion::default());
+            vm.add_external_resource(packet.clone());
+            let data = Pointer::new(
+                PointerAttributes::NON_NULL
+                    | PointerAttributes::ARITHMETIC
+                    | PointerAttributes::READABLE,
+                packet.clone(),
+            );
+            let context = pointed(StructRegion::new(
+                vec![data, Pointer::end(packet)],
+                &[1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+            ));
+            vm.add_external_resource(context.clone());
+            *vm.reg(1) = Pointer::new(
+                PointerAttributes::NON_NULL | PointerAttributes::READABLE,
+                context,
+            )
+            .into();
+        },
+        ..HELPERS
+    };
+    let code = |checked: i32, read: i16| {
+        [
+            // r2 = data, r3 = data_end
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 1, 2, 0, 0),
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 1, 3, 8, 0),
+            // if data + checked > data_end goto drop
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 2, 4, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 4, 0, checked),
+            Instruction::pack(BPF_JMP | BPF_JGT | BPF_X, 3, 4, 2, 0),
+            // r0 = *(u16 *) (data + read)
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_H, 2, 0, read, 0),
+            BPF_JMP_EXIT as u64,
+            // drop: r0 = 0
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    // The ether type of an Ethernet header
+    assert!(Analyzer::analyze(&code(14, 12), &PACKET).is_ok());
+    assert!(matches!(
+        Analyzer::analyze(&code(14, 13), &PACKET),
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+    assert!(matches!(
+        Analyzer::analyze(&code(0, 0), &PACKET),
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+}
+
+#[test]
+fn test_verify_report() {
+    let code = [
+        // if r1 == 0 goto +1
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 1, 1, 0),
+        // r1 = r6
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let parsed = ProgramInfo::new(&code).unwrap();
+    let config = AnalyzerConfig {
+        setup: &|vm| *vm.reg(1) = Scalar::unknown().into(),
+        ..Default::default()
+    };
+    // Reading the uninitialized r6
+    let report = Analyzer::verify(&code, &config).unwrap();
+    assert!(matches!(
+        report.result,
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+    assert_eq!(report.info.functions.len(), parsed.functions.len());
+    for (reported, parsed) in report.info.functions.iter().zip(&parsed.functions) {
+        assert_eq!(reported.block_starts, parsed.block_starts);
+        assert_eq!(reported.from, parsed.from);
+        assert_eq!(reported.to, parsed.to);
+    }
+    assert_eq!(report.info.map_references, parsed.map_references);
+    assert_eq!(report.info.kfuncs, parsed.kfuncs);
+
+    // Unparsable code
+    assert!(Analyzer::verify(&code[..3], &config).is_err());
+}
+
+#[test]
+fn test_exit_register_ranges() {
+    let code = [
+        // r0 = get_prandom_u32()
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+        // if r0 == 0 goto +2
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 2, 0),
+        // r0 = 1
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 1),
+        BPF_JMP_EXIT as u64,
+        BPF_JMP_EXIT as u64,
+    ];
+    let config = AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        ..Default::default()
+    };
+    let report = Analyzer::verify(&code, &config).unwrap();
+    assert!(report.result.is_ok());
+    assert_eq!(report.exit_register_ranges[0], Some((0, 1)));
+    // Clobbered by the call, uninitialized or pointers
+    assert_eq!(report.exit_register_ranges[1], None);
+    assert_eq!(report.exit_register_ranges[6], None);
+    assert_eq!(report.exit_register_ranges[10], None);
+}
+
+#[test]
+fn test_pointer_subtraction() {
+    let code = [
+        // r0 = get_prandom_u32() & 7
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+        Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 0, 0, 7),
+        // r1 = r10 - 16
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -16),
+        // r2 = r10 - 32 + r0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -32),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 0, 2, 0, 0),
+        // r0 = r1 - r2
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_SUB | BPF_X, 2, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let config = AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        ..Default::default()
+    };
+    let report = Analyzer::verify(&code, &config).unwrap();
+    assert!(report.result.is_ok());
+    assert_eq!(report.exit_register_ranges[0], Some((9, 16)));
+
+    let config = AnalyzerConfig {
+        setup: &|vm| {
+            let context = pointed(DynamicRegion::new(16));
+            vm.add_external_resource(context.clone());
+            *vm.reg(1) = Pointer::nrwa(context).into();
+        },
+        ..Default::default()
+    };
+    let invalid = [
+        // r2 = r10 - r1, pointers into different regions
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_SUB | BPF_X, 1, 2, 0, 0),
+        // r2 = 0 - r1, subtracting a pointer from a scalar
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_SUB | BPF_X, 1, 2, 0, 0),
+    ];
+    for pair in invalid.chunks(2) {
+        let code = [
+            pair[0],
+            pair[1],
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        assert!(Analyzer::analyze(&code, &config).is_err());
+    }
+    // r2 = r1 - r1
+    let code = [
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_SUB | BPF_X, 1, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 2, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(Analyzer::analyze(&code, &config).is_ok());
+}
+
+#[test]
+fn test_helper_buffer() {
+    /// Returns a pointer to a 16-byte buffer, starting at offset `BASE`
+    struct BufferFunc<const BASE: i64>;
+
+    impl<const BASE: i64> VerifiableCall<CheckedValue, BranchState> for BufferFunc<BASE> {
+        fn call(&self, vm: &mut BranchState) -> Result<CheckedValue, IllegalFunctionCall> {
+            let mut buffer = DynamicRegion::new(16);
+            buffer.set_base(BASE);
+            let buffer = pointed(buffer);
+            vm.add_external_resource(buffer.clone());
+            Ok(Pointer::nrwa(buffer).into())
+        }
+    }
+
+    const BUFFER_HELPERS: &[&dyn VerifiableCall<CheckedValue, BranchState>] = &[
+        ebpf_analyzer::spec::proto::helpers::BPF_HELPER_INVALID,
+        &BufferFunc::<0>,
+        &BufferFunc::<-16>,
+    ];
+    let config = AnalyzerConfig {
+        helpers: BUFFER_HELPERS,
+        ..Default::default()
+    };
+    // (helper, offset, ok): r0 = helper(); r0 = *(u8 *)(r0 + offset)
+    let accesses = [
+        (1, 0, true),
+        (1, 15, true),
+        (1, 16, false),
+        (1, -1, false),
+        (2, -16, true),
+        (2, -1, true),
+        (2, 0, false),
+        (2, -17, false),
+    ];
+    for (helper, offset, ok) in accesses {
+        let code = [
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, helper),
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_B, 0, 0, offset, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        assert_eq!(
+            Analyzer::analyze(&code, &config).is_ok(),
+            ok,
+            "{helper} {offset}"
+        );
+    }
+}
+
+#[test]
+fn test_misaligned_access() {
+    let config = AnalyzerConfig {
+        setup: &|vm| {
+            let context = pointed(DynamicRegion::new(16));
+            vm.add_external_resource(context.clone());
+            *vm.reg(1) = Pointer::nrwa(context).into();
+            *vm.reg(2) = Scalar::unknown().into();
+        },
+        ..Default::default()
+    };
+    // r1 += imm; r0 = *(u32 *)(r1 + off)
+    let accesses = [
+        (0, 4, true),
+        (0, 1, false),
+        (2, 4, false),
+        (2, 2, true),
+        (-3, 4, false),
+    ];
+    for (imm, off, ok) in accesses {
+        let code = [
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, imm),
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 1, 0, off, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        assert_eq!(Analyzer::analyze(&code, &config).is_ok(), ok, "{imm} {off}");
+    }
+
+    // Variable offsets with known trailing zeros
+    for (mask, ok) in [(0b1100, true), (0b1110, false)] {
+        let code = [
+            // r2 &= mask; r1 += r2
+            Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 2, 0, mask),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 2, 1, 0, 0),
+            // *(u32 *)(r1 + 0) = 0
+            Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 1, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        assert_eq!(Analyzer::analyze(&code, &config).is_ok(), ok, "{mask:b}");
+    }
+
+    // Alignment counts from the start of the region, not from the offset zero
+    let based = AnalyzerConfig {
+        setup: &|vm| {
+            let mut region = DynamicRegion::new(12);
+            region.set_base(-12);
+            let region = pointed(region);
+            vm.add_external_resource(region.clone());
+            *vm.reg(1) = Pointer::nrwa(region).into();
+        },
+        ..Default::default()
+    };
+    for (off, ok) in [(-12, true), (-8, false)] {
+        let code = [
+            // r0 = *(u64 *)(r1 + off)
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 1, 0, off, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        assert_eq!(Analyzer::analyze(&code, &based).is_ok(), ok, "{off}");
+    }
+}
+
+#[test]
+fn test_branchless_select() {
+    let code = [
+        // r0 = r2 & 1
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 2, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 0, 0, 1),
+        // r0 = ((r0 - 1) & 3) | (~(r0 - 1) & 10)
+        Instruction::pack(BPF_ALU64 | BPF_SUB | BPF_K, 0, 0, 0, 1),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 3, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 3, 0, 3),
+        Instruction::pack(BPF_ALU64 | BPF_XOR | BPF_K, 0, 0, 0, -1),
+        Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 0, 0, 10),
+        Instruction::pack(BPF_ALU64 | BPF_OR | BPF_X, 3, 0, 0, 0),
+        // *(u8 *)(r1 + r0 + 4) = 0
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 0, 1, 0, 0),
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_B, 0, 1, 4, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let config = AnalyzerConfig {
+        setup: &|vm| {
+            let context = pointed(DynamicRegion::new(16));
+            vm.add_external_resource(context.clone());
+            *vm.reg(1) = Pointer::nrwa(context).into();
+            *vm.reg(2) = Scalar::unknown().into();
+        },
+        ..Default::default()
+    };
+    let report = Analyzer::verify(&code, &config).unwrap();
+    assert!(report.result.is_ok());
+    // Covering both 3 and 10
+    assert_eq!(report.exit_register_ranges[0], Some((0, 11)));
+}
+
+#[test]
+fn test_state_pruning() {
+    let code = [
+        // r6 = 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 6, 0, 0),
+        // loop: r0 = get_prandom_u32()
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+        // if r0 > 5 goto +1
+        Instruction::pack(BPF_JMP | BPF_JGT | BPF_K, 0, 0, 1, 5),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        // r6 += 1; if r6 < 24 goto loop
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 6, 0, 1),
+        Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 6, -5, 24),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let config = AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        processed_instruction_limit: 10_000,
+        ..Default::default()
+    };
+    // Without pruning, the 2^24 paths exhaust the instruction limit
+    assert!(Analyzer::analyze(&code, &config).is_ok());
+
+    // States are never pruned against those they are forked from
+    let unbounded = [
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+        // if r0 > 5 goto -2
+        Instruction::pack(BPF_JMP | BPF_JGT | BPF_K, 0, 0, -2, 5),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(matches!(
+        Analyzer::analyze(&unbounded, &config),
+        Err(VerificationError::IllegalContext(_))
+    ));
+}
+
+#[test]
+fn test_pruned_branches() {
+    let code = [
+        // r0 = 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        // if r0 s< 0 goto +1
+        Instruction::pack(BPF_JMP | BPF_JSLT | BPF_K, 0, 0, 1, 0),
+        BPF_JMP_EXIT as u64,
+        // Unreachable: r0 = r6
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let report = Analyzer::verify(&code, &AnalyzerConfig::default()).unwrap();
+    assert!(report.result.is_ok());
+    assert_eq!(report.pruned_branches, [PruneReason::Contradiction(1)]);
+
+    // The loop from test_state_pruning
+    let code = [
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 6, 0, 0),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+        Instruction::pack(BPF_JMP | BPF_JGT | BPF_K, 0, 0, 1, 5),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 6, 0, 1),
+        Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 6, -5, 24),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let config = AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        ..Default::default()
+    };
+    let report = Analyzer::verify(&code, &config).unwrap();
+    assert!(report.result.is_ok());
+    assert!(report
+        .pruned_branches
+        .iter()
+        .any(|reason| matches!(reason, PruneReason::Subsumed(_))));
+}
+
+#[test]
+fn test_func_pointer() {
+    const CALLBACK: AnalyzerConfig = AnalyzerConfig {
+        helpers: &[
+            // (0) reserved
+            &StaticFunctionCall::new(
+                [
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                ],
+                ReturnType::None,
+            ),
+            // (1) takes a callback
+            &StaticFunctionCall::new(
+                [
+                    ArgumentType::Callback,
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                    ArgumentType::Any,
+                ],
+                ReturnType::Scalar,
+            ),
+        ],
+        ..HELPERS
+    };
+    let code = |usage: u64| {
+        [
+            // r1 = callback
+            Instruction::pack(BPF_LD | BPF_IMM | BPF_DW, BPF_IMM64_FUNC, 1, 0, 4),
+            0,
+            usage,
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+            // callback
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let analyze = |usage| Analyzer::analyze(&code(usage), &CALLBACK);
+    assert!(analyze(Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1)).is_ok());
+    for usage in [
+        // Dereferencing
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 1, 0, 0, 0),
+        // Arithmetic
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, 8),
+        // Comparison
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 1, 0, 0),
+    ] {
+        assert!(matches!(
+            analyze(usage),
+            Err(VerificationError::IllegalStateChange(_))
+        ));
+    }
+    // Not a function pointer
+    let mut code = code(Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1));
+    code[0] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0);
+    code[1] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0);
+    assert!(Analyzer::analyze(&code[..5], &CALLBACK).is_err());
+}
+
+#[test]
+fn test_for_each_map_elem() {
+    use ebpf_analyzer::spec::proto::helpers::{BPF_HELPER_FOR_EACH_MAP_ELEM, BPF_HELPER_INVALID};
+    let code = [
+        // r1 = map (fd 1)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        // r2 = callback
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_FUNC, 2, 0, 6),
+        0,
+        // r3 = ctx, r4 = flags
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 4, 0, 0),
+        // r0 = for_each_map_elem(r1, r2, r3, r4)
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+        // callback(map, key, value, ctx): r0 = *(u64 *)(r3 + 0)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 3, 0, 0, 0),
+        // if r0 > 10 goto +2
+        Instruction::pack(BPF_JMP | BPF_JGT | BPF_K, 0, 0, 2, 10),
+        // return 0 (continue)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+        // return 1 (stop)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 1),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |code: &[u64]| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                helpers: &[BPF_HELPER_INVALID, BPF_HELPER_FOR_EACH_MAP_ELEM],
+                map_fd_collector: &|_| {
+                    Some(MapInfo {
+                        map_type: MapType::Array,
+                        max_size: 4,
+                        key_size: 4,
+                        value_size: 8,
+                        readonly: false,
+                        initial_value: None,
+                        program_type: None,
+                    })
+                },
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(&code).is_ok());
+
+    let expect_error = |code: &[u64], message: &str| match analyze(code) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert!(branch.borrow_mut().messages()[0].contains(message));
+        }
+        _ => panic!("expecting {message}"),
+    };
+    // Returning values other than 0 / 1
+    let mut invalid = code;
+    invalid[13] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 2);
+    expect_error(&invalid, "Callback return value out of range");
+    invalid[13] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 0, 0, 0);
+    expect_error(&invalid, "Callback return value out of range");
+    // Reading beyond the value
+    let mut invalid = code;
+    invalid[9] = Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 3, 0, 8, 0);
+    expect_error(&invalid, "Illegal access");
+    // The key is read-only
+    invalid[9] = Instruction::pack(BPF_STX | BPF_MEM | BPF_W, 0, 2, 0, 0);
+    expect_error(&invalid, "Illegal access");
+    // Non-zero flags
+    let mut invalid = code;
+    invalid[5] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 4, 0, 1);
+    expect_error(&invalid, "Function call failed");
+}
+
+#[test]
+fn test_timer_lifecycle() {
+    use ebpf_analyzer::spec::proto::helpers::{
+        BPF_HELPER_INVALID, BPF_HELPER_MAP_LOOKUP_ELEM, BPF_HELPER_TIMER_INIT,
+        BPF_HELPER_TIMER_SET_CALLBACK, BPF_HELPER_TIMER_START,
+    };
+    let init = Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2);
+    let set_callback = Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 3);
+    let start = Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 4);
+    let code = [
+        // r1 = map (fd 1)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        // *(u32 *)(r10 - 4) = 0
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 10, -4, 0),
+        // r0 = map_lookup_elem(r1, r2 = r10 - 4)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        // if r0 == 0 goto exit
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 14, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 6, 0, 0),
+        // timer_init(r1 = value, r2 = map, r3 = 0)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 1, 0, 0),
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 2, 0, 1),
+        0,
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, 0),
+        init,
+        // timer_set_callback(r1 = value, r2 = callback)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 1, 0, 0),
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_FUNC, 2, 0, 8),
+        0,
+        set_callback,
+        // timer_start(r1 = value, r2 = 1000, r3 = 0)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 1000),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, 0),
+        start,
+        // exit: return 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+        // callback(map, key, value): *(u64 *)(r3 + 0) = 0; return 0
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 3, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |code: &[u64]| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                helpers: &[
+                    BPF_HELPER_INVALID,
+                    BPF_HELPER_MAP_LOOKUP_ELEM,
+                    BPF_HELPER_TIMER_INIT,
+                    BPF_HELPER_TIMER_SET_CALLBACK,
+                    BPF_HELPER_TIMER_START,
+                ],
+                map_fd_collector: &|_| {
+                    Some(MapInfo {
+                        map_type: MapType::Array,
+                        max_size: 1,
+                        key_size: 4,
+                        value_size: 16,
+                        readonly: false,
+                        initial_value: None,
+                        program_type: None,
+                    })
+                },
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(&code).is_ok());
+
+    let expect_error = |code: &[u64], message: &str| match analyze(code) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert!(branch.borrow_mut().messages()[0].contains(message));
+        }
+        _ => panic!("expecting {message}"),
+    };
+    let nop = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0);
+    // Setting the callback or starting the timer before initializing it
+    let mut invalid = code;
+    invalid[12] = nop;
+    expect_error(&invalid, "Function call failed");
+    invalid[16] = nop;
+    expect_error(&invalid, "Function call failed");
+    let mut invalid = code;
+    invalid[12] = start;
+    invalid[20] = init;
+    expect_error(&invalid, "Function call failed");
+    // Timers must lie in map values
+    let mut invalid = code;
+    invalid[8] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0);
+    invalid[9] = Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -16);
+    invalid[10] = nop;
+    expect_error(&invalid, "Function call failed");
+}
+
+#[test]
+fn test_timer_map_value() {
+    use ebpf_analyzer::spec::proto::helpers::{
+        BPF_HELPER_INVALID, BPF_HELPER_MAP_DELETE_ELEM, BPF_HELPER_MAP_LOOKUP_ELEM,
+        BPF_HELPER_TIMER_INIT, BPF_HELPER_TIMER_START,
+    };
+    let analyze = |code: &[u64], setup: &dyn Fn(&mut BranchState)| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                helpers: &[
+                    BPF_HELPER_INVALID,
+                    BPF_HELPER_MAP_LOOKUP_ELEM,
+                    BPF_HELPER_TIMER_INIT,
+                    BPF_HELPER_TIMER_START,
+                    BPF_HELPER_MAP_DELETE_ELEM,
+                ],
+                map_fd_collector: &|_| {
+                    Some(MapInfo {
+                        map_type: MapType::Hash,
+                        max_size: 1,
+                        key_size: 4,
+                        value_size: 16,
+                        readonly: false,
+                        initial_value: None,
+                        program_type: None,
+                    })
+                },
+                setup,
+                ..Default::default()
+            },
+        )
+    };
+    let code = [
+        // r1 = map (fd 1)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        // *(u32 *)(r10 - 4) = 0
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 10, -4, 0),
+        // r0 = map_lookup_elem(r1, r2 = r10 - 4)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        // if r0 == 0 goto exit
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 15, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 6, 0, 0),
+        // timer_init(r1 = value, r2 = map, r3 = 0)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 1, 0, 0),
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 2, 0, 1),
+        0,
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, 0),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2),
+        // map_delete_elem(r1 = map, r2 = r10 - 4)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 4),
+        // timer_start(r1 = value, r2 = 1000, r3 = 0)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 1000),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, 0),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 3),
+        // exit: return 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    // The timer is gone along with the deleted element
+    assert!(analyze(&code, &|_| {}).is_err());
+    let nop = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0);
+    let mut kept = code;
+    kept[13..18].fill(nop);
+    assert!(analyze(&kept, &|_| {}).is_ok());
+
+    // Writable memory other than map values is rejected
+    let init = [
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 2, 0, 1),
+        0,
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, 0),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(analyze(&init, &|vm| {
+        let region = pointed(DynamicRegion::new(16));
+        vm.add_external_resource(region.clone());
+        *vm.reg(1) = Pointer::nrwa(region).into();
+    })
+    .is_err());
+}
+
+#[test]
+fn test_byte_swap() {
+    use ebpf_analyzer::interpreter::value::ByteSwap;
+    let analyze = |value: u64, op: u8, width: i32, expected: u64| {
+        let code = [
+            // r0 = value
+            Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, 0, 0, 0, value as i32),
+            value & 0xFFFF_FFFF_0000_0000,
+            // r0 = le16 / be16 / ... r0
+            Instruction::pack(BPF_ALU | BPF_END | op, 0, 0, 0, width),
+            // if r0 == expected goto exit
+            Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, 0, 1, 0, expected as i32),
+            expected & 0xFFFF_FFFF_0000_0000,
+            Instruction::pack(BPF_JMP | BPF_JEQ | BPF_X, 1, 0, 1, 0),
+            // Illegal unless r0 is imprecise
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 0, 0, 0, 0),
+            // exit
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        Analyzer::analyze(&code, &AnalyzerConfig::default())
+    };
+    for value in [0x0102, 0x0807_0605_0403_0201u64] {
+        for op in [BPF_TO_LE, BPF_TO_BE] {
+            for width in [16, 32, 64] {
+                let mut expected = value;
+                if op == BPF_TO_LE {
+                    expected.host_to_le(width);
+                } else {
+                    expected.host_to_be(width);
+                }
+                assert!(analyze(value, op, width, expected).is_ok());
+                assert!(analyze(value, op, width, expected ^ 1).is_err());
+            }
+        }
+    }
+    let be16 = if cfg!(target_endian = "big") {
+        0x0102
+    } else {
+        0x0201
+    };
+    assert!(analyze(0x0304_0102, BPF_TO_BE, 16, be16).is_ok());
+}
+
+#[test]
+fn test_max_blocks() {
+    // Each conditional jump ends a block: 8 jumps, 9 blocks
+    let mut code = Vec::new();
+    for _ in 0..8 {
+        code.push(Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 1, 0, 0));
+    }
+    code.push(Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0));
+    code.push(BPF_JMP_EXIT as u64);
+    let analyze = |max_blocks| {
+        Analyzer::analyze(
+            &code,
+            &AnalyzerConfig {
+                setup: &|vm| *vm.reg(1) = Scalar::unknown().into(),
+                max_blocks,
+                ..Default::default()
+            },
+        )
+    };
+    let blocks = analyze(None).unwrap().functions[0].block_count();
+    assert!(blocks > 4);
+    assert!(analyze(Some(blocks)).is_ok());
+    assert!(matches!(
+        analyze(Some(4)),
+        Err(VerificationError::TooManyBlocks)
+    ));
+}
+
+#[test]
+fn test_branch_limits() {
+    // if r1 > 5 goto +0; ...; if r5 > 5 goto +0, forking 2^5 disjoint paths
+    let mut code = Vec::new();
+    for i in 1..=5 {
+        code.push(Instruction::pack(BPF_JMP | BPF_JGT | BPF_K, 0, i, 0, 5));
+    }
+    code.push(Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0));
+    code.push(BPF_JMP_EXIT as u64);
+    let analyze = |max_pending_branches, max_total_branches| {
+        Analyzer::analyze(
+            &code,
+            &AnalyzerConfig {
+                setup: &|vm| {
+                    for i in 1..=5 {
+                        *vm.reg(i) = Scalar::unknown().into();
+                    }
+                },
+                max_pending_branches,
+                max_total_branches,
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(5, 32).is_ok());
+    // The initial branch forks 5 times before any pending one gets explored
+    assert!(matches!(
+        analyze(4, 100),
+        Err(VerificationError::BranchLimitExceeded)
+    ));
+    assert!(matches!(
+        analyze(100, 31),
+        Err(VerificationError::BranchLimitExceeded)
+    ));
+}
+
+#[test]
+fn test_preserve_context() {
+    let code = [
+        // r6 = r1; r1 = 0; r1 = r6
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 6, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let analyze = |code: &[u64], preserve_context| {
+        Analyzer::analyze(
+            code,
+            &AnalyzerConfig {
+                program_type: Some(ProgramType::SocketFilter),
+                preserve_context,
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(&code, false).is_ok());
+    assert!(matches!(
+        analyze(&code, true),
+        Err(VerificationError::ContextOverwritten(1))
+    ));
+    // Reading from the context is fine
+    let mut read = code;
+    read[1] = Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 1, 7, 0, 0);
+    read[2] = Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 7, 0, 8);
+    assert!(analyze(&read, true).is_ok());
+}
+
+#[test]
+fn test_byte_stores_initialize_stack() {
+    const HELPERS: &[&dyn VerifiableCall<CheckedValue, BranchState>] = &[
+        ebpf_analyzer::spec::proto::helpers::BPF_HELPER_INVALID,
+        // (1) requires 16 initialized bytes in r1
+        &StaticFunctionCall::new(
+            [
+                ArgumentType::FixedMemory(16),
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        ),
+    ];
+    let analyze = |skipped: i16| {
+        // *(u8 *)(r10 - i) = i, except for `skipped`
+        let mut code: Vec<u64> = (1..=16)
+            .filter(|&i| i != skipped)
+            .map(|i| Instruction::pack(BPF_ST | BPF_MEM | BPF_B, 0, 10, -i, i as i32))
+            .collect();
+        code.extend([
+            // r1 = r10 - 16; call 1
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -16),
+            Instruction::pack(BPF_JMP | BPF_CALL, 0, 0, 0, 1),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]);
+        Analyzer::analyze(
+            &code,
+            &AnalyzerConfig {
+                helpers: HELPERS,
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(0).is_ok());
+    for skipped in [1, 8, 9, 16] {
+        match analyze(skipped) {
+            Err(VerificationError::IllegalStateChange(branch)) => {
+                assert!(branch.borrow_mut().messages()[0].contains("Function call failed"));
+            }
+            Err(err) => panic!("{err:?}"),
+            Ok(_) => panic!("Stack bytes not initialized: -{skipped}"),
+        }
+    }
+}
+
+#[test]
+fn test_mov32_nonnegative() {
+    let analyze = |mov: u8| {
+        let code = [
+            // r2 = get_prandom_u32()
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 2, 0, 0),
+            // r1 = r2 / w1 = w2
+            Instruction::pack(mov | BPF_MOV | BPF_X, 2, 1, 0, 0),
+            // if r1 s< 0 goto +2
+            Instruction::pack(BPF_JMP | BPF_JSLT | BPF_K, 0, 1, 2, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+            // Illegal unless dead
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        Analyzer::analyze(
+            &code,
+            &AnalyzerConfig {
+                helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+                ..Default::default()
+            },
+        )
+    };
+    assert!(analyze(BPF_ALU).is_ok());
+    assert!(analyze(BPF_ALU64).is_err());
+}
+
+#[test]
+fn test_map_value_null_check() {
+    let code = [
+        // r1 = map (fd 1)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        // *(u32 *)(r10 - 4) = 0
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 10, -4, 0),
+        // r0 = map_lookup_elem(r1, r2 = r10 - 4)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        // if r0 == 0 goto +1
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1, 0),
+        // r1 = *(u64 *)(r0 + 0)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 0, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let config = AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        map_fd_collector: &|_| {
+            Some(MapInfo {
+                map_type: MapType::Hash,
+                max_size: 16,
+                key_size: 4,
+                value_size: 8,
+                readonly: false,
+                initial_value: None,
+                program_type: None,
+            })
+        },
+        ..Default::default()
+    };
+    assert!(Analyzer::analyze(&code, &config).is_ok());
+
+    // The value is null if the key is absent
+    let mut unchecked = code;
+    unchecked[6] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 0, 0, 0);
+    match Analyzer::analyze(&unchecked, &config) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert!(branch.borrow().messages()[0].contains("Illegal access"));
+        }
+        _ => panic!("map values should be checked against null"),
+    }
+
+    // The key region must cover the key size of the map
+    let wide_key = AnalyzerConfig {
+        map_fd_collector: &|_| {
+            Some(MapInfo {
+                map_type: MapType::Hash,
+                max_size: 16,
+                key_size: 8,
+                value_size: 8,
+                readonly: false,
+                initial_value: None,
+                program_type: None,
+            })
+        },
+        ..config
+    };
+    match Analyzer::analyze(&code, &wide_key) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert!(branch.borrow().messages()[0].contains("Function call failed"));
+        }
+        _ => panic!("keys should be checked against the key size"),
+    }
+}
+
+#[test]
+fn test_dynptr_slice() {
+    use ebpf_analyzer::spec::proto::helpers;
+    const DYNPTR_HELPERS: &[&dyn VerifiableCall<CheckedValue, BranchState>] = &[
+        helpers::BPF_HELPER_INVALID,
+        helpers::BPF_HELPER_DYNPTR_FROM_MEM,
+        helpers::BPF_HELPER_DYNPTR_DATA,
+    ];
+    let config = AnalyzerConfig {
+        helpers: DYNPTR_HELPERS,
+        ..Default::default()
+    };
+    let code = |offset, len| {
+        [
+            // *(u64 *)(r10 - 8) = 7
+            Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -8, 7),
+            // bpf_dynptr_from_mem(r10 - 8, 8, 0, r10 - 24)
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -8),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 8),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 4, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 4, 0, -24),
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+            // r0 = bpf_dynptr_data(r10 - 24, offset, len)
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -24),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, offset),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 3, 0, len),
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2),
+            // if r0 == 0 goto +1
+            Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1, 0),
+            // r0 = *(u32 *)(r0 + 0)
+            Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 0, 0, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let rejected = |code: &[u64], message: &str| match Analyzer::analyze(code, &config) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            let messages = branch.borrow().messages().join(";");
+            assert!(messages.contains(message), "{messages}");
+        }
+        _ => panic!("expecting rejection: {message}"),
+    };
+    assert!(Analyzer::analyze(&code(4, 4), &config).is_ok());
+
+    // Beyond the 8-byte buffer
+    rejected(&code(6, 4), "Function call failed");
+
+    // The slice is null if the dynptr is invalid at runtime
+    let mut unchecked = code(4, 4);
+    unchecked[13] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 0, 0, 0);
+    rejected(&unchecked, "Illegal access");
+
+    // Slicing an uninitialized dynptr
+    let mut uninit = code(4, 4);
+    uninit[7] = Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -24, 0);
+    rejected(&uninit, "Function call failed");
+}
+
+#[test]
+fn test_max_live_map_values() {
+    let code = [
+        // r1 = map (fd 1)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        // *(u32 *)(r10 - 4) = 0
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 10, -4, 0),
+        // r6 = map_lookup_elem(r1, r2 = r10 - 4)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 6, 0, 0),
+        // r7 = map_lookup_elem(r1 = map, r2 = r10 - 4)
+        Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+        0,
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+        Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 7, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let config = |max_live_map_values| AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        map_fd_collector: &|_| {
+            Some(MapInfo {
+                map_type: MapType::Hash,
+                max_size: 16,
+                key_size: 4,
+                value_size: 8,
+                readonly: false,
+                initial_value: None,
+                program_type: None,
+            })
+        },
+        max_live_map_values,
+        ..Default::default()
+    };
+    assert!(Analyzer::analyze(&code, &config(None)).is_ok());
+    assert!(Analyzer::analyze(&code, &config(Some(2))).is_ok());
+    match Analyzer::analyze(&code, &config(Some(1))) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert!(branch.borrow().messages()[0].contains("Too many live map values"));
+        }
+        _ => panic!("holding two map values should exceed the limit"),
+    }
+
+    // The first value is no longer live once dropped
+    let mut dropped = code;
+    dropped[6] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 6, 0, 0);
+    assert!(Analyzer::analyze(&dropped, &config(Some(1))).is_ok());
+}
+
+#[test]
+fn test_helper_calls_in_loop() {
+    let code = |iterations: i32| {
+        [
+            // r6 = 0; r7 = 0
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 6, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 7, 0, 0),
+            // loop: r6 += get_prandom_u32() & 3
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 0, 0, 3),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 0, 6, 0, 0),
+            // r7 += 1; if r7 < iterations goto loop
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 7, 0, 1),
+            Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 7, -5, iterations),
+            // r0 = map_lookup_elem(fd 1, &(u32) 0)
+            Instruction::pack(BPF_LD | BPF_DW | BPF_IMM, BPF_IMM64_MAP_FD, 1, 0, 1),
+            0,
+            Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 10, -4, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 2, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, -4),
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+            // if r0 != 0: *(u8 *)(r0 + r6) = 0
+            Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 2, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 6, 0, 0, 0),
+            Instruction::pack(BPF_ST | BPF_MEM | BPF_B, 0, 0, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let config = AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        processed_instruction_limit: 10_000,
+        map_fd_collector: &|_| {
+            Some(MapInfo {
+                map_type: MapType::Array,
+                max_size: 1,
+                key_size: 4,
+                value_size: 64,
+                readonly: false,
+                initial_value: None,
+                program_type: None,
+            })
+        },
+        ..Default::default()
+    };
+    let report = Analyzer::verify(&code(16), &config).unwrap();
+    assert!(report.result.is_ok());
+    assert_eq!(report.exit_register_ranges[6], Some((0, 48)));
+    // r6 may reach 93, beyond the map value
+    assert!(matches!(
+        Analyzer::analyze(&code(31), &config),
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+
+    // Arguments are clobbered by each call
+    let mut clobbered = code(16);
+    clobbered[3] = Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 1, 0, 0, 0);
+    assert!(matches!(
+        Analyzer::analyze(&clobbered, &config),
+        Err(VerificationError::IllegalStateChange(_))
+    ));
+}
+
+#[test]
+fn test_bounded_loops() {
+    let code = |start: u64, bound: u64| {
+        [
+            // for (r0 = start; r0 < bound; r0++) {}
+            start,
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+            bound,
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let zero = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0);
+    let unknown = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 0, 0, 0);
+    let lt_10 = Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 0, -2, 10);
+    let ne_r1 = Instruction::pack(BPF_JMP | BPF_JNE | BPF_X, 1, 0, -2, 0);
+    let config = |limit| AnalyzerConfig {
+        setup: &|vm| *vm.reg(1) = Scalar::unknown().into(),
+        max_loop_iterations: Some(limit),
+        ..Default::default()
+    };
+    let analyze = |code: &[u64], limit| match Analyzer::analyze(code, &config(limit)) {
+        Err(VerificationError::IllegalStateChange(branch)) => Err(format!("{:?}", branch.borrow())),
+        Err(err) => panic!("{err:?}"),
+        Ok(_) => Ok(()),
+    };
+
+    // Jumping back 9 times
+    assert!(analyze(&code(zero, lt_10), 9).is_ok());
+    let err = analyze(&code(zero, lt_10), 8).unwrap_err();
+    assert!(err.contains("Loop does not terminate"), "{err}");
+    // r0 in [0, 7], narrowed by `r0 < 10` until no path may jump back
+    let mut masked = code(unknown, lt_10).to_vec();
+    let and_7 = Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 0, 0, 7);
+    masked.insert(1, and_7);
+    assert!(analyze(&masked, 9).is_ok());
+    // The bound is never reached when r0 > r1
+    let err = analyze(&code(zero, ne_r1), 100).unwrap_err();
+    assert!(err.contains("Loop does not terminate"), "{err}");
+
+    // for (r0 = 0; r0 < 10; r0++) {}, jumping back with `ja`
+    let for_loop = [
+        zero,
+        Instruction::pack(BPF_JMP | BPF_JGE | BPF_K, 0, 0, 2, 10),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+        Instruction::pack(BPF_JMP | BPF_JA, 0, 0, -3, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(analyze(&for_loop, 10).is_ok());
+    let err = analyze(&for_loop, 9).unwrap_err();
+    assert!(err.contains("Loop does not terminate"), "{err}");
+    // Not a counted loop, with r1 also compared in the loop, but still capped
+    let mut ja_loop = for_loop.to_vec();
+    ja_loop[2] = Instruction::pack(BPF_JMP | BPF_JEQ | BPF_X, 1, 0, 1, 0);
+    let err = analyze(&ja_loop, 100).unwrap_err();
+    assert!(err.contains("Loop does not terminate"), "{err}");
+    // The inner loop breaks out after two iterations, resetting its counter each time
+    let nested = [
+        // r2 = 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 0),
+        // do { r0 = 0;
+        zero,
+        //   do { r0 += 1; if r0 == 2 break; } while (r0 < 10);
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1, 2),
+        Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 0, -3, 10),
+        //   r2 += 1; } while (r2 < 3);
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 2, 0, 1),
+        Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 2, -6, 3),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(analyze(&nested, 2).is_ok());
+    let err = analyze(&nested, 1).unwrap_err();
+    assert!(err.contains("Loop does not terminate"), "{err}");
+
+    // No limit by default: loops still run until they fall through
+    let config = AnalyzerConfig {
+        setup: &|vm| *vm.reg(1) = Scalar::unknown().into(),
+        ..Default::default()
+    };
+    assert_eq!(config.max_loop_iterations, None);
+    assert!(Analyzer::analyze(&code(zero, lt_10), &config).is_ok());
+    assert!(Analyzer::analyze(&masked, &config).is_ok());
+    // and those never falling through are left to the instruction limit
+    let config = AnalyzerConfig {
+        processed_instruction_limit: 300,
+        ..config
+    };
+    assert!(matches!(
+        Analyzer::analyze(&code(zero, ne_r1), &config),
+        Err(VerificationError::IllegalContext(
+            "Too many instructions to process"
+        ))
+    ));
+}
+
+#[test]
+fn test_full_frame_reader() {
+    use ebpf_analyzer::spec::proto::helpers;
+    const FRAME_HELPERS: &[&dyn VerifiableCall<CheckedValue, BranchState>] =
+        &[helpers::BPF_HELPER_INVALID, helpers::BPF_HELPER_READ_FRAME];
+    let config = AnalyzerConfig {
+        helpers: FRAME_HELPERS,
+        ..Default::default()
+    };
+    let code = |initialized: i16, passed: i32| {
+        [
+            // *(u64 *)(r10 - 8) = 0; *(u64 *)(r10 - 24) = 0
+            Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -8, 0),
+            Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -24, 0),
+            // *(u64 *)(r10 + initialized) = 0
+            Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, initialized, 0),
+            // read_frame(r10 + passed)
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, passed),
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let rejected = |code: &[u64]| match Analyzer::analyze(code, &config) {
+        Err(VerificationError::IllegalStateChange(branch)) => {
+            assert!(branch.borrow().messages()[0].contains("Function call failed"));
+        }
+        _ => panic!("the frame should be fully initialized and covered"),
+    };
+    assert!(Analyzer::analyze(&code(-16, -24), &config).is_ok());
+    // r10 - 16 is never written
+    rejected(&code(-8, -24));
+    // Not covering r10 - 24
+    rejected(&code(-16, -16));
+    // Not a stack pointer
+    let mut scalar = code(-16, -24);
+    scalar[3] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 0);
+    rejected(&scalar);
+}
+
+#[test]
+fn test_loop_type_oscillation() {
+    let code = |initial: u64, body: u64, out: u64| {
+        [
+            // r6 = 0; r7 = initial
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 6, 0, 0),
+            initial,
+            // if get_prandom_u32() == 0 goto out
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 5, 0),
+            // loop: if r6 > 3 goto out; r6 += 1; r7 = body
+            Instruction::pack(BPF_JMP | BPF_JGT | BPF_K, 0, 6, 4, 3),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 6, 0, 1),
+            body,
+            // if get_prandom_u32() != 0 goto loop
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_JMP | BPF_JNE | BPF_K, 0, 0, -5, 0),
+            // out: r1 = out; return 0
+            out,
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let config = AnalyzerConfig {
+        helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+        ..Default::default()
+    };
+    let scalar = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 7, 0, 1);
+    let pointer = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 7, 0, 0);
+    // r7 is live at the loop header if it is read after the loop
+    let live = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 7, 1, 0, 0);
+    let dead = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 0);
+    assert!(Analyzer::analyze(&code(scalar, scalar, live), &config).is_ok());
+    assert!(Analyzer::analyze(&code(pointer, pointer, live), &config).is_ok());
+    for (initial, body) in [(scalar, pointer), (pointer, scalar)] {
+        match Analyzer::analyze(&code(initial, body, live), &config) {
+            Err(VerificationError::IllegalStateChange(branch)) => {
+                assert_eq!(
+                    branch.borrow().messages()[0],
+                    "register type changes across loop iteration"
+                );
+            }
+            _ => panic!("r7 should be rejected for changing its type"),
+        }
+        // A dead register may change its type
+        assert!(Analyzer::analyze(&code(initial, body, dead), &config).is_ok());
+    }
+}
+
+#[test]
+fn test_typed_kernel_pointer() {
+    use ebpf_analyzer::{
+        spec::proto::{helpers, ReturnType, StaticFunctionCall},
+        track::pointees::{
+            kernel_region::{KernelStruct, TASK_STRUCT_TYPE_ID},
+            struct_region::FieldType,
+        },
+    };
+    // `(task) -> scalar`
+    const TASK_HELPERS: &[&dyn VerifiableCall<CheckedValue, BranchState>] = &[
+        helpers::BPF_HELPER_INVALID,
+        helpers::BPF_HELPER_GET_CURRENT_TASK_BTF,
+        &StaticFunctionCall::new(
+            [
+                ArgumentType::ResourceType((TASK_STRUCT_TYPE_ID, ResourceOperation::Unknown)),
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+                ArgumentType::Any,
+            ],
+            ReturnType::Scalar,
+        ),
+    ];
+    // `pid` at offset 8, with a layout only living as long as the config
+    let fields = vec![(8, 4, FieldType::Scalar)];
+    let task_struct = KernelStruct {
+        type_id: TASK_STRUCT_TYPE_ID,
+        size: 16,
+        fields: &fields,
+    };
+    let config = AnalyzerConfig {
+        helpers: TASK_HELPERS,
+        kernel_structs: &[task_struct],
+        ..Default::default()
+    };
+    let code = |access: u64| {
+        [
+            // r6 = get_current_task()
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 1),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 6, 0, 0),
+            access,
+            // use_task(r6)
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 6, 1, 0, 0),
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 2),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    // r0 = task->pid
+    let read = Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 6, 0, 8, 0);
+    assert!(Analyzer::analyze(&code(read), &config).is_ok());
+
+    let rejected = |access: u64| {
+        assert!(matches!(
+            Analyzer::analyze(&code(access), &config),
+            Err(VerificationError::IllegalStateChange(_))
+        ));
+    };
+    // Fields not listed
+    rejected(Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 6, 0, 0, 0));
+    rejected(Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 6, 0, 8, 0));
+    // Read-only
+    rejected(Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 6, 8, 0));
+    // No arithmetic
+    rejected(Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 6, 0, 8));
+    // The layout is needed
+    let no_layout = AnalyzerConfig {
+        helpers: TASK_HELPERS,
+        ..Default::default()
+    };
+    assert!(Analyzer::analyze(&code(read), &no_layout).is_err());
+}
+
+#[test]
+fn test_max_pointer_offset() {
+    let code = |offset: i32| {
+        [
+            // r1 = r10 + offset
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+            Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, offset),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    };
+    let config = |max_pointer_offset| AnalyzerConfig {
+        max_pointer_offset,
+        ..Default::default()
+    };
+    assert!(Analyzer::analyze(&code(1 << 20), &config(None)).is_ok());
+    assert!(Analyzer::analyze(&code(-512), &config(Some(4096))).is_ok());
+    for offset in [8192, -8192] {
+        match Analyzer::analyze(&code(offset), &config(Some(4096))) {
+            Err(VerificationError::IllegalStateChange(branch)) => {
+                assert!(branch.borrow().messages()[0].contains("Pointer offset out of window"));
+            }
+            _ => panic!("the offset should exceed the window"),
+        }
+    }
+
+    // Only the arithmetic is checked, not offsets the pointers already carry
+    let moved = [
+        // r1 = r10 - 256 - 256
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, -256),
+        Instruction::pack(BPF_ALU64 | BPF_SUB | BPF_K, 0, 1, 0, 256),
+        // *(r10 - 8) = r1; r2 = *(r10 - 8)
+        Instruction::pack(BPF_STX | BPF_MEM | BPF_DW, 1, 10, -8, 0),
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 10, 2, -8, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(Analyzer::analyze(&moved, &config(Some(256))).is_ok());
+    assert!(Analyzer::analyze(&moved, &config(Some(255))).is_err());
+    let scalar = [
+        // r2 = 8192; r1 = r10 + r2
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 8192),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 10, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 2, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    assert!(Analyzer::analyze(&scalar, &config(None)).is_ok());
+    assert!(Analyzer::analyze(&scalar, &config(Some(4096))).is_err());
+}
+
+#[test]
+fn test_line_info() {
+    let sections = elf::read_sections("tests/bpf-src/line-info.o");
+    let code = elf::read_code(&sections, "xdp");
+    let lines = LineInfo::parse(&sections[".BTF"], &sections[".BTF.ext"], "xdp").unwrap();
+    let source = String::from("tests/bpf-src/line-info.c");
+    assert_eq!(lines.line_info(0), Some((source.clone(), 3)));
+    assert_eq!(lines.line_info(1), Some((source.clone(), 4)));
+
+    // Reading through the uninitialized r1 at `ctx[4]`
+    let config = AnalyzerConfig {
+        line_info: Some(&lines),
+        ..Default::default()
+    };
+    match Analyzer::analyze(&code, &config) {
+        Err(VerificationError::At((file, 3, err))) => {
+            assert_eq!(file, source);
+            assert!(matches!(*err, VerificationError::IllegalStateChange(_)));
+            assert_eq!(err.pc(), Some(0));
+        }
+        Err(err) => panic!("{err:?}"),
+        Ok(_) => panic!("r1 is not readable"),
+    }
+}
+
+#[test]
+fn test_error_pc() {
+    let code = [
+        // r0 = 0; do { r0 += 1; } while (r0 < 10);
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+        Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 0, -2, 10),
+        // if r2 == 0 goto +1, with r2 uninitialized
+        Instruction::pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 2, 1, 0),
+        BPF_JMP_EXIT as u64,
+        BPF_JMP_EXIT as u64,
+    ];
+    // Rejected at the jump back, while the branch is already at pc 1
+    let config = AnalyzerConfig {
+        max_loop_iterations: Some(1),
+        ..Default::default()
+    };
+    let pc = |config| {
+        Analyzer::analyze(&code, config)
+            .err()
+            .and_then(|err| err.pc())
+    };
+    assert_eq!(pc(&config), Some(2));
+    // Otherwise, rejected at the comparison reading r2
+    assert_eq!(pc(&Default::default()), Some(3));
+}
@@ -32,7 +32,7 @@ fn test_with_assembly(
             helpers,
             setup,
             processed_instruction_limit: 20,
-            map_fd_collector: &|_| None,
+            ..Default::default()
         },
     ) {
         Ok(_) if success => {}
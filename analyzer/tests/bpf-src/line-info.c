@@ -0,0 +1,5 @@
+__attribute__((section("xdp"))) int prog(int *ctx)
+{
+    int value = ctx[4];
+    return value + 1;
+}
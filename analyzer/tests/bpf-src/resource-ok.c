@@ -1,4 +1,4 @@
-static void *(*new_resource)(long i) = (void *)3;
+static void *(*new_resource)(long i) = (void *)7;
 static void (*use_resource)(void *i) = (void *)4;
 static void (*del_resource)(void *i) = (void *)5;
 
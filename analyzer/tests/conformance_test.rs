@@ -100,7 +100,7 @@ fn analyze_with_conformance_data(data: &ConformanceData) -> Result<(), ()> {
                 .into();
                 *vm.reg(2) = Scalar::constant64(data.memory.len() as u64).into();
             },
-            map_fd_collector: &|_| None,
+            ..Default::default()
         },
     ) {
         Ok(_) => Ok(()),
@@ -136,3 +136,50 @@ fn test_one() {
     let data = get_conformance_data("./tests/conformance/call-stack-fail.data.txt").unwrap();
     assert!(test_with_conformance_data(data).is_ok());
 }
+
+/// Splits `.data` files into the assembly, the input memory and the expected `r0`
+#[cfg(feature = "conformance")]
+fn split_data(data: &str) -> (String, Vec<u8>, u64) {
+    let (mut asm, mut memory, mut result) = (String::new(), Vec::new(), None);
+    let mut section = "";
+    for line in data.lines() {
+        if let Some(name) = line.strip_prefix("-- ") {
+            section = name.trim();
+            continue;
+        }
+        match section {
+            "asm" => {
+                asm.push_str(line);
+                asm.push('\n');
+            }
+            "mem" => memory.extend(
+                line.split_whitespace()
+                    .map(|byte| u8::from_str_radix(byte, 16).unwrap()),
+            ),
+            "result" if !line.trim().is_empty() => {
+                let hex = line.trim().trim_start_matches("0x");
+                result = Some(u64::from_str_radix(hex, 16).unwrap());
+            }
+            _ => {}
+        }
+    }
+    (asm, memory, result.expect("missing result"))
+}
+
+#[cfg(feature = "conformance")]
+#[test]
+fn test_in_tree_conformance() {
+    use ebpf_analyzer::interpreter::conformance::run_case;
+
+    for entry in std::fs::read_dir("./tests/bpf-src/conformance").unwrap() {
+        let path = entry.unwrap().path();
+        let (asm, mut memory, expected) = split_data(&std::fs::read_to_string(&path).unwrap());
+        // Safety: the in-tree cases only access their input memory and stack
+        assert_eq!(
+            unsafe { run_case(&asm, &mut memory, expected) },
+            Ok(()),
+            "{}",
+            path.display()
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use ebpf_analyzer::{
     analyzer::{Analyzer, AnalyzerConfig, VerificationError},
     blocks::ProgramInfo,
-    spec::{Instruction, ParsedInstruction},
+    spec::{instructions, Instruction, ParsedInstruction},
 };
 use llvm_util::parse_llvm_dump;
 
@@ -54,7 +54,49 @@ fn validate_valid_blocks() {
 fn validate_unreachable_blocks() {
     let code = parse_llvm_dump(SIMPLE2);
     match Analyzer::analyze(&code, &AnalyzerConfig::default()) {
-        Err(VerificationError::IllegalGraph) => {}
+        Err(VerificationError::UnreachableCode(_)) => {}
         _ => panic!("Should contain unreachable blocks"),
     }
 }
+
+#[test]
+fn validate_assembled_code() {
+    let code = bpfasm::parse(
+        "mov r0, 0
+ldxw r1, [r1+8]
+jeq r1, 0, done
+stxdw [r10-8], r1
+lddw r2, 0x100000000
+lock fetch add32 [r10-8], r0
+done:
+exit",
+    )
+    .unwrap();
+    let text: Vec<String> = instructions(&code)
+        .map(|(_, insn)| {
+            if let ParsedInstruction::Instruction(i) = &insn {
+                assert!(i.validate().is_ok());
+            }
+            insn.to_string()
+        })
+        .collect();
+    assert_eq!(
+        text,
+        [
+            "r0 = 0x0",
+            "r1 = *(u32 *)(r1 + 8)",
+            "if r1 == 0x0 goto +4",
+            "*(u64 *)(r10 - 8) = r1",
+            "r2 = ll 0x100000000",
+            "w0 = atomic_fetch_add((u32 *)(r10 - 8), w0)",
+            "exit",
+        ]
+    );
+
+    // Stack-passing subprograms from the conformance data
+    let data = include_str!("bpf-src/asm/call-stack.data");
+    let asm = data.split("-- ").find_map(|s| s.strip_prefix("asm\n"));
+    let code = bpfasm::parse(asm.unwrap()).unwrap();
+    let info = ProgramInfo::new(&code).unwrap();
+    assert_eq!(info.functions.len(), 3);
+}
@@ -37,8 +37,12 @@ fn test_simple() {
                         max_size: 1,
                         key_size: info.key_size(),
                         value_size: info.value_size(),
+                        readonly: false,
+                        initial_value: None,
+                        program_type: None,
                     })
                 },
+                ..Default::default()
             },
         )
         .map_err(|e| (name, e))
@@ -6,7 +6,7 @@ use ebpf_analyzer::interpreter::{
 };
 use ebpf_consts::{
     BPF_ADD, BPF_ALU, BPF_ALU64, BPF_DIV, BPF_K, BPF_MOD, BPF_MUL, BPF_SUB, BPF_X,
-    WRITABLE_REGISTER_COUNT, BPF_NEG, BPF_MOV, BPF_AND, BPF_OR, BPF_XOR, BPF_LSH, BPF_RSH, BPF_ARSH, BPF_END, BPF_TO_LE, BPF_TO_BE,
+    WRITABLE_REGISTER_COUNT, BPF_NEG, BPF_SDIV, BPF_SMOD, BPF_MOV, BPF_AND, BPF_OR, BPF_XOR, BPF_LSH, BPF_RSH, BPF_ARSH, BPF_END, BPF_TO_LE, BPF_TO_BE,
 };
 
 #[test]
@@ -115,7 +115,49 @@ pub fn test_bitwise() {
     assert_biop(BPF_ALU | BPF_END | BPF_TO_BE, number & 0xFFFF, 16, (number as u16).to_be() as u64);
 }
 
+#[test]
+pub fn test_signed_division() {
+    let minus = |v: i64| v as u64;
+    assert_signed_biop(BPF_ALU64 | BPF_DIV | BPF_X, minus(-7), 2, minus(-3));
+    assert_signed_biop(BPF_ALU64 | BPF_DIV | BPF_K, 7, minus(-2), minus(-3));
+    assert_signed_biop(BPF_ALU64 | BPF_DIV | BPF_X, minus(i64::MIN), minus(-1), minus(i64::MIN));
+    assert_signed_biop(BPF_ALU64 | BPF_DIV | BPF_X, minus(-7), 0, 0);
+    assert_signed_biop(BPF_ALU | BPF_DIV | BPF_X, minus(-7), 2, (-3i32) as u32 as u64);
+    assert_signed_biop(BPF_ALU | BPF_DIV | BPF_K, i32::MIN as u32 as u64, minus(-1), i32::MIN as u32 as u64);
+
+    assert_signed_biop(BPF_ALU64 | BPF_MOD | BPF_X, minus(-7), 2, minus(-1));
+    assert_signed_biop(BPF_ALU64 | BPF_MOD | BPF_K, 7, minus(-2), 1);
+    assert_signed_biop(BPF_ALU64 | BPF_MOD | BPF_X, minus(i64::MIN), minus(-1), 0);
+    assert_signed_biop(BPF_ALU64 | BPF_MOD | BPF_X, minus(-7), 0, minus(-7));
+    assert_signed_biop(BPF_ALU | BPF_MOD | BPF_X, minus(-7), 0, (-7i32) as u32 as u64);
+    assert_signed_biop(BPF_ALU | BPF_MOD | BPF_K, i32::MIN as u32 as u64, minus(-1), 0);
+}
+
+#[test]
+pub fn test_sign_extending_move() {
+    let mov64 = BPF_ALU64 | BPF_MOV | BPF_X;
+    let mov32 = BPF_ALU | BPF_MOV | BPF_X;
+    assert_biop_with_offset(mov64, 8, 1, 0x1234_5680, 0xFFFF_FFFF_FFFF_FF80);
+    assert_biop_with_offset(mov64, 8, 1, 0x1234_567F, 0x7F);
+    assert_biop_with_offset(mov64, 16, 1, 0x8000, 0xFFFF_FFFF_FFFF_8000);
+    assert_biop_with_offset(mov64, 32, 1, 0x1_8000_0000, 0xFFFF_FFFF_8000_0000);
+    assert_biop_with_offset(mov64, 32, 1, 0xFFFF_FFFF_7FFF_FFFF, 0x7FFF_FFFF);
+    // Zero-extended into 64 bits after the sign extension
+    assert_biop_with_offset(mov32, 8, 1, 0xFF, 0xFFFF_FFFF);
+    assert_biop_with_offset(mov32, 16, 1, 0xFFFF_8000, 0xFFFF_8000);
+    assert_biop_with_offset(mov32, 16, 1, 0xFFFF_7FFF, 0x7FFF);
+}
+
 pub fn assert_biop(op: u8, dst_v: u64, src_v: u64, result: u64) {
+    assert_biop_with_offset(op, 0, dst_v, src_v, result)
+}
+
+pub fn assert_signed_biop(op: u8, dst_v: u64, src_v: u64, result: u64) {
+    let off = if (op & 0xF0) == BPF_DIV { BPF_SDIV } else { BPF_SMOD };
+    assert_biop_with_offset(op, off, dst_v, src_v, result)
+}
+
+pub fn assert_biop_with_offset(op: u8, off: i16, dst_v: u64, src_v: u64, result: u64) {
     let v = Rc::new(RefCell::new(UncheckedVm::<Wrapping<u64>>::new(HelperCollection::new(&[]))));
     let mut vm = v.borrow_mut();
     assert!(vm.is_valid());
@@ -127,7 +169,7 @@ pub fn assert_biop(op: u8, dst_v: u64, src_v: u64, result: u64) {
         let src = (WRITABLE_REGISTER_COUNT - 1) as u64;
         *vm.reg(src as u8) = Wrapping(src_v);
         op as u64 | (src << 12) | (dst << 8)
-    };
+    } | ((off as u16 as u64) << 16);
     let code = vec![c, 0];
     run(&code, &mut vm, &mut NoOpContext{});
     assert_eq!(vm.reg(dst as u8).0, result);
@@ -1,12 +1,19 @@
 use std::{num::Wrapping, rc::Rc, cell::RefCell};
 
-use ebpf_analyzer::interpreter::{
-    run,
-    vm::{UncheckedVm, Vm}, context::NoOpContext, helper::HelperCollection,
+use ebpf_analyzer::{
+    branch::checked_value::CheckedValue,
+    interpreter::{
+        run,
+        vm::{UncheckedVm, Vm}, context::NoOpContext, helper::HelperCollection,
+    },
+    spec::Instruction,
+    track::scalar::Scalar,
 };
+use ebpf_atomic::{Atomic, AtomicError};
 use ebpf_consts::{
     BPF_ATOMIC, BPF_ATOMIC_ADD, BPF_ATOMIC_AND, BPF_ATOMIC_CMPXCHG, BPF_ATOMIC_FETCH,
-    BPF_ATOMIC_OR, BPF_ATOMIC_XCHG, BPF_ATOMIC_XOR, BPF_DW, BPF_STX, STACK_REGISTER,
+    BPF_ATOMIC_OR, BPF_ATOMIC_XCHG, BPF_ATOMIC_XOR, BPF_B, BPF_DW, BPF_H, BPF_STX,
+    STACK_REGISTER,
 };
 
 #[test]
@@ -90,3 +97,27 @@ pub fn assert_atomic(imm: i32, r0: u64, src_v: u64, target: u64, expected: u64,
         assert_eq!(vm.ro_reg(src as u8).0, returns);
     }
 }
+
+#[test]
+pub fn test_unsupported_atomic_widths() {
+    for (width, size) in [(BPF_B, 1), (BPF_H, 2)] {
+        let v = RefCell::new(UncheckedVm::<Wrapping<u64>>::new(HelperCollection::new(&[])));
+        let mut vm = v.borrow_mut();
+        let code = [Instruction::pack(
+            BPF_STX | BPF_ATOMIC | width,
+            1,
+            STACK_REGISTER,
+            -8,
+            BPF_ATOMIC_ADD,
+        )];
+        run(&code, &mut vm, &mut NoOpContext {});
+        let message = format!("Atomic failed: unsupported size {size}");
+        assert_eq!(vm.message(), Some(message.as_str()));
+
+        let value: CheckedValue = Scalar::unknown().into();
+        assert_eq!(
+            value.fetch_add(0, &value, size).unwrap_err(),
+            AtomicError::UnsupportedBitness { size }
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Runs with both the `opcode_match!` dispatch and the pre-expanded one
+//! (`cargo test --features no-macros`), which must behave the same.
+
+use std::{cell::RefCell, num::Wrapping, rc::Rc};
+
+use ebpf_analyzer::{
+    interpreter::{
+        context::NoOpContext,
+        helper::HelperCollection,
+        run,
+        vm::{ConcreteVm, UncheckedVm, Vm},
+    },
+    spec::Instruction,
+};
+use ebpf_consts::*;
+
+#[test]
+pub fn test_sample_program() {
+    let code = [
+        // r1 = 0; *(u64 *)(r10 - 8) = 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 1, 0, 0),
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -8, 0),
+        // loop: r1 += 1; r2 = r1; r2 *= r1
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, 1),
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MUL | BPF_X, 1, 2, 0, 0),
+        // lock *(u64 *)(r10 - 8) += r2
+        Instruction::pack(BPF_STX | BPF_ATOMIC | BPF_DW, 2, 10, -8, BPF_ATOMIC_ADD),
+        // if r1 < 10 goto loop
+        Instruction::pack(BPF_JMP | BPF_JLT | BPF_K, 0, 1, -5, 10),
+        // r0 = *(u64 *)(r10 - 8), the sum of squares of 1 ~ 10
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 10, 0, -8, 0),
+        // r0 <<= 1; w0 -= 5
+        Instruction::pack(BPF_ALU64 | BPF_LSH | BPF_K, 0, 0, 0, 1),
+        Instruction::pack(BPF_ALU | BPF_SUB | BPF_K, 0, 0, 0, 5),
+        BPF_JMP_EXIT as u64,
+    ];
+    let v = Rc::new(RefCell::new(UncheckedVm::<Wrapping<u64>>::new(
+        HelperCollection::new(&[]),
+    )));
+    let mut vm = v.borrow_mut();
+    run(&code, &mut vm, &mut NoOpContext {});
+    assert!(vm.is_valid());
+    assert_eq!(vm.ro_reg(0).0, 385 * 2 - 5);
+}
+
+#[test]
+pub fn test_concrete_vm() {
+    let code = [
+        // r0 = 1; r0 += 2
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 1),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 2),
+        BPF_JMP_EXIT as u64,
+    ];
+    let v = RefCell::new(ConcreteVm::new(HelperCollection::new(&[])));
+    let mut vm = v.borrow_mut();
+    run(&code, &mut vm, &mut NoOpContext {});
+    assert!(vm.is_valid());
+    assert_eq!(vm.ro_reg(0).0, 3);
+}
+
+#[test]
+pub fn test_concrete_vm_wrapping() {
+    let code = [
+        // r0 = 0; r0 -= 1
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_SUB | BPF_K, 0, 0, 0, 1),
+        // r1 = r0; r1 += 2; r1 *= r0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 1, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 1, 0, 2),
+        Instruction::pack(BPF_ALU64 | BPF_MUL | BPF_X, 0, 1, 0, 0),
+        // w2 = 0x7fffffff; w2 += w2
+        Instruction::pack(BPF_ALU | BPF_MOV | BPF_K, 0, 2, 0, i32::MAX),
+        Instruction::pack(BPF_ALU | BPF_ADD | BPF_X, 2, 2, 0, 0),
+        BPF_JMP_EXIT as u64,
+    ];
+    let v = RefCell::new(ConcreteVm::new(HelperCollection::new(&[])));
+    let mut vm = v.borrow_mut();
+    run(&code, &mut vm, &mut NoOpContext {});
+    assert!(vm.is_valid());
+    assert_eq!(vm.ro_reg(0).0, u64::MAX);
+    assert_eq!(vm.ro_reg(1).0, u64::MAX);
+    assert_eq!(vm.ro_reg(2).0, 0xffff_fffe);
+}
+
+#[test]
+pub fn test_concrete_vm_stack_and_helpers() {
+    let code = [
+        // *(u8 *)(r10 - 4) = 4; r1 = *(u8 *)(r10 - 4)
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_B, 0, 10, -4, 4),
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_B, 10, 1, -4, 0),
+        // r0 = helper_1(r1, r2)
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 2, 0, 10),
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_HELPER, 0, 0, 1),
+        BPF_JMP_EXIT as u64,
+    ];
+    let v = RefCell::new(ConcreteVm::new(HelperCollection::new(&[
+        |_, _, _, _, _| 0,
+        |r1, r2, _, _, _| r1 * r2,
+    ])));
+    let mut vm = v.borrow_mut();
+    run(&code, &mut vm, &mut NoOpContext {});
+    assert!(vm.is_valid());
+    assert_eq!(vm.ro_reg(0).0, 40);
+    assert_eq!(vm.stack()[STACK_SIZE - 4..], [4, 0, 0, 0]);
+}
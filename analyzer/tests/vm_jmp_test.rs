@@ -1,8 +1,12 @@
 use std::{num::Wrapping, rc::Rc, cell::RefCell};
 
-use ebpf_analyzer::interpreter::{
-    run,
-    vm::{UncheckedVm, Vm}, context::NoOpContext, helper::HelperCollection,
+use ebpf_analyzer::{
+    analyzer::{Analyzer, AnalyzerConfig},
+    interpreter::{
+        run, run_with_budget,
+        vm::{UncheckedVm, Vm}, context::NoOpContext, helper::HelperCollection,
+    },
+    spec::Instruction,
 };
 use ebpf_consts::*;
 
@@ -56,6 +60,134 @@ pub fn test_jumps() {
     assert_jumps(BPF_JMP | BPF_EXIT, 0, 0, true);
 }
 
+#[test]
+pub fn test_jump_immediates() {
+    // w1 = 0xFFFFFFFF: -1 when signed, u32::MAX when unsigned
+    assert_jumps(BPF_JMP32 | BPF_JSLT | BPF_K, 0xFFFF_FFFF, 0, true);
+    assert_jumps(BPF_JMP32 | BPF_JSGE | BPF_K, 0xFFFF_FFFF, 0, false);
+    assert_jumps(BPF_JMP32 | BPF_JLT | BPF_K, 0xFFFF_FFFF, 5, false);
+    assert_jumps(BPF_JMP32 | BPF_JGT | BPF_K, 0xFFFF_FFFF, 5, true);
+    assert_jumps(BPF_JMP32 | BPF_JEQ | BPF_K, 0xFFFF_FFFF, 0xFFFF_FFFF, true);
+    assert_jumps(BPF_JMP32 | BPF_JSLE | BPF_K, 0xFFFF_FFFF, 0xFFFF_FFFF, true);
+    assert_jumps(BPF_JMP32 | BPF_JLE | BPF_K, 0xFFFF_FFFE, 0xFFFF_FFFF, true);
+
+    // The immediate is sign-extended for 64-bit comparisons, even unsigned ones
+    assert_jumps(BPF_JMP | BPF_JEQ | BPF_K, u64::MAX, 0xFFFF_FFFF, true);
+    assert_jumps(BPF_JMP | BPF_JEQ | BPF_K, 0xFFFF_FFFF, 0xFFFF_FFFF, false);
+    assert_jumps(BPF_JMP | BPF_JLT | BPF_K, 0xFFFF_FFFF, 0xFFFF_FFFF, true);
+    assert_jumps(BPF_JMP | BPF_JSLT | BPF_K, 0xFFFF_FFFF, 0xFFFF_FFFF, false);
+}
+
+#[test]
+pub fn test_verified_jump_immediates() {
+    // w1 = 0xFFFFFFFF, while the other branch reads an uninitialized register
+    let verify = |op: u8, imm: i32, jumps: bool| {
+        let code = [
+            Instruction::pack(BPF_ALU | BPF_MOV | BPF_K, 0, 1, 0, -1),
+            Instruction::pack(op, 0, 1, 2, imm),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            Instruction::pack(BPF_JMP | BPF_JA, 0, 0, 1, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 2, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        let result = Analyzer::analyze(&code, &AnalyzerConfig::default());
+        assert_eq!(result.is_err(), jumps);
+    };
+    verify(BPF_JMP32 | BPF_JSLT | BPF_K, 0, true);
+    verify(BPF_JMP32 | BPF_JLT | BPF_K, 5, false);
+    // Upper half zeroed by the 32-bit move, so it is not `-1` in 64 bits
+    verify(BPF_JMP | BPF_JEQ | BPF_K, -1, false);
+}
+
+#[test]
+pub fn test_alu32_zero_extension() {
+    // r6 = unknown; w6 op= w7; if r6 > r8 (0xFFFFFFFF) goto uninit; if r6 s< 0 goto uninit
+    let verify = |op: u8, imm: i32| {
+        let src = if op & BPF_X == 0 { 0 } else { 7 };
+        let code = [
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 6, 0, 0),
+            Instruction::pack(BPF_JMP_CALL, 0, 0, 0, 7),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 0, 7, 0, 0),
+            Instruction::pack(BPF_ALU | BPF_MOV | BPF_K, 0, 8, 0, -1),
+            Instruction::pack(op, src, 6, 0, imm),
+            Instruction::pack(BPF_JMP | BPF_JGT | BPF_X, 8, 6, 3, 0),
+            Instruction::pack(BPF_JMP | BPF_JSLT | BPF_K, 0, 6, 2, 0),
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+            Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 5, 0, 0, 0),
+            BPF_JMP_EXIT as u64,
+        ];
+        let result = Analyzer::analyze(
+            &code,
+            &AnalyzerConfig {
+                helpers: ebpf_analyzer::spec::proto::helpers::HELPERS,
+                ..Default::default()
+            },
+        );
+        assert!(result.is_ok(), "{op:#x} {imm}");
+    };
+    verify(BPF_ALU | BPF_MOV | BPF_X, 0);
+    verify(BPF_ALU | BPF_ADD | BPF_K, 1);
+    verify(BPF_ALU | BPF_ADD | BPF_X, 0);
+    verify(BPF_ALU | BPF_SUB | BPF_K, -1);
+    verify(BPF_ALU | BPF_SUB | BPF_X, 0);
+    verify(BPF_ALU | BPF_MUL | BPF_X, 0);
+    verify(BPF_ALU | BPF_OR | BPF_K, -1);
+    verify(BPF_ALU | BPF_AND | BPF_X, 0);
+    verify(BPF_ALU | BPF_XOR | BPF_K, -1);
+    verify(BPF_ALU | BPF_LSH | BPF_K, 3);
+    verify(BPF_ALU | BPF_NEG | BPF_K, 0);
+    verify(BPF_ALU | BPF_DIV | BPF_K, 3);
+    verify(BPF_ALU | BPF_MOD | BPF_X, 0);
+}
+
+#[test]
+pub fn test_long_jump() {
+    // r0 = 1; if r0 != 1 goto +1; gotol +40000; r0 = r5 (uninitialized) x 40000; exit
+    let mut code = vec![Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 5, 0, 0, 0); 40004];
+    code[0] = Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 1);
+    code[1] = Instruction::pack(BPF_JMP | BPF_JNE | BPF_K, 0, 0, 1, 1);
+    code[2] = Instruction::pack(BPF_JMP32 | BPF_JA, 0, 0, 0, 40000);
+    code[40003] = BPF_JMP_EXIT as u64;
+
+    let v = RefCell::new(UncheckedVm::<Wrapping<u64>>::new(HelperCollection::new(&[])));
+    let mut vm = v.borrow_mut();
+    run(&code, &mut vm, &mut NoOpContext {});
+    assert_eq!(vm.reg(0).0, 1);
+
+    assert!(Analyzer::analyze(&code, &AnalyzerConfig::default()).is_ok());
+    // Jumping one instruction short reads r5
+    code[2] = Instruction::pack(BPF_JMP32 | BPF_JA, 0, 0, 0, 39999);
+    assert!(Analyzer::analyze(&code, &AnalyzerConfig::default()).is_err());
+}
+
+#[test]
+pub fn test_run_with_budget() {
+    let v = RefCell::new(UncheckedVm::<Wrapping<u64>>::new(HelperCollection::new(&[])));
+    let mut vm = v.borrow_mut();
+    // r0 = 0; loop: r0 += 1; goto loop
+    let code = [
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+        Instruction::pack(BPF_JMP | BPF_JA, 0, 0, -2, 0),
+    ];
+    run_with_budget(&code, &mut vm, &mut NoOpContext {}, 1001);
+    assert!(!vm.is_valid());
+    assert_eq!(vm.reg(0).0, 500);
+
+    // Exiting right within the budget
+    let v = RefCell::new(UncheckedVm::<Wrapping<u64>>::new(HelperCollection::new(&[])));
+    let mut vm = v.borrow_mut();
+    let code = [
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 7),
+        BPF_JMP_EXIT as u64,
+    ];
+    run_with_budget(&code, &mut vm, &mut NoOpContext {}, 2);
+    assert!(vm.is_valid());
+    assert_eq!(vm.reg(0).0, 7);
+}
+
 pub fn assert_jumps(op: u8, dst_v: u64, src_v: u64, jumps: bool) {
     const NUMBER: u64 = 0x0EADBEEF;
     let v = Rc::new(RefCell::new(UncheckedVm::<Wrapping<u64>>::new(HelperCollection::new(&[]))));
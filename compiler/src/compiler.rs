@@ -1,16 +1,19 @@
 //! An eBPF assembler using Cranelift
 
-use alloc::{string::ToString, vec::Vec};
+use alloc::{format, string::ToString, vec::Vec};
+use anyhow::anyhow;
 use cranelift_codegen::{
     entity::EntityRef,
     ir::{
         condcodes::IntCC, types::*, AtomicRmwOp, Block, Endianness, InstBuilder, MemFlags,
-        Signature, StackSlotData, StackSlotKind, UserFuncName,
+        Signature, StackSlotData, StackSlotKind, UserFuncName, Value,
     },
     Context,
 };
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
 use cranelift_module::{FuncId, Linkage, Module, ModuleError};
+#[cfg(feature = "object")]
+use cranelift_object::{ObjectBuilder, ObjectModule};
 use ebpf_analyzer::{
     blocks::{FunctionBlock, ProgramInfo},
     interpreter::helper::HelperPointer,
@@ -20,6 +23,8 @@ use ebpf_consts::*;
 use opcode_macros::opcode_match;
 
 use crate::module::BpfModule;
+#[cfg(feature = "object")]
+use crate::module::{ebpf_signature, host_isa};
 
 /// eBPF assembler config
 pub struct Compiler {}
@@ -43,11 +48,65 @@ impl Compiler {
         runtime: &Runtime,
     ) -> Result<(FuncId, LinkageModule), ModuleError> {
         let mut module = BpfModule::new().map_err(|e| ModuleError::Backend(e.into()))?;
+        let signature = module.signature().clone();
+        let functions = self.define_functions(
+            code,
+            info,
+            runtime,
+            &mut module,
+            &signature,
+            Some(runtime.helpers),
+        )?;
+        module.finalize_definitions()?;
+        Ok((functions[0], module))
+    }
+
+    /// Compiles the code into a relocatable object file for the host
+    ///
+    /// Instead of going through [Runtime::helpers], helper calls are emitted against
+    /// the undefined symbols `bpf_helper_<index>` (e.g., `bpf_helper_1` for `bpf_map_lookup_elem`),
+    /// which are to be resolved at link time.
+    /// Functions are exported under the same names as in [Compiler::compile], the main one being `u0:0`.
+    #[cfg(feature = "object")]
+    pub fn compile_object(
+        &self,
+        code: &[u64],
+        info: &ProgramInfo,
+        runtime: &Runtime,
+    ) -> Result<Vec<u8>, ModuleError> {
+        let isa = host_isa().map_err(|e| ModuleError::Backend(e.into()))?;
+        let builder = ObjectBuilder::new(isa, "ebpf", cranelift_module::default_libcall_names())?;
+        let mut module = ObjectModule::new(builder);
+        self.define_functions(code, info, runtime, &mut module, &ebpf_signature(), None)?;
+        module
+            .finish()
+            .emit()
+            .map_err(|e| ModuleError::Backend(anyhow!("Unable to emit the object: {e}")))
+    }
+
+    /// Declares and defines all functions in the program
+    ///
+    /// Helpers are called by their addresses if `helpers` is given,
+    /// or through imported `bpf_helper_<index>` symbols otherwise.
+    fn define_functions<M: Module>(
+        &self,
+        code: &[u64],
+        info: &ProgramInfo,
+        runtime: &Runtime,
+        module: &mut M,
+        signature: &Signature,
+        helpers: Option<&[HelperPointer]>,
+    ) -> Result<Vec<FuncId>, ModuleError> {
+        // Checks that all used maps are available before generating any code
+        for fd in info.map_fds() {
+            if (runtime.map_fd_mapper)(fd).is_none() {
+                return Err(ModuleError::Backend(anyhow!("Map fd {fd} not available")));
+            }
+        }
 
         let mut context = Context::new();
         let mut builder_context = FunctionBuilderContext::new();
-        let signature = module.signature().clone();
-        let functions = self.functions(info, &mut module, &signature)?;
+        let functions = self.functions(info, module, signature)?;
 
         for (i, f) in info.functions.iter().enumerate() {
             context.func.name = UserFuncName::user(0, i as u32);
@@ -55,8 +114,11 @@ impl Compiler {
             let sig_ref = context.func.import_signature(signature.clone());
 
             let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
-            let stack = builder
-                .create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 512));
+            let stack_size = self.stack_size(info, i);
+            let stack = builder.create_sized_stack_slot(StackSlotData::new(
+                StackSlotKind::ExplicitSlot,
+                stack_size,
+            ));
             let registers = self.function_registers(&mut builder);
 
             let mut blocks: Vec<Block> = Vec::new();
@@ -78,7 +140,7 @@ impl Compiler {
                         builder.def_var(registers[k], param);
                     });
                     let stack_base = builder.ins().stack_addr(I64, stack, 0);
-                    let stack_top = builder.ins().iadd_imm(stack_base, 512);
+                    let stack_top = builder.ins().iadd_imm(stack_base, stack_size as i64);
                     builder.def_var(registers[10], stack_top);
                 } else {
                     builder.switch_to_block(*block);
@@ -131,9 +193,30 @@ impl Compiler {
                                         let dst = builder.ins().ireduce(t, dst);
                                     ##
 
-                                    #?((!isub))
+                                    #?((!isub, !udiv, !urem))
                                         let result = builder.ins().#"{}_imm"2(dst, insn.imm as i64);
                                     ##
+                                    // Division by a zero immediate traps, so we follow the spec instead
+                                    #?((udiv))
+                                        let result = if insn.is_signed_division() {
+                                            let rhs = builder.ins().iconst(t, insn.imm as i64);
+                                            self.push_division(&mut builder, dst, rhs, true, false)
+                                        } else if insn.imm == 0 {
+                                            builder.ins().iconst(t, 0)
+                                        } else {
+                                            builder.ins().udiv_imm(dst, insn.imm as i64)
+                                        };
+                                    ##
+                                    #?((urem))
+                                        let result = if insn.is_signed_division() {
+                                            let rhs = builder.ins().iconst(t, insn.imm as i64);
+                                            self.push_division(&mut builder, dst, rhs, true, true)
+                                        } else if insn.imm == 0 {
+                                            dst
+                                        } else {
+                                            builder.ins().urem_imm(dst, insn.imm as i64)
+                                        };
+                                    ##
                                     #?((isub))
                                         // There is no `isub_imm`
                                         let result = builder.ins().iadd_imm(dst, -(insn.imm as i64));
@@ -147,33 +230,37 @@ impl Compiler {
                                     let rhs = builder.ins().ireduce(I32, rhs);
                                 ##
 
-                                // Avoid FPU traps
-                                #?((udiv)|(urem))
-                                    let is_zero = builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
-                                    let one = builder.ins().iconst(t, 1);
-                                    let rhs = builder.ins().select(is_zero, one, rhs);
-                                ##
-
                                 #?((MOV))
-                                    let result = rhs;
+                                    // Sign-extending moves
+                                    let result = match insn.is_sign_extending_move() {
+                                        Some(width) => {
+                                            let narrow = match width {
+                                                8 => I8,
+                                                16 => I16,
+                                                _ => I32,
+                                            };
+                                            let rhs = builder.ins().ireduce(narrow, rhs);
+                                            builder.ins().sextend(t, rhs)
+                                        }
+                                        None => rhs,
+                                    };
                                 ##
                                 #?((!MOV))
                                     let dst = builder.use_var(dst_reg);
                                     #?((ALU32))
                                         let dst = builder.ins().ireduce(I32, dst);
                                     ##
-                                    let result = builder.ins().#=2(dst, rhs);
-                                ##
-
-                                // Make behaviours match the spec
-                                #?((udiv))
-                                    // zero_division ? 0 : result
-                                    let zero = builder.ins().iconst(t, 0);
-                                    let result = builder.ins().select(is_zero, zero, result);
-                                ##
-                                #?((urem))
-                                    // zero_division ? dst : result
-                                    let result = builder.ins().select(is_zero, dst, result);
+                                    #?((!udiv, !urem))
+                                        let result = builder.ins().#=2(dst, rhs);
+                                    ##
+                                    #?((udiv))
+                                        let signed = insn.is_signed_division();
+                                        let result = self.push_division(&mut builder, dst, rhs, signed, false);
+                                    ##
+                                    #?((urem))
+                                        let signed = insn.is_signed_division();
+                                        let result = self.push_division(&mut builder, dst, rhs, signed, true);
+                                    ##
                                 ##
                             ##
 
@@ -212,10 +299,20 @@ impl Compiler {
                             match insn.src_reg() {
                                 BPF_CALL_HELPER => {
                                     let helper = insn.imm;
-                                    let callee = builder.ins().iconst(
-                                        I64, runtime.helpers[helper as usize] as *const
-                                        HelperPointer as u64 as i64);
-                                    let inst = builder.ins().call_indirect(sig_ref, callee, args);
+                                    let inst = if let Some(helpers) = helpers {
+                                        let callee = builder.ins().iconst(
+                                            I64, helpers[helper as usize] as *const
+                                            HelperPointer as u64 as i64);
+                                        builder.ins().call_indirect(sig_ref, callee, args)
+                                    } else {
+                                        let id = module.declare_function(
+                                            &format!("bpf_helper_{helper}"),
+                                            Linkage::Import,
+                                            signature,
+                                        )?;
+                                        let callee = module.declare_func_in_func(id, builder.func);
+                                        builder.ins().call(callee, args)
+                                    };
                                     let result = builder.inst_results(inst)[0];
                                     builder.def_var(registers[0], result);
                                 }
@@ -227,11 +324,11 @@ impl Compiler {
                                     let result = builder.inst_results(call)[0];
                                     builder.def_var(registers[0], result);
                                 }
-                                _ => panic!("Unsupported call")
+                                _ => panic!("Unsupported call: {insn}")
                             }
                         }
-                        // BPF_JA: Unconditional jump
-                        [[BPF_JMP: JMP], [BPF_JA: JA]] => {
+                        // BPF_JA: Unconditional jump (including `gotol` from BPF_JMP32)
+                        [[BPF_JMP: JMP, BPF_JMP32: JMP32], [BPF_JA: JA]] => {
                             builder.ins().jump(blocks[f.from[j][0]], &[]);
                             jumped = true;
                         }
@@ -297,7 +394,20 @@ impl Compiler {
                                     let rhs = builder.ins().iconst(I64, value as i64);
                                     builder.def_var(registers[insn.dst_reg() as usize], rhs);
                                 }
-                                _ => panic!("Unsupported instruction"),
+                                BPF_IMM64_MAP_FD | BPF_IMM64_MAP_VALUE => {
+                                    let map = (runtime.map_fd_mapper)(insn.imm).ok_or_else(|| {
+                                        ModuleError::Backend(anyhow!("Map fd {} not available", insn.imm))
+                                    })?;
+                                    let value = if insn.src_reg() == BPF_IMM64_MAP_VALUE {
+                                        // The offset into the map value lies in the next imm
+                                        map.wrapping_add((next >> 32) as u32 as u64)
+                                    } else {
+                                        map
+                                    };
+                                    let rhs = builder.ins().iconst(I64, value as i64);
+                                    builder.def_var(registers[insn.dst_reg() as usize], rhs);
+                                }
+                                _ => panic!("Unsupported instruction: {insn}"),
                             }
                         }
                         [[BPF_LDX: LDX], [BPF_MEM: MEM],
@@ -381,7 +491,7 @@ impl Compiler {
                             self.push_atomic(insn, &mut builder, &registers, I64);
                         }
                         _ => {
-                            panic!("Unsupported instruction");
+                            panic!("Unsupported instruction: {insn}");
                         }
                     }
                 }
@@ -395,8 +505,51 @@ impl Compiler {
             module.define_function(functions[i], &mut context)?;
             module.clear_context(&mut context);
         }
-        module.finalize_definitions()?;
-        Ok((functions[0], module))
+        Ok(functions)
+    }
+
+    /// Pushes a division (or modulo) that follows the spec instead of trapping
+    ///
+    /// - `x / 0 == 0` and `x % 0 == x`;
+    /// - for signed ones, `INT_MIN / -1 == INT_MIN` and `INT_MIN % -1 == 0`.
+    fn push_division(
+        &self,
+        builder: &mut FunctionBuilder,
+        dst: Value,
+        rhs: Value,
+        signed: bool,
+        modulo: bool,
+    ) -> Value {
+        let t = builder.func.dfg.value_type(dst);
+        let zero = builder.ins().iconst(t, 0);
+        let one = builder.ins().iconst(t, 1);
+        // Avoid FPU traps by dividing by one instead
+        let is_zero = builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
+        let divisor = builder.ins().select(is_zero, one, rhs);
+        let result = if signed {
+            // INT_MIN / -1 traps as well, while `-x` wraps around just fine
+            let is_minus_one = builder.ins().icmp_imm(IntCC::Equal, rhs, -1);
+            let divisor = builder.ins().select(is_minus_one, one, divisor);
+            if modulo {
+                // x % 1 == 0 == x % -1
+                builder.ins().srem(dst, divisor)
+            } else {
+                let quotient = builder.ins().sdiv(dst, divisor);
+                let negated = builder.ins().ineg(dst);
+                builder.ins().select(is_minus_one, negated, quotient)
+            }
+        } else if modulo {
+            builder.ins().urem(dst, divisor)
+        } else {
+            builder.ins().udiv(dst, divisor)
+        };
+        if modulo {
+            // zero_division ? dst : result
+            builder.ins().select(is_zero, dst, result)
+        } else {
+            // zero_division ? 0 : result
+            builder.ins().select(is_zero, zero, result)
+        }
     }
 
     fn push_atomic(
@@ -476,10 +629,10 @@ impl Compiler {
         (to, i + 1)
     }
 
-    fn functions(
+    fn functions<M: Module>(
         &self,
         info: &ProgramInfo,
-        module: &mut LinkageModule,
+        module: &mut M,
         signature: &Signature,
     ) -> Result<Vec<FuncId>, ModuleError> {
         let mut functions: Vec<FuncId> = Vec::new();
@@ -496,6 +649,19 @@ impl Compiler {
         Ok(functions)
     }
 
+    /// Returns the stack size of a function, rounded up to 8 bytes
+    ///
+    /// It falls back to [STACK_SIZE] if the program is not analyzed
+    /// (that is, [ProgramInfo::max_stack_depth] is not filled in).
+    fn stack_size(&self, info: &ProgramInfo, function: usize) -> u32 {
+        let depth = info
+            .max_stack_depth
+            .get(function)
+            .copied()
+            .unwrap_or(STACK_SIZE);
+        depth.next_multiple_of(8) as u32
+    }
+
     fn function_registers(&self, builder: &mut FunctionBuilder) -> [Variable; 11] {
         let registers: [Variable; 11] = [
             Variable::new(0),
@@ -604,7 +770,7 @@ exit",
                         }
                     },
                     processed_instruction_limit: 20,
-                    map_fd_collector: &|_| None,
+                    ..Default::default()
                 },
             )
             .unwrap(),
@@ -629,3 +795,303 @@ exit",
         );
     }
 }
+
+#[test]
+fn test_multiple_functions() {
+    let code = [
+        // main:
+        // 0: r6 = r1
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 6, 0, 0),
+        // 1: *(u64 *)(r10 - 8) = r2
+        Instruction::pack(BPF_STX | BPF_MEM | BPF_DW, 2, 10, -8, 0),
+        // 2: r1 = r2
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 2, 1, 0, 0),
+        // 3: call test (pc + 4)
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 4),
+        // 4: r1 = *(u64 *)(r10 - 8)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 10, 1, -8, 0),
+        // 5: r0 += r1
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 1, 0, 0, 0),
+        // 6: r0 += r6
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 6, 0, 0, 0),
+        // 7: exit
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+        // test: clobbering its own r6 and stack
+        // 8: r6 = 100
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 6, 0, 100),
+        // 9: *(u64 *)(r10 - 8) = 0
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -8, 0),
+        // 10: call triple (pc + 1)
+        Instruction::pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 1),
+        // 11: exit
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+        // triple:
+        // 12: r0 = r1
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 0, 0, 0),
+        // 13: r0 *= 3
+        Instruction::pack(BPF_ALU64 | BPF_MUL | BPF_K, 0, 0, 0, 3),
+        // 14: exit
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let info = ProgramInfo::new(&code).unwrap();
+    assert_eq!(info.functions.len(), 3);
+    let c = Compiler {};
+    let (main, module) = c
+        .compile(
+            &code,
+            &info,
+            &Runtime {
+                helpers: &[],
+                map_fd_mapper: &|_| None,
+            },
+        )
+        .unwrap();
+    let entry = module.get_finalized_function(main).unwrap();
+    use llvm_util::conformance::copy_to_executable_memory;
+    let exec = copy_to_executable_memory(entry);
+    let main_fn = unsafe { to_ebpf_function(exec.as_ptr()) };
+    for (a, b) in [(0, 0), (1, 2), (37, 349), (u32::MAX as u64, 7)] {
+        assert_eq!(main_fn(a, b, 0, 0, 0), b * 3 + b + a);
+    }
+}
+
+#[test]
+fn test_map_fd() {
+    let code = [
+        // r1 = &map_value(fd 3) + 8
+        Instruction::pack(BPF_LD | BPF_IMM | BPF_DW, BPF_IMM64_MAP_VALUE, 1, 0, 3),
+        8 << 32,
+        // r0 = *(u64 *)(r1 + 0)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 1, 0, 0, 0),
+        // r2 = map (fd 3)
+        Instruction::pack(BPF_LD | BPF_IMM | BPF_DW, BPF_IMM64_MAP_FD, 2, 0, 3),
+        0,
+        // r3 = *(u64 *)(r2 + 0)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 2, 3, 0, 0),
+        // r0 += r3
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 3, 0, 0, 0),
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let info = ProgramInfo::new(&code).unwrap();
+    let buffer = alloc::boxed::Box::new([5u64, 37u64]);
+    let address = buffer.as_ptr() as u64;
+    let mapper = |fd: i32| if fd == 3 { Some(address) } else { None };
+    let c = Compiler {};
+    let (main, module) = c
+        .compile(
+            &code,
+            &info,
+            &Runtime {
+                helpers: &[],
+                map_fd_mapper: &mapper,
+            },
+        )
+        .unwrap();
+    let entry = module.get_finalized_function(main).unwrap();
+    use llvm_util::conformance::copy_to_executable_memory;
+    let exec = copy_to_executable_memory(entry);
+    let main_fn = unsafe { to_ebpf_function(exec.as_ptr()) };
+    assert_eq!(main_fn(0, 0, 0, 0, 0), 42);
+
+    // Unknown map fds
+    assert!(c
+        .compile(
+            &code,
+            &info,
+            &Runtime {
+                helpers: &[],
+                map_fd_mapper: &|_| None,
+            },
+        )
+        .is_err());
+}
+
+#[test]
+fn test_stack_size() {
+    let code = [
+        // *(u64 *)(r10 - 16) = r1
+        Instruction::pack(BPF_STX | BPF_MEM | BPF_DW, 1, 10, -16, 0),
+        // *(u32 *)(r10 - 4) = 3
+        Instruction::pack(BPF_ST | BPF_MEM | BPF_W, 0, 10, -4, 3),
+        // r0 = *(u64 *)(r10 - 16)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_DW, 10, 0, -16, 0),
+        // r1 = *(u32 *)(r10 - 4)
+        Instruction::pack(BPF_LDX | BPF_MEM | BPF_W, 10, 1, -4, 0),
+        // r0 += r1
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 1, 0, 0, 0),
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    use ebpf_analyzer::interpreter::vm::Vm;
+    use llvm_util::conformance::copy_to_executable_memory;
+    let c = Compiler {};
+    let unanalyzed = ProgramInfo::new(&code).unwrap();
+    assert_eq!(c.stack_size(&unanalyzed, 0), STACK_SIZE as u32);
+
+    let info = ebpf_analyzer::analyzer::Analyzer::analyze(
+        &code,
+        &ebpf_analyzer::analyzer::AnalyzerConfig {
+            helpers: &[],
+            setup: &|vm| {
+                *vm.reg(1) = ebpf_analyzer::track::scalar::Scalar::unknown().into();
+            },
+            processed_instruction_limit: 20,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(c.stack_size(&info, 0), 16);
+
+    for info in [unanalyzed, info] {
+        let (main, module) = c
+            .compile(
+                &code,
+                &info,
+                &Runtime {
+                    helpers: &[],
+                    map_fd_mapper: &|_| None,
+                },
+            )
+            .unwrap();
+        let exec = copy_to_executable_memory(module.get_finalized_function(main).unwrap());
+        let main_fn = unsafe { to_ebpf_function(exec.as_ptr()) };
+        assert_eq!(main_fn(39, 0, 0, 0, 0), 42);
+    }
+}
+
+#[test]
+fn test_zero_division_imm() {
+    // Rejected by the analyzer, so we construct `ProgramInfo` without verification
+    let code = [
+        // r0 = r1 / 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_DIV | BPF_K, 0, 0, 0, 0),
+        // r2 = r1 % 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 2, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOD | BPF_K, 0, 2, 0, 0),
+        // w3 = w1 % 0
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 3, 0, 0),
+        Instruction::pack(BPF_ALU | BPF_MOD | BPF_K, 0, 3, 0, 0),
+        // r0 += r2 + r3
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 2, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_X, 3, 0, 0, 0),
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let info = ProgramInfo::new(&code).unwrap();
+    let (main, module) = Compiler {}
+        .compile(
+            &code,
+            &info,
+            &Runtime {
+                helpers: &[],
+                map_fd_mapper: &|_| None,
+            },
+        )
+        .unwrap();
+    let entry = module.get_finalized_function(main).unwrap();
+    use llvm_util::conformance::copy_to_executable_memory;
+    let exec = copy_to_executable_memory(entry);
+    let main_fn = unsafe { to_ebpf_function(exec.as_ptr()) };
+    for a in [0, 1, 37, u32::MAX as u64 + 3] {
+        assert_eq!(main_fn(a, 0, 0, 0, 0), a + (a & 0xFFFF_FFFF));
+    }
+}
+
+#[test]
+fn test_signed_division() {
+    let code = [
+        // r0 = r1 s/ r2
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_DIV | BPF_X, 2, 0, BPF_SDIV, 0),
+        // r3 = r1 s% r2
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 3, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOD | BPF_X, 2, 3, BPF_SMOD, 0),
+        // w4 = w1 s/ -3
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 4, 0, 0),
+        Instruction::pack(BPF_ALU | BPF_DIV | BPF_K, 0, 4, BPF_SDIV, -3),
+        // r5 = r1 s% -3
+        Instruction::pack(BPF_ALU64 | BPF_MOV | BPF_X, 1, 5, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_MOD | BPF_K, 0, 5, BPF_SMOD, -3),
+        // Packs the results into r0
+        Instruction::pack(BPF_ALU64 | BPF_LSH | BPF_K, 0, 0, 0, 8),
+        Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 3, 0, 0xFF),
+        Instruction::pack(BPF_ALU64 | BPF_OR | BPF_X, 3, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_LSH | BPF_K, 0, 0, 0, 8),
+        Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 4, 0, 0xFF),
+        Instruction::pack(BPF_ALU64 | BPF_OR | BPF_X, 4, 0, 0, 0),
+        Instruction::pack(BPF_ALU64 | BPF_LSH | BPF_K, 0, 0, 0, 8),
+        Instruction::pack(BPF_ALU64 | BPF_AND | BPF_K, 0, 5, 0, 0xFF),
+        Instruction::pack(BPF_ALU64 | BPF_OR | BPF_X, 5, 0, 0, 0),
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let info = ProgramInfo::new(&code).unwrap();
+    let (main, module) = Compiler {}
+        .compile(
+            &code,
+            &info,
+            &Runtime {
+                helpers: &[],
+                map_fd_mapper: &|_| None,
+            },
+        )
+        .unwrap();
+    let entry = module.get_finalized_function(main).unwrap();
+    use llvm_util::conformance::copy_to_executable_memory;
+    let exec = copy_to_executable_memory(entry);
+    let main_fn = unsafe { to_ebpf_function(exec.as_ptr()) };
+    let expected = |a: i64, b: i64| {
+        let (quotient, remainder) = match b {
+            0 => (0, a),
+            _ => (a.wrapping_div(b), a.wrapping_rem(b)),
+        };
+        let bytes = [
+            0,
+            remainder as u8,
+            (a as i32).wrapping_div(-3) as u8,
+            a.wrapping_rem(-3) as u8,
+        ];
+        (quotient as u64) << 24 | u32::from_be_bytes(bytes) as u64
+    };
+    for (a, b) in [
+        (-7, 2),
+        (7, -2),
+        (-7, -2),
+        (-8, 0),
+        (i64::MIN, -1),
+        (i64::MIN + 1, 0),
+        (i32::MIN as i64, 3),
+    ] {
+        assert_eq!(
+            main_fn(a as u64, b as u64, 0, 0, 0),
+            expected(a, b),
+            "{a} / {b}"
+        );
+    }
+}
+
+#[cfg(feature = "object")]
+#[test]
+fn test_compile_object() {
+    use object::{Object, ObjectSymbol};
+    let code = [
+        // r0 = bpf_helper_1(r1, ...)
+        Instruction::pack(BPF_JMP | BPF_CALL, BPF_CALL_HELPER, 0, 0, 1),
+        // r0 += 1
+        Instruction::pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+        Instruction::pack(BPF_JMP_EXIT, 0, 0, 0, 0),
+    ];
+    let info = ProgramInfo::new(&code).unwrap();
+    let bytes = Compiler {}
+        .compile_object(
+            &code,
+            &info,
+            &Runtime {
+                helpers: &[],
+                map_fd_mapper: &|_| None,
+            },
+        )
+        .unwrap();
+    let file = object::File::parse(bytes.as_slice()).unwrap();
+    let symbol = |name: &str| file.symbols().find(|s| s.name() == Ok(name));
+    assert!(symbol("u0:0").unwrap().is_definition());
+    assert!(symbol("bpf_helper_1").unwrap().is_undefined());
+}
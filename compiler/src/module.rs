@@ -48,6 +48,33 @@ impl FunctionDefinition {
     }
 }
 
+/// Creates the host [TargetIsa] with settings suitable for eBPF functions
+pub(crate) fn host_isa() -> Result<Box<dyn TargetIsa>, LookupError> {
+    let builder = isa::lookup(Triple::host())?;
+    let mut flags_builder = settings::builder();
+    flags_builder
+        .set("is_pic", "true")
+        .map_err(|_| LookupError::Unsupported)?;
+    flags_builder
+        .set("enable_float", "false")
+        .map_err(|_| LookupError::Unsupported)?;
+    flags_builder
+        .set("enable_atomics", "true")
+        .map_err(|_| LookupError::Unsupported)?;
+    let flags = settings::Flags::new(flags_builder);
+    builder.finish(flags).map_err(|_| LookupError::Unsupported)
+}
+
+/// The signature of eBPF functions and helpers: `fn(u64, u64, u64, u64, u64) -> u64`
+pub(crate) fn ebpf_signature() -> Signature {
+    let value = AbiParam::new(I64);
+    Signature {
+        params: alloc::vec![value, value, value, value, value],
+        returns: alloc::vec![value],
+        call_conv: CallConv::SystemV,
+    }
+}
+
 /// A [Module] implementation
 pub struct BpfModule {
     isa: Box<dyn TargetIsa>,
@@ -60,31 +87,11 @@ pub struct BpfModule {
 impl BpfModule {
     /// Creates a new module according to the host [TargetIsa]
     pub fn new() -> Result<Self, LookupError> {
-        let builder = isa::lookup(Triple::host())?;
-        let mut flags_builder = settings::builder();
-        flags_builder
-            .set("is_pic", "true")
-            .map_err(|_| LookupError::Unsupported)?;
-        flags_builder
-            .set("enable_float", "false")
-            .map_err(|_| LookupError::Unsupported)?;
-        flags_builder
-            .set("enable_atomics", "true")
-            .map_err(|_| LookupError::Unsupported)?;
-        let flags = settings::Flags::new(flags_builder);
-        let isa = builder
-            .finish(flags)
-            .map_err(|_| LookupError::Unsupported)?;
-        let value = AbiParam::new(I64);
         Ok(Self {
-            isa,
+            isa: host_isa()?,
             declarations: ModuleDeclarations::default(),
             definitions: SecondaryMap::new(),
-            signature: Signature {
-                params: alloc::vec![value, value, value, value, value],
-                returns: alloc::vec![value],
-                call_conv: CallConv::SystemV,
-            },
+            signature: ebpf_signature(),
             binary: None,
         })
     }
@@ -37,6 +37,33 @@ fn test_local_call() {
     test_with_conformance_data(&data);
 }
 
+#[test]
+fn test_cmpxchg32_zero_extension() {
+    // r0 has its upper bits set, which are ignored by the comparison,
+    // and the fetched value gets zero-extended into r0, plus 0x100 if exchanged
+    for (initial, returns) in [(5, 0x105), (9, 9)] {
+        let code = bpfasm::parse(&format!(
+            "stdw [r10-8], {initial}
+            lddw r0, 0xffffffff00000005
+            mov r1, 7
+            lock cmpxchg32 [r10-8], r1
+            ldxdw r2, [r10-8]
+            jeq r2, 7, +1
+            exit
+            add r0, 0x100
+            exit"
+        ))
+        .unwrap();
+        test_with_conformance_data(&ConformanceData {
+            name: format!("cmpxchg32-{initial}"),
+            memory: Vec::new(),
+            returns,
+            code,
+            error: String::new(),
+        });
+    }
+}
+
 fn test_with_conformance_data(data: &ConformanceData) {
     if data.error.is_empty() {
         std::println!("Running {}", data.name);
@@ -68,7 +95,7 @@ fn test_with_conformance_data(data: &ConformanceData) {
                     *vm.reg(2) = Scalar::constant64(data.memory.len() as u64).into();
                 },
                 processed_instruction_limit: 1_000_000,
-                map_fd_collector: &|_| None,
+                ..Default::default()
             },
         )
         .unwrap();
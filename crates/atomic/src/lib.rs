@@ -13,12 +13,38 @@
 use core::{num::Wrapping, sync::atomic::Ordering};
 
 /// Error when performing atomic operations
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum AtomicError {
     /// Invalid memory access
     IllegalAccess,
-    /// Unsupported size (currently only 32-bit / 64-bits)
-    UnsupportedBitness,
+    /// Unsupported size in bytes (currently only 32-bit / 64-bits, see [check_size])
+    UnsupportedBitness {
+        /// The operand size in bytes
+        size: usize,
+    },
+}
+
+impl AtomicError {
+    /// Describes the error, naming the unsupported size if any
+    pub fn message(&self) -> &'static str {
+        match self {
+            AtomicError::IllegalAccess => "Atomic failed",
+            AtomicError::UnsupportedBitness { size: 1 } => "Atomic failed: unsupported size 1",
+            AtomicError::UnsupportedBitness { size: 2 } => "Atomic failed: unsupported size 2",
+            AtomicError::UnsupportedBitness { size: 4 } => "Atomic failed: unsupported size 4",
+            AtomicError::UnsupportedBitness { size: 8 } => "Atomic failed: unsupported size 8",
+            AtomicError::UnsupportedBitness { .. } => "Atomic failed: unsupported size",
+        }
+    }
+}
+
+/// Checks whether atomic operations of `size` bytes are supported with the enabled features
+pub fn check_size(size: usize) -> Result<(), AtomicError> {
+    match size {
+        4 if cfg!(feature = "atomic32") => Ok(()),
+        8 if cfg!(feature = "atomic64") => Ok(()),
+        _ => Err(AtomicError::UnsupportedBitness { size }),
+    }
 }
 
 /// A trait wrapping up `AtomicU32` and `AtomicU64`, which might not be available
@@ -102,7 +128,7 @@ macro_rules! atomic_impl {
                 8 => Ok(
                     unsafe { crate::u64::from_u64_addr(ptr) }.$func_name(*rhs, Ordering::SeqCst)
                 ),
-                _ => Err(AtomicError::UnsupportedBitness),
+                _ => Err(AtomicError::UnsupportedBitness { size }),
             }
         }
     };
@@ -148,7 +174,7 @@ impl Atomic for u64 {
                     Err(v) => v,
                 },
             ),
-            _ => Err(AtomicError::UnsupportedBitness),
+            _ => Err(AtomicError::UnsupportedBitness { size }),
         }
     }
 }
@@ -220,10 +246,20 @@ fn test_memory_access() -> Result<(), AtomicError> {
     assert!(i == 32);
     assert!(j == 32);
 
-    assert!(ptr_i.fetch_add(0, &Default::default(), 2).is_err());
-    assert!(ptr_i.compare_exchange(0, &Default::default(), &Default::default(), 2).is_err());
-    assert!(ptr_j.fetch_add(0, &Default::default(), 2).is_err());
-    assert!(ptr_j.compare_exchange(0, &Default::default(), &Default::default(), 2).is_err());
+    for size in [1, 2] {
+        let unsupported = Err(AtomicError::UnsupportedBitness { size });
+        assert_eq!(ptr_i.fetch_add(0, &Default::default(), size), unsupported);
+        assert_eq!(ptr_j.swap(0, &Default::default(), size), unsupported);
+        assert_eq!(
+            ptr_j.compare_exchange(0, &Default::default(), &Default::default(), size),
+            unsupported
+        );
+        assert_eq!(check_size(size), unsupported.map(|_| ()));
+    }
+    assert_eq!(
+        AtomicError::UnsupportedBitness { size: 2 }.message(),
+        "Atomic failed: unsupported size 2"
+    );
 
     extern crate std;
     std::println!("Tese debug: {:?}", AtomicError::IllegalAccess);
@@ -0,0 +1,557 @@
+//! This crate assembles eBPF assembly text into bytecode
+//!
+//! It accepts the syntax used by [bpf_conformance](https://github.com/Alan-Jowett/bpf_conformance)
+//! test data, so that tests no longer need an external assembler:
+//!
+//! ```text
+//! mov r0, 0
+//! jeq r1, 0, done      # jumps to a label
+//! ldxw r0, [r1+8]
+//! done:
+//! exit
+//! ```
+//!
+//! - ALU operations are 64-bit unless suffixed with `32` (`add32 r0, 1`).
+//! - Memory operands are either `[r1+8]` or `*(u32 *)(r1 + 8)`. With the latter,
+//!   the size suffix of the mnemonic may be omitted (`ldx r0, *(u32 *)(r1 + 8)`).
+//! - Jump targets are either relative offsets (`+2`, `-1`) or labels,
+//!   and so are targets of local calls (`call local func`).
+//! - Comments start with `#`.
+
+#![no_std]
+#![forbid(missing_docs)]
+
+extern crate alloc;
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use ebpf_consts::mask::*;
+use ebpf_consts::*;
+
+/// Error when parsing the assembly
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line number, starting from 1
+    pub line: usize,
+    /// What went wrong
+    pub kind: ParseErrorKind,
+}
+
+/// See [ParseError]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Unrecognized mnemonic
+    UnknownMnemonic(String),
+    /// Operand count mismatch, with the expected count
+    OperandCount(usize),
+    /// Malformed operand
+    InvalidOperand(String),
+    /// Immediate number, offset or jump target not fitting into its field
+    OutOfRange(String),
+    /// The size suffix of the mnemonic contradicts the one of the memory operand
+    SizeMismatch,
+    /// Jumping to or calling an undefined label
+    UndefinedLabel(String),
+    /// A label defined more than once
+    DuplicateLabel(String),
+}
+
+use ParseErrorKind::*;
+
+/// Parses the assembly into bytecode
+///
+/// Each line holds an optional label (`name:`) and at most one instruction.
+/// `lddw` takes up two slots, as is required by wide instructions.
+pub fn parse(text: &str) -> Result<Vec<u64>, ParseError> {
+    let mut assembler = Assembler::default();
+    for (i, line) in text.lines().enumerate() {
+        assembler.line = i + 1;
+        assembler.parse_line(line)?;
+    }
+    assembler.resolve()
+}
+
+const ALU_OPS: &[(&str, u8, i16)] = &[
+    ("add", BPF_ADD, 0),
+    ("sub", BPF_SUB, 0),
+    ("mul", BPF_MUL, 0),
+    ("div", BPF_DIV, 0),
+    ("sdiv", BPF_DIV, BPF_SDIV),
+    ("or", BPF_OR, 0),
+    ("and", BPF_AND, 0),
+    ("lsh", BPF_LSH, 0),
+    ("rsh", BPF_RSH, 0),
+    ("mod", BPF_MOD, 0),
+    ("smod", BPF_MOD, BPF_SMOD),
+    ("xor", BPF_XOR, 0),
+    ("mov", BPF_MOV, 0),
+    ("arsh", BPF_ARSH, 0),
+];
+
+const JMP_OPS: &[(&str, u8)] = &[
+    ("jeq", BPF_JEQ),
+    ("jgt", BPF_JGT),
+    ("jge", BPF_JGE),
+    ("jset", BPF_JSET),
+    ("jne", BPF_JNE),
+    ("jsgt", BPF_JSGT),
+    ("jsge", BPF_JSGE),
+    ("jlt", BPF_JLT),
+    ("jle", BPF_JLE),
+    ("jslt", BPF_JSLT),
+    ("jsle", BPF_JSLE),
+];
+
+const ATOMIC_OPS: &[(&str, i32)] = &[
+    ("add", BPF_ATOMIC_ADD),
+    ("or", BPF_ATOMIC_OR),
+    ("and", BPF_ATOMIC_AND),
+    ("xor", BPF_ATOMIC_XOR),
+    ("xchg", BPF_ATOMIC_XCHG),
+    ("cmpxchg", BPF_ATOMIC_CMPXCHG),
+];
+
+/// Load / store mnemonics, followed by an optional size suffix
+const MEMORY_OPS: &[(&str, u8)] = &[
+    ("ldxs", BPF_LDX | BPF_MEMSX),
+    ("ldx", BPF_LDX | BPF_MEM),
+    ("stx", BPF_STX | BPF_MEM),
+    ("st", BPF_ST | BPF_MEM),
+    ("ldabs", BPF_LD | BPF_ABS),
+    ("ldind", BPF_LD | BPF_IND),
+];
+
+/// An instruction referring to a label, patched after all labels are known
+struct Fixup {
+    pc: usize,
+    line: usize,
+    label: String,
+    /// Whether the relative target goes into `imm` (calls) instead of `off` (jumps)
+    call: bool,
+}
+
+#[derive(Default)]
+struct Assembler {
+    code: Vec<u64>,
+    labels: BTreeMap<String, usize>,
+    fixups: Vec<Fixup>,
+    line: usize,
+}
+
+/// Splits off the first word
+fn split_word(s: &str) -> (&str, &str) {
+    match s.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (s, ""),
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parses a decimal or hexadecimal number, optionally signed
+fn number(s: &str) -> Option<i128> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    if !s.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => s.parse::<u64>().ok()?,
+    } as i128;
+    Some(if negative { -value } else { value })
+}
+
+/// Parses a size suffix or the bit count in a cast
+fn size(suffix: &str) -> Option<u8> {
+    match suffix {
+        "b" | "8" => Some(BPF_B),
+        "h" | "16" => Some(BPF_H),
+        "w" | "32" => Some(BPF_W),
+        "dw" | "64" => Some(BPF_DW),
+        _ => None,
+    }
+}
+
+/// Splits off a `32` / `64` suffix, returning whether it is a 32-bit operation
+fn bitness(mnemonic: &str) -> (&str, bool) {
+    match mnemonic.strip_suffix("32") {
+        Some(base) => (base, true),
+        None => (mnemonic.strip_suffix("64").unwrap_or(mnemonic), false),
+    }
+}
+
+/// Either a register (`BPF_X`) or an immediate number (`BPF_K`)
+enum Source {
+    Register(u8),
+    Immediate(i32),
+}
+
+impl Assembler {
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), ParseError> {
+        let mut line = line.split('#').next().unwrap_or_default().trim();
+        if let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if !is_identifier(label) {
+                return Err(self.error(InvalidOperand(label.to_string())));
+            }
+            if self
+                .labels
+                .insert(label.to_string(), self.code.len())
+                .is_some()
+            {
+                return Err(self.error(DuplicateLabel(label.to_string())));
+            }
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let (mnemonic, rest) = split_word(line);
+        if mnemonic == "lock" {
+            let (op, rest) = split_word(rest);
+            let (fetch, op, rest) = match op {
+                "fetch" => {
+                    let (op, rest) = split_word(rest);
+                    (true, op, rest)
+                }
+                _ => (false, op, rest),
+            };
+            return self.atomic(op, fetch, &Self::operands(rest));
+        }
+        self.instruction(mnemonic, &Self::operands(rest))
+    }
+
+    fn operands(s: &str) -> Vec<&str> {
+        if s.is_empty() {
+            Vec::new()
+        } else {
+            s.split(',').map(str::trim).collect()
+        }
+    }
+
+    fn expect<'a, const N: usize>(&self, operands: &[&'a str]) -> Result<[&'a str; N], ParseError> {
+        operands.try_into().map_err(|_| self.error(OperandCount(N)))
+    }
+
+    fn push(&mut self, opcode: u8, dst: u8, src: u8, off: i16, imm: i32) {
+        self.code.push(
+            opcode as u64
+                | (dst as u64) << 8
+                | (src as u64) << 12
+                | (off as u16 as u64) << 16
+                | (imm as u32 as u64) << 32,
+        );
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: &[&str]) -> Result<(), ParseError> {
+        match mnemonic {
+            "exit" => {
+                self.expect::<0>(operands)?;
+                self.push(BPF_JMP_EXIT, 0, 0, 0, 0);
+            }
+            "call" => self.call(operands)?,
+            "ja" => {
+                let [target] = self.expect(operands)?;
+                let off = self.target(target, false)?;
+                self.push(BPF_JMP | BPF_JA, 0, 0, off as i16, 0);
+            }
+            "lddw" => {
+                let [dst, imm] = self.expect(operands)?;
+                let dst = self.register(dst)?;
+                let imm = self.immediate64(imm)?;
+                self.push(BPF_LD | BPF_DW | BPF_IMM, dst, 0, 0, imm as i32);
+                self.push(0, 0, 0, 0, (imm >> 32) as i32);
+            }
+            "neg" | "neg32" | "neg64" => {
+                let [dst] = self.expect(operands)?;
+                let dst = self.register(dst)?;
+                let class = if bitness(mnemonic).1 {
+                    BPF_ALU
+                } else {
+                    BPF_ALU64
+                };
+                self.push(class | BPF_NEG | BPF_K, dst, 0, 0, 0);
+            }
+            _ => return self.operation(mnemonic, operands),
+        }
+        Ok(())
+    }
+
+    /// ALU, byte swap, conditional jump and memory instructions
+    fn operation(&mut self, mnemonic: &str, operands: &[&str]) -> Result<(), ParseError> {
+        let (base, is_32) = bitness(mnemonic);
+        if let Some(&(_, op, off)) = ALU_OPS.iter().find(|(name, _, _)| *name == base) {
+            let [dst, src] = self.expect(operands)?;
+            let dst = self.register(dst)?;
+            let class = if is_32 { BPF_ALU } else { BPF_ALU64 };
+            match self.source(src)? {
+                Source::Register(src) => self.push(class | op | BPF_X, dst, src, off, 0),
+                Source::Immediate(imm) => self.push(class | op | BPF_K, dst, 0, off, imm),
+            }
+            return Ok(());
+        }
+        if let Some(&(_, op)) = JMP_OPS.iter().find(|(name, _)| *name == base) {
+            let [dst, src, target] = self.expect(operands)?;
+            let dst = self.register(dst)?;
+            let source = self.source(src)?;
+            let off = self.target(target, false)? as i16;
+            let class = if is_32 { BPF_JMP32 } else { BPF_JMP };
+            match source {
+                Source::Register(src) => self.push(class | op | BPF_X, dst, src, off, 0),
+                Source::Immediate(imm) => self.push(class | op | BPF_K, dst, 0, off, imm),
+            }
+            return Ok(());
+        }
+        for (prefix, order, class) in [
+            ("le", BPF_TO_LE, BPF_ALU),
+            ("be", BPF_TO_BE, BPF_ALU),
+            ("bswap", BPF_TO_LE, BPF_ALU64),
+        ] {
+            if let Some(width @ ("16" | "32" | "64")) = mnemonic.strip_prefix(prefix) {
+                let [dst] = self.expect(operands)?;
+                let dst = self.register(dst)?;
+                let width = width.parse().unwrap_or_default();
+                self.push(class | BPF_END | order, dst, 0, 0, width);
+                return Ok(());
+            }
+        }
+        for &(prefix, op) in MEMORY_OPS {
+            let Some(suffix) = mnemonic.strip_prefix(prefix) else {
+                continue;
+            };
+            let size = match suffix {
+                // Legacy packet access has no memory operand to get the size from
+                "" if op & BPF_OPCODE_CLASS_MASK == BPF_LD => continue,
+                "" => None,
+                _ => match size(suffix) {
+                    Some(size) => Some(size),
+                    None => continue,
+                },
+            };
+            return self.memory_instruction(op, size, operands);
+        }
+        Err(self.error(UnknownMnemonic(mnemonic.to_string())))
+    }
+
+    fn memory_instruction(
+        &mut self,
+        op: u8,
+        size: Option<u8>,
+        operands: &[&str],
+    ) -> Result<(), ParseError> {
+        let class = op & BPF_OPCODE_CLASS_MASK;
+        if let (BPF_LD, Some(size)) = (class, size) {
+            if op & BPF_OPCODE_MODIFIER_MASK == BPF_ABS {
+                let [imm] = self.expect(operands)?;
+                let imm = self.immediate(imm)?;
+                self.push(op | size, 0, 0, 0, imm);
+            } else {
+                let [src, imm] = self.expect(operands)?;
+                let src = self.register(src)?;
+                let imm = self.immediate(imm)?;
+                self.push(op | size, 0, src, 0, imm);
+            }
+            return Ok(());
+        }
+
+        let [first, second] = self.expect(operands)?;
+        if class == BPF_LDX {
+            let dst = self.register(first)?;
+            let (src, off, cast) = self.memory(second)?;
+            let size = self.merge_sizes(size, cast)?;
+            self.push(op | size, dst, src, off, 0);
+        } else {
+            let (dst, off, cast) = self.memory(first)?;
+            let size = self.merge_sizes(size, cast)?;
+            if class == BPF_STX {
+                let src = self.register(second)?;
+                self.push(op | size, dst, src, off, 0);
+            } else {
+                let imm = self.immediate(second)?;
+                self.push(op | size, dst, 0, off, imm);
+            }
+        }
+        Ok(())
+    }
+
+    fn atomic(&mut self, op: &str, fetch: bool, operands: &[&str]) -> Result<(), ParseError> {
+        let (base, is_32) = bitness(op);
+        let Some(&(_, imm)) = ATOMIC_OPS.iter().find(|(name, _)| *name == base) else {
+            return Err(self.error(UnknownMnemonic(op.to_string())));
+        };
+        let imm = if fetch { imm | BPF_ATOMIC_FETCH } else { imm };
+        let [dst, src] = self.expect(operands)?;
+        let (dst, off, cast) = self.memory(dst)?;
+        let src = self.register(src)?;
+        let size = match (is_32, cast) {
+            (true, None | Some(BPF_W)) | (false, Some(BPF_W)) => BPF_W,
+            (false, None | Some(BPF_DW)) => BPF_DW,
+            _ => return Err(self.error(SizeMismatch)),
+        };
+        self.push(BPF_STX | BPF_ATOMIC | size, dst, src, off, imm);
+        Ok(())
+    }
+
+    fn call(&mut self, operands: &[&str]) -> Result<(), ParseError> {
+        let [target] = self.expect(operands)?;
+        match target.strip_prefix("local") {
+            Some(label) if label.starts_with(char::is_whitespace) => {
+                let imm = self.target(label.trim(), true)?;
+                self.push(BPF_JMP_CALL, 0, BPF_CALL_PSEUDO, 0, imm);
+            }
+            _ => {
+                let imm = self.immediate(target)?;
+                self.push(BPF_JMP_CALL, 0, BPF_CALL_HELPER, 0, imm);
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks the size from either the mnemonic or the casted memory operand
+    fn merge_sizes(&self, size: Option<u8>, cast: Option<u8>) -> Result<u8, ParseError> {
+        match (size, cast) {
+            (Some(size), None) | (None, Some(size)) => Ok(size),
+            (Some(size), Some(cast)) if size == cast => Ok(size),
+            _ => Err(self.error(SizeMismatch)),
+        }
+    }
+
+    fn register(&self, s: &str) -> Result<u8, ParseError> {
+        match s.strip_prefix('r') {
+            Some(n @ ("0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "10")) => {
+                Ok(n.parse().unwrap_or_default())
+            }
+            _ => Err(self.error(InvalidOperand(s.to_string()))),
+        }
+    }
+
+    fn source(&self, s: &str) -> Result<Source, ParseError> {
+        if s.starts_with('r') {
+            Ok(Source::Register(self.register(s)?))
+        } else {
+            Ok(Source::Immediate(self.immediate(s)?))
+        }
+    }
+
+    /// Parses a 32-bit immediate number, either signed or unsigned
+    fn immediate(&self, s: &str) -> Result<i32, ParseError> {
+        let value = number(s).ok_or_else(|| self.error(InvalidOperand(s.to_string())))?;
+        if (i32::MIN as i128..=u32::MAX as i128).contains(&value) {
+            Ok(value as i32)
+        } else {
+            Err(self.error(OutOfRange(s.to_string())))
+        }
+    }
+
+    fn immediate64(&self, s: &str) -> Result<i64, ParseError> {
+        let value = number(s).ok_or_else(|| self.error(InvalidOperand(s.to_string())))?;
+        if (i64::MIN as i128..=u64::MAX as i128).contains(&value) {
+            Ok(value as i64)
+        } else {
+            Err(self.error(OutOfRange(s.to_string())))
+        }
+    }
+
+    fn offset(&self, s: &str) -> Result<i16, ParseError> {
+        let value = number(s).ok_or_else(|| self.error(InvalidOperand(s.to_string())))?;
+        i16::try_from(value).map_err(|_| self.error(OutOfRange(s.to_string())))
+    }
+
+    /// Parses a relative offset (16-bit for jumps or 32-bit for calls),
+    /// or records a fixup for a label
+    fn target(&mut self, s: &str, call: bool) -> Result<i32, ParseError> {
+        if is_identifier(s) {
+            self.fixups.push(Fixup {
+                pc: self.code.len(),
+                line: self.line,
+                label: s.to_string(),
+                call,
+            });
+            Ok(0)
+        } else if call {
+            let value = number(s).ok_or_else(|| self.error(InvalidOperand(s.to_string())))?;
+            i32::try_from(value).map_err(|_| self.error(OutOfRange(s.to_string())))
+        } else {
+            Ok(self.offset(s)? as i32)
+        }
+    }
+
+    /// Parses `[r1+8]` or `*(u32 *)(r1 + 8)`, returning the register, the offset
+    /// and the size if casted
+    fn memory(&self, s: &str) -> Result<(u8, i16, Option<u8>), ParseError> {
+        let invalid = || self.error(InvalidOperand(s.to_string()));
+        let (inner, size) = match s.strip_prefix('[') {
+            Some(rest) => (rest.strip_suffix(']').ok_or_else(invalid)?, None),
+            None => {
+                let cast = s.strip_prefix('*').ok_or_else(invalid)?.trim_start();
+                let (ty, rest) = cast
+                    .strip_prefix('(')
+                    .and_then(|cast| cast.split_once(')'))
+                    .ok_or_else(invalid)?;
+                let ty = ty.trim().strip_suffix('*').ok_or_else(invalid)?.trim();
+                let size = ty
+                    .strip_prefix('u')
+                    .or_else(|| ty.strip_prefix('s'))
+                    .and_then(size)
+                    .ok_or_else(invalid)?;
+                let inner = rest
+                    .trim()
+                    .strip_prefix('(')
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .ok_or_else(invalid)?;
+                (inner, Some(size))
+            }
+        };
+        let (reg, off) = match inner.find(['+', '-']) {
+            Some(i) => {
+                let off: String = inner[i..].split_whitespace().collect();
+                (inner[..i].trim(), self.offset(&off)?)
+            }
+            None => (inner.trim(), 0),
+        };
+        Ok((self.register(reg)?, off, size))
+    }
+
+    fn resolve(mut self) -> Result<Vec<u64>, ParseError> {
+        for fixup in &self.fixups {
+            let error = |kind| ParseError {
+                line: fixup.line,
+                kind,
+            };
+            let target = *self
+                .labels
+                .get(&fixup.label)
+                .ok_or_else(|| error(UndefinedLabel(fixup.label.clone())))?;
+            let relative = target as i128 - fixup.pc as i128 - 1;
+            let insn = &mut self.code[fixup.pc];
+            if fixup.call {
+                let imm =
+                    i32::try_from(relative).map_err(|_| error(OutOfRange(fixup.label.clone())))?;
+                *insn = (*insn & 0xFFFF_FFFF) | ((imm as u32 as u64) << 32);
+            } else {
+                let off =
+                    i16::try_from(relative).map_err(|_| error(OutOfRange(fixup.label.clone())))?;
+                *insn = (*insn & !(0xFFFF << 16)) | ((off as u16 as u64) << 16);
+            }
+        }
+        Ok(self.code)
+    }
+}
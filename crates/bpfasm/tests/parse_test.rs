@@ -0,0 +1,159 @@
+use bpfasm::{parse, ParseError, ParseErrorKind};
+use ebpf_consts::*;
+
+fn pack(opcode: u8, src: u8, dst: u8, off: i16, imm: i32) -> u64 {
+    opcode as u64
+        | (dst as u64) << 8
+        | (src as u64) << 12
+        | (off as u16 as u64) << 16
+        | (imm as u32 as u64) << 32
+}
+
+fn error(text: &str) -> (usize, ParseErrorKind) {
+    match parse(text) {
+        Err(ParseError { line, kind }) => (line, kind),
+        Ok(code) => panic!("unexpected success: {code:x?}"),
+    }
+}
+
+#[test]
+fn test_alu() {
+    let code = parse(
+        "mov r0, 1
+        add32 r0, r1
+        sub64 r0, -0x10
+        sdiv r2, r3
+        neg32 r4
+        le16 r5
+        be64 r6
+        bswap32 r7
+        lddw r8, 0x100000002",
+    )
+    .unwrap();
+    assert_eq!(
+        code,
+        [
+            pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 1),
+            pack(BPF_ALU | BPF_ADD | BPF_X, 1, 0, 0, 0),
+            pack(BPF_ALU64 | BPF_SUB | BPF_K, 0, 0, 0, -16),
+            pack(BPF_ALU64 | BPF_DIV | BPF_X, 3, 2, BPF_SDIV, 0),
+            pack(BPF_ALU | BPF_NEG | BPF_K, 0, 4, 0, 0),
+            pack(BPF_ALU | BPF_END | BPF_TO_LE, 0, 5, 0, 16),
+            pack(BPF_ALU | BPF_END | BPF_TO_BE, 0, 6, 0, 64),
+            pack(BPF_ALU64 | BPF_END | BPF_TO_LE, 0, 7, 0, 32),
+            pack(BPF_LD | BPF_DW | BPF_IMM, 0, 8, 0, 2),
+            pack(0, 0, 0, 0, 1),
+        ]
+    );
+    assert_eq!(
+        parse("mov32 r1, 0xffffffff").unwrap(),
+        [pack(BPF_ALU | BPF_MOV | BPF_K, 0, 1, 0, -1)]
+    );
+}
+
+#[test]
+fn test_memory() {
+    let code = parse(
+        "ldxw r0, [r1+8]
+        ldx r0, *(u32 *)(r1 + 8)
+        stdw [r10-16], 2
+        st *(u64 *)(r10 - 16), 2
+        stxb [r10], r1
+        ldxsh r2, [r3-2]
+        lock add [r1+8], r2
+        lock fetch or32 [r1], r2
+        lock cmpxchg *(u32 *)(r1 + 4), r2
+        ldabsw 4
+        ldindh r1, 2",
+    )
+    .unwrap();
+    assert_eq!(
+        code,
+        [
+            pack(BPF_LDX | BPF_MEM | BPF_W, 1, 0, 8, 0),
+            pack(BPF_LDX | BPF_MEM | BPF_W, 1, 0, 8, 0),
+            pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -16, 2),
+            pack(BPF_ST | BPF_MEM | BPF_DW, 0, 10, -16, 2),
+            pack(BPF_STX | BPF_MEM | BPF_B, 1, 10, 0, 0),
+            pack(BPF_LDX | BPF_MEMSX | BPF_H, 3, 2, -2, 0),
+            pack(BPF_STX | BPF_ATOMIC | BPF_DW, 2, 1, 8, BPF_ATOMIC_ADD),
+            pack(
+                BPF_STX | BPF_ATOMIC | BPF_W,
+                2,
+                1,
+                0,
+                BPF_ATOMIC_OR | BPF_ATOMIC_FETCH
+            ),
+            pack(BPF_STX | BPF_ATOMIC | BPF_W, 2, 1, 4, BPF_ATOMIC_CMPXCHG),
+            pack(BPF_LD | BPF_ABS | BPF_W, 0, 0, 0, 4),
+            pack(BPF_LD | BPF_IND | BPF_H, 1, 0, 0, 2),
+        ]
+    );
+}
+
+#[test]
+fn test_labels() {
+    let code = parse(
+        "# comments are ignored
+        mov r0, 0
+        loop:
+        jeq r0, 10, done   # forward
+        lddw r1, 1
+        add r0, 1
+        ja loop
+        done: call local func
+        call 1
+        exit
+        func:
+        jne32 r1, r2, -1
+        exit",
+    )
+    .unwrap();
+    assert_eq!(
+        code,
+        [
+            pack(BPF_ALU64 | BPF_MOV | BPF_K, 0, 0, 0, 0),
+            pack(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 4, 10),
+            pack(BPF_LD | BPF_DW | BPF_IMM, 0, 1, 0, 1),
+            0,
+            pack(BPF_ALU64 | BPF_ADD | BPF_K, 0, 0, 0, 1),
+            pack(BPF_JMP | BPF_JA, 0, 0, -5, 0),
+            pack(BPF_JMP_CALL, BPF_CALL_PSEUDO, 0, 0, 2),
+            pack(BPF_JMP_CALL, BPF_CALL_HELPER, 0, 0, 1),
+            BPF_JMP_EXIT as u64,
+            pack(BPF_JMP32 | BPF_JNE | BPF_X, 2, 1, -1, 0),
+            BPF_JMP_EXIT as u64,
+        ]
+    );
+}
+
+#[test]
+fn test_errors() {
+    use ParseErrorKind::*;
+
+    assert_eq!(
+        error("mov r0, 0\nfoo r0"),
+        (2, UnknownMnemonic("foo".into()))
+    );
+    assert_eq!(error("exit r0"), (1, OperandCount(0)));
+    assert_eq!(error("mov r11, 0"), (1, InvalidOperand("r11".into())));
+    assert_eq!(
+        error("mov r0, 0x100000000"),
+        (1, OutOfRange("0x100000000".into()))
+    );
+    assert_eq!(
+        error("ldxw r0, [r1+32768]"),
+        (1, OutOfRange("+32768".into()))
+    );
+    assert_eq!(error("ldxw r0, *(u64 *)(r1)"), (1, SizeMismatch));
+    assert_eq!(error("lock add *(u16 *)(r1), r2"), (1, SizeMismatch));
+    assert_eq!(error("ldx r0, [r1]"), (1, SizeMismatch));
+    assert_eq!(
+        error("mov r0, 0\nja done\nexit"),
+        (2, UndefinedLabel("done".into()))
+    );
+    assert_eq!(
+        error("a:\nmov r0, 0\na:\nexit"),
+        (3, DuplicateLabel("a".into()))
+    );
+}
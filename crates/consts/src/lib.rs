@@ -45,6 +45,8 @@ pub type CodeUnit = u64;
 
 /// Stack for the eBPF stack, in bytes.
 pub const STACK_SIZE: usize = 512;
+/// Maximum combined stack size of all frames along a call chain, in bytes.
+pub const MAX_COMBINED_STACK_SIZE: usize = 512;
 
 /// Writable register count, that is, R0, ..., R9
 pub const WRITABLE_REGISTER_COUNT: u8 = 10;
@@ -100,7 +102,8 @@ pub const BPF_ABS     : u8 = 0x20;
 pub const BPF_IND     : u8 = 0x40;
 /// BPF mode modifier: load from / store to memory.
 pub const BPF_MEM     : u8 = 0x60;
-// [ 0x80 reserved ]
+/// BPF mode modifier: sign-extending load from memory.
+pub const BPF_MEMSX   : u8 = 0x80;
 // [ 0xa0 reserved ]
 /// BPF mode modifier: exclusive add.
 pub const BPF_ATOMIC  : u8 = 0xc0;
@@ -172,6 +175,12 @@ pub const BPF_ARSH  : u8 = 0xc0;
 /// BPF ALU/ALU64 operation code: endianness conversion.
 pub const BPF_END   : u8 = 0xd0;
 
+// Offset selectors -- BPF_DIV and BPF_MOD:
+/// BPF ALU/ALU64 offset selector for `BPF_DIV`: signed division.
+pub const BPF_SDIV  : i16 = 1;
+/// BPF ALU/ALU64 offset selector for `BPF_MOD`: signed modulus.
+pub const BPF_SMOD  : i16 = 1;
+
 // Operation codes -- BPF_JMP class:
 /// BPF JMP operation code: jump.
 pub const BPF_JA    : u8 = 0x00;
@@ -201,6 +210,8 @@ pub const BPF_JLE   : u8 = 0xb0;
 pub const BPF_JSLT  : u8 = 0xc0;
 /// BPF JMP operation code: jump if lower or equal (signed).
 pub const BPF_JSLE  : u8 = 0xd0;
+/// BPF JMP operation code: conditional pseudo jump (`may_goto`).
+pub const BPF_JCOND : u8 = 0xe0;
 
 /// BPF opcode: `call imm` /// helper function call to helper with key `imm`.
 pub const BPF_JMP_CALL       : u8 = BPF_JMP   | BPF_CALL;
@@ -251,6 +262,36 @@ pub mod maps {
         Hash   = 1,
         /// Array map type `BPF_MAP_TYPE_ARRAY`
         Array  = 2,
+        /// Program array map type `BPF_MAP_TYPE_PROG_ARRAY` (for tail calls)
+        ProgArray = 3,
+        /// Ring buffer map type `BPF_MAP_TYPE_RINGBUF`
+        RingBuf = 27,
+    }
+}
+
+/// Program constants
+pub mod programs {
+    /// Types of programs
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ProgType {
+        /// Unspecified program type `BPF_PROG_TYPE_UNSPEC`
+        Unspec       = 0,
+        /// Socket filter program type `BPF_PROG_TYPE_SOCKET_FILTER`
+        SocketFilter = 1,
+        /// Kprobe program type `BPF_PROG_TYPE_KPROBE`
+        Kprobe       = 2,
+        /// Traffic control classifier program type `BPF_PROG_TYPE_SCHED_CLS`
+        SchedCls     = 3,
+        /// Traffic control action program type `BPF_PROG_TYPE_SCHED_ACT`
+        SchedAct     = 4,
+        /// Tracepoint program type `BPF_PROG_TYPE_TRACEPOINT`
+        Tracepoint   = 5,
+        /// XDP program type `BPF_PROG_TYPE_XDP`
+        Xdp          = 6,
+        /// Perf event program type `BPF_PROG_TYPE_PERF_EVENT`
+        PerfEvent    = 7,
+        /// Raw tracepoint program type `BPF_PROG_TYPE_RAW_TRACEPOINT`
+        RawTracepoint = 17,
     }
 }
 
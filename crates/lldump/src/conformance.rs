@@ -54,7 +54,7 @@ pub fn get_conformance_data(path: &str) -> Result<ConformanceData, io::Error> {
             for dw in bytes.chunks(8) {
                 let mut array: [u8; 8] = Default::default();
                 array.copy_from_slice(dw);
-                data.code.push(u64::from_ne_bytes(array));
+                data.code.push(u64::from_le_bytes(array));
             }
         }
     }
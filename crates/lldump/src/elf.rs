@@ -0,0 +1,59 @@
+//! Reads raw sections from (64-bit, little-endian) ELF object files
+//!
+//! Aya parses programs and maps but keeps `.BTF.ext` to itself,
+//! so we read the sections we need directly.
+
+use std::collections::HashMap;
+
+fn u16_at(data: &[u8], offset: usize) -> usize {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap()) as usize
+}
+
+fn u32_at(data: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+}
+
+fn u64_at(data: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()) as usize
+}
+
+/// Reads all sections of an object file by their names
+pub fn read_sections(path: &str) -> HashMap<String, Vec<u8>> {
+    let data = std::fs::read(path).unwrap();
+    assert_eq!(&data[0..4], b"\x7fELF", "Not an ELF file");
+    assert_eq!(data[4], 2, "Not a 64-bit ELF file");
+    assert_eq!(data[5], 1, "Not a little-endian ELF file");
+
+    let headers = u64_at(&data, 0x28);
+    let size = u16_at(&data, 0x3A);
+    let count = u16_at(&data, 0x3C);
+    let header = |i: usize| &data[headers + i * size..headers + (i + 1) * size];
+    let contents = |i: usize| {
+        // SHT_NOBITS (e.g. `.bss`) takes up no space in the file
+        if u32_at(header(i), 0x04) == 8 {
+            return &data[0..0];
+        }
+        let offset = u64_at(header(i), 0x18);
+        &data[offset..offset + u64_at(header(i), 0x20)]
+    };
+
+    let names = contents(u16_at(&data, 0x3E));
+    (0..count)
+        .map(|i| {
+            let name = &names[u32_at(header(i), 0)..];
+            let name = &name[..name.iter().position(|c| *c == 0).unwrap()];
+            (
+                String::from_utf8(name.to_vec()).unwrap(),
+                contents(i).to_vec(),
+            )
+        })
+        .collect()
+}
+
+/// Reads the code of a program section, e.g., `xdp`
+pub fn read_code(sections: &HashMap<String, Vec<u8>>, section: &str) -> Vec<u64> {
+    sections[section]
+        .chunks(8)
+        .map(|insn| u64::from_le_bytes(insn.try_into().unwrap()))
+        .collect()
+}
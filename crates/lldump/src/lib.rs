@@ -1,6 +1,7 @@
 //! This crate is a mess, containing all kinds of utilities for tests.
 
 pub mod conformance;
+pub mod elf;
 pub mod helper;
 pub mod object;
 
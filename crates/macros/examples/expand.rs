@@ -0,0 +1,31 @@
+//! Generates `analyzer/src/interpreter/dispatch_expanded.rs`
+//!
+//! It reads `dispatch.rs` from stdin and writes the expanded code to stdout,
+//! which should then get formatted with `rustfmt`. See `scripts/expand-dispatch.sh`.
+
+#[path = "../src/block.rs"]
+mod block;
+#[path = "../src/exhaustive.rs"]
+mod exhaustive;
+#[path = "../src/expand.rs"]
+mod expand;
+#[path = "../src/generator.rs"]
+mod generator;
+#[path = "../src/parser.rs"]
+mod parser;
+
+use std::io::Read;
+
+fn main() {
+    let mut source = String::new();
+    std::io::stdin()
+        .read_to_string(&mut source)
+        .expect("failed to read stdin");
+    match expand::expand_dispatch(&source) {
+        Ok(expanded) => print!("{expanded}"),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    bracketed, parenthesized,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Ident, LitInt, Path, Token,
+};
+
+use crate::parser::{Full, MatchArm, OpcodeMatches};
+
+/// The `#![exhaustive(MASK except A, B)]` marker on the arm following it
+///
+/// The mask designates a bit field, whose values should all be covered
+/// for every other combination in the arm.
+pub struct Exhaustive {
+    /// The field mask, looked up in the namespace
+    pub mask: Path,
+    /// Values of the field that are not checked, either constant names or integer literals
+    pub except: Vec<Full>,
+}
+
+impl Parse for Exhaustive {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let _: Token!(#) = input.parse()?;
+        let _: Token!(!) = input.parse()?;
+        let content;
+        bracketed!(content in input);
+        let marker: Ident = content.parse()?;
+        if marker != "exhaustive" {
+            return Err(syn::Error::new(marker.span(), "Expecting `exhaustive`"));
+        }
+        let list;
+        parenthesized!(list in content);
+        let mask: Path = list.parse()?;
+        let except = if list.is_empty() {
+            Vec::new()
+        } else {
+            let keyword: Ident = list.parse()?;
+            if keyword != "except" {
+                return Err(syn::Error::new(keyword.span(), "Expecting `except`"));
+            }
+            let names: Punctuated<Full, Token!(,)> = list.parse_terminated(|input| {
+                if input.peek(LitInt) {
+                    Ok(input.parse::<LitInt>()?.to_string())
+                } else {
+                    Ok(input.parse::<Ident>()?.to_string())
+                }
+            })?;
+            if names.is_empty() {
+                return Err(syn::Error::new(keyword.span(), "Empty `except`"));
+            }
+            names.into_iter().collect()
+        };
+        Ok(Exhaustive { mask, except })
+    }
+}
+
+/// Iterates over all combinations of the components in an arm, ranges by their lower bounds
+fn components(arm: &MatchArm) -> Vec<Vec<&Full>> {
+    let mut all: Vec<Vec<&Full>> = vec![Vec::new()];
+    for group in &arm.combinations {
+        all = all
+            .iter()
+            .flat_map(|prefix| {
+                group.0.iter().map(move |(_, full, _)| {
+                    let mut combination = prefix.clone();
+                    combination.push(full);
+                    combination
+                })
+            })
+            .collect();
+    }
+    all
+}
+
+/// Generates a constant evaluated by the compiler, failing if an opcode is not covered
+///
+/// `patterns` are the patterns of all conditional arms, each followed by `=> { return true; }`,
+/// a block that `rustfmt` keeps as is, whether the guard is long or not.
+/// Every value of the marked fields is checked, stepping by the lowest bit of the mask,
+/// and all opcodes not covered are listed in the error.
+pub fn generate_check(matches: &OpcodeMatches, patterns: TokenStream) -> TokenStream {
+    let (value, value_type) = (&matches.value, &matches.value_type);
+    let namespace = &matches.namespace.namespace;
+    let name = |full: &Full| Ident::new(full, Span::call_site());
+    let checks = matches
+        .matches
+        .iter()
+        .flat_map(|arm| arm.exhaustive.iter().map(move |marker| (arm, marker)))
+        .map(|(arm, marker)| {
+            let mask = &marker.mask;
+            let bases = components(arm).into_iter().map(|combination| {
+                let combination = combination.into_iter().map(name);
+                quote!(#(#namespace #combination)|*)
+            });
+            let except = marker.except.iter().map(|full| {
+                if full.starts_with(|c: char| c.is_ascii_digit()) {
+                    let literal = LitInt::new(full, Span::call_site());
+                    quote!(#literal)
+                } else {
+                    let ident = name(full);
+                    quote!(#namespace #ident)
+                }
+            });
+            quote! {
+                check(#namespace #mask, &[#(#bases),*], &[#(#except),*]);
+            }
+        });
+    quote! {
+        const _: () = {
+            const fn covered(#value: #value_type) -> bool {
+                match #value {
+                    #patterns
+                    _ => {}
+                }
+                false
+            }
+            const fn check(mask: #value_type, bases: &[#value_type], except: &[#value_type]) {
+                const PREFIX: &[u8] = b"Opcodes not covered in the `exhaustive` field of an arm:";
+                const DIGITS: &[u8] = b"0123456789abcdef";
+                let mut report = [0u8; 512];
+                let mut len = 0;
+                while len < PREFIX.len() {
+                    report[len] = PREFIX[len];
+                    len += 1;
+                }
+                let step = mask & mask.wrapping_neg();
+                let mut full = false;
+                let mut i = 0;
+                while i < bases.len() && !full {
+                    let mut field: #value_type = 0;
+                    loop {
+                        let mut skipped = field & !mask != 0;
+                        let mut k = 0;
+                        while k < except.len() {
+                            skipped |= field == except[k];
+                            k += 1;
+                        }
+                        let opcode = (bases[i] & !mask) | field;
+                        if !skipped && !covered(opcode) {
+                            // Appends ` 0x..`, or ` ...` once the report is full
+                            if len + 24 > report.len() {
+                                report[len] = b' ';
+                                report[len + 1] = b'.';
+                                report[len + 2] = b'.';
+                                report[len + 3] = b'.';
+                                len += 4;
+                                full = true;
+                                break;
+                            }
+                            report[len] = b' ';
+                            report[len + 1] = b'0';
+                            report[len + 2] = b'x';
+                            len += 3;
+                            let opcode = opcode as u64;
+                            let mut shift = 4;
+                            while shift < 64 && opcode >> shift != 0 {
+                                shift += 4;
+                            }
+                            while shift > 0 {
+                                shift -= 4;
+                                report[len] = DIGITS[((opcode >> shift) & 0xf) as usize];
+                                len += 1;
+                            }
+                        }
+                        if field == mask {
+                            break;
+                        }
+                        field += step;
+                    }
+                    i += 1;
+                }
+                if len != PREFIX.len() {
+                    // The report is always ASCII
+                    if let Ok(message) = core::str::from_utf8(report.split_at(len).0) {
+                        panic!("{}", message);
+                    }
+                }
+            }
+            #(#checks)*
+        };
+    }
+}
+
+#[test]
+fn test_exhaustive() {
+    use crate::generator::generate;
+
+    let s = quote! {
+        opcode as u8 in ebpf_consts,
+        #![exhaustive(mask::BPF_OPCODE_JMP_MASK except BPF_JA, BPF_EXIT, 0xf0)]
+        [[BPF_JMP: _], [BPF_X: _, BPF_K: _], [BPF_JLT..=BPF_JLE: _]] => {}
+        [[BPF_JMP: _], [BPF_JA: _]] => {}
+        _ => {}
+    };
+    let matches = syn::parse2::<OpcodeMatches>(s).unwrap();
+    assert_eq!(matches.matches[0].exhaustive.len(), 1);
+    assert_eq!(
+        matches.matches[0].exhaustive[0].except,
+        ["BPF_JA", "BPF_EXIT", "0xf0"]
+    );
+    let generated = generate(&matches).to_string();
+    let expected = [
+        "const fn covered (opcode : u8) -> bool",
+        "let step = mask & mask . wrapping_neg () ;",
+        "check (ebpf_consts :: mask :: BPF_OPCODE_JMP_MASK , & [\
+         ebpf_consts :: BPF_JMP | ebpf_consts :: BPF_X | ebpf_consts :: BPF_JLT , \
+         ebpf_consts :: BPF_JMP | ebpf_consts :: BPF_K | ebpf_consts :: BPF_JLT] , \
+         & [ebpf_consts :: BPF_JA , ebpf_consts :: BPF_EXIT , 0xf0]) ;",
+    ];
+    for part in expected {
+        assert!(generated.contains(part), "{part} not in {generated}");
+    }
+
+    let s = quote! {
+        opcode as u8,
+        [[BPF_JMP: _]] => {}
+        _ => {}
+    };
+    let generated = generate(&syn::parse2::<OpcodeMatches>(s).unwrap()).to_string();
+    assert!(!generated.contains("covered"));
+
+    let s = quote! {
+        opcode as u8,
+        #![exhaustive(BPF_OPCODE_JMP_MASK except)]
+        [[BPF_JMP: _]] => {}
+    };
+    assert!(syn::parse2::<OpcodeMatches>(s).is_err());
+
+    let s = quote! {
+        opcode as u8,
+        #![exhaustive(BPF_OPCODE_JMP_MASK)]
+        _ => {}
+    };
+    assert!(syn::parse2::<OpcodeMatches>(s).is_err());
+}
@@ -0,0 +1,77 @@
+//! Expands `opcode_match!` invocations in source files
+//!
+//! It is not part of the proc-macro crate itself. Instead, `examples/expand.rs`
+//! and the tests include it, along with the modules it uses.
+
+use crate::{generator::generate, parser::OpcodeMatches};
+
+const INVOCATION: &str = "opcode_match! {";
+
+/// Module docs of the expanded interpreter dispatch
+const DISPATCH_HEADER: &str = "\
+//! The instruction dispatch of the interpreter, see [run].
+//!
+//! GENERATED from `dispatch.rs` by expanding its `opcode_match!` macros and formatting
+//! the result with `rustfmt`. Do not edit by hand: regenerate it whenever `dispatch.rs` changes.
+";
+
+/// Returns the length of the macro body starting at `body`, up to the closing brace
+fn body_len(body: &[u8]) -> Option<usize> {
+    let mut depth = 1;
+    let mut i = 0;
+    while depth > 0 {
+        match *body.get(i)? {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b'"' => {
+                i += 1;
+                while *body.get(i)? != b'"' {
+                    if body[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'/' if body.get(i + 1) == Some(&b'/') => {
+                while *body.get(i)? != b'\n' {
+                    i += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Some(i - 1)
+}
+
+/// Expands every `opcode_match! { ... }` in `source`
+///
+/// The expanded code is left unformatted.
+pub fn expand(source: &str) -> syn::Result<String> {
+    let mut expanded = String::new();
+    let mut rest = source;
+    while let Some(start) = rest.find(INVOCATION) {
+        expanded.push_str(&rest[..start]);
+        let body = &rest[start + INVOCATION.len()..];
+        let len = body_len(body.as_bytes()).ok_or_else(|| {
+            syn::Error::new(proc_macro2::Span::call_site(), "Unclosed opcode_match!")
+        })?;
+        let matches: OpcodeMatches = syn::parse_str(&body[..len])?;
+        expanded.push_str(&generate(&matches).to_string());
+        rest = &body[len + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Generates `dispatch_expanded.rs` from the interpreter `dispatch.rs`
+///
+/// Module docs get replaced and the macro import dropped.
+pub fn expand_dispatch(source: &str) -> syn::Result<String> {
+    let code = source
+        .lines()
+        .skip_while(|line| line.starts_with("//!"))
+        .filter(|line| line.trim() != "use opcode_macros::opcode_match;")
+        .fold(String::new(), |code, line| code + line + "\n");
+    Ok(String::from(DISPATCH_HEADER) + &expand(&code)?)
+}
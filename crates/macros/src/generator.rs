@@ -1,41 +1,37 @@
 use std::str::FromStr;
 
-use proc_macro::TokenStream;
 use proc_macro2::{Group, Ident, Literal, Span, TokenStream as TokenStream2};
 use quote::{quote, ToTokens, TokenStreamExt};
 
 use crate::{
     block::{CodeBlock, Replacing},
-    parser::{Alias, Aliases, Full, Namespace, OpcodeMatches},
+    exhaustive::generate_check,
+    parser::{Alias, Aliases, Full, MatchArm, OpcodeMatches},
 };
 
 /// Generates a match statement from a parsed `OpcodeMatches`
-pub fn generate(matches: &OpcodeMatches) -> TokenStream {
+pub fn generate(matches: &OpcodeMatches) -> TokenStream2 {
     let value = matches.value.clone();
     let mut branches = TokenStream2::default();
     let mut consts = TokenStream2::default();
+    let mut patterns = TokenStream2::default();
     for arm in &matches.matches {
         if arm.combinations.is_empty() {
             construct_code(&Vec::new(), &Vec::new(), &arm.code, &mut branches);
         } else {
-            add_all_combinations(
-                &arm.combinations,
-                &arm.code,
-                &mut branches,
-                &mut consts,
-                &arm.header,
-                &matches.value_type,
-                &matches.namespace,
-            );
+            add_all_combinations(arm, &mut branches, &mut consts, &mut patterns, matches);
         }
     }
+    if matches.matches.iter().any(|arm| !arm.exhaustive.is_empty()) {
+        consts.extend(generate_check(matches, patterns));
+    }
     consts.extend(quote! {
         match #value {
             #branches
         }
     });
 
-    quote!({ #consts }).into()
+    quote!({ #consts })
 }
 
 struct ConstName(String, Option<TokenStream2>);
@@ -60,31 +56,51 @@ impl ToTokens for Component {
     }
 }
 
+/// Adds match arms for all combinations in the arm
+///
+/// `patterns` collects the patterns, mapped to `true`, for the exhaustiveness check,
+/// whose range guards avoid [RangeInclusive::contains](core::ops::RangeInclusive::contains)
+/// since it is evaluated by the compiler.
 fn add_all_combinations(
-    combinations: &[Aliases],
-    code: &CodeBlock,
+    arm: &MatchArm,
     branches: &mut TokenStream2,
     consts: &mut TokenStream2,
-    header: &Option<TokenStream2>,
-    value_type: &Ident,
-    namespace: &Namespace,
+    patterns: &mut TokenStream2,
+    matches: &OpcodeMatches,
 ) {
-    let mut current: Vec<usize> = Vec::new();
-    current.resize(combinations.len(), 0);
+    let (combinations, code, header) = (&arm.combinations, &arm.code, &arm.header);
+    let value = &matches.value;
+    let value_type = &matches.value_type;
+    let namespace = &matches.namespace;
+    let mut current: Vec<usize> = vec![0; combinations.len()];
     let mut aliases: Vec<&[Alias]> = Vec::new();
     let mut enabled: Vec<String> = Vec::new();
     let mut components: Vec<Component> = Vec::new();
+    let mut range: Option<(Component, Component)>;
     loop {
         aliases.clear();
         enabled.clear();
         components.clear();
+        range = None;
         for (i, ele) in current.iter().enumerate() {
             let alias = &combinations[i].0[*ele];
             aliases.push(&alias.0);
-            components.push(Component {
+            let component = Component {
                 name: alias.1.clone(),
                 namespace: namespace.namespace.clone(),
-            });
+            };
+            if let Some(end) = &alias.2 {
+                if range.is_some() {
+                    panic!("Only one range is allowed in a combination");
+                }
+                let end = Component {
+                    name: end.clone(),
+                    namespace: namespace.namespace.clone(),
+                };
+                range = Some((component, end));
+            } else {
+                components.push(component);
+            }
             enabled.extend(alias.0.iter().cloned());
             enabled.push(alias.1.to_string());
         }
@@ -92,24 +108,71 @@ fn add_all_combinations(
         enabled.dedup();
         let mut match_code = TokenStream2::default();
         construct_code(&aliases, &enabled, code, &mut match_code);
-        let const_name = if components.len() > 1 {
+        // Along with the pattern is the value that `bind` binds to,
+        // which is the matched value itself for ranges
+        let (pattern, check, bound) = if let Some((start, end)) = &range {
+            // Matches the fixed bits exactly and the bits of the range field by comparison
+            let mut names: Vec<&str> = components.iter().map(|c| c.name.as_str()).collect();
+            let range_name = format!("{}_TO_{}", start.name, end.name);
+            names.push(&range_name);
+            let const_name = ConstName(names.join("_"), None);
+            let mask_name = ConstName(format!("{}_MASK", const_name.0), None);
+            let fixed = if components.is_empty() {
+                quote!(0)
+            } else {
+                quote!(#(#components)|*)
+            };
+            consts.extend(quote! {
+                const #const_name : #value_type = #fixed;
+                const #mask_name : #value_type = {
+                    let bits = #start | #end;
+                    (bits & bits.wrapping_neg()).wrapping_sub(1)
+                };
+            });
+            let pattern = quote! {
+                _ if (#value & #mask_name) == #const_name
+                    && (#start..=#end).contains(&(#value & !#mask_name))
+            };
+            let check = quote! {
+                _ if (#value & #mask_name) == #const_name
+                    && #start <= (#value & !#mask_name)
+                    && (#value & !#mask_name) <= #end
+            };
+            (pattern, check, quote!(#value))
+        } else if components.len() > 1 {
             let const_name = get_const_name(&components);
             consts.extend(quote! {
                 const #const_name : #value_type = #(#components)|*;
             });
-            const_name
+            let pattern = const_name.to_token_stream();
+            (pattern.clone(), pattern.clone(), pattern)
         } else {
-            ConstName(components[0].name.clone(), Some(namespace.namespace.clone()))
+            let const_name = ConstName(
+                components[0].name.clone(),
+                Some(namespace.namespace.clone()),
+            );
+            let pattern = const_name.to_token_stream();
+            (pattern.clone(), pattern.clone(), pattern)
         };
+        if let Some(binding) = &arm.binding {
+            // Conditional code may leave it unused in some combinations
+            match_code = quote! {
+                #[allow(unused_variables)]
+                let #binding: #value_type = #bound;
+                #match_code
+            };
+        }
         if let Some(header) = header {
             branches.extend(quote! {
                 #header
-                #const_name => { #match_code }
+                #pattern => { #match_code }
             });
+            patterns.extend(quote!(#header #check => { return true; }));
         } else {
             branches.extend(quote! {
-                #const_name => { #match_code }
+                #pattern => { #match_code }
             });
+            patterns.extend(quote!(#check => { return true; }));
         }
         if !increment(&mut current, combinations) {
             break;
@@ -179,7 +242,7 @@ fn construct_code(
     }
 }
 
-fn increment(current: &mut Vec<usize>, combinations: &[Aliases]) -> bool {
+fn increment(current: &mut [usize], combinations: &[Aliases]) -> bool {
     for i in (0..current.len()).rev() {
         current[i] += 1;
         if current[i] < combinations[i].0.len() {
@@ -245,3 +308,45 @@ fn test_raw_malformed() {
         &mut TokenStream2::new(),
     );
 }
+
+#[test]
+fn test_range_arms() {
+    let input = quote! {
+        opcode as u8 in ebpf_consts,
+        [[BPF_JMP: jmp], [BPF_X: x], [BPF_JLT..=BPF_JSLE: cmp, BPF_JEQ: eq]] => {
+            #?((cmp)) 1 ##
+            #?((eq)) 2 ##
+        }
+        _ => 0,
+    };
+    let matches = syn::parse2::<OpcodeMatches>(input).unwrap();
+    let generated = generate(&matches).to_string();
+    let expected = [
+        "const BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE : u8 = ebpf_consts :: BPF_JMP | ebpf_consts :: BPF_X ;",
+        "let bits = ebpf_consts :: BPF_JLT | ebpf_consts :: BPF_JSLE ;",
+        "_ if (opcode & BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK) == BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE \
+         && (ebpf_consts :: BPF_JLT ..= ebpf_consts :: BPF_JSLE) . contains (& (opcode & ! BPF_JMP_BPF_X_BPF_JLT_TO_BPF_JSLE_MASK)) => { 1 }",
+        "BPF_JMP_BPF_X_BPF_JEQ => { 2 }",
+    ];
+    for arm in expected {
+        assert!(generated.contains(arm), "{arm} not in {generated}");
+    }
+}
+
+#[test]
+fn test_binding() {
+    let input = quote! {
+        opcode as u8 in ebpf_consts,
+        [[BPF_JMP: _], [BPF_X: _, BPF_JA..=BPF_JEQ: _]] => bind matched { matched }
+        _ => 0,
+    };
+    let matches = syn::parse2::<OpcodeMatches>(input).unwrap();
+    let generated = generate(&matches).to_string();
+    let expected = [
+        "BPF_JMP_BPF_X => { # [allow (unused_variables)] let matched : u8 = BPF_JMP_BPF_X ; matched }",
+        "contains (& (opcode & ! BPF_JMP_BPF_JA_TO_BPF_JEQ_MASK)) => { # [allow (unused_variables)] let matched : u8 = opcode ; matched }",
+    ];
+    for arm in expected {
+        assert!(generated.contains(arm), "{arm} not in {generated}");
+    }
+}
@@ -6,6 +6,7 @@ mod parser;
 mod generator;
 mod block;
 mod opcode_gen;
+mod exhaustive;
 
 use opcode_gen::OpcodeGen;
 use parser::OpcodeMatches;
@@ -179,10 +180,109 @@ use crate::generator::generate;
 /// A condition can be negated with an exclamation mark `!cond1`.
 ///
 /// We don't allow nested conditions.
+///
+/// ### Binding
+///
+/// To get the matched opcode as a value, use `=> bind name { ... }`, which
+/// injects `let name: u8 = <the folded constant>;` (or the type from `as`)
+/// at the top of the arm, allowed to go unused in combinations where
+/// [conditional blocks](#conditional-blocks) leave out its uses:
+///
+/// ```rust
+/// # use opcode_macros::opcode_match;
+/// # use ebpf_consts::{mask::BPF_OPCODE_CLASS_MASK, *};
+/// # let opcode = BPF_JMP32 | BPF_X | BPF_JEQ;
+/// let width = opcode_match! {
+///     opcode as u8 in ebpf_consts,
+///     [[BPF_JMP: _, BPF_JMP32: _], [BPF_X: _, BPF_K: _], [BPF_JEQ: _]] => bind matched {
+///         if matched & BPF_OPCODE_CLASS_MASK == BPF_JMP32 { 32 } else { 64 }
+///     }
+///     _ => 0,
+/// };
+/// # assert_eq!(width, 32);
+/// ```
+///
+/// For arms with [ranges](#ranges), it is bound to the matched value.
+///
+/// ## Ranges
+///
+/// A component can also be an inclusive range like `BPF_JLT..=BPF_JSLE`,
+/// covering all values of the bit field between the two constants with a single arm.
+///
+/// ```rust
+/// # use opcode_macros::opcode_match;
+/// # use ebpf_consts::*;
+/// # let opcode = BPF_JMP | BPF_X | BPF_JSLT;
+/// let kind = opcode_match! {
+///     opcode as u8 in ebpf_consts,
+///     [[BPF_JMP: _], [BPF_X: _], [BPF_JLT..=BPF_JSLE: "cmp", BPF_JEQ: "eq"]] => { #2 }
+///     _ => "other",
+/// };
+/// assert_eq!(kind, "cmp");
+/// ```
+///
+/// It becomes a match guard instead of a constant pattern, comparing the other
+/// components exactly and the field with the range. The field is taken to be
+/// the bits starting from the lowest bit set in either bound, so the bounds
+/// should not both leave the lowest bit of the field unset
+/// (e.g., `BPF_JA..=BPF_JGT` will not do).
+/// At most one range is allowed in a combination.
+///
+/// ## Exhaustiveness
+///
+/// By default, opcodes not covered by any arm silently fall into the `_` arm.
+/// To catch forgotten values of a bit field, put an `#![exhaustive(MASK)]` marker,
+/// with the mask of the field, before an arm:
+///
+/// ```rust
+/// # use opcode_macros::opcode_match;
+/// # use ebpf_consts::*;
+/// # let opcode = BPF_JMP | BPF_K | BPF_JSET;
+/// let kind = opcode_match! {
+///     opcode as u8 in ebpf_consts,
+///     #![exhaustive(mask::BPF_OPCODE_JMP_MASK
+///         except BPF_JA, BPF_EXIT, BPF_JCOND, 0xf0)]
+///     [[BPF_JMP: _], [BPF_K: _, BPF_X: _],
+///      [BPF_JEQ: "eq", BPF_JGT..=BPF_JGE: "cmp", BPF_JSET: "set",
+///       BPF_JNE..=BPF_JSGE: "cmp", BPF_JLT..=BPF_JSLE: "cmp"]] => {
+///         #2
+///     }
+///     [[BPF_JMP: _], [BPF_JA: "ja", BPF_EXIT: "exit"]] => { #1 }
+///     [[BPF_JMP: _], [BPF_K: _, BPF_X: _], [BPF_CALL: "call"]] => { #2 }
+///     _ => "other",
+/// };
+/// # assert_eq!(kind, "set");
+/// ```
+///
+/// For every combination in the arm, each value of the field must then be covered
+/// by an arm of the match, or the compilation fails listing the missing opcodes:
+///
+/// ```compile_fail
+/// # use opcode_macros::opcode_match;
+/// # use ebpf_consts::*;
+/// # let opcode = BPF_JMP | BPF_K | BPF_JSET;
+/// let kind = opcode_match! {
+///     opcode as u8 in ebpf_consts,
+///     // error: Opcodes not covered in the `exhaustive` field of an arm: 0x25 0x35 0x45 ...
+///     #![exhaustive(mask::BPF_OPCODE_JMP_MASK except BPF_JA)]
+///     [[BPF_JMP: _], [BPF_K: _, BPF_X: _], [BPF_JEQ: "eq"]] => {
+///         #2
+///     }
+///     _ => "other",
+/// };
+/// ```
+///
+/// The mask is looked up in the namespace. Every value of the field is checked,
+/// stepping by the lowest bit set in the mask, except those listed after `except`,
+/// either as constants in the namespace or as integer literals (e.g., `0xf0`,
+/// for values without a constant). The check is a constant evaluated by the compiler,
+/// so ranges cover whatever values lie between their bounds.
+/// Arms without a `[...]` header (e.g., `0 => {}`) are not taken into account.
+/// Multiple markers before an arm are checked separately.
 #[proc_macro]
 pub fn opcode_match(input: TokenStream) -> TokenStream {
     let matches = parse_macro_input!(input as OpcodeMatches);
-    generate(&matches)
+    generate(&matches).into()
 }
 
 /// Generates opcode from bit field enums
@@ -7,7 +7,10 @@ use syn::{
     token, Ident, LitStr, Token,
 };
 
-use crate::block::{CodeBlock, Replacing};
+use crate::{
+    block::{CodeBlock, Replacing},
+    exhaustive::Exhaustive,
+};
 
 /// The root node
 pub struct OpcodeMatches {
@@ -27,6 +30,10 @@ pub struct MatchArm {
     pub code: CodeBlock,
     /// Headers like `#[cfg(...)]`
     pub header: Option<TokenStream>,
+    /// The variable in `=> bind name { ... }`, bound to the matched constant
+    pub binding: Option<Ident>,
+    /// Exhaustiveness markers preceding the arm
+    pub exhaustive: Vec<Exhaustive>,
 }
 
 /// Opcode component namespace
@@ -39,13 +46,16 @@ pub type Alias = String;
 pub type Full = String;
 
 /// The aliasing part of the macro, like `BPF_ALU64: ALU64`
-pub struct Aliases(pub Vec<(Vec<Alias>, Full)>);
+///
+/// Each component comes with the inclusive end of its range if it is a range like
+/// `BPF_JLT..=BPF_JSLE: cmp`.
+pub struct Aliases(pub Vec<(Vec<Alias>, Full, Option<Full>)>);
 
 impl Aliases {
     pub fn contains(&self, alias: &str) -> bool {
         self.0
             .iter()
-            .any(|(s, t)| s.iter().any(|i| i == alias) || *t == alias)
+            .any(|(s, t, _)| s.iter().any(|i| i == alias) || *t == alias)
     }
 }
 
@@ -89,6 +99,7 @@ impl Parse for OpcodeMatches {
         let (value, value_type, namespace) = Self::parse_opcode(input)?;
         let mut arms: Vec<MatchArm> = Vec::new();
         let mut attribute: Option<TokenStream> = None;
+        let mut markers: Vec<Exhaustive> = Vec::new();
         while !input.is_empty() {
             arms.push(if input.peek(token::Bracket) {
                 // Conditional arms
@@ -96,7 +107,12 @@ impl Parse for OpcodeMatches {
                 if let Some(attr) = attribute.take() {
                     arm.header.replace(attr);
                 }
+                arm.exhaustive = core::mem::take(&mut markers);
                 arm
+            } else if input.peek(Token!(#)) && input.peek2(Token!(!)) {
+                // Exhaustiveness markers, e.g., `#![exhaustive(mask::BPF_OPCODE_JMP_MASK)]`
+                markers.push(input.parse()?);
+                continue;
             } else if input.peek(Token!(#)) {
                 // Attributes for that branch, e.g., `#[cfg(test)]`
                 let hash: Token!(#) = input.parse()?;
@@ -118,6 +134,9 @@ impl Parse for OpcodeMatches {
                 continue;
             } else {
                 // Unconditional arms, e.g., `_ => 0,`
+                if !markers.is_empty() {
+                    return Err(input.error("Expecting a conditional arm after `exhaustive`"));
+                }
                 let mut code = until_bracket(&input)?;
                 if let Some(attr) = attribute.take() {
                     code.insert(0, Replacing::None(attr));
@@ -125,6 +144,9 @@ impl Parse for OpcodeMatches {
                 MatchArm::as_is(CodeBlock(code))
             });
         }
+        if !markers.is_empty() {
+            return Err(input.error("Expecting a conditional arm after `exhaustive`"));
+        }
         Ok(OpcodeMatches {
             value,
             value_type,
@@ -166,6 +188,8 @@ impl MatchArm {
         MatchArm {
             combinations: Vec::new(),
             header: None,
+            binding: None,
+            exhaustive: Vec::new(),
             code,
         }
     }
@@ -179,6 +203,15 @@ impl Parse for MatchArm {
             header.parse_terminated(Aliases::parse)?;
         let combinations = Vec::from_iter(combinations);
         let _: Token!(=>) = input.parse()?;
+        let binding = if input.peek(Ident) {
+            let bind: Ident = input.parse()?;
+            if bind != "bind" {
+                return Err(syn::Error::new(bind.span(), "Expecting `bind` or a block"));
+            }
+            Some(input.parse()?)
+        } else {
+            None
+        };
         let code;
         braced!(code in input);
         let code: CodeBlock = code.parse()?;
@@ -186,6 +219,8 @@ impl Parse for MatchArm {
         Ok(MatchArm {
             combinations,
             header: None,
+            binding,
+            exhaustive: Vec::new(),
             code,
         })
     }
@@ -193,12 +228,20 @@ impl Parse for MatchArm {
 
 struct AliasPair {
     pub full: String,
+    pub end: Option<String>,
     pub alias: Vec<Alias>,
 }
 
 impl Parse for AliasPair {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let component: Ident = input.parse()?;
+        let end = if input.peek(Token!(..=)) {
+            let _: Token!(..=) = input.parse()?;
+            let end: Ident = input.parse()?;
+            Some(end.to_string())
+        } else {
+            None
+        };
         let _: Token!(:) = input.parse()?;
         let alias = if input.peek(Ident) {
             let ident: Ident = input.parse()?;
@@ -218,6 +261,7 @@ impl Parse for AliasPair {
         };
         Ok(AliasPair {
             full: component.to_string(),
+            end,
             alias,
         })
     }
@@ -241,7 +285,7 @@ impl Parse for Aliases {
             content.parse_terminated(AliasPair::parse)?;
         let mut result = Aliases(Vec::new());
         for ele in aliases {
-            result.0.push((ele.alias, ele.full.clone()));
+            result.0.push((ele.alias, ele.full.clone(), ele.end));
         }
         Ok(result)
     }
@@ -282,6 +326,43 @@ fn test_parsers() {
     assert!(syn::parse2::<OpcodeMatches>(s).is_ok());
 }
 
+#[test]
+fn test_ranges() {
+    let s = quote::quote! {
+        a as u8,
+        [[BPF_JMP: jmp], [BPF_JLT..=BPF_JSLE: cmp, BPF_JA: ja]] => {}
+    };
+    let parsed = syn::parse2::<OpcodeMatches>(s).unwrap();
+    let ranges = &parsed.matches[0].combinations[1].0;
+    assert_eq!(ranges[0].1, "BPF_JLT");
+    assert_eq!(ranges[0].2.as_deref(), Some("BPF_JSLE"));
+    assert_eq!(ranges[1].2, None);
+
+    let s = quote::quote! {
+        a as u8,
+        [[BPF_JLT..=: cmp]] => {}
+    };
+    assert!(syn::parse2::<OpcodeMatches>(s).is_err());
+}
+
+#[test]
+fn test_binding() {
+    let s = quote::quote! {
+        a as u8,
+        [[BPF_ADD: add]] => bind matched { matched }
+        _ => 0,
+    };
+    let parsed = syn::parse2::<OpcodeMatches>(s).unwrap();
+    assert_eq!(parsed.matches[0].binding.as_ref().unwrap(), "matched");
+    assert!(parsed.matches[1].binding.is_none());
+
+    let s = quote::quote! {
+        a as u8,
+        [[BPF_ADD: add]] => bound matched { matched }
+    };
+    assert!(syn::parse2::<OpcodeMatches>(s).is_err());
+}
+
 #[test]
 fn test_in() {
     let s = quote::quote! {
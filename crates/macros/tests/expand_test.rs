@@ -0,0 +1,58 @@
+#[path = "../src/block.rs"]
+mod block;
+#[path = "../src/exhaustive.rs"]
+mod exhaustive;
+#[path = "../src/expand.rs"]
+mod expand;
+#[path = "../src/generator.rs"]
+mod generator;
+#[path = "../src/parser.rs"]
+mod parser;
+
+use std::{fs::read_to_string, path::Path, str::FromStr};
+
+use proc_macro2::{TokenStream, TokenTree};
+
+/// Flattens the tokens, dropping trailing commas in groups that `rustfmt` may add
+fn flatten(stream: TokenStream, tokens: &mut Vec<String>) {
+    let trees: Vec<TokenTree> = stream.into_iter().collect();
+    for (i, tree) in trees.iter().enumerate() {
+        match tree {
+            TokenTree::Group(group) => {
+                tokens.push(format!("{:?}", group.delimiter()));
+                flatten(group.stream(), tokens);
+                tokens.push(String::from("end"));
+            }
+            TokenTree::Punct(p) if p.as_char() == ',' && i + 1 == trees.len() => {}
+            _ => tokens.push(tree.to_string()),
+        }
+    }
+}
+
+/// Compares tokens only, so that formatting and plain comments do not matter
+fn tokens(code: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    flatten(TokenStream::from_str(code).unwrap(), &mut tokens);
+    tokens
+}
+
+#[test]
+fn test_dispatch_expanded() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../analyzer/src/interpreter");
+    let source = read_to_string(dir.join("dispatch.rs")).unwrap();
+    let expanded = read_to_string(dir.join("dispatch_expanded.rs")).unwrap();
+    assert!(
+        tokens(&expand::expand_dispatch(&source).unwrap()) == tokens(&expanded),
+        "dispatch_expanded.rs is out of date, run scripts/expand-dispatch.sh"
+    );
+}
+
+#[test]
+fn test_expand() {
+    let source =
+        "let a = 1;\nopcode_match! { opcode as u8 in ns, [[A: a]] => { \"}\" } _ => {} }\n";
+    let expanded = expand::expand(source).unwrap();
+    assert!(expanded.starts_with("let a = 1;\n"));
+    assert!(!expanded.contains("opcode_match"));
+    assert!(expand::expand("opcode_match! { opcode,").is_err());
+}
@@ -103,3 +103,89 @@ fn test_with_same_aliases() {
         }
     }
 }
+
+fn jump_kind(opcode: u8) -> &'static str {
+    opcode_match! {
+        opcode as u8 in ebpf_consts,
+        [[BPF_JMP: jmp, BPF_JMP32: jmp32], [BPF_X: x, BPF_K: k],
+         [BPF_JLT..=BPF_JSLE: ["cmp", "lt_to_sle"], BPF_JEQ: ["eq", "eq"]]] => {
+            #?((jmp32)) "jmp32" ##
+            #?((jmp, x)) #:1:2 ##
+            #?((jmp, k, cmp)) "k" ##
+            #?((jmp, k, eq)) #2 ##
+        }
+        _ => "none",
+    }
+}
+
+#[test]
+fn test_range() {
+    for op in [BPF_JLT, BPF_JLE, BPF_JSLT, BPF_JSLE] {
+        assert_eq!(jump_kind(BPF_JMP | BPF_X | op), "lt_to_sle");
+        assert_eq!(jump_kind(BPF_JMP | BPF_K | op), "k");
+        assert_eq!(jump_kind(BPF_JMP32 | BPF_X | op), "jmp32");
+    }
+    assert_eq!(jump_kind(BPF_JMP | BPF_X | BPF_JEQ), "eq");
+    assert_eq!(jump_kind(BPF_JMP | BPF_K | BPF_JEQ), "eq");
+    for op in [
+        BPF_JA, BPF_JGT, BPF_JGE, BPF_JSET, BPF_JNE, BPF_JSGT, BPF_JSGE, BPF_CALL, BPF_EXIT,
+    ] {
+        assert_eq!(jump_kind(BPF_JMP | BPF_X | op), "none");
+    }
+    assert_eq!(jump_kind(BPF_ALU | BPF_X | BPF_JLT), "none");
+}
+
+fn jump_width(opcode: u8) -> u32 {
+    opcode_match! {
+        opcode as u8 in ebpf_consts,
+        #![exhaustive(mask::BPF_OPCODE_JMP_MASK
+            except BPF_JA, BPF_CALL, BPF_EXIT, BPF_JCOND, 0xf0)]
+        [[BPF_JMP: jmp], [BPF_K: _, BPF_X: _],
+         [BPF_JEQ..=BPF_JSGE: _, BPF_JLT..=BPF_JSLE: _]] => {
+            64
+        }
+        // Signed comparisons and `BPF_JGT` / `BPF_JGE` are left to the `_` arm
+        #![exhaustive(mask::BPF_OPCODE_JMP_MASK except
+            BPF_JA, BPF_JGT, BPF_JGE, BPF_JNE, BPF_JSGT, BPF_JSGE, BPF_CALL, BPF_EXIT,
+            BPF_JSLT, BPF_JSLE, BPF_JCOND, 0xf0)]
+        [[BPF_JMP32: jmp32], [BPF_K: _, BPF_X: _], [BPF_JLE: _, BPF_JSET: _]] => {
+            32
+        }
+        [[BPF_JMP32: jmp32], [BPF_K: _, BPF_X: _], [BPF_JEQ: _, BPF_JLT: _]] => {
+            32
+        }
+        _ => 0,
+    }
+}
+
+#[test]
+fn test_exhaustive() {
+    assert_eq!(jump_width(BPF_JMP | BPF_X | BPF_JSET), 64);
+    assert_eq!(jump_width(BPF_JMP | BPF_K | BPF_JSGE), 64);
+    assert_eq!(jump_width(BPF_JMP32 | BPF_K | BPF_JLE), 32);
+    assert_eq!(jump_width(BPF_JMP32 | BPF_K | BPF_JSGT), 0);
+}
+
+#[test]
+fn test_bind() {
+    use ebpf_consts::mask::BPF_OPCODE_CLASS_MASK;
+    let width = |opcode: u8| {
+        opcode_match! {
+            opcode as u8 in ebpf_consts,
+            [[BPF_JMP: _, BPF_JMP32: _], [BPF_K: _, BPF_X: _],
+             [BPF_JEQ: _, BPF_JLT..=BPF_JSLE: _]] => bind matched {
+                assert_eq!(matched, opcode);
+                if matched & BPF_OPCODE_CLASS_MASK == BPF_JMP32 { 32 } else { 64 }
+            }
+            [[BPF_JMP_EXIT: _]] => bind exit {
+                assert_eq!(exit, BPF_JMP_EXIT);
+                0
+            }
+            _ => panic!(),
+        }
+    };
+    assert_eq!(width(BPF_JMP32 | BPF_X | BPF_JEQ), 32);
+    assert_eq!(width(BPF_JMP | BPF_K | BPF_JEQ), 64);
+    assert_eq!(width(BPF_JMP32 | BPF_K | BPF_JSLT), 32);
+    assert_eq!(width(BPF_JMP_EXIT), 0);
+}
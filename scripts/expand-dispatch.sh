@@ -0,0 +1,15 @@
+#!/bin/sh
+
+# Regenerates analyzer/src/interpreter/dispatch_expanded.rs from dispatch.rs,
+# which is used with the `no-macros` feature of ebpf-analyzer.
+# The `expand_test` test of opcode-macros fails whenever the two get out of sync.
+
+set -e
+
+root=$(dirname "$0")/..
+dir="$root/analyzer/src/interpreter"
+
+cargo run --quiet --manifest-path "$root/Cargo.toml" -p opcode-macros --example expand \
+  < "$dir/dispatch.rs" > "$dir/dispatch_expanded.rs.tmp"
+rustfmt --edition 2021 "$dir/dispatch_expanded.rs.tmp"
+mv "$dir/dispatch_expanded.rs.tmp" "$dir/dispatch_expanded.rs"